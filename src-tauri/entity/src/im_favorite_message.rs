@@ -0,0 +1,32 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 收藏的消息快照，create_time 为收藏时间，tags 为标签（JSON 字符串数组）
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "im_favorite_message")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub message_id: String,
+    #[serde(skip)]
+    #[sea_orm(primary_key)]
+    pub login_uid: String,
+    pub room_id: String,
+    pub from_uid: String,
+    pub from_nickname: Option<String>,
+    pub from_avatar: Option<String>,
+    pub message_type: Option<u8>,
+    pub body: Option<String>,
+    pub thumbnail_path: Option<String>,
+    pub send_time: Option<i64>,
+    /// 收藏时从消息体提取的可检索文本
+    pub search_text: String,
+    pub tags: String,
+    pub create_time: i64,
+    pub update_time: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -23,6 +23,10 @@ pub struct Model {
     /// 消息发送状态: pending, success, fail
     pub send_status: String,
     pub time_block: Option<i64>,
+    /// 最后一次编辑时间，未编辑过为空
+    pub edited_at: Option<i64>,
+    /// 被回复的消息 ID，写入时从消息体中提取
+    pub reply_to_id: Option<String>,
 }
 
 impl ActiveModelBehavior for ActiveModel {}
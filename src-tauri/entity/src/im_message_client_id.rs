@@ -0,0 +1,22 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 本地发送消息的客户端 ID 与服务端 ID 的映射，server_id 为空表示尚未收到服务端确认
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "im_message_client_id")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub client_id: String,
+    #[serde(skip)]
+    #[sea_orm(primary_key)]
+    pub login_uid: String,
+    pub server_id: Option<String>,
+    pub room_id: String,
+    pub create_time: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,24 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// @ 了当前登录用户的消息，由 im_message 上的触发器在写入时维护
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "im_message_mention")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub message_id: String,
+    #[serde(skip)]
+    #[sea_orm(primary_key)]
+    pub login_uid: String,
+    pub room_id: String,
+    pub send_time: Option<i64>,
+    /// 仅 @全体成员
+    pub mention_all: bool,
+    pub is_read: bool,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,27 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 消息编辑记录，每次编辑保存编辑前的消息体，revision 从 1 递增
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "im_message_revision")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub message_id: String,
+    #[serde(skip)]
+    #[sea_orm(primary_key)]
+    pub login_uid: String,
+    #[sea_orm(primary_key)]
+    pub revision: i32,
+    pub room_id: String,
+    /// 编辑前的消息体
+    pub body: Option<String>,
+    /// 编辑前的 edited_at，撤销本次编辑时恢复
+    pub previous_edited_at: Option<i64>,
+    pub edited_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
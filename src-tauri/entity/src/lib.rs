@@ -1,7 +1,21 @@
 pub mod im_config;
 pub mod im_contact;
+pub mod im_deleted_message;
+pub mod im_favorite_message;
+pub mod im_link_preview;
 pub mod im_message;
+pub mod im_message_client_id;
+pub mod im_message_mention;
+pub mod im_message_prune_record;
+pub mod im_message_read_count;
+pub mod im_message_read_receipt;
+pub mod im_message_revision;
+pub mod im_outbox;
 pub mod im_room;
+pub mod im_room_clear_record;
+pub mod im_room_draft;
 pub mod im_room_member;
+pub mod im_room_read_cursor;
+pub mod im_scheduled_message;
 pub mod im_user;
 pub mod prelude;
@@ -5,6 +5,22 @@ mod m20241220_000002_add_token_field;
 mod m20241220_000003_add_refresh_token_field;
 mod m20250917_000001_update_msg_table;
 mod m20250917_000002_add_thumbnail_path;
+mod m20251020_000001_create_message_fts;
+mod m20251021_000001_create_deletion_tables;
+mod m20251022_000001_create_prune_record;
+mod m20251023_000001_add_message_edit;
+mod m20251024_000001_add_reply_to_id;
+mod m20251025_000001_create_message_mention;
+mod m20251026_000001_create_room_read_cursor;
+mod m20251027_000001_create_read_receipt;
+mod m20251028_000001_create_room_draft;
+mod m20251029_000001_create_scheduled_message;
+mod m20251030_000001_create_outbox;
+mod m20251031_000001_create_message_client_id;
+mod m20251101_000001_create_favorite_message;
+mod m20251102_000001_create_message_stats_version;
+mod m20251103_000001_add_media_gallery_index;
+mod m20251104_000001_create_link_preview;
 
 pub struct Migrator;
 
@@ -17,6 +33,22 @@ impl MigratorTrait for Migrator {
             Box::new(m20241220_000003_add_refresh_token_field::Migration),
             Box::new(m20250917_000001_update_msg_table::Migration),
             Box::new(m20250917_000002_add_thumbnail_path::Migration),
+            Box::new(m20251020_000001_create_message_fts::Migration),
+            Box::new(m20251021_000001_create_deletion_tables::Migration),
+            Box::new(m20251022_000001_create_prune_record::Migration),
+            Box::new(m20251023_000001_add_message_edit::Migration),
+            Box::new(m20251024_000001_add_reply_to_id::Migration),
+            Box::new(m20251025_000001_create_message_mention::Migration),
+            Box::new(m20251026_000001_create_room_read_cursor::Migration),
+            Box::new(m20251027_000001_create_read_receipt::Migration),
+            Box::new(m20251028_000001_create_room_draft::Migration),
+            Box::new(m20251029_000001_create_scheduled_message::Migration),
+            Box::new(m20251030_000001_create_outbox::Migration),
+            Box::new(m20251031_000001_create_message_client_id::Migration),
+            Box::new(m20251101_000001_create_favorite_message::Migration),
+            Box::new(m20251102_000001_create_message_stats_version::Migration),
+            Box::new(m20251103_000001_add_media_gallery_index::Migration),
+            Box::new(m20251104_000001_create_link_preview::Migration),
         ]
     }
 }
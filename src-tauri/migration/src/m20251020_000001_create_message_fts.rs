@@ -0,0 +1,127 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// 从消息体中提取可检索文本的视图，提取规则只在这里维护一份：
+// - text_content：文本内容；合并转发消息的 content 为字符串数组，拼接后索引
+// - file_name：文件/视频等消息的文件名；不同来源使用的字段名不一致，同一条消息也可能
+//   同时带有多个名称（如 fileName 与 original_name），全部拼接后索引
+// - extra：回复引用的用户名与内容片段
+// 撤回消息（type = 2）不进入索引；URL 等字段不提取，避免误命中
+const CREATE_SOURCE_VIEW: &str = r#"
+CREATE VIEW IF NOT EXISTS im_message_search_source AS
+SELECT
+    im_message.rowid AS message_rowid,
+    CASE
+        WHEN json_valid(body) THEN
+            CASE json_type(body, '$.content')
+                WHEN 'text' THEN json_extract(body, '$.content')
+                WHEN 'array' THEN (SELECT group_concat(value, ' ') FROM json_each(body, '$.content'))
+            END
+        ELSE body
+    END AS text_content,
+    CASE
+        WHEN json_valid(body) THEN
+            (
+                SELECT group_concat(value, ' ')
+                FROM json_each(body)
+                WHERE type = 'text' AND key IN (
+                    'fileName', 'filename', 'file_name',
+                    'originalFileName', 'originalName', 'original_name',
+                    'name', 'title', 'fileTitle'
+                )
+            )
+    END AS file_name,
+    CASE
+        WHEN json_valid(body) AND json_type(body, '$.reply') = 'object' THEN
+            trim(
+                COALESCE(json_extract(body, '$.reply.username'), '') || ' ' ||
+                COALESCE(
+                    CASE json_type(body, '$.reply.body')
+                        WHEN 'text' THEN json_extract(body, '$.reply.body')
+                        WHEN 'object' THEN json_extract(body, '$.reply.body.content')
+                    END,
+                    ''
+                )
+            )
+    END AS extra
+FROM im_message
+WHERE message_type IS NULL OR message_type <> 2
+"#;
+
+// trigram 分词器按三字符切分，无需词典即可检索中文
+const CREATE_FTS_TABLE: &str = r#"
+CREATE VIRTUAL TABLE IF NOT EXISTS im_message_fts USING fts5(
+    text_content,
+    file_name,
+    extra,
+    tokenize = 'trigram'
+)
+"#;
+
+// FTS 行的 rowid 与 im_message 的 rowid 一一对应
+const CREATE_TRIGGERS: [&str; 3] = [
+    r#"
+CREATE TRIGGER IF NOT EXISTS im_message_fts_after_insert AFTER INSERT ON im_message BEGIN
+    INSERT INTO im_message_fts (rowid, text_content, file_name, extra)
+    SELECT message_rowid, text_content, file_name, extra
+    FROM im_message_search_source WHERE message_rowid = NEW.rowid;
+END
+"#,
+    r#"
+CREATE TRIGGER IF NOT EXISTS im_message_fts_after_update AFTER UPDATE OF body, message_type ON im_message BEGIN
+    DELETE FROM im_message_fts WHERE rowid = OLD.rowid;
+    INSERT INTO im_message_fts (rowid, text_content, file_name, extra)
+    SELECT message_rowid, text_content, file_name, extra
+    FROM im_message_search_source WHERE message_rowid = NEW.rowid;
+END
+"#,
+    r#"
+CREATE TRIGGER IF NOT EXISTS im_message_fts_after_delete AFTER DELETE ON im_message BEGIN
+    DELETE FROM im_message_fts WHERE rowid = OLD.rowid;
+END
+"#,
+];
+
+const BACKFILL: &str = r#"
+INSERT INTO im_message_fts (rowid, text_content, file_name, extra)
+SELECT message_rowid, text_content, file_name, extra FROM im_message_search_source
+"#;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared(CREATE_SOURCE_VIEW).await?;
+        db.execute_unprepared(CREATE_FTS_TABLE).await?;
+        for trigger in CREATE_TRIGGERS {
+            db.execute_unprepared(trigger).await?;
+        }
+        // 为已有消息建立索引
+        db.execute_unprepared("DELETE FROM im_message_fts").await?;
+        db.execute_unprepared(BACKFILL).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        for trigger in [
+            "im_message_fts_after_insert",
+            "im_message_fts_after_update",
+            "im_message_fts_after_delete",
+        ] {
+            db.execute_unprepared(&format!("DROP TRIGGER IF EXISTS {}", trigger))
+                .await?;
+        }
+        db.execute_unprepared("DROP TABLE IF EXISTS im_message_fts")
+            .await?;
+        db.execute_unprepared("DROP VIEW IF EXISTS im_message_search_source")
+            .await?;
+
+        Ok(())
+    }
+}
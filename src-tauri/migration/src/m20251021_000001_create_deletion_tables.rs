@@ -0,0 +1,169 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// 按房间、登录用户取消息并按数值 ID 排序（游标分页）时使用的表达式索引，
+// 表达式需与查询中的 CAST("id" AS INTEGER) 保持一致
+const CREATE_NUMERIC_ID_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS idx_im_message_room_login_numeric_id \
+     ON im_message (room_id, login_uid, CAST(id AS INTEGER))";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 旧版本在运行时以相同结构创建过这两张表，这里使用 if_not_exists 兼容已有数据
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImDeletedMessage::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ImDeletedMessage::Id).string().not_null())
+                    .col(ColumnDef::new(ImDeletedMessage::RoomId).string().not_null())
+                    .col(
+                        ColumnDef::new(ImDeletedMessage::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImDeletedMessage::DeletedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ImDeletedMessage::Id)
+                            .col(ImDeletedMessage::LoginUid),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImRoomClearRecord::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImRoomClearRecord::RoomId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImRoomClearRecord::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImRoomClearRecord::ClearedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImRoomClearRecord::LastClearedMsgId).string())
+                    .primary_key(
+                        Index::create()
+                            .col(ImRoomClearRecord::RoomId)
+                            .col(ImRoomClearRecord::LoginUid),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 按房间查询/删除已删除记录
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_im_deleted_message_login_room")
+                    .table(ImDeletedMessage::Table)
+                    .col(ImDeletedMessage::LoginUid)
+                    .col(ImDeletedMessage::RoomId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        // 按房间、登录用户取消息并按发送时间排序/过滤
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_im_message_room_login_send_time")
+                    .table(ImMessage::Table)
+                    .col(ImMessage::RoomId)
+                    .col(ImMessage::LoginUid)
+                    .col(ImMessage::SendTime)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(CREATE_NUMERIC_ID_INDEX)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP INDEX IF EXISTS idx_im_message_room_login_numeric_id")
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_im_message_room_login_send_time")
+                    .table(ImMessage::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImRoomClearRecord::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImDeletedMessage::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImDeletedMessage {
+    Table,
+    Id,
+    RoomId,
+    LoginUid,
+    DeletedAt,
+}
+
+#[derive(DeriveIden)]
+enum ImRoomClearRecord {
+    Table,
+    RoomId,
+    LoginUid,
+    ClearedAt,
+    LastClearedMsgId,
+}
+
+#[derive(DeriveIden)]
+enum ImMessage {
+    Table,
+    RoomId,
+    LoginUid,
+    SendTime,
+}
@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImMessagePruneRecord::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImMessagePruneRecord::RoomId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessagePruneRecord::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessagePruneRecord::PrunedUntil)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessagePruneRecord::PrunedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ImMessagePruneRecord::RoomId)
+                            .col(ImMessagePruneRecord::LoginUid),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImMessagePruneRecord::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImMessagePruneRecord {
+    Table,
+    RoomId,
+    LoginUid,
+    PrunedUntil,
+    PrunedAt,
+}
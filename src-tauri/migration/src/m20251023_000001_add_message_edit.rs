@@ -0,0 +1,119 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// 消息删除（含清空房间、按保留策略清理）时一并删除编辑记录
+const CREATE_DELETE_TRIGGER: &str = r#"
+CREATE TRIGGER IF NOT EXISTS im_message_revision_after_message_delete AFTER DELETE ON im_message BEGIN
+    DELETE FROM im_message_revision WHERE message_id = old.id AND login_uid = old.login_uid;
+END
+"#;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImMessage::Table)
+                    .add_column(ColumnDef::new(ImMessage::EditedAt).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImMessageRevision::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImMessageRevision::MessageId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageRevision::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageRevision::Revision)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageRevision::RoomId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImMessageRevision::Body).text())
+                    .col(ColumnDef::new(ImMessageRevision::PreviousEditedAt).big_integer())
+                    .col(
+                        ColumnDef::new(ImMessageRevision::EditedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ImMessageRevision::MessageId)
+                            .col(ImMessageRevision::LoginUid)
+                            .col(ImMessageRevision::Revision),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(CREATE_DELETE_TRIGGER)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP TRIGGER IF EXISTS im_message_revision_after_message_delete")
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImMessageRevision::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImMessage::Table)
+                    .drop_column(ImMessage::EditedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImMessage {
+    Table,
+    EditedAt,
+}
+
+#[derive(DeriveIden)]
+enum ImMessageRevision {
+    Table,
+    MessageId,
+    LoginUid,
+    Revision,
+    RoomId,
+    Body,
+    PreviousEditedAt,
+    EditedAt,
+}
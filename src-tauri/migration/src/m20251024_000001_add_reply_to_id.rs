@@ -0,0 +1,76 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// 回复消息的被回复 ID：服务端消息体为 reply.id，本地发送的消息体为 replyMsgId
+const BACKFILL: &str = r#"
+UPDATE im_message
+SET reply_to_id = CAST(COALESCE(json_extract(body, '$.reply.id'), json_extract(body, '$.replyMsgId')) AS TEXT)
+WHERE json_valid(body)
+    AND COALESCE(json_extract(body, '$.reply.id'), json_extract(body, '$.replyMsgId')) IS NOT NULL
+"#;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImMessage::Table)
+                    .add_column(ColumnDef::new(ImMessage::ReplyToId).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        // 按被回复消息查找回复
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_im_message_login_reply_to")
+                    .table(ImMessage::Table)
+                    .col(ImMessage::LoginUid)
+                    .col(ImMessage::ReplyToId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(BACKFILL)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_im_message_login_reply_to")
+                    .table(ImMessage::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImMessage::Table)
+                    .drop_column(ImMessage::ReplyToId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImMessage {
+    Table,
+    LoginUid,
+    ReplyToId,
+}
@@ -0,0 +1,171 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// HuLa 服务端约定：文本消息 body.atUidList 中的 uid 0 表示 @全体成员。
+/// JSON 中可能是数字 0 或字符串 "0"，比较前统一转为文本
+const MENTION_ALL_UID: &str = "0";
+
+/// 消息 @ 了当前登录用户：atUidList 含登录用户 uid 或 [`MENTION_ALL_UID`]。
+/// 自己发送的消息和已撤回的消息（type = 2）不计入。row 为触发器中的 new 或回填时的表别名
+fn mention_condition(row: &str) -> String {
+    format!(
+        "{row}.uid <> {row}.login_uid \
+         AND ({row}.message_type IS NULL OR {row}.message_type <> 2) \
+         AND EXISTS (\
+             SELECT 1 FROM json_each(iif(json_valid({row}.body), {row}.body, '{{}}'), '$.atUidList') \
+             WHERE CAST(value AS TEXT) IN ({row}.login_uid, '{MENTION_ALL_UID}'))"
+    )
+}
+
+/// 仅 @全体成员、未单独 @ 当前用户
+fn mention_all_expr(row: &str) -> String {
+    format!(
+        "NOT EXISTS (\
+             SELECT 1 FROM json_each(iif(json_valid({row}.body), {row}.body, '{{}}'), '$.atUidList') \
+             WHERE CAST(value AS TEXT) = {row}.login_uid)"
+    )
+}
+
+fn insert_mention_sql() -> String {
+    format!(
+        "INSERT INTO im_message_mention (message_id, login_uid, room_id, send_time, mention_all, is_read) \
+         SELECT new.id, new.login_uid, new.room_id, new.send_time, {}, 0 \
+         WHERE {} \
+         ON CONFLICT(message_id, login_uid) DO UPDATE SET \
+         room_id = excluded.room_id, send_time = excluded.send_time, mention_all = excluded.mention_all",
+        mention_all_expr("new"),
+        mention_condition("new")
+    )
+}
+
+fn create_triggers() -> Vec<String> {
+    let insert = insert_mention_sql();
+    let condition = mention_condition("new");
+    vec![
+        format!(
+            "CREATE TRIGGER IF NOT EXISTS im_message_mention_after_insert AFTER INSERT ON im_message BEGIN {insert}; END"
+        ),
+        // 内容变化（编辑、撤回）时重新判断，保留已读状态
+        format!(
+            "CREATE TRIGGER IF NOT EXISTS im_message_mention_after_update AFTER UPDATE OF body, message_type, send_time ON im_message BEGIN \
+             DELETE FROM im_message_mention WHERE message_id = old.id AND login_uid = old.login_uid AND NOT ({condition}); \
+             {insert}; END"
+        ),
+        "CREATE TRIGGER IF NOT EXISTS im_message_mention_after_delete AFTER DELETE ON im_message BEGIN \
+         DELETE FROM im_message_mention WHERE message_id = old.id AND login_uid = old.login_uid; END"
+            .to_string(),
+    ]
+}
+
+// 已有消息的提及视为已读，避免升级后出现大量历史未读提及
+fn backfill_sql() -> String {
+    format!(
+        "INSERT OR IGNORE INTO im_message_mention (message_id, login_uid, room_id, send_time, mention_all, is_read) \
+         SELECT m.id, m.login_uid, m.room_id, m.send_time, {}, 1 \
+         FROM im_message m \
+         WHERE {}",
+        mention_all_expr("m"),
+        mention_condition("m")
+    )
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImMessageMention::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImMessageMention::MessageId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageMention::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImMessageMention::RoomId).string().not_null())
+                    .col(ColumnDef::new(ImMessageMention::SendTime).big_integer())
+                    .col(
+                        ColumnDef::new(ImMessageMention::MentionAll)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageMention::IsRead)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ImMessageMention::MessageId)
+                            .col(ImMessageMention::LoginUid),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 按房间统计、清除未读提及
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_im_message_mention_login_room_read")
+                    .table(ImMessageMention::Table)
+                    .col(ImMessageMention::LoginUid)
+                    .col(ImMessageMention::RoomId)
+                    .col(ImMessageMention::IsRead)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        let db = manager.get_connection();
+        for trigger in create_triggers() {
+            db.execute_unprepared(&trigger).await?;
+        }
+        db.execute_unprepared(&backfill_sql()).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        for trigger in [
+            "im_message_mention_after_insert",
+            "im_message_mention_after_update",
+            "im_message_mention_after_delete",
+        ] {
+            db.execute_unprepared(&format!("DROP TRIGGER IF EXISTS {}", trigger))
+                .await?;
+        }
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImMessageMention::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImMessageMention {
+    Table,
+    MessageId,
+    LoginUid,
+    RoomId,
+    SendTime,
+    MentionAll,
+    IsRead,
+}
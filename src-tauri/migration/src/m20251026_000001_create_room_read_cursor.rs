@@ -0,0 +1,68 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImRoomReadCursor::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ImRoomReadCursor::RoomId).string().not_null())
+                    .col(
+                        ColumnDef::new(ImRoomReadCursor::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImRoomReadCursor::LastReadMsgId).string())
+                    .col(
+                        ColumnDef::new(ImRoomReadCursor::LastReadTime)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImRoomReadCursor::MarkedUnread)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(ColumnDef::new(ImRoomReadCursor::UpdateTime).big_integer())
+                    .primary_key(
+                        Index::create()
+                            .col(ImRoomReadCursor::RoomId)
+                            .col(ImRoomReadCursor::LoginUid),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImRoomReadCursor::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImRoomReadCursor {
+    Table,
+    RoomId,
+    LoginUid,
+    LastReadMsgId,
+    LastReadTime,
+    MarkedUnread,
+    UpdateTime,
+}
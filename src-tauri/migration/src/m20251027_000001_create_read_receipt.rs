@@ -0,0 +1,146 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// 消息删除时一并删除已读回执缓存
+const CREATE_DELETE_TRIGGER: &str = r#"
+CREATE TRIGGER IF NOT EXISTS im_message_read_receipt_after_message_delete AFTER DELETE ON im_message BEGIN
+    DELETE FROM im_message_read_receipt WHERE message_id = old.id AND login_uid = old.login_uid;
+    DELETE FROM im_message_read_count WHERE message_id = old.id AND login_uid = old.login_uid;
+END
+"#;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImMessageReadReceipt::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImMessageReadReceipt::MessageId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageReadReceipt::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageReadReceipt::ReaderUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageReadReceipt::IsRead)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(ColumnDef::new(ImMessageReadReceipt::UpdateTime).big_integer())
+                    .primary_key(
+                        Index::create()
+                            .col(ImMessageReadReceipt::MessageId)
+                            .col(ImMessageReadReceipt::LoginUid)
+                            .col(ImMessageReadReceipt::ReaderUid),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImMessageReadCount::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImMessageReadCount::MessageId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageReadCount::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageReadCount::ReadCount)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(ImMessageReadCount::UnreadCount).integer())
+                    .col(
+                        ColumnDef::new(ImMessageReadCount::FetchedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ImMessageReadCount::MessageId)
+                            .col(ImMessageReadCount::LoginUid),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(CREATE_DELETE_TRIGGER)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "DROP TRIGGER IF EXISTS im_message_read_receipt_after_message_delete",
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImMessageReadCount::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImMessageReadReceipt::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImMessageReadReceipt {
+    Table,
+    MessageId,
+    LoginUid,
+    ReaderUid,
+    IsRead,
+    UpdateTime,
+}
+
+#[derive(DeriveIden)]
+enum ImMessageReadCount {
+    Table,
+    MessageId,
+    LoginUid,
+    ReadCount,
+    UnreadCount,
+    FetchedAt,
+}
@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImRoomDraft::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ImRoomDraft::RoomId).string().not_null())
+                    .col(ColumnDef::new(ImRoomDraft::LoginUid).string().not_null())
+                    .col(ColumnDef::new(ImRoomDraft::Content).text())
+                    .col(ColumnDef::new(ImRoomDraft::ReplyToId).string())
+                    .col(ColumnDef::new(ImRoomDraft::Mentions).text())
+                    .col(ColumnDef::new(ImRoomDraft::Attachments).text())
+                    .col(
+                        ColumnDef::new(ImRoomDraft::UpdateTime)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ImRoomDraft::RoomId)
+                            .col(ImRoomDraft::LoginUid),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImRoomDraft::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImRoomDraft {
+    Table,
+    RoomId,
+    LoginUid,
+    Content,
+    ReplyToId,
+    Mentions,
+    Attachments,
+    UpdateTime,
+}
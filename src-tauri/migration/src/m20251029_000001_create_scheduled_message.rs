@@ -0,0 +1,105 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImScheduledMessage::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ImScheduledMessage::Id).string().not_null())
+                    .col(
+                        ColumnDef::new(ImScheduledMessage::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImScheduledMessage::RoomId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImScheduledMessage::Request)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImScheduledMessage::SendAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImScheduledMessage::Status)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImScheduledMessage::SentMsgId).string())
+                    .col(ColumnDef::new(ImScheduledMessage::Error).text())
+                    .col(
+                        ColumnDef::new(ImScheduledMessage::CreateTime)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImScheduledMessage::UpdateTime)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ImScheduledMessage::Id)
+                            .col(ImScheduledMessage::LoginUid),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 调度器按状态和发送时间取到期任务
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_im_scheduled_message_login_status_send_at")
+                    .table(ImScheduledMessage::Table)
+                    .col(ImScheduledMessage::LoginUid)
+                    .col(ImScheduledMessage::Status)
+                    .col(ImScheduledMessage::SendAt)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImScheduledMessage::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImScheduledMessage {
+    Table,
+    Id,
+    LoginUid,
+    RoomId,
+    Request,
+    SendAt,
+    Status,
+    SentMsgId,
+    Error,
+    CreateTime,
+    UpdateTime,
+}
@@ -0,0 +1,87 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImOutbox::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ImOutbox::Id).string().not_null())
+                    .col(ColumnDef::new(ImOutbox::LoginUid).string().not_null())
+                    .col(ColumnDef::new(ImOutbox::RoomId).string().not_null())
+                    .col(ColumnDef::new(ImOutbox::Seq).big_integer().not_null())
+                    .col(ColumnDef::new(ImOutbox::Request).text().not_null())
+                    .col(ColumnDef::new(ImOutbox::Status).string().not_null())
+                    .col(
+                        ColumnDef::new(ImOutbox::Attempts)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(ImOutbox::NextAttemptAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImOutbox::LastError).text())
+                    .col(
+                        ColumnDef::new(ImOutbox::CreateTime)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImOutbox::UpdateTime)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(Index::create().col(ImOutbox::Id).col(ImOutbox::LoginUid))
+                    .to_owned(),
+            )
+            .await?;
+
+        // 同一房间按入队顺序发送
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_im_outbox_login_room_seq")
+                    .table(ImOutbox::Table)
+                    .col(ImOutbox::LoginUid)
+                    .col(ImOutbox::RoomId)
+                    .col(ImOutbox::Seq)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ImOutbox::Table).if_exists().to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImOutbox {
+    Table,
+    Id,
+    LoginUid,
+    RoomId,
+    Seq,
+    Request,
+    Status,
+    Attempts,
+    NextAttemptAt,
+    LastError,
+    CreateTime,
+    UpdateTime,
+}
@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImMessageClientId::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImMessageClientId::ClientId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageClientId::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImMessageClientId::ServerId).string())
+                    .col(
+                        ColumnDef::new(ImMessageClientId::RoomId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageClientId::CreateTime)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ImMessageClientId::ClientId)
+                            .col(ImMessageClientId::LoginUid),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 保存服务端消息时按服务端 ID 查找对应的本地临时消息
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_im_message_client_id_login_server")
+                    .table(ImMessageClientId::Table)
+                    .col(ImMessageClientId::LoginUid)
+                    .col(ImMessageClientId::ServerId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImMessageClientId::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImMessageClientId {
+    Table,
+    ClientId,
+    LoginUid,
+    ServerId,
+    RoomId,
+    CreateTime,
+}
@@ -0,0 +1,119 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 收藏保存消息快照，不随 im_message 的删除、清空和清理而变化
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImFavoriteMessage::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImFavoriteMessage::MessageId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImFavoriteMessage::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImFavoriteMessage::RoomId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImFavoriteMessage::FromUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImFavoriteMessage::FromNickname).string())
+                    .col(ColumnDef::new(ImFavoriteMessage::FromAvatar).string())
+                    .col(ColumnDef::new(ImFavoriteMessage::MessageType).small_integer())
+                    .col(ColumnDef::new(ImFavoriteMessage::Body).text())
+                    .col(ColumnDef::new(ImFavoriteMessage::ThumbnailPath).string())
+                    .col(ColumnDef::new(ImFavoriteMessage::SendTime).big_integer())
+                    .col(
+                        ColumnDef::new(ImFavoriteMessage::SearchText)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(ImFavoriteMessage::Tags)
+                            .text()
+                            .not_null()
+                            .default("[]"),
+                    )
+                    .col(
+                        ColumnDef::new(ImFavoriteMessage::CreateTime)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImFavoriteMessage::UpdateTime)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ImFavoriteMessage::MessageId)
+                            .col(ImFavoriteMessage::LoginUid),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 按收藏时间倒序分页
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_im_favorite_message_login_create_time")
+                    .table(ImFavoriteMessage::Table)
+                    .col(ImFavoriteMessage::LoginUid)
+                    .col(ImFavoriteMessage::CreateTime)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImFavoriteMessage::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImFavoriteMessage {
+    Table,
+    MessageId,
+    LoginUid,
+    RoomId,
+    FromUid,
+    FromNickname,
+    FromAvatar,
+    MessageType,
+    Body,
+    ThumbnailPath,
+    SendTime,
+    SearchText,
+    Tags,
+    CreateTime,
+    UpdateTime,
+}
@@ -0,0 +1,104 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+fn bump_version_sql(row: &str) -> String {
+    format!(
+        "INSERT INTO im_message_stats_version (login_uid, room_id, version) \
+         VALUES ({row}.login_uid, {row}.room_id, 1) \
+         ON CONFLICT(login_uid, room_id) DO UPDATE SET version = version + 1"
+    )
+}
+
+// 消息写入时递增所在房间的版本号，统计结果的缓存按版本号失效。
+// 只关心影响统计的列，time_block、缩略图、发送状态等更新不触发
+fn create_triggers() -> Vec<String> {
+    let bump_new = bump_version_sql("new");
+    let bump_old = bump_version_sql("old");
+    vec![
+        format!(
+            "CREATE TRIGGER IF NOT EXISTS im_message_stats_after_insert AFTER INSERT ON im_message BEGIN {bump_new}; END"
+        ),
+        format!(
+            "CREATE TRIGGER IF NOT EXISTS im_message_stats_after_update AFTER UPDATE OF room_id, uid, message_type, body, send_time ON im_message BEGIN \
+             {bump_old}; {bump_new}; END"
+        ),
+        format!(
+            "CREATE TRIGGER IF NOT EXISTS im_message_stats_after_delete AFTER DELETE ON im_message BEGIN {bump_old}; END"
+        ),
+    ]
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImMessageStatsVersion::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImMessageStatsVersion::LoginUid)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageStatsVersion::RoomId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImMessageStatsVersion::Version)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ImMessageStatsVersion::LoginUid)
+                            .col(ImMessageStatsVersion::RoomId),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let db = manager.get_connection();
+        for trigger in create_triggers() {
+            db.execute_unprepared(&trigger).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        for trigger in [
+            "im_message_stats_after_insert",
+            "im_message_stats_after_update",
+            "im_message_stats_after_delete",
+        ] {
+            db.execute_unprepared(&format!("DROP TRIGGER IF EXISTS {}", trigger))
+                .await?;
+        }
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImMessageStatsVersion::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImMessageStatsVersion {
+    Table,
+    LoginUid,
+    RoomId,
+    Version,
+}
@@ -0,0 +1,52 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 媒体库按类型取消息并按 (send_time, id) 倒序翻页；
+        // room_id 放在末尾，按房间筛选时直接在索引中过滤，无需回表
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_im_message_login_type_send_time_room")
+                    .table(ImMessage::Table)
+                    .col(ImMessage::LoginUid)
+                    .col(ImMessage::MessageType)
+                    .col(ImMessage::SendTime)
+                    .col(ImMessage::Id)
+                    .col(ImMessage::RoomId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_im_message_login_type_send_time_room")
+                    .table(ImMessage::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImMessage {
+    Table,
+    Id,
+    LoginUid,
+    RoomId,
+    MessageType,
+    SendTime,
+}
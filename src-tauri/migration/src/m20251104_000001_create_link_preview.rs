@@ -0,0 +1,87 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 链接预览缓存，只保存公开网页的元数据，不区分登录用户
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImLinkPreview::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImLinkPreview::Url)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ImLinkPreview::Title).string())
+                    .col(ColumnDef::new(ImLinkPreview::Description).text())
+                    .col(ColumnDef::new(ImLinkPreview::Image).string())
+                    .col(ColumnDef::new(ImLinkPreview::SiteName).string())
+                    .col(ColumnDef::new(ImLinkPreview::Favicon).string())
+                    .col(
+                        ColumnDef::new(ImLinkPreview::Success)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(ImLinkPreview::FetchedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImLinkPreview::ExpiresAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 清理过期缓存
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_im_link_preview_expires_at")
+                    .table(ImLinkPreview::Table)
+                    .col(ImLinkPreview::ExpiresAt)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ImLinkPreview::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImLinkPreview {
+    Table,
+    Url,
+    Title,
+    Description,
+    Image,
+    SiteName,
+    Favicon,
+    Success,
+    FetchedAt,
+    ExpiresAt,
+}
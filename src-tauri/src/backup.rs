@@ -0,0 +1,830 @@
+//! 本地数据的加密备份与恢复
+//!
+//! 备份为 zip 归档：
+//! - `manifest.json`：格式、版本及原设备上的路径信息
+//! - `db.sqlite`：使用用户口令经 sqlcipher_export 重新加密的数据库
+//! - `files.sqlite`：同样以用户口令加密的文件库，按块保存附件缓存（`userData/`）
+//!   和消息引用的本地缩略图（`thumbnails/`）
+//!
+//! 数据库密钥与设备绑定，恢复时先用口令打开备份库，再以当前设备密钥导出为暂存库，
+//! 下次启动建立连接前替换正式库（见 [`apply_staged_restore`]）。口令校验通过前不写入任何本地文件。
+
+use crate::common::sqlcipher::{cleanup_sqlite_sidecar_files, escape_sqlite_single_quoted};
+use crate::error::CommonError;
+use crate::repository::im_message_fts_repository;
+use chrono::Utc;
+use sea_orm::sea_query::Value;
+use sea_orm::{
+    ConnectOptions, ConnectionTrait, Database, DatabaseConnection, Statement, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+
+pub const BACKUP_FORMAT: &str = "hula-backup";
+/// 当前备份格式版本，恢复时拒绝其他版本的归档
+pub const BACKUP_FORMAT_VERSION: u32 = 2;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const DATABASE_ENTRY: &str = "db.sqlite";
+const FILES_ENTRY: &str = "files.sqlite";
+/// 文件按块写入文件库，避免单个 BLOB 过大
+const FILE_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+const USER_DATA_PREFIX: &str = "userData/";
+const THUMBNAIL_PREFIX: &str = "thumbnails/";
+const MIN_PASSPHRASE_CHARS: usize = 8;
+const THUMBNAIL_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "webp", "gif"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub format: String,
+    pub version: u32,
+    pub app_version: String,
+    pub created_at: i64,
+    /// 原设备上的附件缓存根目录，恢复时用于改写消息体中的本地路径
+    pub user_data_root: Option<String>,
+    /// 缩略图条目名到原路径的映射
+    #[serde(default)]
+    pub thumbnails: HashMap<String, String>,
+}
+
+/// 备份/恢复涉及的本地路径
+#[derive(Debug, Clone)]
+pub struct BackupPaths {
+    pub db_path: PathBuf,
+    /// 附件缓存根目录（userData）
+    pub user_data_root: Option<PathBuf>,
+    /// 恢复时存放缩略图的目录
+    pub thumbnail_dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupResult {
+    pub output_path: String,
+    pub archive_bytes: u64,
+    pub file_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreResult {
+    pub message_count: u64,
+    pub file_count: usize,
+    /// 数据库在重启后生效
+    pub requires_restart: bool,
+}
+
+fn io_error(action: &str, path: &Path, e: impl std::fmt::Display) -> CommonError {
+    CommonError::RequestError(format!("{} {:?}: {}", action, path, e))
+}
+
+fn zip_error(e: zip::result::ZipError) -> CommonError {
+    CommonError::RequestError(format!("备份归档读写失败: {}", e))
+}
+
+/// 恢复时暂存的数据库路径
+fn staged_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("sqlite.restore")
+}
+
+/// 替换前的数据库保留一份，便于恢复出错时手动还原
+fn pre_restore_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("sqlite.pre-restore")
+}
+
+fn work_dir() -> Result<PathBuf, CommonError> {
+    let dir = std::env::temp_dir().join(format!("hula-backup-{}", Uuid::new_v4().simple()));
+    fs::create_dir_all(&dir).map_err(|e| io_error("创建临时目录失败", &dir, e))?;
+    Ok(dir)
+}
+
+fn validate_passphrase(passphrase: &str) -> Result<(), CommonError> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(CommonError::RequestError(format!(
+            "备份口令至少需要 {} 个字符",
+            MIN_PASSPHRASE_CHARS
+        )));
+    }
+    Ok(())
+}
+
+/// 单连接打开数据库，ATTACH 只对当前连接生效
+async fn open_single(
+    path: &Path,
+    key: &str,
+    mode: &str,
+) -> Result<DatabaseConnection, CommonError> {
+    let mut opt = ConnectOptions::new(format!("sqlite:{}?mode={}", path.display(), mode));
+    opt.sqlcipher_key(key.to_string());
+    opt.max_connections(1)
+        .min_connections(1)
+        .sqlx_logging(false);
+    Ok(Database::connect(opt).await?)
+}
+
+/// 将当前连接的数据库以 key 加密导出到 target
+async fn export_database(
+    db: &DatabaseConnection,
+    target: &Path,
+    key: &str,
+) -> Result<(), CommonError> {
+    if target.exists() {
+        fs::remove_file(target).map_err(|e| io_error("删除旧文件失败", target, e))?;
+    }
+    cleanup_sqlite_sidecar_files(target);
+    // 部分 SQLCipher/平台组合下 ATTACH 不会自动创建新文件
+    File::create(target).map_err(|e| io_error("创建数据库文件失败", target, e))?;
+
+    db.execute_unprepared(&format!(
+        "ATTACH DATABASE '{}' AS export_target KEY '{}';",
+        escape_sqlite_single_quoted(&target.display().to_string()),
+        escape_sqlite_single_quoted(key)
+    ))
+    .await?;
+    let exported = db
+        .query_one(Statement::from_string(
+            db.get_database_backend(),
+            "SELECT sqlcipher_export('export_target');".to_string(),
+        ))
+        .await;
+    db.execute_unprepared("DETACH DATABASE export_target;")
+        .await?;
+    exported?;
+    Ok(())
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            THUMBNAIL_EXTENSIONS
+                .iter()
+                .any(|allowed| ext.eq_ignore_ascii_case(allowed))
+        })
+}
+
+/// 递归列出目录下的普通文件，不跟随符号链接
+fn collect_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files
+}
+
+/// zip 条目名统一使用 "/" 分隔
+fn entry_name(prefix: &str, relative: &Path) -> String {
+    let parts: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    format!("{}{}", prefix, parts.join("/"))
+}
+
+fn read_chunk(source: &mut File) -> std::io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(FILE_CHUNK_BYTES);
+    source
+        .by_ref()
+        .take(FILE_CHUNK_BYTES as u64)
+        .read_to_end(&mut chunk)?;
+    Ok(chunk)
+}
+
+/// 将附件和缩略图按块写入以口令加密的文件库，返回写入的文件数
+async fn write_files_database(
+    target: &Path,
+    passphrase: &str,
+    files: &[(String, PathBuf)],
+) -> Result<usize, CommonError> {
+    let db = open_single(target, passphrase, "rwc").await?;
+    let written = async {
+        let backend = db.get_database_backend();
+        db.execute_unprepared(
+            "CREATE TABLE backup_file (name TEXT NOT NULL, seq INTEGER NOT NULL, \
+             data BLOB NOT NULL, PRIMARY KEY (name, seq))",
+        )
+        .await?;
+        let mut file_count = 0;
+        for (name, path) in files {
+            let mut source = match File::open(path) {
+                Ok(source) => source,
+                Err(e) => {
+                    warn!("Skip backup file {:?}: {}", path, e);
+                    continue;
+                }
+            };
+            let tx = db.begin().await?;
+            let mut seq: i64 = 0;
+            loop {
+                let chunk =
+                    read_chunk(&mut source).map_err(|e| io_error("读取备份文件失败", path, e))?;
+                // 空文件也写入一块，大小恰好为整块时最后读到的空块不写入
+                if chunk.is_empty() && seq > 0 {
+                    break;
+                }
+                let last = chunk.len() < FILE_CHUNK_BYTES;
+                tx.execute(Statement::from_sql_and_values(
+                    backend,
+                    "INSERT INTO backup_file (name, seq, data) VALUES (?, ?, ?)",
+                    vec![
+                        Value::from(name.clone()),
+                        Value::from(seq),
+                        Value::from(chunk),
+                    ],
+                ))
+                .await?;
+                seq += 1;
+                if last {
+                    break;
+                }
+            }
+            tx.commit().await?;
+            file_count += 1;
+        }
+        Ok::<usize, CommonError>(file_count)
+    }
+    .await;
+    db.close().await?;
+    written
+}
+
+fn write_archive(
+    output_path: &Path,
+    manifest: &BackupManifest,
+    database: &Path,
+    files_database: &Path,
+) -> Result<(), CommonError> {
+    let file =
+        File::create(output_path).map_err(|e| io_error("创建备份文件失败", output_path, e))?;
+    let mut writer = zip::ZipWriter::new(BufWriter::new(file));
+    // 数据库已加密，压缩收益很小
+    let stored = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true);
+    let deflated = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(true);
+
+    let manifest_json = serde_json::to_vec_pretty(manifest)
+        .map_err(|e| anyhow::anyhow!("序列化备份清单失败: {}", e))?;
+    writer
+        .start_file(MANIFEST_ENTRY, deflated)
+        .map_err(zip_error)?;
+    writer
+        .write_all(&manifest_json)
+        .map_err(|e| io_error("写入备份清单失败", output_path, e))?;
+
+    for (name, path) in [(DATABASE_ENTRY, database), (FILES_ENTRY, files_database)] {
+        let mut source =
+            BufReader::new(File::open(path).map_err(|e| io_error("读取备份文件失败", path, e))?);
+        writer.start_file(name, stored).map_err(zip_error)?;
+        std::io::copy(&mut source, &mut writer)
+            .map_err(|e| io_error("写入备份文件失败", path, e))?;
+    }
+
+    writer.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+/// 以口令加密导出数据库并与附件、缩略图一起打包
+pub async fn create_backup(
+    device_key: &str,
+    passphrase: &str,
+    paths: &BackupPaths,
+    output_path: &Path,
+    write_lock: &Arc<Mutex<()>>,
+) -> Result<BackupResult, CommonError> {
+    validate_passphrase(passphrase)?;
+    let work_dir = work_dir()?;
+    let result = create_backup_in(
+        device_key,
+        passphrase,
+        paths,
+        output_path,
+        write_lock,
+        &work_dir,
+    )
+    .await;
+    let _ = fs::remove_dir_all(&work_dir);
+    result
+}
+
+async fn create_backup_in(
+    device_key: &str,
+    passphrase: &str,
+    paths: &BackupPaths,
+    output_path: &Path,
+    write_lock: &Arc<Mutex<()>>,
+    work_dir: &Path,
+) -> Result<BackupResult, CommonError> {
+    let exported_db = work_dir.join(DATABASE_ENTRY);
+    let thumbnail_paths = {
+        // 导出期间阻止写入，保证备份的一致性
+        let _guard = write_lock.lock().await;
+        let db = open_single(&paths.db_path, device_key, "rw").await?;
+        let _ = db.execute_unprepared("PRAGMA wal_checkpoint(FULL);").await;
+        let exported = export_database(&db, &exported_db, passphrase).await;
+        let thumbnails = db
+            .query_all(Statement::from_string(
+                db.get_database_backend(),
+                "SELECT DISTINCT thumbnail_path FROM im_message \
+                 WHERE thumbnail_path IS NOT NULL AND thumbnail_path <> ''"
+                    .to_string(),
+            ))
+            .await;
+        db.close().await?;
+        exported?;
+        thumbnails?
+            .iter()
+            .filter_map(|row| row.try_get::<String>("", "thumbnail_path").ok())
+            .collect::<Vec<_>>()
+    };
+
+    let mut files = Vec::new();
+    let mut thumbnail_map = HashMap::new();
+    for (index, original) in thumbnail_paths.into_iter().enumerate() {
+        let path = PathBuf::from(&original);
+        if !is_image(&path) || !path.is_file() {
+            continue;
+        }
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = format!("{}{}_{}", THUMBNAIL_PREFIX, index, file_name);
+        thumbnail_map.insert(name.clone(), original);
+        files.push((name, path));
+    }
+    if let Some(root) = paths.user_data_root.as_ref().filter(|p| p.is_dir()) {
+        for path in collect_files(root) {
+            let Ok(relative) = path.strip_prefix(root) else {
+                continue;
+            };
+            files.push((entry_name(USER_DATA_PREFIX, relative), path));
+        }
+    }
+
+    let manifest = BackupManifest {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: Utc::now().timestamp_millis(),
+        user_data_root: paths
+            .user_data_root
+            .as_ref()
+            .map(|p| p.to_string_lossy().to_string()),
+        thumbnails: thumbnail_map,
+    };
+
+    let files_db = work_dir.join(FILES_ENTRY);
+    let file_count = write_files_database(&files_db, passphrase, &files).await?;
+
+    let part_path = work_dir.join("backup.zip.part");
+    let archive = part_path.clone();
+    tokio::task::spawn_blocking(move || {
+        write_archive(&archive, &manifest, &exported_db, &files_db)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("备份任务异常退出: {}", e))??;
+
+    // 临时目录可能与目标不在同一文件系统，rename 失败时退回复制
+    if fs::rename(&part_path, output_path).is_err() {
+        fs::copy(&part_path, output_path)
+            .map_err(|e| io_error("保存备份文件失败", output_path, e))?;
+    }
+    let archive_bytes = fs::metadata(output_path).map(|m| m.len()).unwrap_or(0);
+    info!(
+        "Local backup created: {:?}, {} files, {} bytes",
+        output_path, file_count, archive_bytes
+    );
+    Ok(BackupResult {
+        output_path: output_path.to_string_lossy().to_string(),
+        archive_bytes,
+        file_count,
+    })
+}
+
+/// 读取并校验备份清单
+fn read_manifest<R: std::io::Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+) -> Result<BackupManifest, CommonError> {
+    let entry = archive
+        .by_name(MANIFEST_ENTRY)
+        .map_err(|_| CommonError::RequestError("不是有效的 HuLa 备份文件".to_string()))?;
+    let manifest: BackupManifest = serde_json::from_reader(entry)
+        .map_err(|e| CommonError::RequestError(format!("备份清单无法解析: {}", e)))?;
+    if manifest.format != BACKUP_FORMAT {
+        return Err(CommonError::RequestError(
+            "不是有效的 HuLa 备份文件".to_string(),
+        ));
+    }
+    if manifest.version > BACKUP_FORMAT_VERSION {
+        return Err(CommonError::RequestError(format!(
+            "备份版本 {} 高于当前支持的版本 {}，请先升级应用",
+            manifest.version, BACKUP_FORMAT_VERSION
+        )));
+    }
+    if manifest.version != BACKUP_FORMAT_VERSION {
+        return Err(CommonError::RequestError(format!(
+            "不支持恢复版本 {} 的备份",
+            manifest.version
+        )));
+    }
+    Ok(manifest)
+}
+
+/// 解压 name 条目到 target，条目不存在时返回 false
+fn extract_entry<R: std::io::Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    name: &str,
+    target: &Path,
+) -> Result<bool, CommonError> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(false),
+        Err(e) => return Err(zip_error(e)),
+    };
+    let mut output = File::create(target).map_err(|e| io_error("解压失败", target, e))?;
+    std::io::copy(&mut entry, &mut output).map_err(|e| io_error("解压失败", target, e))?;
+    Ok(true)
+}
+
+/// 解压清单、数据库和文件库到临时目录，此时还未写入任何本地文件。
+/// 返回清单、数据库路径和文件库路径
+fn extract_databases(
+    archive_path: &Path,
+    work_dir: &Path,
+) -> Result<(BackupManifest, PathBuf, PathBuf), CommonError> {
+    let file =
+        File::open(archive_path).map_err(|e| io_error("打开备份文件失败", archive_path, e))?;
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).map_err(zip_error)?;
+    let manifest = read_manifest(&mut archive)?;
+
+    let database = work_dir.join(DATABASE_ENTRY);
+    if !extract_entry(&mut archive, DATABASE_ENTRY, &database)? {
+        return Err(CommonError::RequestError("备份中缺少数据库".to_string()));
+    }
+    let files_database = work_dir.join(FILES_ENTRY);
+    if !extract_entry(&mut archive, FILES_ENTRY, &files_database)? {
+        return Err(CommonError::RequestError("备份中缺少文件库".to_string()));
+    }
+    Ok((manifest, database, files_database))
+}
+
+/// 只接受由普通路径组成的相对路径，拒绝绝对路径和 ".."，防止写出目标目录
+fn safe_relative_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_)))
+        .then(|| path.to_path_buf())
+}
+
+/// 备份条目在当前设备上的恢复位置，不安全或无法识别的条目返回 None
+fn restore_target(name: &str, paths: &BackupPaths) -> Option<PathBuf> {
+    let relative = safe_relative_path(name)?;
+    if name.starts_with(THUMBNAIL_PREFIX) {
+        Some(paths.thumbnail_dir.join(relative.file_name()?))
+    } else if name.starts_with(USER_DATA_PREFIX) {
+        let inner = relative
+            .strip_prefix(USER_DATA_PREFIX.trim_end_matches('/'))
+            .ok()?;
+        if inner.as_os_str().is_empty() {
+            return None;
+        }
+        Some(paths.user_data_root.as_ref()?.join(inner))
+    } else {
+        None
+    }
+}
+
+/// 已存在且大小一致的附件不再覆盖
+fn needs_restore(target: &Path, size: u64) -> bool {
+    !fs::metadata(target)
+        .map(|m| m.is_file() && m.len() == size)
+        .unwrap_or(false)
+}
+
+fn create_target(target: &Path) -> Result<File, CommonError> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| io_error("创建目录失败", parent, e))?;
+    }
+    File::create(target).map_err(|e| io_error("恢复文件失败", target, e))
+}
+
+/// 从加密的文件库恢复附件和缩略图，返回缩略图原路径到新路径的映射及文件数
+async fn restore_encrypted_files(
+    files_database: &Path,
+    passphrase: &str,
+    manifest: &BackupManifest,
+    paths: &BackupPaths,
+) -> Result<(HashMap<String, String>, usize), CommonError> {
+    let db = open_single(files_database, passphrase, "rw").await?;
+    let restored = async {
+        let backend = db.get_database_backend();
+        let rows = db
+            .query_all(Statement::from_string(
+                backend,
+                "SELECT name, SUM(length(data)) AS size FROM backup_file GROUP BY name ORDER BY name"
+                    .to_string(),
+            ))
+            .await?;
+        let mut thumbnail_map = HashMap::new();
+        let mut file_count = 0;
+        for row in rows {
+            let name: String = row.try_get("", "name")?;
+            let size = row.try_get::<i64>("", "size")?.max(0) as u64;
+            let Some(target) = restore_target(&name, paths) else {
+                warn!("Skip unsafe backup entry: {}", name);
+                continue;
+            };
+            if needs_restore(&target, size) {
+                let mut output = create_target(&target)?;
+                for seq in 0i64.. {
+                    let Some(chunk) = db
+                        .query_one(Statement::from_sql_and_values(
+                            backend,
+                            "SELECT data FROM backup_file WHERE name = ? AND seq = ?",
+                            vec![Value::from(name.clone()), Value::from(seq)],
+                        ))
+                        .await?
+                    else {
+                        break;
+                    };
+                    let data: Vec<u8> = chunk.try_get("", "data")?;
+                    output
+                        .write_all(&data)
+                        .map_err(|e| io_error("恢复文件失败", &target, e))?;
+                }
+            }
+            file_count += 1;
+            if let Some(original) = manifest.thumbnails.get(&name) {
+                thumbnail_map.insert(original.clone(), target.to_string_lossy().to_string());
+            }
+        }
+        Ok::<_, CommonError>((thumbnail_map, file_count))
+    }
+    .await;
+    db.close().await?;
+    restored
+}
+
+/// JSON 字符串中的路径会转义反斜杠，改写消息体时两种形式都要处理
+fn json_escaped(value: &str) -> String {
+    let quoted = serde_json::to_string(value).unwrap_or_default();
+    quoted.trim_matches('"').to_string()
+}
+
+/// 将消息中的本地路径改写为当前设备上的路径
+async fn rewrite_local_paths(
+    db: &DatabaseConnection,
+    manifest: &BackupManifest,
+    user_data_root: Option<&Path>,
+    thumbnail_map: &HashMap<String, String>,
+) -> Result<(), CommonError> {
+    let backend = db.get_database_backend();
+    if let (Some(old_root), Some(new_root)) = (&manifest.user_data_root, user_data_root) {
+        let new_root = new_root.to_string_lossy().to_string();
+        if *old_root != new_root {
+            let mut replacements = vec![(old_root.clone(), new_root.clone())];
+            let escaped = (json_escaped(old_root), json_escaped(&new_root));
+            if escaped.0 != *old_root {
+                replacements.push(escaped);
+            }
+            for (from, to) in replacements {
+                db.execute(Statement::from_sql_and_values(
+                    backend,
+                    "UPDATE im_message SET body = replace(body, ?, ?) WHERE instr(body, ?) > 0",
+                    vec![
+                        Value::from(from.clone()),
+                        Value::from(to),
+                        Value::from(from),
+                    ],
+                ))
+                .await?;
+            }
+        }
+    }
+
+    for (original, restored) in thumbnail_map {
+        db.execute(Statement::from_sql_and_values(
+            backend,
+            "UPDATE im_message SET thumbnail_path = ? WHERE thumbnail_path = ?",
+            vec![Value::from(restored.clone()), Value::from(original.clone())],
+        ))
+        .await?;
+    }
+    Ok(())
+}
+
+/// 校验备份并以当前设备密钥生成暂存库，重启后生效
+pub async fn restore_backup(
+    archive_path: &Path,
+    passphrase: &str,
+    device_key: &str,
+    paths: &BackupPaths,
+) -> Result<RestoreResult, CommonError> {
+    let work_dir = work_dir()?;
+    let result = restore_backup_in(archive_path, passphrase, device_key, paths, &work_dir).await;
+    let _ = fs::remove_dir_all(&work_dir);
+    if result.is_err() {
+        let staged = staged_path(&paths.db_path);
+        let _ = fs::remove_file(&staged);
+        cleanup_sqlite_sidecar_files(&staged);
+    }
+    result
+}
+
+async fn restore_backup_in(
+    archive_path: &Path,
+    passphrase: &str,
+    device_key: &str,
+    paths: &BackupPaths,
+    work_dir: &Path,
+) -> Result<RestoreResult, CommonError> {
+    let (manifest, database, files_database) = {
+        let archive_path = archive_path.to_path_buf();
+        let work_dir = work_dir.to_path_buf();
+        tokio::task::spawn_blocking(move || extract_databases(&archive_path, &work_dir))
+            .await
+            .map_err(|e| anyhow::anyhow!("恢复任务异常退出: {}", e))??
+    };
+    info!(
+        "Restoring backup created at {} by version {}",
+        manifest.created_at, manifest.app_version
+    );
+
+    // 口令错误时 SQLCipher 无法解密，读取 sqlite_master 会报 "file is not a database"
+    let source = open_single(&database, passphrase, "rw").await?;
+    let verified = source
+        .query_one(Statement::from_string(
+            source.get_database_backend(),
+            "SELECT count(*) FROM sqlite_master".to_string(),
+        ))
+        .await;
+    if verified.is_err() {
+        let _ = source.close().await;
+        return Err(CommonError::RequestError(
+            "备份口令错误或备份文件已损坏".to_string(),
+        ));
+    }
+    let staged = staged_path(&paths.db_path);
+    let exported = export_database(&source, &staged, device_key).await;
+    source.close().await?;
+    exported?;
+
+    // 口令已校验，再写入附件和缩略图
+    let (thumbnail_map, file_count) =
+        restore_encrypted_files(&files_database, passphrase, &manifest, paths).await?;
+
+    let db = open_single(&staged, device_key, "rw").await?;
+    let prepared = async {
+        rewrite_local_paths(
+            &db,
+            &manifest,
+            paths.user_data_root.as_deref(),
+            &thumbnail_map,
+        )
+        .await?;
+        // sqlcipher_export 不保留 im_message 的 rowid，需要重建全文索引
+        im_message_fts_repository::rebuild_message_fts(&db).await?;
+        let row = db
+            .query_one(Statement::from_string(
+                db.get_database_backend(),
+                "SELECT count(*) AS total FROM im_message".to_string(),
+            ))
+            .await?;
+        Ok::<u64, CommonError>(match row {
+            Some(row) => row.try_get::<i64>("", "total")?.max(0) as u64,
+            None => 0,
+        })
+    }
+    .await;
+    db.close().await?;
+    let message_count = prepared?;
+
+    info!(
+        "Backup staged for restore: {:?}, {} messages, {} files",
+        staged, message_count, file_count
+    );
+    Ok(RestoreResult {
+        message_count,
+        file_count,
+        requires_restart: true,
+    })
+}
+
+/// 启动时若存在暂存库，则用其替换正式库，原库保留为 .pre-restore
+pub fn apply_staged_restore(db_path: &Path) -> Result<(), CommonError> {
+    let staged = staged_path(db_path);
+    if !staged.exists() {
+        return Ok(());
+    }
+    info!("Applying staged database restore: {:?}", staged);
+
+    cleanup_sqlite_sidecar_files(db_path);
+    cleanup_sqlite_sidecar_files(&staged);
+    if db_path.exists() {
+        let previous = pre_restore_path(db_path);
+        if previous.exists() {
+            fs::remove_file(&previous)
+                .map_err(|e| io_error("删除旧的数据库备份失败", &previous, e))?;
+        }
+        fs::rename(db_path, &previous).map_err(|e| io_error("保留原数据库失败", db_path, e))?;
+    }
+    fs::rename(&staged, db_path).map_err(|e| io_error("替换为恢复的数据库失败", db_path, e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_staged_restore_keeps_previous_database() {
+        let dir = work_dir().unwrap();
+        let db_path = dir.join("db.sqlite");
+        fs::write(&db_path, b"old").unwrap();
+        apply_staged_restore(&db_path).unwrap();
+        assert_eq!(fs::read(&db_path).unwrap(), b"old");
+
+        fs::write(staged_path(&db_path), b"new").unwrap();
+        apply_staged_restore(&db_path).unwrap();
+        assert_eq!(fs::read(&db_path).unwrap(), b"new");
+        assert_eq!(fs::read(pre_restore_path(&db_path)).unwrap(), b"old");
+        assert!(!staged_path(&db_path).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_target_rejects_escaping_entries() {
+        let paths = BackupPaths {
+            db_path: PathBuf::from("/app/db.sqlite"),
+            thumbnail_dir: PathBuf::from("/app/thumbnails"),
+            user_data_root: Some(PathBuf::from("/data/hula")),
+        };
+        assert_eq!(
+            restore_target("userData/1/a.png", &paths),
+            Some(PathBuf::from("/data/hula/1/a.png"))
+        );
+        assert_eq!(
+            restore_target("thumbnails/0_a.png", &paths),
+            Some(PathBuf::from("/app/thumbnails/0_a.png"))
+        );
+        assert_eq!(restore_target("userData/../../etc/passwd", &paths), None);
+        assert_eq!(restore_target("/userData/a.png", &paths), None);
+        assert_eq!(restore_target("userData/", &paths), None);
+        assert_eq!(restore_target(DATABASE_ENTRY, &paths), None);
+    }
+
+    fn manifest_archive(version: u32) -> zip::ZipArchive<std::io::Cursor<Vec<u8>>> {
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        {
+            let mut writer = zip::ZipWriter::new(&mut buffer);
+            writer
+                .start_file(MANIFEST_ENTRY, SimpleFileOptions::default())
+                .unwrap();
+            let manifest = serde_json::json!({
+                "format": BACKUP_FORMAT,
+                "version": version,
+                "appVersion": "9.9.9",
+                "createdAt": 0,
+                "userDataRoot": null
+            });
+            std::io::Write::write_all(&mut writer, manifest.to_string().as_bytes()).unwrap();
+            writer.finish().unwrap();
+        }
+        zip::ZipArchive::new(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_read_manifest_rejects_other_versions() {
+        let err = read_manifest(&mut manifest_archive(BACKUP_FORMAT_VERSION + 1)).unwrap_err();
+        assert!(err.to_string().contains("请先升级应用"));
+        let err = read_manifest(&mut manifest_archive(BACKUP_FORMAT_VERSION - 1)).unwrap_err();
+        assert!(err.to_string().contains("不支持恢复"));
+        assert!(read_manifest(&mut manifest_archive(BACKUP_FORMAT_VERSION)).is_ok());
+    }
+}
@@ -0,0 +1,1040 @@
+//! 聊天记录导出
+//!
+//! 按房间、时间范围分批读取本地消息，渲染为 HTML / Markdown / JSON / TXT 并流式写入文件。
+//! 写入时先输出到 `.part` 临时文件，完成后再重命名，取消或失败时删除临时文件。
+
+use crate::error::CommonError;
+use crate::repository::im_message_fts_repository::escape_html;
+use crate::repository::im_message_repository::{self, MessageWithThumbnail};
+use crate::repository::{im_contact_repository, im_room_member_repository};
+use base64::Engine;
+use chrono::{Local, TimeZone};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+
+/// 每批读取的消息数
+const EXPORT_BATCH_SIZE: u64 = 500;
+/// HTML 中内嵌缩略图的大小上限
+const MAX_EMBEDDED_THUMBNAIL_BYTES: u64 = 2 * 1024 * 1024;
+
+lazy_static::lazy_static! {
+    /// 正在进行的导出任务及其取消标记
+    static ref EXPORT_TASKS: Mutex<HashMap<String, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+}
+
+/// 注册导出任务，返回的句柄释放时自动注销
+pub fn register_export(export_id: &str) -> Result<ExportHandle, CommonError> {
+    let mut tasks = EXPORT_TASKS.lock().unwrap_or_else(|e| e.into_inner());
+    if tasks.contains_key(export_id) {
+        return Err(anyhow::anyhow!("导出任务已存在: {}", export_id).into());
+    }
+    let cancelled = Arc::new(AtomicBool::new(false));
+    tasks.insert(export_id.to_string(), cancelled.clone());
+    Ok(ExportHandle {
+        export_id: export_id.to_string(),
+        cancelled,
+    })
+}
+
+/// 取消导出任务，任务不存在时返回 false
+pub fn cancel_export(export_id: &str) -> bool {
+    let tasks = EXPORT_TASKS.lock().unwrap_or_else(|e| e.into_inner());
+    match tasks.get(export_id) {
+        Some(cancelled) => {
+            cancelled.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+pub struct ExportHandle {
+    export_id: String,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ExportHandle {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for ExportHandle {
+    fn drop(&mut self) {
+        let mut tasks = EXPORT_TASKS.lock().unwrap_or_else(|e| e.into_inner());
+        tasks.remove(&self.export_id);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Html,
+    Markdown,
+    Json,
+    Txt,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Html => "html",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Txt => "txt",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportRequest {
+    pub room_ids: Vec<String>,
+    pub format: ExportFormat,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub output_path: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportStage {
+    Running,
+    Done,
+    Cancelled,
+    Failed,
+}
+
+/// 导出进度事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportProgress {
+    pub export_id: String,
+    pub stage: ExportStage,
+    pub room_id: Option<String>,
+    pub processed: u64,
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResult {
+    pub export_id: String,
+    pub output_path: String,
+    pub room_count: usize,
+    pub message_count: u64,
+    pub bytes_written: u64,
+}
+
+/// 导出中的房间信息
+#[derive(Debug, Clone)]
+struct ExportRoom {
+    room_id: String,
+    room_name: String,
+    /// 发送人 uid 到显示名的映射
+    member_names: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportReply {
+    username: String,
+    text: String,
+}
+
+/// 按消息类型归一化后的内容
+#[derive(Debug, Clone, Serialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+enum ExportContent {
+    Text {
+        text: String,
+    },
+    Recalled {
+        text: String,
+    },
+    Image {
+        url: Option<String>,
+        width: Option<u64>,
+        height: Option<u64>,
+        size: Option<u64>,
+    },
+    File {
+        file_name: String,
+        size: Option<u64>,
+        url: Option<String>,
+    },
+    Video {
+        file_name: String,
+        size: Option<u64>,
+        url: Option<String>,
+    },
+    Voice {
+        seconds: Option<u64>,
+        url: Option<String>,
+    },
+    Emoji {
+        url: Option<String>,
+    },
+    Location {
+        address: String,
+    },
+    Merge {
+        records: Vec<String>,
+    },
+    System {
+        text: String,
+    },
+    Unknown {
+        message_type: Option<u8>,
+        text: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportEntry {
+    id: String,
+    send_time: Option<i64>,
+    sender_uid: String,
+    sender_name: String,
+    content: ExportContent,
+    /// 原始消息类型与消息体，供导入时无损还原
+    message_type: Option<u8>,
+    body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply: Option<ExportReply>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail_path: Option<String>,
+    /// 内嵌到 HTML 的缩略图
+    #[serde(skip)]
+    thumbnail_data_uri: Option<String>,
+}
+
+fn str_field(body: &Value, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .find_map(|key| body.get(*key).and_then(Value::as_str))
+        .map(str::to_string)
+}
+
+fn u64_field(body: &Value, key: &str) -> Option<u64> {
+    body.get(key).and_then(|v| {
+        v.as_u64()
+            .or_else(|| v.as_f64().map(|f| f.max(0.0) as u64))
+            .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+    })
+}
+
+/// 消息体中的文本：字符串本身或 content 字段
+fn text_of(body: &Value) -> Option<String> {
+    match body {
+        Value::String(text) => Some(text.clone()),
+        Value::Object(_) => str_field(body, &["content"]),
+        _ => None,
+    }
+}
+
+fn parse_content(message_type: Option<u8>, body: &Value) -> ExportContent {
+    match message_type {
+        Some(2) => ExportContent::Recalled {
+            text: text_of(body).unwrap_or_else(|| "消息已撤回".to_string()),
+        },
+        Some(3) => ExportContent::Image {
+            url: str_field(body, &["url"]),
+            width: u64_field(body, "width"),
+            height: u64_field(body, "height"),
+            size: u64_field(body, "size"),
+        },
+        Some(4) => ExportContent::File {
+            file_name: str_field(body, &["fileName", "filename", "file_name"])
+                .unwrap_or_else(|| "未知文件".to_string()),
+            size: u64_field(body, "size"),
+            url: str_field(body, &["url"]),
+        },
+        Some(5) => ExportContent::Voice {
+            seconds: u64_field(body, "second"),
+            url: str_field(body, &["url"]),
+        },
+        Some(6) => ExportContent::Video {
+            file_name: str_field(body, &["filename", "fileName", "file_name"])
+                .unwrap_or_else(|| "视频".to_string()),
+            size: u64_field(body, "size"),
+            url: str_field(body, &["url"]),
+        },
+        Some(7) => ExportContent::Emoji {
+            url: str_field(body, &["url"]),
+        },
+        Some(8) => ExportContent::System {
+            text: text_of(body).unwrap_or_default(),
+        },
+        Some(9) => ExportContent::Merge {
+            records: body
+                .get("content")
+                .and_then(Value::as_array)
+                .map(|items| {
+                    items
+                        .iter()
+                        .filter_map(|item| item.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        },
+        Some(18) => ExportContent::Location {
+            address: str_field(body, &["address"]).unwrap_or_default(),
+        },
+        _ => match text_of(body) {
+            Some(text) => ExportContent::Text { text },
+            None => ExportContent::Unknown {
+                message_type,
+                text: body.to_string(),
+            },
+        },
+    }
+}
+
+fn parse_reply(body: &Value) -> Option<ExportReply> {
+    let reply = body.get("reply").filter(|r| r.is_object())?;
+    let text = match reply.get("body") {
+        Some(body) => text_of(body).unwrap_or_default(),
+        None => String::new(),
+    };
+    Some(ExportReply {
+        username: str_field(reply, &["username"]).unwrap_or_default(),
+        text,
+    })
+}
+
+impl ExportEntry {
+    fn from_message(record: &MessageWithThumbnail, room: &ExportRoom) -> Self {
+        let message = &record.message;
+        let body = match message.body.as_deref() {
+            Some(raw) => {
+                serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+            }
+            None => Value::Null,
+        };
+        let sender_name = room
+            .member_names
+            .get(&message.uid)
+            .cloned()
+            .or_else(|| message.nickname.clone().filter(|n| !n.is_empty()))
+            .unwrap_or_else(|| message.uid.clone());
+
+        ExportEntry {
+            id: message.id.clone(),
+            send_time: message.send_time,
+            sender_uid: message.uid.clone(),
+            sender_name,
+            content: parse_content(message.message_type, &body),
+            message_type: message.message_type,
+            body: message.body.clone(),
+            reply: parse_reply(&body),
+            thumbnail_path: record.thumbnail_path.clone(),
+            thumbnail_data_uri: None,
+        }
+    }
+}
+
+fn format_time(send_time: Option<i64>) -> String {
+    send_time
+        .and_then(|ms| Local.timestamp_millis_opt(ms).single())
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+fn format_size(size: Option<u64>) -> String {
+    let Some(size) = size else {
+        return String::new();
+    };
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// 纯文本形式的消息内容，TXT 与 Markdown 共用
+fn plain_content(content: &ExportContent) -> String {
+    let text = match content {
+        ExportContent::Text { text } => text.clone(),
+        ExportContent::Recalled { text } => format!("[撤回] {}", text),
+        ExportContent::Image {
+            url, width, height, ..
+        } => match (width, height) {
+            (Some(w), Some(h)) => format!("[图片 {}x{}] {}", w, h, url.as_deref().unwrap_or("")),
+            _ => format!("[图片] {}", url.as_deref().unwrap_or("")),
+        },
+        ExportContent::File {
+            file_name, size, ..
+        } => format!("[文件] {} {}", file_name, format_size(*size)),
+        ExportContent::Video {
+            file_name, size, ..
+        } => format!("[视频] {} {}", file_name, format_size(*size)),
+        ExportContent::Voice { seconds, .. } => format!("[语音] {}\"", seconds.unwrap_or(0)),
+        ExportContent::Emoji { url } => format!("[表情] {}", url.as_deref().unwrap_or("")),
+        ExportContent::Location { address } => format!("[位置] {}", address),
+        ExportContent::Merge { records } => format!("[聊天记录]\n{}", records.join("\n")),
+        ExportContent::System { text } => format!("[系统消息] {}", text),
+        ExportContent::Unknown { text, .. } => text.clone(),
+    };
+    text.trim_end().to_string()
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '~'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+const HTML_STYLE: &str = "body{font-family:-apple-system,'PingFang SC','Microsoft YaHei',sans-serif;\
+max-width:860px;margin:0 auto;padding:24px;color:#222;background:#f6f6f6}\
+h1{font-size:20px}h2{font-size:17px;border-bottom:1px solid #ddd;padding-bottom:6px;margin-top:32px}\
+.msg{background:#fff;border-radius:8px;padding:10px 14px;margin:8px 0}\
+.meta{font-size:12px;color:#888;margin-bottom:4px}.sender{color:#13987f;font-weight:600;margin-right:8px}\
+.reply{border-left:3px solid #ccc;padding-left:8px;color:#666;font-size:13px;margin:4px 0}\
+.recalled,.system{color:#999;font-style:italic}.body{white-space:pre-wrap;word-break:break-word}\
+.merge{background:#fafafa;border:1px solid #eee;border-radius:6px;padding:6px 10px}\
+img.thumb{max-width:320px;max-height:320px;border-radius:6px;display:block}";
+
+/// 各格式的渲染器，输出的文本片段按顺序写入文件
+trait ExportRenderer {
+    fn header(&mut self, exported_at: &str) -> String;
+    fn room_start(&mut self, room: &ExportRoom) -> String;
+    fn entry(&mut self, entry: &ExportEntry) -> String;
+    fn room_end(&mut self) -> String;
+    fn footer(&mut self) -> String;
+}
+
+struct HtmlRenderer;
+
+impl HtmlRenderer {
+    fn content(entry: &ExportEntry) -> String {
+        let link = |url: &Option<String>, label: &str| match url {
+            Some(url) => format!(
+                "<a href=\"{}\">{}</a>",
+                escape_html(url),
+                escape_html(label)
+            ),
+            None => escape_html(label),
+        };
+        let thumbnail = entry
+            .thumbnail_data_uri
+            .as_ref()
+            .map(|uri| format!("<img class=\"thumb\" src=\"{}\" alt=\"\">", uri))
+            .unwrap_or_default();
+
+        match &entry.content {
+            ExportContent::Text { text } => {
+                format!("<div class=\"body\">{}</div>", escape_html(text))
+            }
+            ExportContent::Recalled { text } => {
+                format!("<div class=\"recalled\">{}</div>", escape_html(text))
+            }
+            ExportContent::System { text } => {
+                format!("<div class=\"system\">{}</div>", escape_html(text))
+            }
+            ExportContent::Image { url, .. } => {
+                format!("<div>{}{}</div>", thumbnail, link(url, "[图片]"))
+            }
+            ExportContent::Video {
+                file_name,
+                size,
+                url,
+            } => format!(
+                "<div>{}{} {}</div>",
+                thumbnail,
+                link(url, &format!("[视频] {}", file_name)),
+                format_size(*size)
+            ),
+            ExportContent::Merge { records } => format!(
+                "<div class=\"merge\">[聊天记录]<br>{}</div>",
+                records
+                    .iter()
+                    .map(|r| escape_html(r))
+                    .collect::<Vec<_>>()
+                    .join("<br>")
+            ),
+            ExportContent::File {
+                file_name,
+                size,
+                url,
+            } => format!(
+                "<div>{} {}</div>",
+                link(url, &format!("[文件] {}", file_name)),
+                format_size(*size)
+            ),
+            ExportContent::Voice { url, .. } | ExportContent::Emoji { url } => {
+                format!("<div>{}</div>", link(url, &plain_content(&entry.content)))
+            }
+            _ => format!(
+                "<div class=\"body\">{}</div>",
+                escape_html(&plain_content(&entry.content))
+            ),
+        }
+    }
+}
+
+impl ExportRenderer for HtmlRenderer {
+    fn header(&mut self, exported_at: &str) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>聊天记录</title>\n<style>{}</style>\n</head>\n<body>\n\
+             <h1>聊天记录</h1>\n<p class=\"meta\">导出时间：{}</p>\n",
+            HTML_STYLE,
+            escape_html(exported_at)
+        )
+    }
+
+    fn room_start(&mut self, room: &ExportRoom) -> String {
+        format!("<section>\n<h2>{}</h2>\n", escape_html(&room.room_name))
+    }
+
+    fn entry(&mut self, entry: &ExportEntry) -> String {
+        let reply = entry
+            .reply
+            .as_ref()
+            .map(|r| {
+                format!(
+                    "<div class=\"reply\">{}：{}</div>",
+                    escape_html(&r.username),
+                    escape_html(&r.text)
+                )
+            })
+            .unwrap_or_default();
+        format!(
+            "<div class=\"msg\" id=\"msg-{}\"><div class=\"meta\"><span class=\"sender\">{}</span>\
+             <time>{}</time></div>{}{}</div>\n",
+            escape_html(&entry.id),
+            escape_html(&entry.sender_name),
+            format_time(entry.send_time),
+            reply,
+            Self::content(entry)
+        )
+    }
+
+    fn room_end(&mut self) -> String {
+        "</section>\n".to_string()
+    }
+
+    fn footer(&mut self) -> String {
+        "</body>\n</html>\n".to_string()
+    }
+}
+
+struct MarkdownRenderer;
+
+impl ExportRenderer for MarkdownRenderer {
+    fn header(&mut self, exported_at: &str) -> String {
+        format!("# 聊天记录\n\n> 导出时间：{}\n\n", exported_at)
+    }
+
+    fn room_start(&mut self, room: &ExportRoom) -> String {
+        format!("## {}\n\n", escape_markdown(&room.room_name))
+    }
+
+    fn entry(&mut self, entry: &ExportEntry) -> String {
+        let mut out = format!(
+            "**{}** `{}`\n\n",
+            escape_markdown(&entry.sender_name),
+            format_time(entry.send_time)
+        );
+        if let Some(reply) = &entry.reply {
+            out.push_str(&format!(
+                "> {}：{}\n\n",
+                escape_markdown(&reply.username),
+                escape_markdown(&reply.text).replace('\n', " ")
+            ));
+        }
+        let content = match &entry.content {
+            ExportContent::Image { url: Some(url), .. } => format!("![图片]({})", url),
+            ExportContent::File {
+                file_name,
+                size,
+                url: Some(url),
+            } => format!(
+                "[{}]({}) {}",
+                escape_markdown(file_name),
+                url,
+                format_size(*size)
+            ),
+            ExportContent::Merge { records } => {
+                let mut merged = "聊天记录：\n".to_string();
+                for record in records {
+                    merged.push_str(&format!("- {}\n", escape_markdown(record)));
+                }
+                merged
+            }
+            ExportContent::Recalled { .. } | ExportContent::System { .. } => {
+                format!("*{}*", escape_markdown(&plain_content(&entry.content)))
+            }
+            _ => escape_markdown(&plain_content(&entry.content)).replace('\n', "  \n"),
+        };
+        out.push_str(&content);
+        out.push_str("\n\n");
+        out
+    }
+
+    fn room_end(&mut self) -> String {
+        "---\n\n".to_string()
+    }
+
+    fn footer(&mut self) -> String {
+        String::new()
+    }
+}
+
+struct TxtRenderer;
+
+impl ExportRenderer for TxtRenderer {
+    fn header(&mut self, exported_at: &str) -> String {
+        format!("聊天记录\n导出时间：{}\n\n", exported_at)
+    }
+
+    fn room_start(&mut self, room: &ExportRoom) -> String {
+        format!(
+            "==================== {} ====================\n\n",
+            room.room_name
+        )
+    }
+
+    fn entry(&mut self, entry: &ExportEntry) -> String {
+        let mut out = format!("{} {}\n", format_time(entry.send_time), entry.sender_name);
+        if let Some(reply) = &entry.reply {
+            out.push_str(&format!(
+                "「{}：{}」\n",
+                reply.username,
+                reply.text.replace('\n', " ")
+            ));
+        }
+        out.push_str(&plain_content(&entry.content));
+        out.push_str("\n\n");
+        out
+    }
+
+    fn room_end(&mut self) -> String {
+        "\n".to_string()
+    }
+
+    fn footer(&mut self) -> String {
+        String::new()
+    }
+}
+
+/// JSON 导出的格式标识与版本，导入时据此校验
+pub const JSON_EXPORT_FORMAT: &str = "hula-chat-export";
+pub const JSON_EXPORT_VERSION: u32 = 1;
+
+/// JSON 按 { format, version, exportedAt, rooms: [{ roomId, roomName, messages: [...] }] } 结构流式输出
+#[derive(Default)]
+struct JsonRenderer {
+    rooms_written: usize,
+    entries_in_room: usize,
+}
+
+impl ExportRenderer for JsonRenderer {
+    fn header(&mut self, exported_at: &str) -> String {
+        format!(
+            "{{\"format\":{},\"version\":{},\"exportedAt\":{},\"rooms\":[",
+            Value::String(JSON_EXPORT_FORMAT.to_string()),
+            JSON_EXPORT_VERSION,
+            Value::String(exported_at.to_string())
+        )
+    }
+
+    fn room_start(&mut self, room: &ExportRoom) -> String {
+        let separator = if self.rooms_written > 0 { "," } else { "" };
+        self.rooms_written += 1;
+        self.entries_in_room = 0;
+        format!(
+            "{}\n{{\"roomId\":{},\"roomName\":{},\"messages\":[",
+            separator,
+            Value::String(room.room_id.clone()),
+            Value::String(room.room_name.clone())
+        )
+    }
+
+    fn entry(&mut self, entry: &ExportEntry) -> String {
+        let separator = if self.entries_in_room > 0 { "," } else { "" };
+        self.entries_in_room += 1;
+        format!(
+            "{}\n{}",
+            separator,
+            serde_json::to_string(entry).unwrap_or_else(|_| "null".to_string())
+        )
+    }
+
+    fn room_end(&mut self) -> String {
+        "\n]}".to_string()
+    }
+
+    fn footer(&mut self) -> String {
+        "\n]}\n".to_string()
+    }
+}
+
+fn renderer_for(format: ExportFormat) -> Box<dyn ExportRenderer + Send> {
+    match format {
+        ExportFormat::Html => Box::new(HtmlRenderer),
+        ExportFormat::Markdown => Box::new(MarkdownRenderer),
+        ExportFormat::Json => Box::new(JsonRenderer::default()),
+        ExportFormat::Txt => Box::new(TxtRenderer),
+    }
+}
+
+/// 读取本地缩略图并转为 data URI，只处理常见图片格式
+async fn thumbnail_data_uri(path: &str) -> Option<String> {
+    let path = Path::new(path);
+    let mime = match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        _ => return None,
+    };
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_EMBEDDED_THUMBNAIL_BYTES {
+        return None;
+    }
+    let data = tokio::fs::read(path).await.ok()?;
+    Some(format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(data)
+    ))
+}
+
+async fn load_rooms(
+    db: &DatabaseConnection,
+    login_uid: &str,
+    room_ids: &[String],
+) -> Result<Vec<ExportRoom>, CommonError> {
+    let contacts = im_contact_repository::list_contact_by_room_ids(db, login_uid, room_ids).await?;
+    let contact_names: HashMap<String, String> = contacts
+        .into_iter()
+        .filter_map(|c| {
+            let name = c
+                .remark
+                .filter(|r| !r.is_empty())
+                .or(c.contact_name)
+                .filter(|n| !n.is_empty())?;
+            Some((c.room_id, name))
+        })
+        .collect();
+
+    let mut rooms = Vec::with_capacity(room_ids.len());
+    for room_id in room_ids {
+        let members =
+            im_room_member_repository::get_room_members_by_room_id(room_id, db, login_uid).await?;
+        let member_names = members
+            .into_iter()
+            .filter_map(|m| {
+                let uid = m.uid?;
+                let name = m.my_name.filter(|n| !n.is_empty()).unwrap_or(m.name);
+                Some((uid, name))
+            })
+            .collect();
+        rooms.push(ExportRoom {
+            room_id: room_id.clone(),
+            room_name: contact_names
+                .get(room_id)
+                .cloned()
+                .unwrap_or_else(|| room_id.clone()),
+            member_names,
+        });
+    }
+    Ok(rooms)
+}
+
+fn part_path(output_path: &Path) -> PathBuf {
+    let mut name = output_path.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+/// 执行导出，progress 在每批消息写入后回调
+pub async fn run_export<F>(
+    db: &DatabaseConnection,
+    login_uid: &str,
+    export_id: &str,
+    request: &ExportRequest,
+    handle: &ExportHandle,
+    mut progress: F,
+) -> Result<ExportResult, CommonError>
+where
+    F: FnMut(ExportProgress),
+{
+    let temp_path = part_path(&request.output_path);
+    let result = write_export(
+        db,
+        login_uid,
+        export_id,
+        request,
+        handle,
+        &temp_path,
+        &mut progress,
+    )
+    .await;
+    match result {
+        Ok(Some(result)) => {
+            tokio::fs::rename(&temp_path, &request.output_path)
+                .await
+                .map_err(|e| anyhow::anyhow!("保存导出文件失败: {}", e))?;
+            progress(ExportProgress {
+                export_id: export_id.to_string(),
+                stage: ExportStage::Done,
+                room_id: None,
+                processed: result.message_count,
+                total: result.message_count,
+            });
+            Ok(result)
+        }
+        Ok(None) => {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            progress(ExportProgress {
+                export_id: export_id.to_string(),
+                stage: ExportStage::Cancelled,
+                room_id: None,
+                processed: 0,
+                total: 0,
+            });
+            Err(CommonError::RequestError("导出已取消".to_string()))
+        }
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            progress(ExportProgress {
+                export_id: export_id.to_string(),
+                stage: ExportStage::Failed,
+                room_id: None,
+                processed: 0,
+                total: 0,
+            });
+            Err(e)
+        }
+    }
+}
+
+/// 写入临时文件，被取消时返回 None
+async fn write_export<F>(
+    db: &DatabaseConnection,
+    login_uid: &str,
+    export_id: &str,
+    request: &ExportRequest,
+    handle: &ExportHandle,
+    temp_path: &Path,
+    progress: &mut F,
+) -> Result<Option<ExportResult>, CommonError>
+where
+    F: FnMut(ExportProgress),
+{
+    let rooms = load_rooms(db, login_uid, &request.room_ids).await?;
+    let mut total = 0;
+    for room in &rooms {
+        total += im_message_repository::count_room_messages(
+            db,
+            login_uid,
+            &room.room_id,
+            request.start_time,
+            request.end_time,
+        )
+        .await?;
+    }
+
+    let file = tokio::fs::File::create(temp_path)
+        .await
+        .map_err(|e| anyhow::anyhow!("创建导出文件失败: {}", e))?;
+    let mut writer = tokio::io::BufWriter::new(file);
+    let mut renderer = renderer_for(request.format);
+    let mut bytes_written = 0u64;
+    let mut processed = 0u64;
+
+    macro_rules! write_chunk {
+        ($chunk:expr) => {{
+            let chunk: String = $chunk;
+            writer
+                .write_all(chunk.as_bytes())
+                .await
+                .map_err(|e| anyhow::anyhow!("写入导出文件失败: {}", e))?;
+            bytes_written += chunk.len() as u64;
+        }};
+    }
+
+    let exported_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    write_chunk!(renderer.header(&exported_at));
+
+    for room in &rooms {
+        write_chunk!(renderer.room_start(room));
+        let mut after = None;
+        loop {
+            if handle.is_cancelled() {
+                return Ok(None);
+            }
+            let batch = im_message_repository::list_room_messages_after(
+                db,
+                login_uid,
+                &room.room_id,
+                request.start_time,
+                request.end_time,
+                after,
+                EXPORT_BATCH_SIZE,
+            )
+            .await?;
+            let Some(last) = batch.last() else {
+                break;
+            };
+            after = Some((
+                last.message.send_time.unwrap_or(0),
+                last.message.id.parse().unwrap_or(i64::MAX),
+            ));
+
+            let mut chunk = String::new();
+            for record in &batch {
+                let mut entry = ExportEntry::from_message(record, room);
+                if request.format == ExportFormat::Html {
+                    if let Some(path) = entry.thumbnail_path.as_deref() {
+                        entry.thumbnail_data_uri = thumbnail_data_uri(path).await;
+                    }
+                }
+                chunk.push_str(&renderer.entry(&entry));
+            }
+            write_chunk!(chunk);
+
+            processed += batch.len() as u64;
+            progress(ExportProgress {
+                export_id: export_id.to_string(),
+                stage: ExportStage::Running,
+                room_id: Some(room.room_id.clone()),
+                processed,
+                total,
+            });
+            if (batch.len() as u64) < EXPORT_BATCH_SIZE {
+                break;
+            }
+        }
+        write_chunk!(renderer.room_end());
+    }
+    write_chunk!(renderer.footer());
+    writer
+        .flush()
+        .await
+        .map_err(|e| anyhow::anyhow!("写入导出文件失败: {}", e))?;
+
+    Ok(Some(ExportResult {
+        export_id: export_id.to_string(),
+        output_path: request.output_path.to_string_lossy().to_string(),
+        room_count: rooms.len(),
+        message_count: processed,
+        bytes_written,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(message_type: u8, body: &str) -> ExportEntry {
+        let room = ExportRoom {
+            room_id: "1".to_string(),
+            room_name: "测试群".to_string(),
+            member_names: HashMap::from([("10001".to_string(), "张三".to_string())]),
+        };
+        let record = MessageWithThumbnail::new(
+            entity::im_message::Model {
+                id: "100".to_string(),
+                uid: "10001".to_string(),
+                nickname: None,
+                room_id: "1".to_string(),
+                send_time: Some(0),
+                message_type: Some(message_type),
+                body: Some(body.to_string()),
+                message_marks: None,
+                create_time: None,
+                update_time: None,
+                login_uid: "10001".to_string(),
+                send_status: "success".to_string(),
+                time_block: None,
+                edited_at: None,
+                reply_to_id: None,
+            },
+            None,
+        );
+        ExportEntry::from_message(&record, &room)
+    }
+
+    #[test]
+    fn test_parse_message_kinds() {
+        let reply = entry(
+            16,
+            r#"{"content":"<b>好的</b>","reply":{"username":"李四","body":"明天见"}}"#,
+        );
+        assert_eq!(reply.sender_name, "张三");
+        assert_eq!(reply.reply.as_ref().unwrap().text, "明天见");
+        let html = HtmlRenderer.entry(&reply);
+        assert!(html.contains("&lt;b&gt;好的&lt;/b&gt;"));
+        assert!(html.contains("李四：明天见"));
+
+        let merge = entry(9, r#"{"content":["张三: 在吗","李四: 在"],"body":[]}"#);
+        assert_eq!(
+            plain_content(&merge.content),
+            "[聊天记录]\n张三: 在吗\n李四: 在"
+        );
+
+        let file = entry(
+            4,
+            r#"{"fileName":"报告.pdf","size":2048,"url":"https://x/a.pdf"}"#,
+        );
+        assert_eq!(plain_content(&file.content), "[文件] 报告.pdf 2.0 KB");
+
+        let recalled = entry(2, "\"张三撤回了一条消息\"");
+        assert!(matches!(recalled.content, ExportContent::Recalled { .. }));
+    }
+
+    #[test]
+    fn test_json_renderer_output_is_valid() {
+        let mut renderer = JsonRenderer::default();
+        let room = ExportRoom {
+            room_id: "1".to_string(),
+            room_name: "测试群".to_string(),
+            member_names: HashMap::new(),
+        };
+        let mut out = renderer.header("2025-01-01 00:00:00");
+        out.push_str(&renderer.room_start(&room));
+        out.push_str(&renderer.entry(&entry(1, r#"{"content":"a"}"#)));
+        out.push_str(&renderer.entry(&entry(1, r#"{"content":"b"}"#)));
+        out.push_str(&renderer.room_end());
+        out.push_str(&renderer.room_start(&room));
+        out.push_str(&renderer.room_end());
+        out.push_str(&renderer.footer());
+
+        let value: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value["rooms"].as_array().unwrap().len(), 2);
+        assert_eq!(value["format"], JSON_EXPORT_FORMAT);
+        assert_eq!(value["rooms"][0]["messages"][1]["content"]["text"], "b");
+        assert_eq!(
+            value["rooms"][0]["messages"][1]["body"],
+            r#"{"content":"b"}"#
+        );
+    }
+}
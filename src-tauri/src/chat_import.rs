@@ -0,0 +1,367 @@
+//! 聊天记录导入
+//!
+//! 读取 JSON 格式的导出文件并合并到当前用户的 im_message 中：按消息 ID 去重，保留原始 send_time，
+//! 跳过已删除、已清空或已按保留策略清理的消息，写入后重新计算受影响范围的 time_block。
+//! 同一 ID 的本地消息与导入内容不一致时视为冲突，保留本地数据。dry_run 时只生成报告，不写库。
+
+use crate::chat_export::{JSON_EXPORT_FORMAT, JSON_EXPORT_VERSION};
+use crate::error::CommonError;
+use crate::repository::im_message_repository::{self, MessageWithThumbnail};
+use entity::im_message;
+use sea_orm::{ConnectionTrait, DatabaseConnection, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+
+/// 报告中最多列出的冲突条数
+const MAX_REPORTED_CONFLICTS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportArchive {
+    format: Option<String>,
+    version: Option<u32>,
+    rooms: Vec<ImportRoom>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportRoom {
+    room_id: String,
+    #[serde(default)]
+    messages: Vec<ImportEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportEntry {
+    id: String,
+    send_time: Option<i64>,
+    sender_uid: String,
+    sender_name: Option<String>,
+    message_type: Option<u8>,
+    body: Option<String>,
+    /// 导出时归一化的内容，缺少原始消息体时用于还原纯文本消息
+    content: Option<Value>,
+    thumbnail_path: Option<String>,
+}
+
+/// 冲突原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportConflictReason {
+    /// 本地同 ID 消息属于其他房间
+    DifferentRoom,
+    /// 本地同 ID 消息的发送人、时间或内容不同
+    DifferentContent,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportConflict {
+    pub message_id: String,
+    pub room_id: String,
+    pub reason: ImportConflictReason,
+}
+
+/// 导入结果，dry_run 时 imported 为将要写入的条数
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub total: u64,
+    pub imported: u64,
+    /// 本地已存在且内容一致，或在文件中重复出现的消息
+    pub duplicates: u64,
+    pub conflicts: u64,
+    /// 已删除、已清空或已按保留策略清理的消息
+    pub skipped_deleted: u64,
+    /// 缺少必要字段、无法还原的消息
+    pub invalid: u64,
+    pub rooms: usize,
+    /// 冲突明细，最多 MAX_REPORTED_CONFLICTS 条
+    pub conflict_samples: Vec<ImportConflict>,
+}
+
+async fn read_archive(path: &Path) -> Result<ImportArchive, CommonError> {
+    let is_json = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    if !is_json {
+        return Err(anyhow::anyhow!("仅支持导入 JSON 格式的聊天记录").into());
+    }
+
+    let path = path.to_path_buf();
+    let archive: ImportArchive = tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&path)
+            .map_err(|e| anyhow::anyhow!("打开导入文件失败 {}: {}", path.display(), e))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| anyhow::anyhow!("解析导入文件失败: {}", e))
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("读取导入文件失败: {}", e))??;
+
+    if archive
+        .format
+        .as_deref()
+        .is_some_and(|format| format != JSON_EXPORT_FORMAT)
+    {
+        return Err(anyhow::anyhow!("不是 HuLa 导出的聊天记录文件").into());
+    }
+    if archive
+        .version
+        .is_some_and(|version| version > JSON_EXPORT_VERSION)
+    {
+        return Err(anyhow::anyhow!("导入文件版本过新，请升级应用后再试").into());
+    }
+    Ok(archive)
+}
+
+impl ImportEntry {
+    /// 还原为 im_message 记录，缺少原始消息体时仅支持纯文本消息
+    fn into_message(self, room_id: &str, login_uid: &str) -> Option<MessageWithThumbnail> {
+        if self.id.is_empty() || self.sender_uid.is_empty() {
+            return None;
+        }
+        let send_time = self.send_time?;
+        let (message_type, body) = match self.body {
+            Some(body) => (self.message_type, body),
+            None => {
+                let content = self.content?;
+                if content.get("kind").and_then(Value::as_str) != Some("text") {
+                    return None;
+                }
+                let text = content.get("text").and_then(Value::as_str)?;
+                (Some(1), serde_json::json!({ "content": text }).to_string())
+            }
+        };
+        let nickname = self
+            .sender_name
+            .filter(|name| !name.is_empty() && *name != self.sender_uid);
+        // 只保留本机仍然存在的缩略图
+        let thumbnail_path = self
+            .thumbnail_path
+            .filter(|path| PathBuf::from(path).is_file());
+
+        Some(MessageWithThumbnail::new(
+            im_message::Model {
+                id: self.id,
+                uid: self.sender_uid,
+                nickname,
+                room_id: room_id.to_string(),
+                send_time: Some(send_time),
+                message_type,
+                body: Some(body),
+                message_marks: None,
+                create_time: None,
+                update_time: None,
+                login_uid: login_uid.to_string(),
+                send_status: "success".to_string(),
+                time_block: None,
+                edited_at: None,
+                reply_to_id: None,
+            },
+            thumbnail_path,
+        ))
+    }
+}
+
+fn same_message(local: &im_message::Model, imported: &im_message::Model) -> bool {
+    local.uid == imported.uid
+        && local.send_time == imported.send_time
+        && local.message_type == imported.message_type
+        && local.body == imported.body
+}
+
+/// 对比本地数据生成导入报告和待写入的消息，写入时需在持有写锁的事务中调用
+async fn plan_import<C>(
+    db: &C,
+    login_uid: &str,
+    archive: ImportArchive,
+    dry_run: bool,
+) -> Result<(ImportReport, Vec<MessageWithThumbnail>), CommonError>
+where
+    C: ConnectionTrait,
+{
+    let mut report = ImportReport {
+        dry_run,
+        rooms: archive.rooms.len(),
+        ..Default::default()
+    };
+
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    for room in archive.rooms {
+        for entry in room.messages {
+            report.total += 1;
+            match entry.into_message(&room.room_id, login_uid) {
+                Some(record) if seen.insert(record.message.id.clone()) => candidates.push(record),
+                Some(_) => report.duplicates += 1,
+                None => report.invalid += 1,
+            }
+        }
+    }
+
+    let ids: Vec<String> = candidates.iter().map(|r| r.message.id.clone()).collect();
+    let existing = im_message_repository::find_messages_by_ids(db, login_uid, &ids).await?;
+    let mut new_messages = Vec::new();
+    for record in candidates {
+        let Some(local) = existing.get(&record.message.id) else {
+            new_messages.push(record);
+            continue;
+        };
+        let reason = if local.room_id != record.message.room_id {
+            ImportConflictReason::DifferentRoom
+        } else if !same_message(local, &record.message) {
+            ImportConflictReason::DifferentContent
+        } else {
+            report.duplicates += 1;
+            continue;
+        };
+        report.conflicts += 1;
+        if report.conflict_samples.len() < MAX_REPORTED_CONFLICTS {
+            report.conflict_samples.push(ImportConflict {
+                message_id: record.message.id,
+                room_id: record.message.room_id,
+                reason,
+            });
+        }
+    }
+
+    let new_count = new_messages.len();
+    let kept = im_message_repository::filter_skipped_messages(db, new_messages).await?;
+    report.skipped_deleted = (new_count - kept.len()) as u64;
+    report.imported = kept.len() as u64;
+    Ok((report, kept))
+}
+
+/// 导入 JSON 聊天记录，dry_run 为 true 时只返回报告
+pub async fn import_chat_history(
+    db: &DatabaseConnection,
+    write_lock: &Arc<Mutex<()>>,
+    login_uid: &str,
+    path: &Path,
+    dry_run: bool,
+) -> Result<ImportReport, CommonError> {
+    let archive = read_archive(path).await?;
+    if dry_run {
+        return Ok(plan_import(db, login_uid, archive, true).await?.0);
+    }
+
+    let _guard = write_lock.lock().await;
+    let txn = db.begin().await?;
+    let (report, to_insert) = plan_import(&txn, login_uid, archive, false).await?;
+
+    let mut earliest_by_room: HashMap<String, i64> = HashMap::new();
+    for record in &to_insert {
+        if let Some(send_time) = record.message.send_time {
+            earliest_by_room
+                .entry(record.message.room_id.clone())
+                .and_modify(|t| *t = (*t).min(send_time))
+                .or_insert(send_time);
+        }
+    }
+    im_message_repository::save_all(&txn, to_insert).await?;
+    for (room_id, from_send_time) in &earliest_by_room {
+        im_message_repository::recompute_time_blocks(&txn, login_uid, room_id, *from_send_time)
+            .await?;
+    }
+    txn.commit().await?;
+
+    info!(
+        "Imported chat history from {}: {:?}",
+        path.display(),
+        report
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+    use sea_orm::{EntityTrait, QueryOrder};
+
+    fn archive(entries: &[(&str, i64, &str)]) -> ImportArchive {
+        let messages = entries
+            .iter()
+            .map(|(id, send_time, text)| ImportEntry {
+                id: id.to_string(),
+                send_time: Some(*send_time),
+                sender_uid: "10002".to_string(),
+                sender_name: Some("李四".to_string()),
+                message_type: Some(1),
+                body: Some(format!(r#"{{"content":"{}"}}"#, text)),
+                content: None,
+                thumbnail_path: None,
+            })
+            .collect();
+        ImportArchive {
+            format: Some(JSON_EXPORT_FORMAT.to_string()),
+            version: Some(JSON_EXPORT_VERSION),
+            rooms: vec![ImportRoom {
+                room_id: "1".to_string(),
+                messages,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plan_import_dedups_and_respects_tombstones() {
+        let db = migrated_db().await;
+
+        let minute = 60 * 1000;
+        let local = archive(&[("1", 0, "a"), ("2", 30 * minute, "b")]);
+        let (_, local) = plan_import(&db, "10001", local, false).await.unwrap();
+        im_message_repository::save_all(&db, local).await.unwrap();
+        im_message_repository::record_deleted_message(&db, "4", "1", "10001")
+            .await
+            .unwrap();
+
+        let incoming = archive(&[
+            ("1", 0, "a"),
+            ("2", 30 * minute, "changed"),
+            ("3", 15 * minute, "c"),
+            ("3", 15 * minute, "c"),
+            ("4", 16 * minute, "d"),
+        ]);
+        let (report, to_insert) = plan_import(&db, "10001", incoming, true).await.unwrap();
+        assert_eq!(report.total, 5);
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.duplicates, 2);
+        assert_eq!(report.conflicts, 1);
+        assert_eq!(
+            report.conflict_samples[0].reason,
+            ImportConflictReason::DifferentContent
+        );
+        assert_eq!(report.skipped_deleted, 1);
+
+        im_message_repository::save_all(&db, to_insert)
+            .await
+            .unwrap();
+        im_message_repository::recompute_time_blocks(&db, "10001", "1", 15 * minute)
+            .await
+            .unwrap();
+        let blocks: Vec<(String, Option<i64>)> = im_message::Entity::find()
+            .order_by_asc(im_message::Column::SendTime)
+            .all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.id, m.time_block))
+            .collect();
+        assert_eq!(
+            blocks,
+            vec![
+                ("1".to_string(), None),
+                ("3".to_string(), Some(15 * minute)),
+                ("2".to_string(), Some(15 * minute)),
+            ]
+        );
+    }
+}
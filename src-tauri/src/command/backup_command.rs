@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+use tauri::{AppHandle, State};
+use tracing::info;
+
+use crate::AppData;
+use crate::backup::{self, BackupPaths, BackupResult, RestoreResult};
+use crate::common::{app_paths, sqlcipher};
+use crate::error::CommonError;
+
+async fn backup_paths(
+    app_handle: &AppHandle,
+    state: &State<'_, AppData>,
+) -> Result<BackupPaths, CommonError> {
+    let db_path = state
+        .config
+        .lock()
+        .await
+        .database
+        .database_path(app_handle)?;
+    let thumbnail_dir = app_paths::thumbnail_dir(app_handle)?;
+    Ok(BackupPaths {
+        db_path,
+        user_data_root: app_paths::user_data_root(app_handle),
+        thumbnail_dir,
+    })
+}
+
+/// 使用口令加密备份本地数据库、附件和缩略图，三者在归档中均不以明文保存
+#[tauri::command]
+pub async fn backup_local_data(
+    output_path: String,
+    passphrase: String,
+    app_handle: AppHandle,
+    state: State<'_, AppData>,
+) -> Result<BackupResult, String> {
+    info!("Creating local backup: {}", output_path);
+    let paths = backup_paths(&app_handle, &state)
+        .await
+        .map_err(|e| e.to_string())?;
+    let device_key = sqlcipher::get_or_create_sqlcipher_key(&app_handle)
+        .await
+        .map_err(|e| e.to_string())?;
+    backup::create_backup(
+        &device_key,
+        &passphrase,
+        &paths,
+        &PathBuf::from(output_path),
+        &state.write_lock,
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// 从备份恢复本地数据，口令校验通过后才写入附件和缩略图，数据库在应用重启后替换
+#[tauri::command]
+pub async fn restore_local_data(
+    archive_path: String,
+    passphrase: String,
+    app_handle: AppHandle,
+    state: State<'_, AppData>,
+) -> Result<RestoreResult, String> {
+    info!("Restoring local backup: {}", archive_path);
+    let paths = backup_paths(&app_handle, &state)
+        .await
+        .map_err(|e| e.to_string())?;
+    let device_key = sqlcipher::get_or_create_sqlcipher_key(&app_handle)
+        .await
+        .map_err(|e| e.to_string())?;
+    backup::restore_backup(
+        &PathBuf::from(archive_path),
+        &passphrase,
+        &device_key,
+        &paths,
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
@@ -1,8 +1,11 @@
 use crate::AppData;
 use crate::command::message_command::MessageResp;
-use crate::repository::im_message_repository;
+use crate::repository::im_contact_repository;
+use crate::repository::im_message_fts_repository::{self, FtsQuery, GlobalSearchFilter};
+use crate::repository::im_message_repository::{self, MessageKey};
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::ops::Deref;
 use tauri::State;
 use tracing::{error, info};
@@ -38,6 +41,65 @@ pub struct ChatHistoryResponse {
     pub messages: Vec<MessageResp>,
     pub has_more: bool,
     pub current_page: u32,
+    /// 关键词命中摘要（已转义的 HTML，关键词以 <mark> 包裹），key 为消息 ID
+    #[serde(default)]
+    pub snippets: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchChatMessagesParam {
+    pub keyword: String,
+    /// 为空时跨房间检索
+    pub room_id: Option<String>,
+    pub pagination: PaginationParam,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessageSearchHit {
+    pub message: MessageResp,
+    pub snippet: String,
+    /// 相关度得分，越小越相关；关键词过短时按时间倒序，得分为空
+    pub rank: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalSearchParam {
+    pub keyword: Option<String>,
+    /// 发送人 uid
+    pub sender_uids: Option<Vec<String>>,
+    /// 消息类型，参见前端 MsgEnum
+    pub message_types: Option<Vec<u8>>,
+    pub date_range: Option<DateRange>,
+    /// 仅返回 @ 了我的消息（含 @全体成员）
+    #[serde(default)]
+    pub mentions_me: bool,
+    /// 上一页返回的游标，首页为空
+    pub cursor: Option<String>,
+    pub page_size: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomSearchGroup {
+    pub room_id: String,
+    pub room_name: Option<String>,
+    pub avatar: Option<String>,
+    /// 该房间在全部结果中的命中数
+    pub hit_count: u64,
+    /// 本页中属于该房间的命中消息
+    pub hits: Vec<ChatMessageSearchHit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalSearchResponse {
+    /// 按房间分组，房间顺序为本页中最新命中消息的顺序
+    pub groups: Vec<RoomSearchGroup>,
+    pub cursor: Option<String>,
+    pub is_last: bool,
 }
 
 /// 查询聊天历史记录的Tauri命令
@@ -81,6 +143,25 @@ pub async fn query_chat_history(
                 e.to_string()
             })?;
 
+    // 关键词命中摘要
+    let snippets = match param.search_keyword.as_deref().and_then(FtsQuery::parse) {
+        Some(query) if !messages.is_empty() => {
+            let ids: Vec<String> = messages.iter().map(|m| m.message.id.clone()).collect();
+            im_message_fts_repository::fetch_snippets(
+                state.db_conn.deref(),
+                &login_uid,
+                &query,
+                &ids,
+            )
+            .await
+            .map_err(|e| {
+                error!("获取搜索摘要失败: {}", e);
+                e.to_string()
+            })?
+        }
+        _ => HashMap::new(),
+    };
+
     // 转换为响应格式
     let message_resps: Vec<MessageResp> = messages
         .into_iter()
@@ -94,11 +175,57 @@ pub async fn query_chat_history(
         messages: message_resps,
         has_more,
         current_page: param.pagination.page,
+        snippets,
     };
 
     Ok(response)
 }
 
+/// 全文检索消息，按相关度排序并返回高亮摘要
+#[tauri::command]
+pub async fn search_chat_messages(
+    param: SearchChatMessagesParam,
+    state: State<'_, AppData>,
+) -> Result<Vec<ChatMessageSearchHit>, String> {
+    info!(
+        "全文检索消息 - 房间ID: {:?}, 关键词: {}, 页码: {}",
+        param.room_id, param.keyword, param.pagination.page
+    );
+
+    let Some(query) = FtsQuery::parse(&param.keyword) else {
+        return Ok(Vec::new());
+    };
+
+    let login_uid = {
+        let user_info = state.user_info.lock().await;
+        user_info.uid.clone()
+    };
+
+    let offset = param.pagination.page.saturating_sub(1) * param.pagination.page_size;
+    let hits = im_message_fts_repository::search_messages(
+        state.db_conn.deref(),
+        &login_uid,
+        param.room_id.as_deref(),
+        &query,
+        param.pagination.page_size,
+        offset,
+    )
+    .await
+    .map_err(|e| {
+        error!("全文检索消息失败: {}", e);
+        e.to_string()
+    })?;
+
+    Ok(hits
+        .into_iter()
+        .map(|hit| ChatMessageSearchHit {
+            message: crate::command::message_command::convert_message_to_resp(hit.message, None),
+            snippet: hit.snippet,
+            rank: hit.rank,
+        })
+        .collect())
+}
+
 /// 内部查询条件结构
 #[derive(Debug, Clone)]
 pub struct ChatHistoryQueryCondition {
@@ -146,3 +273,103 @@ fn parse_sort_order(sort_order: &Option<String>) -> SortOrder {
         None => SortOrder::Desc, // 默认降序（最新的在前）
     }
 }
+
+/// 跨房间检索当前用户的全部消息，结果按房间分组
+#[tauri::command]
+pub async fn search_all_messages(
+    param: GlobalSearchParam,
+    state: State<'_, AppData>,
+) -> Result<GlobalSearchResponse, String> {
+    info!(
+        "跨房间检索消息 - 关键词: {:?}, 发送人: {:?}, 类型: {:?}, 仅@我: {}, 游标: {:?}",
+        param.keyword, param.sender_uids, param.message_types, param.mentions_me, param.cursor
+    );
+
+    let filter = GlobalSearchFilter {
+        query: param.keyword.as_deref().and_then(FtsQuery::parse),
+        sender_uids: param.sender_uids.unwrap_or_default(),
+        message_types: param.message_types.unwrap_or_default(),
+        start_time: param.date_range.as_ref().and_then(|r| r.start_time),
+        end_time: param.date_range.as_ref().and_then(|r| r.end_time),
+        mentions_me: param.mentions_me,
+    };
+    // 不允许无条件检索全部消息
+    if filter.is_empty() {
+        return Ok(GlobalSearchResponse {
+            groups: Vec::new(),
+            cursor: None,
+            is_last: true,
+        });
+    }
+
+    let cursor = match param.cursor.as_deref().filter(|c| !c.is_empty()) {
+        Some(cursor) => Some(MessageKey::parse(cursor).ok_or_else(|| {
+            error!("无效的检索游标: {}", cursor);
+            format!("无效的检索游标: {}", cursor)
+        })?),
+        None => None,
+    };
+
+    let login_uid = {
+        let user_info = state.user_info.lock().await;
+        user_info.uid.clone()
+    };
+
+    let page = im_message_fts_repository::search_global(
+        state.db_conn.deref(),
+        &login_uid,
+        &filter,
+        cursor.as_ref(),
+        param.page_size.clamp(1, 200),
+    )
+    .await
+    .map_err(|e| {
+        error!("跨房间检索消息失败: {}", e);
+        e.to_string()
+    })?;
+
+    let mut groups: Vec<RoomSearchGroup> = Vec::new();
+    for hit in page.hits {
+        let room_id = hit.message.message.room_id.clone();
+        let search_hit = ChatMessageSearchHit {
+            message: crate::command::message_command::convert_message_to_resp(hit.message, None),
+            snippet: hit.snippet,
+            rank: hit.rank,
+        };
+        match groups.iter_mut().find(|g| g.room_id == room_id) {
+            Some(group) => group.hits.push(search_hit),
+            None => groups.push(RoomSearchGroup {
+                hit_count: page.room_hit_counts.get(&room_id).copied().unwrap_or(0),
+                room_id,
+                room_name: None,
+                avatar: None,
+                hits: vec![search_hit],
+            }),
+        }
+    }
+
+    // 房间名称和头像取自本地会话列表
+    let room_ids: Vec<String> = groups.iter().map(|g| g.room_id.clone()).collect();
+    let contacts = im_contact_repository::list_contact_by_room_ids(
+        state.db_conn.deref(),
+        &login_uid,
+        &room_ids,
+    )
+    .await
+    .map_err(|e| {
+        error!("查询会话信息失败: {}", e);
+        e.to_string()
+    })?;
+    for group in groups.iter_mut() {
+        if let Some(contact) = contacts.iter().find(|c| c.room_id == group.room_id) {
+            group.room_name = contact.contact_name.clone();
+            group.avatar = contact.avatar.clone();
+        }
+    }
+
+    Ok(GlobalSearchResponse {
+        groups,
+        is_last: page.next_cursor.is_none(),
+        cursor: page.next_cursor,
+    })
+}
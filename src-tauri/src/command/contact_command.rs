@@ -1,23 +1,34 @@
 use crate::AppData;
+use crate::command::draft_command::load_draft_previews;
 use crate::error::CommonError;
 use crate::im_request_client::{ImRequestClient, ImUrl};
 use crate::repository::im_contact_repository::{save_contact_batch, update_contact_hide};
+use crate::repository::im_room_read_cursor_repository;
 
 use entity::im_contact;
 use sea_orm::DatabaseConnection;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::Arc;
 use tauri::State;
 use tokio::sync::Mutex;
 use tracing::{error, info};
 
+/// 会话列表项，附带本地草稿预览
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactResp {
+    #[serde(flatten)]
+    pub contact: im_contact::Model,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub draft: Option<String>,
+}
+
 #[tauri::command]
-pub async fn list_contacts_command(
-    state: State<'_, AppData>,
-) -> Result<Vec<im_contact::Model>, String> {
+pub async fn list_contacts_command(state: State<'_, AppData>) -> Result<Vec<ContactResp>, String> {
     info!("Querying all conversation list:");
-    let result: Result<Vec<im_contact::Model>, CommonError> = async {
+    let result: Result<Vec<ContactResp>, CommonError> = async {
         // 获取当前登录用户的 uid
         let login_uid = {
             let user_info = state.user_info.lock().await;
@@ -27,6 +38,16 @@ pub async fn list_contacts_command(
         let data =
             fetch_and_update_contacts(state.db_conn.clone(), state.rc.clone(), login_uid.clone())
                 .await?;
+
+        // 附加本地草稿预览
+        let mut drafts = load_draft_previews(&state, &login_uid).await?;
+        let data = data
+            .into_iter()
+            .map(|contact| ContactResp {
+                draft: drafts.remove(&contact.room_id),
+                contact,
+            })
+            .collect();
         return Ok(data);
     }
     .await;
@@ -56,7 +77,7 @@ async fn fetch_and_update_contacts(
         )
         .await?;
 
-    if let Some(data) = resp {
+    if let Some(mut data) = resp {
         // 保存到本地数据库
         save_contact_batch(db_conn.deref(), data.clone(), &login_uid)
             .await
@@ -69,6 +90,21 @@ async fn fetch_and_update_contacts(
                 )
             })?;
 
+        // 服务端未读数只是拉取时的快照，返回前替换为按本地已读游标计算的结果
+        im_room_read_cursor_repository::reconcile_with_contacts(db_conn.deref(), &login_uid)
+            .await?;
+        let unread: HashMap<String, u32> =
+            im_room_read_cursor_repository::list_unread_counts(db_conn.deref(), &login_uid)
+                .await?
+                .into_iter()
+                .map(|c| (c.room_id, c.unread_count))
+                .collect();
+        for contact in data.iter_mut() {
+            if let Some(count) = unread.get(&contact.room_id) {
+                contact.unread_count = Some(*count);
+            }
+        }
+
         Ok(data)
     } else {
         Err(CommonError::UnexpectedError(anyhow::anyhow!(
@@ -0,0 +1,249 @@
+use entity::im_room_draft;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, sleep};
+use tracing::{error, info};
+
+use crate::AppData;
+use crate::command::current_login_uid;
+use crate::error::CommonError;
+use crate::repository::im_room_draft_repository;
+
+/// 草稿写入的防抖时间，输入期间只保留最后一次
+const DRAFT_SAVE_DEBOUNCE_MS: u64 = 500;
+/// 会话列表中草稿预览的最大字符数
+const DRAFT_PREVIEW_MAX_CHARS: usize = 50;
+
+/// 尚未落库的草稿，key 为 (login_uid, room_id)，value 中的序号用于判断是否已被更新的草稿覆盖
+static PENDING_DRAFTS: Lazy<Mutex<HashMap<(String, String), (u64, RoomDraft)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static DRAFT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomDraft {
+    pub room_id: String,
+    #[serde(default)]
+    pub content: String,
+    /// 待回复的消息 ID
+    pub reply_to_id: Option<String>,
+    /// 已 @ 的成员 uid
+    #[serde(default)]
+    pub mentions: Vec<String>,
+    /// 待发送附件的本地路径
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    pub update_time: Option<i64>,
+}
+
+impl RoomDraft {
+    fn is_empty(&self) -> bool {
+        self.content.trim().is_empty() && self.reply_to_id.is_none() && self.attachments.is_empty()
+    }
+
+    /// 会话列表展示的草稿预览
+    pub fn preview(&self) -> String {
+        let content = self.content.trim();
+        if content.is_empty() && !self.attachments.is_empty() {
+            return "[附件]".to_string();
+        }
+        content.chars().take(DRAFT_PREVIEW_MAX_CHARS).collect()
+    }
+
+    fn into_model(self, login_uid: &str) -> im_room_draft::Model {
+        im_room_draft::Model {
+            room_id: self.room_id,
+            login_uid: login_uid.to_string(),
+            content: Some(self.content),
+            reply_to_id: self.reply_to_id,
+            mentions: serde_json::to_string(&self.mentions).ok(),
+            attachments: serde_json::to_string(&self.attachments).ok(),
+            update_time: self
+                .update_time
+                .unwrap_or_else(|| chrono::Utc::now().timestamp_millis()),
+        }
+    }
+}
+
+impl From<im_room_draft::Model> for RoomDraft {
+    fn from(model: im_room_draft::Model) -> Self {
+        let parse_list = |value: Option<String>| -> Vec<String> {
+            value
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or_default()
+        };
+        RoomDraft {
+            room_id: model.room_id,
+            content: model.content.unwrap_or_default(),
+            reply_to_id: model.reply_to_id,
+            mentions: parse_list(model.mentions),
+            attachments: parse_list(model.attachments),
+            update_time: Some(model.update_time),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct DraftChangedEvent {
+    room_id: String,
+    /// 草稿被清除时为空
+    preview: Option<String>,
+}
+
+/// 写入或删除草稿，调用方需持有 write_lock
+async fn write_draft(
+    state: &AppData,
+    login_uid: &str,
+    draft: RoomDraft,
+) -> Result<(), CommonError> {
+    if draft.is_empty() {
+        im_room_draft_repository::delete_draft(state.db_conn.deref(), login_uid, &draft.room_id)
+            .await
+    } else {
+        im_room_draft_repository::save_draft(state.db_conn.deref(), draft.into_model(login_uid))
+            .await
+    }
+}
+
+/// 保存草稿，短时间内的多次保存只写入最后一次；内容为空时删除草稿
+#[tauri::command]
+pub async fn save_draft(
+    mut draft: RoomDraft,
+    state: State<'_, AppData>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let login_uid = current_login_uid(&state).await?;
+    draft.update_time = Some(chrono::Utc::now().timestamp_millis());
+    let key = (login_uid.clone(), draft.room_id.clone());
+    let seq = DRAFT_SEQ.fetch_add(1, Ordering::SeqCst);
+    let event = DraftChangedEvent {
+        room_id: draft.room_id.clone(),
+        preview: (!draft.is_empty()).then(|| draft.preview()),
+    };
+    PENDING_DRAFTS
+        .lock()
+        .await
+        .insert(key.clone(), (seq, draft));
+    let _ = app_handle.emit("draft-changed", &event);
+
+    let app_handle = app_handle.clone();
+    tokio::spawn(async move {
+        sleep(Duration::from_millis(DRAFT_SAVE_DEBOUNCE_MS)).await;
+        let state = app_handle.state::<AppData>();
+        // 在写锁内确认序号，避免 clear_draft 删除后又被本次写入恢复
+        let _guard = state.write_lock.lock().await;
+        let draft = {
+            let mut pending = PENDING_DRAFTS.lock().await;
+            match pending.get(&key) {
+                Some((pending_seq, _)) if *pending_seq == seq => {
+                    pending.remove(&key).map(|(_, d)| d)
+                }
+                _ => None,
+            }
+        };
+        if let Some(draft) = draft {
+            if let Err(e) = write_draft(&state, &key.0, draft).await {
+                error!("Failed to save draft for room {}: {}", key.1, e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// 读取房间草稿，优先返回尚未落库的版本
+#[tauri::command]
+pub async fn load_draft(
+    room_id: String,
+    state: State<'_, AppData>,
+) -> Result<Option<RoomDraft>, String> {
+    let login_uid = current_login_uid(&state).await?;
+    if let Some((_, draft)) = PENDING_DRAFTS
+        .lock()
+        .await
+        .get(&(login_uid.clone(), room_id.clone()))
+    {
+        return Ok((!draft.is_empty()).then(|| draft.clone()));
+    }
+    im_room_draft_repository::get_draft(state.db_conn.deref(), &login_uid, &room_id)
+        .await
+        .map(|draft| draft.map(RoomDraft::from))
+        .map_err(|e| e.to_string())
+}
+
+/// 清除房间草稿（消息发送后调用）
+#[tauri::command]
+pub async fn clear_draft(
+    room_id: String,
+    state: State<'_, AppData>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let login_uid = current_login_uid(&state).await?;
+    PENDING_DRAFTS
+        .lock()
+        .await
+        .remove(&(login_uid.clone(), room_id.clone()));
+    {
+        let _guard = state.write_lock.lock().await;
+        im_room_draft_repository::delete_draft(state.db_conn.deref(), &login_uid, &room_id)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    let _ = app_handle.emit(
+        "draft-changed",
+        &DraftChangedEvent {
+            room_id,
+            preview: None,
+        },
+    );
+    Ok(())
+}
+
+/// 当前用户所有房间的草稿预览，key 为 room_id
+pub async fn load_draft_previews(
+    state: &AppData,
+    login_uid: &str,
+) -> Result<HashMap<String, String>, CommonError> {
+    let mut previews: HashMap<String, String> =
+        im_room_draft_repository::list_drafts(state.db_conn.deref(), login_uid)
+            .await?
+            .into_iter()
+            .map(|model| {
+                let draft = RoomDraft::from(model);
+                (draft.room_id.clone(), draft.preview())
+            })
+            .collect();
+    for ((pending_uid, room_id), (_, draft)) in PENDING_DRAFTS.lock().await.iter() {
+        if pending_uid != login_uid {
+            continue;
+        }
+        if draft.is_empty() {
+            previews.remove(room_id);
+        } else {
+            previews.insert(room_id.clone(), draft.preview());
+        }
+    }
+    Ok(previews)
+}
+
+/// 立即写入所有尚未落库的草稿（退出登录、关闭窗口前调用）
+pub async fn flush_pending_drafts(app_handle: &AppHandle) {
+    let state = app_handle.state::<AppData>();
+    let _guard = state.write_lock.lock().await;
+    let pending: Vec<((String, String), (u64, RoomDraft))> =
+        PENDING_DRAFTS.lock().await.drain().collect();
+    if pending.is_empty() {
+        return;
+    }
+    info!("Flushing {} pending drafts", pending.len());
+    for ((login_uid, room_id), (_, draft)) in pending {
+        if let Err(e) = write_draft(&state, &login_uid, draft).await {
+            error!("Failed to flush draft for room {}: {}", room_id, e);
+        }
+    }
+}
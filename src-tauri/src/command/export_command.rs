@@ -0,0 +1,76 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+use tauri::{State, ipc::Channel};
+use tracing::{error, info};
+
+use crate::AppData;
+use crate::chat_export::{self, ExportFormat, ExportProgress, ExportRequest, ExportResult};
+use crate::command::chat_history_command::DateRange;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportChatHistoryParam {
+    /// 前端生成的任务 ID，用于取消导出
+    pub export_id: String,
+    pub room_ids: Vec<String>,
+    pub format: ExportFormat,
+    pub date_range: Option<DateRange>,
+    /// 导出文件路径，未带扩展名时按格式补全
+    pub output_path: String,
+}
+
+/// 导出聊天记录到文件，进度通过 on_progress 推送
+#[tauri::command]
+pub async fn export_chat_history(
+    param: ExportChatHistoryParam,
+    on_progress: Channel<ExportProgress>,
+    state: State<'_, AppData>,
+) -> Result<ExportResult, String> {
+    if param.room_ids.is_empty() {
+        return Err("请选择要导出的会话".to_string());
+    }
+    let login_uid = state.user_info.lock().await.uid.clone();
+    if login_uid.is_empty() {
+        return Err("用户未登录".to_string());
+    }
+
+    let mut output_path = PathBuf::from(&param.output_path);
+    if output_path.extension().is_none() {
+        output_path.set_extension(param.format.extension());
+    }
+    let request = ExportRequest {
+        room_ids: param.room_ids,
+        format: param.format,
+        start_time: param.date_range.as_ref().and_then(|r| r.start_time),
+        end_time: param.date_range.as_ref().and_then(|r| r.end_time),
+        output_path,
+    };
+    info!(
+        "Exporting chat history, export_id: {}, rooms: {}, format: {:?}",
+        param.export_id,
+        request.room_ids.len(),
+        request.format
+    );
+
+    let handle = chat_export::register_export(&param.export_id).map_err(|e| e.to_string())?;
+    chat_export::run_export(
+        state.db_conn.as_ref(),
+        &login_uid,
+        &param.export_id,
+        &request,
+        &handle,
+        |progress| {
+            if let Err(e) = on_progress.send(progress) {
+                error!("Failed to send export progress: {}", e);
+            }
+        },
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// 取消正在进行的导出
+#[tauri::command]
+pub async fn cancel_chat_export(export_id: String) -> Result<bool, String> {
+    Ok(chat_export::cancel_export(&export_id))
+}
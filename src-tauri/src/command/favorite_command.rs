@@ -0,0 +1,289 @@
+use chrono::Utc;
+use entity::{im_favorite_message, im_message};
+use sea_orm::{ConnectionTrait, Statement};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use tracing::info;
+
+use crate::AppData;
+use crate::command::current_login_uid;
+use crate::command::message_command::{MessageResp, convert_message_to_resp};
+use crate::pojo::common::CursorPageResp;
+use crate::repository::im_favorite_message_repository::{
+    self as repository, FavoriteFilter, FavoriteTagCount,
+};
+use crate::repository::im_message_repository::{self, MessageWithThumbnail};
+
+/// 单条收藏最多的标签数
+const MAX_TAGS: usize = 20;
+/// 单个标签的最大长度（字符）
+const MAX_TAG_CHARS: usize = 32;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FavoriteResp {
+    pub message_id: String,
+    pub room_id: String,
+    pub tags: Vec<String>,
+    /// 收藏时间
+    pub favorited_at: i64,
+    pub from_avatar: Option<String>,
+    /// 收藏时的消息快照
+    pub message: MessageResp,
+}
+
+impl From<im_favorite_message::Model> for FavoriteResp {
+    fn from(model: im_favorite_message::Model) -> Self {
+        let tags = serde_json::from_str(&model.tags).unwrap_or_default();
+        let record = MessageWithThumbnail::new(
+            im_message::Model {
+                id: model.message_id.clone(),
+                uid: model.from_uid,
+                nickname: model.from_nickname,
+                room_id: model.room_id.clone(),
+                send_time: model.send_time,
+                message_type: model.message_type,
+                body: model.body,
+                message_marks: None,
+                create_time: None,
+                update_time: None,
+                login_uid: model.login_uid,
+                send_status: "success".to_string(),
+                time_block: None,
+                edited_at: None,
+                reply_to_id: None,
+            },
+            model.thumbnail_path,
+        );
+        Self {
+            message_id: model.message_id,
+            room_id: model.room_id,
+            tags,
+            favorited_at: model.create_time,
+            from_avatar: model.from_avatar,
+            message: convert_message_to_resp(record, None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FavoriteQueryParam {
+    pub keyword: Option<String>,
+    pub tag: Option<String>,
+    pub message_types: Option<Vec<u8>>,
+    pub room_id: Option<String>,
+    /// 上一页返回的游标，为空时从最新的收藏开始
+    pub cursor: Option<String>,
+    pub page_size: Option<u32>,
+}
+
+/// 去掉空白和重复的标签，限制数量和长度
+fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if tag.is_empty() || normalized.iter().any(|t| t == tag) {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_CHARS {
+            return Err(format!("标签不能超过 {} 个字符", MAX_TAG_CHARS));
+        }
+        normalized.push(tag.to_string());
+    }
+    if normalized.len() > MAX_TAGS {
+        return Err(format!("标签不能超过 {} 个", MAX_TAGS));
+    }
+    Ok(normalized)
+}
+
+/// 游标格式为 "收藏时间_消息ID"
+fn parse_cursor(cursor: &str) -> Option<(i64, String)> {
+    let (create_time, message_id) = cursor.split_once('_')?;
+    Some((create_time.parse().ok()?, message_id.to_string()))
+}
+
+/// 从群成员信息中查询发送人的群昵称和头像
+async fn find_sender<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message: &im_message::Model,
+) -> Result<(Option<String>, Option<String>), String> {
+    let row = db
+        .query_one(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            "SELECT COALESCE(NULLIF(my_name, ''), name) AS name, avatar FROM im_room_member \
+             WHERE login_uid = ? AND room_id = ? AND uid = ? LIMIT 1",
+            [
+                login_uid.into(),
+                message.room_id.clone().into(),
+                message.uid.clone().into(),
+            ],
+        ))
+        .await
+        .map_err(|e| e.to_string())?;
+    let (name, avatar) = match row {
+        Some(row) => (
+            row.try_get::<Option<String>>("", "name")
+                .map_err(|e| e.to_string())?,
+            row.try_get::<Option<String>>("", "avatar")
+                .map_err(|e| e.to_string())?,
+        ),
+        None => (None, None),
+    };
+    Ok((message.nickname.clone().or(name), avatar))
+}
+
+/// 收藏消息，保存消息内容与发送人的快照；已收藏时刷新快照，tags 不为空时同时替换标签
+#[tauri::command]
+pub async fn add_favorite_message(
+    message_id: String,
+    tags: Option<Vec<String>>,
+    state: State<'_, AppData>,
+) -> Result<FavoriteResp, String> {
+    let login_uid = current_login_uid(&state).await?;
+    let tags = tags.map(normalize_tags).transpose()?;
+    let db = state.db_conn.as_ref();
+
+    let record = im_message_repository::find_message_with_thumbnail(db, &login_uid, &message_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "消息不存在".to_string())?;
+    if record.message.message_type == Some(2) {
+        return Err("撤回的消息不能收藏".to_string());
+    }
+    let (from_nickname, from_avatar) = find_sender(db, &login_uid, &record.message).await?;
+    let search_text = repository::extract_search_text(db, &login_uid, &message_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let now = Utc::now().timestamp_millis();
+    let model = im_favorite_message::Model {
+        message_id: message_id.clone(),
+        login_uid: login_uid.clone(),
+        room_id: record.message.room_id,
+        from_uid: record.message.uid,
+        from_nickname,
+        from_avatar,
+        message_type: record.message.message_type,
+        body: record.message.body,
+        thumbnail_path: record.thumbnail_path,
+        send_time: record.message.send_time,
+        search_text,
+        tags: "[]".to_string(),
+        create_time: now,
+        update_time: now,
+    };
+
+    let favorite = {
+        let _guard = state.write_lock.lock().await;
+        repository::upsert(db, model)
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Some(tags) = &tags {
+            repository::set_tags(db, &login_uid, &message_id, tags, now)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        repository::find(db, &login_uid, &message_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "收藏失败".to_string())?
+    };
+    info!("Added message {} to favorites", message_id);
+    Ok(favorite.into())
+}
+
+/// 取消收藏，返回删除条数
+#[tauri::command]
+pub async fn remove_favorite_messages(
+    message_ids: Vec<String>,
+    state: State<'_, AppData>,
+) -> Result<u64, String> {
+    let login_uid = current_login_uid(&state).await?;
+    let _guard = state.write_lock.lock().await;
+    repository::remove(state.db_conn.as_ref(), &login_uid, &message_ids)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 替换收藏的标签
+#[tauri::command]
+pub async fn set_favorite_tags(
+    message_id: String,
+    tags: Vec<String>,
+    state: State<'_, AppData>,
+) -> Result<Vec<String>, String> {
+    let login_uid = current_login_uid(&state).await?;
+    let tags = normalize_tags(tags)?;
+    let _guard = state.write_lock.lock().await;
+    let updated = repository::set_tags(
+        state.db_conn.as_ref(),
+        &login_uid,
+        &message_id,
+        &tags,
+        Utc::now().timestamp_millis(),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    if !updated {
+        return Err("收藏不存在".to_string());
+    }
+    Ok(tags)
+}
+
+/// 按收藏时间倒序分页查询收藏，支持关键词、标签、消息类型和房间过滤
+#[tauri::command]
+pub async fn list_favorite_messages(
+    param: Option<FavoriteQueryParam>,
+    state: State<'_, AppData>,
+) -> Result<CursorPageResp<Vec<FavoriteResp>>, String> {
+    let login_uid = current_login_uid(&state).await?;
+    let param = param.unwrap_or_default();
+    let before = match param.cursor.as_deref().filter(|c| !c.is_empty()) {
+        Some(cursor) => {
+            Some(parse_cursor(cursor).ok_or_else(|| format!("无效的收藏游标: {}", cursor))?)
+        }
+        None => None,
+    };
+    let page_size = param.page_size.unwrap_or(20).clamp(1, 200);
+    let filter = FavoriteFilter {
+        keyword: param.keyword.filter(|k| !k.trim().is_empty()),
+        tag: param.tag.filter(|t| !t.is_empty()),
+        message_types: param.message_types.unwrap_or_default(),
+        room_id: param.room_id.filter(|r| !r.is_empty()),
+    };
+
+    let db = state.db_conn.as_ref();
+    let total = repository::count(db, &login_uid, &filter)
+        .await
+        .map_err(|e| e.to_string())?;
+    // 多取一条判断是否还有下一页
+    let mut favorites = repository::list(db, &login_uid, &filter, before, page_size as u64 + 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    let is_last = favorites.len() <= page_size as usize;
+    favorites.truncate(page_size as usize);
+    let cursor = match favorites.last() {
+        Some(last) if !is_last => format!("{}_{}", last.create_time, last.message_id),
+        _ => String::new(),
+    };
+
+    Ok(CursorPageResp {
+        cursor,
+        is_last,
+        list: Some(favorites.into_iter().map(FavoriteResp::from).collect()),
+        total,
+    })
+}
+
+/// 列出所有标签及其收藏数
+#[tauri::command]
+pub async fn list_favorite_tags(
+    state: State<'_, AppData>,
+) -> Result<Vec<FavoriteTagCount>, String> {
+    let login_uid = current_login_uid(&state).await?;
+    repository::list_tags(state.db_conn.as_ref(), &login_uid)
+        .await
+        .map_err(|e| e.to_string())
+}
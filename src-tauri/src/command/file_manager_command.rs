@@ -1,13 +1,18 @@
 use crate::AppData;
-use crate::repository::im_message_repository::{self, MessageWithThumbnail};
+use crate::common::app_paths::user_data_root;
+use crate::repository::im_message_repository::{self, MessageKey, MessageWithThumbnail};
 use entity::im_message;
-use sea_orm::{ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
 
 use serde::{Deserialize, Serialize};
 use std::ops::Deref;
-use tauri::State;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, State};
 use tracing::info;
 
+/// 媒体库包含的消息类型：图片、文件、语音、视频、表情
+const MEDIA_MESSAGE_TYPES: [u8; 5] = [3, 4, 5, 6, 7];
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct FileInfo {
@@ -250,6 +255,7 @@ fn convert_message_to_file_info(record: MessageWithThumbnail) -> Option<FileInfo
     let MessageWithThumbnail {
         message,
         thumbnail_path,
+        ..
     } = record;
 
     // 解析消息体中的文件信息
@@ -352,12 +358,22 @@ fn parse_size_value(value: &serde_json::Value) -> Option<i64> {
 /// 根据消息类型获取文件类型
 fn get_file_type_from_message_type(message_type: u8) -> String {
     match message_type {
+        3 => "image".to_string(),
         4 => "file".to_string(),
+        5 => "voice".to_string(),
         6 => "video".to_string(),
+        7 => "emoji".to_string(),
         _ => "unknown".to_string(),
     }
 }
 
+/// 媒体库类型名对应的消息类型
+fn get_message_type_from_media_type(media_type: &str) -> Option<u8> {
+    MEDIA_MESSAGE_TYPES
+        .into_iter()
+        .find(|message_type| get_file_type_from_message_type(*message_type) == media_type)
+}
+
 /// 格式化时间戳
 fn format_timestamp(timestamp: i64) -> String {
     use chrono::{Local, TimeZone};
@@ -444,6 +460,251 @@ fn format_display_date(date: chrono::NaiveDate) -> String {
     }
 }
 
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaGalleryParam {
+    /// 为空时查询所有房间
+    pub room_id: Option<String>,
+    /// image、file、voice、video、emoji，为空时查询全部
+    pub media_types: Option<Vec<String>>,
+    /// 上一页返回的游标，为空时从最新的消息开始
+    pub cursor: Option<String>,
+    pub page_size: Option<u32>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaItem {
+    pub id: String,
+    pub room_id: String,
+    pub media_type: String,
+    pub url: Option<String>,
+    pub thumbnail_url: Option<String>,
+    pub file_name: Option<String>,
+    pub file_size: Option<i64>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    /// 语音时长（秒）
+    pub duration: Option<u64>,
+    pub send_time: i64,
+    pub sender: UserInfo,
+    /// 本地已下载文件的路径，未下载时为空
+    pub local_path: Option<String>,
+    pub is_downloaded: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaMonthGroup {
+    /// 格式为 yyyy-MM，相邻两页的首尾可能是同一个月，前端按 month 合并
+    pub month: String,
+    pub display_month: String,
+    pub items: Vec<MediaItem>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaGalleryResponse {
+    pub groups: Vec<MediaMonthGroup>,
+    pub cursor: String,
+    pub has_more: bool,
+}
+
+fn body_str(body: &serde_json::Value, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .find_map(|key| body.get(*key).and_then(|v| v.as_str()))
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+/// 已下载的文件：优先使用消息体中记录的本地路径，其次是前端默认的下载目录 userData/uid/roomId
+async fn find_local_file(
+    user_dir: Option<&Path>,
+    room_id: &str,
+    local_path: Option<&str>,
+    file_name: Option<&str>,
+) -> Option<String> {
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    if let Some(local_path) = local_path {
+        let path = PathBuf::from(local_path);
+        if path.is_absolute() {
+            candidates.push(path);
+        }
+    }
+    if let (Some(user_dir), Some(file_name)) = (user_dir, file_name) {
+        candidates.push(user_dir.join(room_id).join(file_name));
+    }
+    for candidate in candidates {
+        if tokio::fs::try_exists(&candidate).await.unwrap_or(false) {
+            return Some(candidate.to_string_lossy().to_string());
+        }
+    }
+    None
+}
+
+async fn convert_message_to_media_item(
+    record: MessageWithThumbnail,
+    user_dir: Option<&Path>,
+) -> Option<MediaItem> {
+    let MessageWithThumbnail {
+        message,
+        thumbnail_path,
+        ..
+    } = record;
+    let message_type = message.message_type?;
+    let body: serde_json::Value = message
+        .body
+        .as_deref()
+        .and_then(|body| serde_json::from_str(body).ok())
+        .unwrap_or(serde_json::Value::Null);
+
+    let url = body_str(&body, &["url", "downloadUrl"]);
+    let file_name = body_str(&body, &["fileName", "filename", "file_name"]).or_else(|| {
+        // 视频、语音等消息体没有文件名时从 URL 中提取
+        url.as_deref()
+            .and_then(|url| url.split('?').next())
+            .and_then(|url| url.rsplit('/').next())
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_string())
+    });
+    let (width, height) = match message_type {
+        6 => (body.get("thumbWidth"), body.get("thumbHeight")),
+        _ => (body.get("width"), body.get("height")),
+    };
+    let thumbnail_url = thumbnail_path
+        .or_else(|| body_str(&body, &["thumbnailPath", "thumbUrl", "thumbnailUrl"]))
+        .or_else(|| match message_type {
+            3 | 7 => url.clone(),
+            _ => None,
+        });
+    let local_path = match message_type {
+        // 图片与表情由前端缓存，不单独下载
+        3 | 7 => None,
+        _ => {
+            find_local_file(
+                user_dir,
+                &message.room_id,
+                body_str(&body, &["localPath"]).as_deref(),
+                file_name.as_deref(),
+            )
+            .await
+        }
+    };
+
+    Some(MediaItem {
+        id: message.id,
+        room_id: message.room_id,
+        media_type: get_file_type_from_message_type(message_type),
+        url,
+        thumbnail_url,
+        file_name,
+        file_size: extract_file_size(&body),
+        width: width.and_then(|v| v.as_u64()),
+        height: height.and_then(|v| v.as_u64()),
+        duration: body.get("second").and_then(|v| v.as_u64()),
+        send_time: message.send_time.unwrap_or(0),
+        sender: UserInfo {
+            id: message.uid,
+            name: message.nickname.unwrap_or("未知用户".to_string()),
+            avatar: "/avatars/default.jpg".to_string(),
+            is_online: None,
+        },
+        is_downloaded: local_path.is_some(),
+        local_path,
+    })
+}
+
+/// 按月分组，items 已按发送时间倒序
+fn group_media_by_month(items: Vec<MediaItem>) -> Vec<MediaMonthGroup> {
+    use chrono::{Datelike, Local, TimeZone};
+
+    let mut groups: Vec<MediaMonthGroup> = Vec::new();
+    let this_year = Local::now().year();
+    for item in items {
+        let (month, display_month) = match Local.timestamp_millis_opt(item.send_time).single() {
+            Some(dt) if dt.year() == this_year => (
+                dt.format("%Y-%m").to_string(),
+                dt.format("%m月").to_string(),
+            ),
+            Some(dt) => (
+                dt.format("%Y-%m").to_string(),
+                dt.format("%Y年%m月").to_string(),
+            ),
+            None => ("unknown".to_string(), "未知时间".to_string()),
+        };
+        match groups.last_mut() {
+            Some(group) if group.month == month => group.items.push(item),
+            _ => groups.push(MediaMonthGroup {
+                month,
+                display_month,
+                items: vec![item],
+            }),
+        }
+    }
+    groups
+}
+
+/// 媒体库：跨房间或在单个房间内按类型浏览图片、视频、语音、文件和表情，按月分组，
+/// 使用 (发送时间, 消息 ID) 游标翻页
+#[tauri::command]
+pub async fn query_media_gallery(
+    param: Option<MediaGalleryParam>,
+    app_handle: AppHandle,
+    state: State<'_, AppData>,
+) -> Result<MediaGalleryResponse, String> {
+    let login_uid = state.user_info.lock().await.uid.clone();
+    let param = param.unwrap_or_default();
+    let message_types: Vec<u8> = match param.media_types.as_deref() {
+        Some(media_types) if !media_types.is_empty() => media_types
+            .iter()
+            .map(|media_type| {
+                get_message_type_from_media_type(media_type)
+                    .ok_or_else(|| format!("不支持的媒体类型: {}", media_type))
+            })
+            .collect::<Result<_, _>>()?,
+        _ => MEDIA_MESSAGE_TYPES.to_vec(),
+    };
+    let before = match param.cursor.as_deref().filter(|c| !c.is_empty()) {
+        Some(cursor) => {
+            Some(MessageKey::parse(cursor).ok_or_else(|| format!("无效的媒体库游标: {}", cursor))?)
+        }
+        None => None,
+    };
+    let page_size = param.page_size.unwrap_or(50).clamp(1, 200) as usize;
+
+    // 多取一条判断是否还有下一页
+    let mut records = im_message_repository::page_media_messages(
+        state.db_conn.deref(),
+        &login_uid,
+        param.room_id.as_deref().filter(|r| !r.is_empty()),
+        &message_types,
+        before.as_ref(),
+        page_size as u64 + 1,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let has_more = records.len() > page_size;
+    records.truncate(page_size);
+    let cursor = match records.last() {
+        Some(last) if has_more => MessageKey::from(&last.message).to_cursor(),
+        _ => String::new(),
+    };
+
+    let user_dir = user_data_root(&app_handle).map(|root| root.join(&login_uid));
+    let mut items = Vec::with_capacity(records.len());
+    for record in records {
+        if let Some(item) = convert_message_to_media_item(record, user_dir.as_deref()).await {
+            items.push(item);
+        }
+    }
+
+    Ok(MediaGalleryResponse {
+        groups: group_media_by_month(items),
+        cursor,
+        has_more,
+    })
+}
+
 /// 获取导航菜单项
 #[tauri::command]
 pub async fn get_navigation_items() -> Result<Vec<NavigationItem>, String> {
@@ -476,85 +737,3 @@ pub async fn get_navigation_items() -> Result<Vec<NavigationItem>, String> {
 
     Ok(items)
 }
-
-/// 调试命令：获取数据库中的消息统计信息
-#[tauri::command]
-pub async fn debug_message_stats(state: State<'_, AppData>) -> Result<serde_json::Value, String> {
-    let login_uid = {
-        let user_info = state.user_info.lock().await;
-        user_info.uid.clone()
-    };
-
-    // 查询总消息数
-    let total_messages = im_message::Entity::find()
-        .filter(im_message::Column::LoginUid.eq(&login_uid))
-        .count(state.db_conn.deref())
-        .await
-        .map_err(|e| format!("查询总消息数失败: {}", e))?;
-
-    // 查询各种类型的消息数
-    let mut stats = serde_json::Map::new();
-    stats.insert(
-        "total_messages".to_string(),
-        serde_json::Value::Number(serde_json::Number::from(total_messages)),
-    );
-
-    // 统计各种消息类型
-    for msg_type in 0..=10u8 {
-        let count = im_message::Entity::find()
-            .filter(im_message::Column::LoginUid.eq(&login_uid))
-            .filter(im_message::Column::MessageType.eq(msg_type))
-            .count(state.db_conn.deref())
-            .await
-            .map_err(|e| format!("查询类型 {} 消息数失败: {}", msg_type, e))?;
-
-        if count > 0 {
-            stats.insert(
-                format!("type_{}", msg_type),
-                serde_json::Value::Number(serde_json::Number::from(count)),
-            );
-        }
-    }
-
-    // 查询最近的几条文件消息样例
-    let sample_messages = im_message::Entity::find()
-        .filter(im_message::Column::LoginUid.eq(&login_uid))
-        .filter(im_message::Column::MessageType.is_in([4, 6]))
-        .order_by_desc(im_message::Column::SendTime)
-        .limit(5)
-        .all(state.db_conn.deref())
-        .await
-        .map_err(|e| format!("查询样例消息失败: {}", e))?;
-
-    let samples: Vec<serde_json::Value> = sample_messages
-        .into_iter()
-        .map(|msg| {
-            serde_json::json!({
-                "id": msg.id,
-                "message_type": msg.message_type,
-                "room_id": msg.room_id,
-                "sender": msg.uid,
-                "nickname": msg.nickname,
-                "send_time": msg.send_time,
-                "body_length": msg.body.as_ref().map(|b| b.len()).unwrap_or(0),
-                "body_content": msg.body.as_ref().map(|b| {
-                    if b.len() > 200 { format!("{}...", &b[..200]) } else { b.clone() }
-                }).unwrap_or_else(|| "null".to_string()),
-                "has_filename": msg.body.as_ref().and_then(|body| {
-                    serde_json::from_str::<serde_json::Value>(body).ok()
-                }).map(|json| {
-                    json["fileName"].as_str()
-                        .map(|name| !name.trim().is_empty())
-                        .unwrap_or(false)
-                }).unwrap_or(false)
-            })
-        })
-        .collect();
-
-    stats.insert(
-        "sample_file_messages".to_string(),
-        serde_json::Value::Array(samples),
-    );
-
-    Ok(serde_json::Value::Object(stats))
-}
@@ -0,0 +1,32 @@
+use std::path::PathBuf;
+use tauri::State;
+use tracing::info;
+
+use crate::AppData;
+use crate::chat_import::{self, ImportReport};
+
+/// 导入 JSON 格式的聊天记录，dry_run 为 true 时只返回去重与冲突报告，不写入数据库
+#[tauri::command]
+pub async fn import_chat_history(
+    input_path: String,
+    dry_run: bool,
+    state: State<'_, AppData>,
+) -> Result<ImportReport, String> {
+    let login_uid = state.user_info.lock().await.uid.clone();
+    if login_uid.is_empty() {
+        return Err("用户未登录".to_string());
+    }
+    info!(
+        "Importing chat history from {}, dry_run: {}",
+        input_path, dry_run
+    );
+    chat_import::import_chat_history(
+        state.db_conn.as_ref(),
+        &state.write_lock,
+        &login_uid,
+        &PathBuf::from(input_path),
+        dry_run,
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
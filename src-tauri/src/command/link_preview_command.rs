@@ -0,0 +1,74 @@
+use chrono::Utc;
+use std::collections::HashMap;
+use tauri::{AppHandle, State};
+use tracing::info;
+
+use crate::AppData;
+use crate::command::current_login_uid;
+use crate::command::message_command::UrlInfo;
+use crate::link_preview::{self, LinkPreviewSettings};
+use crate::repository::im_link_preview_repository as repository;
+
+/// 单次查询的最大链接数
+const MAX_QUERY_URLS: usize = 100;
+
+/// 查询链接预览，只返回已缓存且抓取成功的链接；
+/// 其余链接在后台抓取，完成后通过 link-preview-updated 事件通知。
+/// 供前端在用户主动操作时调用（如输入框中的链接），不受 fetch_incoming 限制
+#[tauri::command]
+pub async fn get_link_previews(
+    urls: Vec<String>,
+    app_handle: AppHandle,
+    state: State<'_, AppData>,
+) -> Result<HashMap<String, UrlInfo>, String> {
+    let mut valid_urls: Vec<String> = Vec::new();
+    for url in urls {
+        if link_preview::is_preview_url(&url) && !valid_urls.contains(&url) {
+            valid_urls.push(url);
+        }
+    }
+    if valid_urls.len() > MAX_QUERY_URLS {
+        return Err(format!("一次最多查询 {} 个链接", MAX_QUERY_URLS));
+    }
+
+    let cached = repository::find_valid(
+        state.db_conn.as_ref(),
+        &valid_urls,
+        Utc::now().timestamp_millis(),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let missing = valid_urls
+        .into_iter()
+        .filter(|url| !cached.contains_key(url))
+        .collect();
+    link_preview::schedule_fetch(&app_handle, missing);
+
+    Ok(cached
+        .into_iter()
+        .filter(|(_, model)| model.success)
+        .map(|(url, model)| (url, UrlInfo::from(model)))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn get_link_preview_settings(
+    state: State<'_, AppData>,
+) -> Result<LinkPreviewSettings, String> {
+    let login_uid = current_login_uid(&state).await?;
+    link_preview::load_settings(state.db_conn.as_ref(), &login_uid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_link_preview_settings(
+    settings: LinkPreviewSettings,
+    state: State<'_, AppData>,
+) -> Result<(), String> {
+    let login_uid = current_login_uid(&state).await?;
+    info!("update link preview settings: {:?}", settings);
+    link_preview::save_settings(state.db_conn.as_ref(), &login_uid, &settings)
+        .await
+        .map_err(|e| e.to_string())
+}
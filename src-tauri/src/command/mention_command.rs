@@ -0,0 +1,78 @@
+use serde::Serialize;
+use std::ops::Deref;
+use tauri::State;
+use tracing::info;
+
+use crate::AppData;
+use crate::command::current_login_uid;
+use crate::command::message_command::{MessageResp, convert_message_to_resp};
+use crate::repository::im_message_mention_repository::{self, RoomMentionCount};
+
+/// 未读提及列表默认返回条数
+const DEFAULT_MENTION_LIMIT: u64 = 100;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MentionResp {
+    pub room_id: String,
+    /// 仅 @全体成员
+    pub mention_all: bool,
+    pub message: MessageResp,
+}
+
+/// 按房间统计未读的 @我 消息，包括 UI 尚未打开过的房间
+#[tauri::command]
+pub async fn get_unread_mention_counts(
+    state: State<'_, AppData>,
+) -> Result<Vec<RoomMentionCount>, String> {
+    let login_uid = current_login_uid(&state).await?;
+    im_message_mention_repository::count_unread_by_room(state.db_conn.deref(), &login_uid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 跨房间列出未读的 @我 消息，按发送时间倒序
+#[tauri::command]
+pub async fn list_unread_mentions(
+    limit: Option<u64>,
+    state: State<'_, AppData>,
+) -> Result<Vec<MentionResp>, String> {
+    let login_uid = current_login_uid(&state).await?;
+    let mentions = im_message_mention_repository::list_unread(
+        state.db_conn.deref(),
+        &login_uid,
+        limit.unwrap_or(DEFAULT_MENTION_LIMIT),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(mentions
+        .into_iter()
+        .map(|(mention, record)| MentionResp {
+            room_id: mention.room_id,
+            mention_all: mention.mention_all,
+            message: convert_message_to_resp(record, None),
+        })
+        .collect())
+}
+
+/// 将房间内发送时间不晚于 read_until 的提及标记为已读，未传 read_until 时全部标记
+#[tauri::command]
+pub async fn mark_mentions_read(
+    room_id: String,
+    read_until: Option<i64>,
+    state: State<'_, AppData>,
+) -> Result<u64, String> {
+    let login_uid = current_login_uid(&state).await?;
+    let _guard = state.write_lock.lock().await;
+    let cleared = im_message_mention_repository::mark_read(
+        state.db_conn.deref(),
+        &login_uid,
+        &room_id,
+        read_until,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    info!("Marked {} mentions read in room {}", cleared, room_id);
+    Ok(cleared)
+}
@@ -1,13 +1,20 @@
 use crate::AppData;
+use crate::command::read_receipt_command;
 use crate::error::CommonError;
 use crate::im_request_client::{ImRequestClient, ImUrl};
+use crate::link_preview;
+use crate::outbox::{self, OutboxEvent, OutboxStatus, OutboxSubscriber};
 use crate::pojo::common::{CursorPageParam, CursorPageResp};
 use crate::repository::im_message_repository::MessageWithThumbnail;
-use crate::repository::{im_message_repository, im_user_repository};
-use crate::vo::vo::ChatMessageReq;
+use crate::repository::{
+    im_message_client_id_repository, im_message_repository, im_outbox_repository,
+    im_user_repository,
+};
+use crate::repository::{im_message_revision_repository, im_message_thread_repository};
+use crate::vo::vo::{ChatMessageReq, EditMessageReq};
 
 use entity::im_user::Entity as ImUserEntity;
-use entity::{im_message, im_user};
+use entity::{im_message, im_message_revision, im_user};
 use once_cell::sync::Lazy;
 use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
 use sea_orm::{DatabaseConnection, TransactionTrait};
@@ -17,7 +24,7 @@ use std::future::Future;
 use std::ops::Deref;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicI64, Ordering};
-use tauri::{State, ipc::Channel};
+use tauri::{AppHandle, State, ipc::Channel};
 use tokio::sync::Mutex;
 use tokio::time::{Duration, sleep};
 use tracing::{debug, error, info, warn};
@@ -99,6 +106,20 @@ pub struct Message {
     pub body: Option<serde_json::Value>,
     pub message_marks: Option<HashMap<String, MessageMark>>,
     pub send_time: Option<i64>,
+    /// 最后一次编辑时间，未编辑过为空
+    pub edited_at: Option<i64>,
+    /// 编辑次数
+    #[serde(default)]
+    pub edit_count: u32,
+    /// 被回复的消息 ID
+    pub reply_to_id: Option<String>,
+    /// 本地已有的回复数
+    #[serde(default)]
+    pub reply_count: u32,
+    /// 自己发送的消息的已读人数（本地缓存），其他消息为空
+    pub read_count: Option<u32>,
+    #[serde(rename = "unReadCount")]
+    pub unread_count: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -107,6 +128,8 @@ pub struct UrlInfo {
     pub title: Option<String>,
     pub description: Option<String>,
     pub image: Option<String>,
+    pub site_name: Option<String>,
+    pub favicon: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -149,6 +172,7 @@ pub struct CursorPageMessageParam {
 pub async fn page_msg(
     param: CursorPageMessageParam,
     state: State<'_, AppData>,
+    app_handle: AppHandle,
 ) -> Result<CursorPageResp<Vec<MessageResp>>, String> {
     // 获取当前登录用户的 uid
     let login_uid = {
@@ -166,8 +190,29 @@ pub async fn page_msg(
     .await
     .map_err(|e| e.to_string())?;
 
-    // 转换数据库模型为响应模型
-    let mut raw_list = db_result.list.unwrap_or_default();
+    let message_resps = build_page_resps(
+        &state,
+        &app_handle,
+        &login_uid,
+        db_result.list.unwrap_or_default(),
+    )
+    .await?;
+
+    Ok(CursorPageResp {
+        cursor: db_result.cursor,
+        is_last: db_result.is_last,
+        list: Some(message_resps),
+        total: db_result.total,
+    })
+}
+
+/// 按发送时间排序后转换为响应模型，计算 time_block 并附加已读人数
+async fn build_page_resps(
+    state: &State<'_, AppData>,
+    app_handle: &AppHandle,
+    login_uid: &str,
+    mut raw_list: Vec<MessageWithThumbnail>,
+) -> Result<Vec<MessageResp>, String> {
     raw_list.sort_by(|a, b| {
         let a_time = a.message.send_time.unwrap_or(0);
         let b_time = b.message.send_time.unwrap_or(0);
@@ -189,7 +234,7 @@ pub async fn page_msg(
                 &msg.message.room_id,
                 &msg.message.id,
                 send_time,
-                &login_uid,
+                login_uid,
             )
             .await
             .map_err(|e| e.to_string())?;
@@ -198,14 +243,286 @@ pub async fn page_msg(
         message_resps.push(resp);
     }
 
-    Ok(CursorPageResp {
-        cursor: db_result.cursor,
-        is_last: db_result.is_last,
-        list: Some(message_resps),
-        total: db_result.total,
+    // 已读人数只是附加信息，读取失败不影响分页结果
+    if let Err(e) =
+        read_receipt_command::attach_read_counts(app_handle, state, login_uid, &mut message_resps)
+            .await
+    {
+        warn!("Failed to attach message read counts: {}", e);
+    }
+    if let Err(e) = link_preview::attach_previews(
+        app_handle,
+        state.db_conn.deref(),
+        login_uid,
+        &mut message_resps,
+    )
+    .await
+    {
+        warn!("Failed to attach link previews: {}", e);
+    }
+    Ok(message_resps)
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchoredPageParam {
+    room_id: String,
+    /// 定位的消息 ID，优先于 anchor_time
+    anchor_id: Option<String>,
+    /// 定位的时间（毫秒），定位到该时间及之后的第一条消息
+    anchor_time: Option<i64>,
+    /// 上次返回的 before_cursor，继续向更早的消息翻页
+    before_cursor: Option<String>,
+    /// 上次返回的 after_cursor，继续向更新的消息翻页
+    after_cursor: Option<String>,
+    page_size: Option<u32>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchoredPageResp {
+    pub list: Vec<MessageResp>,
+    /// 定位到的消息 ID，按游标翻页时为空
+    pub anchor_id: Option<String>,
+    pub before_cursor: String,
+    pub after_cursor: String,
+    pub has_before: bool,
+    pub has_after: bool,
+}
+
+/// 从服务端拉取锚点消息时每页的条数
+const ANCHOR_FETCH_PAGE_SIZE: u32 = 100;
+/// 单次定位补齐本地与锚点之间的消息时最多拉取的页数
+const ANCHOR_FETCH_MAX_PAGES: usize = 30;
+
+/// 以消息或时间为锚点双向分页，用于从搜索结果、引用消息跳转到会话中的指定位置。
+/// 首次定位时锚点前后各取约半页，之后用返回的游标向两个方向继续翻页
+#[tauri::command]
+pub async fn page_msg_around(
+    param: AnchoredPageParam,
+    state: State<'_, AppData>,
+    app_handle: AppHandle,
+) -> Result<AnchoredPageResp, String> {
+    use im_message_repository::{MessageKey, PageDirection};
+
+    let login_uid = state.user_info.lock().await.uid.clone();
+    let page_size = param.page_size.unwrap_or(20).clamp(1, 200) as usize;
+    let db = state.db_conn.deref();
+    let uid = login_uid.as_str();
+    let room_id = param.room_id.as_str();
+    let parse_cursor = |cursor: &str| {
+        MessageKey::parse(cursor).ok_or_else(|| format!("无效的消息游标: {}", cursor))
+    };
+    // 多取一条判断该方向是否还有消息
+    let page = |key: MessageKey, direction: PageDirection, inclusive: bool, limit: usize| async move {
+        let mut list = im_message_repository::page_messages_from_key(
+            db,
+            uid,
+            room_id,
+            &key,
+            direction,
+            inclusive,
+            limit as u64 + 1,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+        let has_more = list.len() > limit;
+        if has_more {
+            match direction {
+                PageDirection::Before => {
+                    list.remove(0);
+                }
+                PageDirection::After => {
+                    list.pop();
+                }
+            }
+        }
+        Ok::<_, String>((list, has_more))
+    };
+
+    let mut anchor_id = None;
+    let (before, has_before, after, has_after) = match (
+        param.before_cursor.as_deref(),
+        param.after_cursor.as_deref(),
+    ) {
+        (Some(cursor), _) if !cursor.is_empty() => {
+            let (before, has_before) = page(
+                parse_cursor(cursor)?,
+                PageDirection::Before,
+                false,
+                page_size,
+            )
+            .await?;
+            (before, has_before, Vec::new(), true)
+        }
+        (_, Some(cursor)) if !cursor.is_empty() => {
+            let (after, has_after) = page(
+                parse_cursor(cursor)?,
+                PageDirection::After,
+                false,
+                page_size,
+            )
+            .await?;
+            (Vec::new(), true, after, has_after)
+        }
+        _ => {
+            // 按消息定位时锚点归入前半页，按时间定位时归入后半页
+            let (key, anchor_in_before) = match param.anchor_id.as_deref() {
+                Some(id) if !id.is_empty() => {
+                    let mut key = im_message_repository::find_message_key(db, uid, room_id, id)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    if key.is_none() {
+                        fetch_anchor_from_server(&state, uid, room_id, id)
+                            .await
+                            .map_err(|e| e.to_string())?;
+                        key = im_message_repository::find_message_key(db, uid, room_id, id)
+                            .await
+                            .map_err(|e| e.to_string())?;
+                    }
+                    (key.ok_or_else(|| "消息不存在".to_string())?, true)
+                }
+                _ => {
+                    let send_time = param
+                        .anchor_time
+                        .ok_or_else(|| "缺少定位的消息或时间".to_string())?;
+                    (
+                        MessageKey {
+                            send_time,
+                            id: String::new(),
+                        },
+                        false,
+                    )
+                }
+            };
+            let before_size = page_size / 2 + usize::from(anchor_in_before);
+            let (before, has_before) = page(
+                key.clone(),
+                PageDirection::Before,
+                anchor_in_before,
+                before_size,
+            )
+            .await?;
+            let (after, has_after) = page(
+                key,
+                PageDirection::After,
+                !anchor_in_before,
+                page_size.saturating_sub(before_size).max(1),
+            )
+            .await?;
+            anchor_id = if anchor_in_before {
+                before.last()
+            } else {
+                after.first().or(before.last())
+            }
+            .map(|record| record.message.id.clone());
+            (before, has_before, after, has_after)
+        }
+    };
+
+    let list: Vec<MessageWithThumbnail> = before.into_iter().chain(after).collect();
+    let before_cursor = list
+        .first()
+        .map(|record| MessageKey::from(&record.message).to_cursor())
+        .unwrap_or_default();
+    let after_cursor = list
+        .last()
+        .map(|record| MessageKey::from(&record.message).to_cursor())
+        .unwrap_or_default();
+    let list = build_page_resps(&state, &app_handle, &login_uid, list).await?;
+
+    Ok(AnchoredPageResp {
+        list,
+        anchor_id,
+        before_cursor,
+        after_cursor,
+        has_before,
+        has_after,
     })
 }
 
+/// 本地没有锚点消息时从服务端拉取。服务端只能按 ID 向前翻页，因此从本地最早的消息开始
+/// 连续往前补齐直到拉到锚点，保证本地历史中间不留空缺。锚点过远时放弃定位，
+/// 已补齐的消息保留，再次定位时从新的最早消息继续
+async fn fetch_anchor_from_server(
+    state: &State<'_, AppData>,
+    login_uid: &str,
+    room_id: &str,
+    anchor_id: &str,
+) -> Result<(), CommonError> {
+    let db = state.db_conn.deref();
+    let mut cursor = im_message_repository::find_oldest_message_key(db, login_uid, room_id)
+        .await?
+        .map(|key| key.id)
+        .unwrap_or_default();
+    // 锚点比本地最早的消息更新却不在本地，说明已被删除或不属于该房间，往前补齐找不到
+    if let (Ok(anchor), Ok(oldest)) = (anchor_id.parse::<u64>(), cursor.parse::<u64>()) {
+        if anchor > oldest {
+            return Ok(());
+        }
+    }
+
+    for _ in 0..ANCHOR_FETCH_MAX_PAGES {
+        let page = fetch_message_page(state, room_id, &cursor).await?;
+        let found = page
+            .list
+            .iter()
+            .flatten()
+            .any(|msg| msg.message.id.as_deref() == Some(anchor_id));
+        save_fetched_messages(state, login_uid, page.list.unwrap_or_default()).await?;
+        if found || page.is_last || page.cursor.is_empty() {
+            return Ok(());
+        }
+        cursor = page.cursor;
+    }
+
+    info!(
+        "Anchor message {} is too far from local history of room {}",
+        anchor_id, room_id
+    );
+    Err(CommonError::RequestError(
+        "定位的消息距离较远，已加载部分更早的消息，请稍后重试".to_string(),
+    ))
+}
+
+/// 拉取 ID 小于 cursor 的一页消息，cursor 为空时从最新的消息开始
+async fn fetch_message_page(
+    state: &State<'_, AppData>,
+    room_id: &str,
+    cursor: &str,
+) -> Result<CursorPageResp<Vec<MessageResp>>, CommonError> {
+    let params = serde_json::json!({
+        "roomId": room_id,
+        "pageSize": ANCHOR_FETCH_PAGE_SIZE,
+        "cursor": cursor,
+    });
+    let mut client = state.rc.lock().await;
+    client
+        .im_request::<CursorPageResp<Vec<MessageResp>>, _, _>(
+            ImUrl::GetMsgPage,
+            None::<serde_json::Value>,
+            Some(params),
+        )
+        .await?
+        .ok_or_else(|| CommonError::RequestError("拉取消息未返回结果".to_string()))
+}
+
+async fn save_fetched_messages(
+    state: &State<'_, AppData>,
+    login_uid: &str,
+    messages: Vec<MessageResp>,
+) -> Result<(), CommonError> {
+    if messages.is_empty() {
+        return Ok(());
+    }
+    let records: Vec<MessageWithThumbnail> = messages
+        .into_iter()
+        .map(|msg| convert_resp_to_record_for_fetch(msg, login_uid.to_string()))
+        .collect();
+    let _guard = state.write_lock.lock().await;
+    im_message_repository::save_all(state.db_conn.deref(), records).await
+}
+
 /// 将数据库消息模型转换为响应模型
 pub fn convert_message_to_resp(
     record: MessageWithThumbnail,
@@ -214,6 +531,8 @@ pub fn convert_message_to_resp(
     let MessageWithThumbnail {
         message: msg,
         thumbnail_path,
+        edit_count,
+        reply_count,
     } = record;
 
     // 解析消息体 - 安全地处理 JSON 解析
@@ -280,6 +599,12 @@ pub fn convert_message_to_resp(
             body,
             message_marks,
             send_time: msg.send_time,
+            edited_at: msg.edited_at,
+            edit_count,
+            reply_to_id: msg.reply_to_id,
+            reply_count,
+            read_count: None,
+            unread_count: None,
         },
         old_msg_id: old_msg_id,
         time_block: msg.time_block,
@@ -547,6 +872,8 @@ fn convert_resp_to_record_for_fetch(msg_resp: MessageResp, uid: String) -> Messa
         login_uid: uid.to_string(),
         send_status: "success".to_string(),
         time_block: msg_resp.time_block,
+        edited_at: msg_resp.message.edited_at,
+        reply_to_id: msg_resp.message.reply_to_id,
     };
 
     let thumbnail_path = extract_thumbnail_path_from_body(&msg_resp.message.body);
@@ -589,27 +916,90 @@ fn inject_thumbnail_path(body: &mut Option<serde_json::Value>, path: Option<&str
     }
 }
 
+/// 发送消息：先写入本地并进入发件箱，由发件箱按房间顺序发送，失败时自动退避重试。
+/// success_channel 在发送成功或最终失败时收到消息，status_channel 收到每次状态变化
 #[tauri::command]
 pub async fn send_msg(
     data: ChatMessageReq,
     state: State<'_, AppData>,
+    app_handle: AppHandle,
     success_channel: Channel<MessageResp>,
     error_channel: Channel<String>,
+    status_channel: Option<Channel<OutboxEvent>>,
 ) -> Result<(), String> {
-    use std::ops::Deref;
-
     // 获取当前登录用户信息
-    let (login_uid, nickname) = {
+    let login_uid = {
         let user_info = state.user_info.lock().await;
-        (user_info.uid.clone(), None) // UserInfo只有uid和token字段，nickname暂时设为None
+        user_info.uid.clone()
     };
 
+    // 同一客户端 ID 重复发送时不重复入库：已发送成功的直接返回服务端消息，仍在发件箱中的只更新回调
+    let db = state.db_conn.as_ref();
+    let server_id = im_message_client_id_repository::find(db, &login_uid, &data.id)
+        .await
+        .map_err(|e| e.to_string())?
+        .and_then(|mapping| mapping.server_id);
+    if let Some(server_id) = server_id {
+        if let Some(record) =
+            im_message_repository::find_message_with_thumbnail(db, &login_uid, &server_id)
+                .await
+                .map_err(|e| e.to_string())?
+        {
+            info!("Message {} was already sent as {}", data.id, server_id);
+            let _ = success_channel.send(convert_message_to_resp(record, Some(data.id.clone())));
+            return Ok(());
+        }
+    }
+    let subscriber = OutboxSubscriber {
+        success_channel: Some(success_channel),
+        error_channel: Some(error_channel),
+        status_channel,
+    };
+    if im_outbox_repository::find(db, &login_uid, &data.id)
+        .await
+        .map_err(|e| e.to_string())?
+        .is_some()
+    {
+        outbox::subscribe(&data.id, subscriber);
+        outbox::wake();
+        return Ok(());
+    }
+
+    let request = serde_json::to_string(&data).map_err(|e| e.to_string())?;
+    let message_record = save_pending_message(&state, &data, &login_uid, Some(&request)).await?;
+
+    outbox::subscribe(&message_record.message.id, subscriber);
+    outbox::emit(
+        &app_handle,
+        OutboxEvent::new(
+            &message_record.message.id,
+            &message_record.message.room_id,
+            OutboxStatus::Queued,
+        ),
+    );
+    outbox::wake();
+
+    Ok(())
+}
+
+/// 发送结果，delivered 为 false 时消息已在本地标记为发送失败
+pub struct DeliveryResult {
+    pub delivered: bool,
+    pub message: MessageResp,
+}
+
+/// 以 pending 状态将待发送的消息写入本地；outbox_request 不为空时在同一事务中加入发件箱
+async fn save_pending_message(
+    state: &AppData,
+    data: &ChatMessageReq,
+    login_uid: &str,
+    outbox_request: Option<&str>,
+) -> Result<MessageWithThumbnail, String> {
+    let nickname = None; // UserInfo只有uid和token字段，nickname暂时设为None
+
     // 生成消息ID
     let current_time = chrono::Utc::now().timestamp_millis();
 
-    // 先克隆data以避免所有权问题
-    let send_data = data.clone();
-
     // 序列化消息体
     let body_json = data
         .body
@@ -620,29 +1010,49 @@ pub async fn send_msg(
     // 创建消息模型
     let message_model = im_message::Model {
         id: data.id.clone(),
-        uid: login_uid.clone(),
+        uid: login_uid.to_string(),
         nickname,
-        room_id: data.room_id.unwrap_or_default(),
+        room_id: data.room_id.clone().unwrap_or_default(),
         message_type: data.msg_type,
         body: body_json,
         message_marks: None,
         send_time: Some(current_time),
         create_time: Some(current_time),
         update_time: Some(current_time),
-        login_uid: login_uid.clone(),
+        login_uid: login_uid.to_string(),
         send_status: "pending".to_string(), // 初始状态为pending
         time_block: None,
+        edited_at: None,
+        reply_to_id: None,
     };
 
-    let mut message_record = MessageWithThumbnail::new(message_model, thumbnail_path);
+    let message_record = MessageWithThumbnail::new(message_model, thumbnail_path);
 
     let write_lock = state.write_lock.clone(); // 克隆全局写锁句柄
-    message_record = run_with_write_lock(write_lock, "send_msg", || {
+    let message_record = run_with_write_lock(write_lock, "send_msg", || {
         let db_conn = state.db_conn.clone(); // 克隆数据库连接供异步使用
-        let mut record = message_record.clone(); // 拷贝消息记录以便闭包内可变
+        let record = message_record.clone(); // 拷贝消息记录以便闭包内使用
+        let outbox_request = outbox_request.map(str::to_string);
         async move {
             let tx = db_conn.begin().await.map_err(CommonError::DatabaseError)?; // 开启事务
-            record = im_message_repository::save_message(&tx, record).await?; // 保存消息
+            im_message_client_id_repository::register(
+                &tx,
+                &record.message.login_uid,
+                &record.message.id,
+                &record.message.room_id,
+            )
+            .await?; // 登记客户端 ID
+            let record = im_message_repository::save_message(&tx, record).await?; // 保存消息
+            if let Some(request) = outbox_request {
+                im_outbox_repository::enqueue(
+                    &tx,
+                    &record.message.login_uid,
+                    &record.message.id,
+                    &record.message.room_id,
+                    &request,
+                )
+                .await?;
+            }
             tx.commit().await.map_err(CommonError::DatabaseError)?; // 提交事务
             Ok(record)
         }
@@ -653,81 +1063,163 @@ pub async fn send_msg(
         "Message saved to local database, ID: {}",
         message_record.message.id.clone()
     );
+    Ok(message_record)
+}
 
-    let msg_id = message_record.message.id.clone();
-
-    // 异步发送到后端接口
-    let db_conn = state.db_conn.clone();
-    let request_client = state.rc.clone();
-    let mut record_for_send = message_record.clone();
-
-    tokio::spawn(async move {
-        // 发送到后端接口
-        let result: Result<Option<MessageResp>, anyhow::Error> = {
-            let mut client = request_client.lock().await;
-            client
-                .im_request(ImUrl::SendMsg, Some(send_data), None::<serde_json::Value>)
-                .await
-        };
+/// 请求服务端发送消息
+pub async fn request_send(
+    request_client: &Mutex<ImRequestClient>,
+    mut send_data: ChatMessageReq,
+) -> Result<MessageResp, CommonError> {
+    if send_data.client_msg_id.is_none() {
+        send_data.client_msg_id = Some(send_data.id.clone());
+    }
+    let mut client = request_client.lock().await;
+    client
+        .im_request::<MessageResp, _, _>(ImUrl::SendMsg, Some(send_data), None::<serde_json::Value>)
+        .await?
+        .ok_or_else(|| CommonError::RequestError("发送消息未返回结果".to_string()))
+}
 
-        let mut id = None;
-
-        // 根据发送结果更新消息状态
-        let status = match result {
-            Ok(Some(mut resp)) => {
-                resp.old_msg_id = Some(msg_id.clone());
-                id = resp.message.id.clone();
-                record_for_send.message.body = resp.message.body.as_ref().and_then(|body| {
-                    if body.is_null() {
-                        None
-                    } else {
-                        serde_json::to_string(body).ok()
-                    }
-                });
-                if let Some(path) = extract_thumbnail_path_from_body(&resp.message.body) {
-                    record_for_send.thumbnail_path = Some(path);
+/// 根据发送结果更新本地消息状态，resp 为空表示发送失败
+pub async fn finish_delivery(
+    db: &DatabaseConnection,
+    login_uid: String,
+    mut record_for_send: MessageWithThumbnail,
+    resp: Option<MessageResp>,
+) -> Result<DeliveryResult, CommonError> {
+    let msg_id = record_for_send.message.id.clone();
+
+    let mut id = None;
+
+    // 根据发送结果更新消息状态
+    let status = match resp {
+        Some(mut resp) => {
+            resp.old_msg_id = Some(msg_id.clone());
+            id = resp.message.id.clone();
+            record_for_send.message.body = resp.message.body.as_ref().and_then(|body| {
+                if body.is_null() {
+                    None
+                } else {
+                    serde_json::to_string(body).ok()
                 }
-                "success"
+            });
+            if let Some(path) = extract_thumbnail_path_from_body(&resp.message.body) {
+                record_for_send.thumbnail_path = Some(path);
             }
-            _ => "fail",
-        };
+            "success"
+        }
+        None => "fail",
+    };
 
-        // 更新消息状态
-        let model = im_message_repository::update_message_status(
-            db_conn.deref(),
-            record_for_send,
-            status,
-            id,
-            login_uid.clone(),
+    // 服务端回推可能已先一步把本地消息改为服务端 ID，先按映射合并再更新状态
+    if let Some(server_id) = &id {
+        im_message_client_id_repository::bind(
+            db,
+            &login_uid,
+            &msg_id,
+            server_id,
+            &record_for_send.message.room_id,
         )
-        .await;
+        .await?;
+        record_for_send.message.id = server_id.clone();
+    }
 
-        match model {
-            Ok(model) => {
-                let resp = convert_message_to_resp(model, Some(msg_id));
-                success_channel.send(resp).unwrap();
-            }
-            Err(e) => {
-                error!("{:?}", e);
-                error_channel.send(msg_id.clone()).unwrap();
-            }
+    // 更新消息状态
+    let model =
+        im_message_repository::update_message_status(db, record_for_send, status, id, login_uid)
+            .await?;
+
+    Ok(DeliveryResult {
+        delivered: status == "success",
+        message: convert_message_to_resp(model, Some(msg_id)),
+    })
+}
+
+/// 后台任务（如定时发送）将消息加入发件箱，与 send_msg 共用同一队列以保证同一房间内的发送顺序；
+/// 发送结果通过发件箱事件通知。同一 ID 之前发送失败留在发件箱中的记录会被新的请求替换
+pub async fn enqueue_message(
+    state: &AppData,
+    app_handle: &AppHandle,
+    data: ChatMessageReq,
+) -> Result<(), String> {
+    let login_uid = state.user_info.lock().await.uid.clone();
+    if login_uid.is_empty() {
+        return Err("用户未登录".to_string());
+    }
+    let db = state.db_conn.as_ref();
+    if im_outbox_repository::find(db, &login_uid, &data.id)
+        .await
+        .map_err(|e| e.to_string())?
+        .is_some()
+    {
+        let _guard = state.write_lock.lock().await;
+        let removed = im_outbox_repository::remove(
+            db,
+            &login_uid,
+            &data.id,
+            &[im_outbox_repository::STATUS_FAILED],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+        if !removed {
+            return Err("消息正在发送队列中".to_string());
         }
-    });
+    }
 
+    let request = serde_json::to_string(&data).map_err(|e| e.to_string())?;
+    let record = save_pending_message(state, &data, &login_uid, Some(&request)).await?;
+    outbox::emit(
+        app_handle,
+        OutboxEvent::new(
+            &record.message.id,
+            &record.message.room_id,
+            OutboxStatus::Queued,
+        ),
+    );
+    outbox::wake();
     Ok(())
 }
 
 #[tauri::command]
-pub async fn save_msg(data: MessageResp, state: State<'_, AppData>) -> Result<(), String> {
+pub async fn save_msg(
+    data: MessageResp,
+    app_handle: AppHandle,
+    state: State<'_, AppData>,
+) -> Result<(), String> {
+    let login_uid = state.user_info.lock().await.uid.clone();
+    // 预览只是附加信息，失败不影响消息保存
+    if let Err(e) =
+        link_preview::prefetch_previews(&app_handle, state.db_conn.deref(), &login_uid, &data).await
+    {
+        warn!("Failed to prefetch link previews: {}", e);
+    }
+    // 自己发送的消息的服务端回推带有客户端 ID，可能先于发送结果到达
+    let client_id = data
+        .old_msg_id
+        .clone()
+        .filter(|_| data.from_user.uid == login_uid);
+
     // 创建 im_message::Model
-    let record = convert_resp_to_record_for_fetch(data, state.user_info.lock().await.uid.clone());
+    let record = convert_resp_to_record_for_fetch(data, login_uid);
 
     let lock = state.write_lock.clone();
     run_with_write_lock(lock, "save_msg", || {
         let db_conn = state.db_conn.clone();
         let record = record.clone();
+        let client_id = client_id.clone();
         async move {
             let tx = db_conn.begin().await?;
+            if let Some(client_id) = client_id {
+                im_message_client_id_repository::bind(
+                    &tx,
+                    &record.message.login_uid,
+                    &client_id,
+                    &record.message.id,
+                    &record.message.room_id,
+                )
+                .await?;
+            }
             im_message_repository::save_message(&tx, record).await?;
             tx.commit().await?;
             Ok(())
@@ -762,6 +1254,244 @@ pub async fn update_message_recall_status(
 
     Ok(())
 }
+/// 编辑消息：先在本地更新（乐观更新）并立即返回，再提交到服务端；
+/// 服务端确认后通过 success_channel 推送，失败时回滚本地编辑并通过 error_channel 推送消息 ID。
+/// 需要在 backend.message_edit 中开启
+#[tauri::command]
+pub async fn edit_message(
+    message_id: String,
+    body: serde_json::Value,
+    state: State<'_, AppData>,
+    success_channel: Channel<MessageResp>,
+    error_channel: Channel<String>,
+) -> Result<MessageResp, String> {
+    if !state.config.lock().await.backend.message_edit {
+        return Err("服务端暂不支持编辑消息".to_string());
+    }
+    let login_uid = state.user_info.lock().await.uid.clone();
+    let message = im_message::Entity::find_by_id((message_id.clone(), login_uid.clone()))
+        .one(state.db_conn.deref())
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "消息不存在".to_string())?;
+    if message.uid != login_uid {
+        return Err("只能编辑自己发送的消息".to_string());
+    }
+    if message.send_status != "success" || message.message_type == Some(2) {
+        return Err("该消息不支持编辑".to_string());
+    }
+
+    let body_json = serde_json::to_string(&body).map_err(|e| e.to_string())?;
+    let edited_at = chrono::Utc::now().timestamp_millis();
+    let applied = run_with_write_lock(state.write_lock.clone(), "edit_message", || {
+        let db_conn = state.db_conn.clone();
+        let (login_uid, message_id, room_id, body_json) = (
+            login_uid.clone(),
+            message_id.clone(),
+            message.room_id.clone(),
+            body_json.clone(),
+        );
+        async move {
+            let tx = db_conn.begin().await?;
+            let applied = im_message_revision_repository::apply_edit(
+                &tx,
+                &login_uid,
+                &message_id,
+                &login_uid,
+                &room_id,
+                &body_json,
+                edited_at,
+            )
+            .await?;
+            tx.commit().await?;
+            Ok(applied)
+        }
+    })
+    .await?
+    .ok_or_else(|| "消息不存在".to_string())?;
+
+    let mut record = MessageWithThumbnail::from(applied.message);
+    record.edit_count = applied.edit_count;
+    let resp = convert_message_to_resp(record, None);
+    info!(
+        "Message {} edited locally, revision: {:?}",
+        message_id, applied.revision
+    );
+
+    let db_conn = state.db_conn.clone();
+    let request_client = state.rc.clone();
+    let write_lock = state.write_lock.clone();
+    let confirmed = resp.clone();
+    let req = EditMessageReq {
+        msg_id: message_id.clone(),
+        room_id: message.room_id,
+        body,
+    };
+    tokio::spawn(async move {
+        let result: Result<Option<serde_json::Value>, anyhow::Error> = {
+            let mut client = request_client.lock().await;
+            client
+                .im_request(ImUrl::EditMsg, Some(req), None::<serde_json::Value>)
+                .await
+        };
+
+        match result {
+            Ok(_) => {
+                if let Err(e) = success_channel.send(confirmed) {
+                    error!("Failed to send edit confirmation: {}", e);
+                }
+            }
+            Err(e) => {
+                error!("Failed to edit message {} on server: {}", message_id, e);
+                if let Some(revision) = applied.revision {
+                    let reverted = run_with_write_lock(write_lock, "revert_edit_message", || {
+                        let db_conn = db_conn.clone();
+                        let (login_uid, message_id) = (login_uid.clone(), message_id.clone());
+                        async move {
+                            im_message_revision_repository::revert_edit(
+                                db_conn.deref(),
+                                &login_uid,
+                                &message_id,
+                                revision,
+                            )
+                            .await
+                        }
+                    })
+                    .await;
+                    if let Err(e) = reverted {
+                        error!(
+                            "Failed to revert local edit of message {}: {}",
+                            message_id, e
+                        );
+                    }
+                }
+                if let Err(e) = error_channel.send(message_id) {
+                    error!("Failed to send edit error: {}", e);
+                }
+            }
+        }
+    });
+
+    Ok(resp)
+}
+
+/// 查询消息的编辑记录
+#[tauri::command]
+pub async fn get_message_revisions(
+    message_id: String,
+    state: State<'_, AppData>,
+) -> Result<Vec<im_message_revision::Model>, String> {
+    let login_uid = state.user_info.lock().await.uid.clone();
+    im_message_revision_repository::list_revisions(state.db_conn.deref(), &login_uid, &message_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 线程中最多返回的消息数
+const MESSAGE_THREAD_LIMIT: u64 = 500;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageThreadResp {
+    /// 线程根消息 ID（本地存在的最早一条被回复消息）
+    pub root_id: String,
+    pub messages: Vec<MessageResp>,
+    /// 超过 MESSAGE_THREAD_LIMIT 条时为 true
+    pub truncated: bool,
+}
+
+/// 查询消息所在的回复线程：先沿回复链找到根消息，再返回根消息下的全部回复
+#[tauri::command]
+pub async fn get_message_thread(
+    message_id: String,
+    state: State<'_, AppData>,
+) -> Result<MessageThreadResp, String> {
+    let login_uid = state.user_info.lock().await.uid.clone();
+    let db = state.db_conn.deref();
+    let root_id = im_message_thread_repository::find_thread_root(db, &login_uid, &message_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "消息不存在".to_string())?;
+    let mut records = im_message_thread_repository::list_thread(
+        db,
+        &login_uid,
+        &root_id,
+        MESSAGE_THREAD_LIMIT + 1,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let truncated = records.len() as u64 > MESSAGE_THREAD_LIMIT;
+    records.truncate(MESSAGE_THREAD_LIMIT as usize);
+
+    Ok(MessageThreadResp {
+        root_id,
+        messages: records
+            .into_iter()
+            .map(|record| convert_message_to_resp(record, None))
+            .collect(),
+        truncated,
+    })
+}
+
+/// WebSocket 推送的消息编辑事件
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageEditEvent {
+    pub msg_id: String,
+    /// 编辑者，只有与消息发送者一致时才应用
+    pub uid: String,
+    pub room_id: String,
+    pub body: serde_json::Value,
+    pub edited_at: Option<i64>,
+}
+
+/// 将其他端的编辑同步到本地，返回是否已应用；消息不在本地、发送者或房间不一致，
+/// 以及未开启 backend.message_edit 时忽略
+pub async fn apply_message_edit_event(
+    state: &AppData,
+    event: MessageEditEvent,
+) -> Result<bool, CommonError> {
+    if !state.config.lock().await.backend.message_edit {
+        warn!("Ignore message edit event, message editing is disabled");
+        return Ok(false);
+    }
+    let login_uid = state.user_info.lock().await.uid.clone();
+    if login_uid.is_empty() {
+        return Ok(false);
+    }
+    let body_json = serde_json::to_string(&event.body)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize edited body: {}", e))?;
+    let edited_at = event
+        .edited_at
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+
+    let _guard = state.write_lock.lock().await;
+    let tx = state.db_conn.begin().await?;
+    let applied = im_message_revision_repository::apply_edit(
+        &tx,
+        &login_uid,
+        &event.msg_id,
+        &event.uid,
+        &event.room_id,
+        &body_json,
+        edited_at,
+    )
+    .await?;
+    tx.commit().await?;
+    let Some(applied) = applied else {
+        warn!(
+            "Ignore edit of unknown or mismatched message {}",
+            event.msg_id
+        );
+        return Ok(false);
+    };
+    debug!(
+        "Applied remote edit of message {}, revision: {:?}",
+        event.msg_id, applied.revision
+    );
+    Ok(true)
+}
+
 #[tauri::command]
 pub async fn delete_message(
     message_id: String,
@@ -861,3 +1591,98 @@ pub async fn delete_room_messages(
 
     Ok(affected_rows)
 }
+
+#[cfg(debug_assertions)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageWriteBenchmark {
+    pub message_count: usize,
+    /// 全部为新消息时的写入耗时
+    pub insert_ms: u64,
+    /// 重复同步相同消息（无变化）时的耗时
+    pub resync_ms: u64,
+    /// 全部消息内容变化时的耗时
+    pub update_ms: u64,
+    /// 新消息写入吞吐（条/秒）
+    pub insert_per_sec: f64,
+}
+
+/// 调试命令：测量批量写入消息的吞吐，数据在事务中写入后回滚，不影响本地数据
+///
+/// 运行期间持有全局写锁，会阻塞其他写入，只在调试构建中提供
+#[cfg(debug_assertions)]
+#[tauri::command]
+pub async fn debug_benchmark_message_write(
+    count: Option<u32>,
+    state: State<'_, AppData>,
+) -> Result<MessageWriteBenchmark, String> {
+    let count = count.unwrap_or(10_000).clamp(1, 100_000) as usize;
+    let login_uid = state.user_info.lock().await.uid.clone();
+
+    let build_messages = |body_suffix: &str| -> Vec<MessageWithThumbnail> {
+        let now = chrono::Utc::now().timestamp_millis();
+        (0..count)
+            .map(|i| {
+                MessageWithThumbnail::from(im_message::Model {
+                    id: format!("{}", 9_000_000_000_000_000_000i64 + i as i64),
+                    uid: login_uid.clone(),
+                    nickname: None,
+                    room_id: "__benchmark__".to_string(),
+                    send_time: Some(now + i as i64),
+                    message_type: Some(1),
+                    body: Some(
+                        serde_json::json!({ "content": format!("benchmark message {} {}", i, body_suffix) })
+                            .to_string(),
+                    ),
+                    message_marks: None,
+                    create_time: Some(now),
+                    update_time: Some(now),
+                    login_uid: login_uid.clone(),
+                    send_status: "success".to_string(),
+                    time_block: None,
+                    edited_at: None,
+                    reply_to_id: None,
+                })
+            })
+            .collect()
+    };
+    let initial = build_messages("v1");
+    let changed = build_messages("v2");
+
+    let result = run_with_write_lock(state.write_lock.clone(), "benchmark_message_write", || {
+        let db_conn = state.db_conn.clone();
+        let initial = initial.clone();
+        let changed = changed.clone();
+        async move {
+            let tx = db_conn.begin().await.map_err(CommonError::DatabaseError)?;
+
+            let started = std::time::Instant::now();
+            im_message_repository::save_all(&tx, initial.clone()).await?;
+            let insert = started.elapsed();
+
+            let started = std::time::Instant::now();
+            im_message_repository::save_all(&tx, initial).await?;
+            let resync = started.elapsed();
+
+            let started = std::time::Instant::now();
+            im_message_repository::save_all(&tx, changed).await?;
+            let update = started.elapsed();
+
+            // 回滚，基准数据不落库
+            tx.rollback().await.map_err(CommonError::DatabaseError)?;
+            Ok((insert, resync, update))
+        }
+    })
+    .await?;
+
+    let (insert, resync, update) = result;
+    let benchmark = MessageWriteBenchmark {
+        message_count: count,
+        insert_ms: insert.as_millis() as u64,
+        resync_ms: resync.as_millis() as u64,
+        update_ms: update.as_millis() as u64,
+        insert_per_sec: count as f64 / insert.as_secs_f64().max(f64::EPSILON),
+    };
+    info!("Message write benchmark: {:?}", benchmark);
+    Ok(benchmark)
+}
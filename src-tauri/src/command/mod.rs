@@ -4,17 +4,39 @@ use crate::AppData;
 
 pub mod ai_command;
 pub mod app_state_command;
+pub mod backup_command;
 pub mod chat_history_command;
 pub mod contact_command;
+pub mod draft_command;
+pub mod export_command;
+pub mod favorite_command;
 pub mod file_manager_command;
+pub mod import_command;
+pub mod link_preview_command;
 pub mod markdown_command;
+pub mod mention_command;
 pub mod message_command;
 pub mod message_mark_command;
+pub mod outbox_command;
+pub mod read_receipt_command;
 pub mod request_command;
+pub mod retention_command;
 pub mod room_member_command;
+pub mod scheduled_message_command;
 pub mod setting_command;
+pub mod stats_command;
+pub mod unread_command;
 pub mod user_command;
 
+/// 当前登录用户的 uid，未登录时返回错误
+pub(crate) async fn current_login_uid(state: &AppData) -> Result<String, String> {
+    let login_uid = state.user_info.lock().await.uid.clone();
+    if login_uid.is_empty() {
+        return Err("用户未登录".to_string());
+    }
+    Ok(login_uid)
+}
+
 // A custom task for setting the state of a setup task
 #[tauri::command]
 pub async fn set_complete(
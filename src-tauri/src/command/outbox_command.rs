@@ -0,0 +1,147 @@
+use entity::im_outbox;
+use tauri::{AppHandle, State, ipc::Channel};
+use tracing::info;
+
+use crate::AppData;
+use crate::command::current_login_uid;
+use crate::command::message_command::MessageResp;
+use crate::outbox::{self, OutboxEvent, OutboxStatus, OutboxSubscriber};
+use crate::repository::im_message_repository;
+use crate::repository::im_outbox_repository::{self as repository, STATUS_FAILED, STATUS_QUEUED};
+use crate::vo::vo::ChatMessageReq;
+
+/// 手动重发发送失败的消息，重新排到所在房间的队尾。
+/// 不在发件箱中的失败消息（如升级前发送失败的）根据本地消息重新入队
+#[tauri::command]
+pub async fn resend_message(
+    msg_id: String,
+    success_channel: Option<Channel<MessageResp>>,
+    status_channel: Option<Channel<OutboxEvent>>,
+    app_handle: AppHandle,
+    state: State<'_, AppData>,
+) -> Result<(), String> {
+    let login_uid = current_login_uid(&state).await?;
+    let db = state.db_conn.as_ref();
+
+    let room_id = {
+        let _guard = state.write_lock.lock().await;
+        let message = im_message_repository::find_message_with_thumbnail(db, &login_uid, &msg_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "消息不存在".to_string())?
+            .message;
+
+        match repository::find(db, &login_uid, &msg_id)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            Some(item) if item.status == STATUS_FAILED => {
+                repository::requeue_failed(db, &login_uid, &msg_id)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            Some(_) => return Err("消息正在发送队列中".to_string()),
+            None => {
+                if message.send_status != "fail" {
+                    return Err("只能重发发送失败的消息".to_string());
+                }
+                let request = ChatMessageReq {
+                    id: message.id.clone(),
+                    room_id: Some(message.room_id.clone()),
+                    msg_type: message.message_type,
+                    body: message
+                        .body
+                        .as_deref()
+                        .and_then(|body| serde_json::from_str(body).ok()),
+                    skip: None,
+                    is_temp: None,
+                    is_push_message: None,
+                    client_msg_id: Some(message.id.clone()),
+                };
+                let request = serde_json::to_string(&request).map_err(|e| e.to_string())?;
+                repository::enqueue(db, &login_uid, &msg_id, &message.room_id, &request)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        im_message_repository::update_send_status(db, &login_uid, &msg_id, "pending")
+            .await
+            .map_err(|e| e.to_string())?;
+        message.room_id
+    };
+
+    info!("Requeued message {} for resend", msg_id);
+    outbox::subscribe(
+        &msg_id,
+        OutboxSubscriber {
+            success_channel,
+            error_channel: None,
+            status_channel,
+        },
+    );
+    outbox::emit(
+        &app_handle,
+        OutboxEvent::new(&msg_id, &room_id, OutboxStatus::Queued),
+    );
+    outbox::wake();
+    Ok(())
+}
+
+/// 取消尚未发送或发送失败的消息，同时删除本地消息；正在发送的消息不能取消
+#[tauri::command]
+pub async fn cancel_outbox_message(
+    msg_id: String,
+    app_handle: AppHandle,
+    state: State<'_, AppData>,
+) -> Result<(), String> {
+    let login_uid = current_login_uid(&state).await?;
+    let db = state.db_conn.as_ref();
+
+    let room_id = {
+        let _guard = state.write_lock.lock().await;
+        let message = im_message_repository::find_message_with_thumbnail(db, &login_uid, &msg_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .map(|record| record.message);
+        let in_outbox = repository::find(db, &login_uid, &msg_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .is_some();
+
+        if in_outbox {
+            let removed =
+                repository::remove(db, &login_uid, &msg_id, &[STATUS_QUEUED, STATUS_FAILED])
+                    .await
+                    .map_err(|e| e.to_string())?;
+            if !removed {
+                return Err("消息正在发送，无法取消".to_string());
+            }
+        } else if !message.as_ref().is_some_and(|m| m.send_status == "fail") {
+            return Err("只能取消未发送或发送失败的消息".to_string());
+        }
+
+        im_message_repository::delete_message_by_id(db, &msg_id, &login_uid)
+            .await
+            .map_err(|e| e.to_string())?;
+        message.map(|m| m.room_id).unwrap_or_default()
+    };
+
+    info!("Cancelled outbox message {}", msg_id);
+    outbox::emit(
+        &app_handle,
+        OutboxEvent::new(&msg_id, &room_id, OutboxStatus::Cancelled),
+    );
+    Ok(())
+}
+
+/// 按入队顺序列出发件箱中排队和发送失败的消息
+#[tauri::command]
+pub async fn list_outbox(
+    room_id: Option<String>,
+    state: State<'_, AppData>,
+) -> Result<Vec<im_outbox::Model>, String> {
+    let login_uid = current_login_uid(&state).await?;
+    repository::list(state.db_conn.as_ref(), &login_uid, room_id.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
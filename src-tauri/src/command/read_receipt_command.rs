@@ -0,0 +1,294 @@
+use entity::im_message_read_receipt;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::ops::Deref;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tracing::{debug, error, warn};
+
+use crate::AppData;
+use crate::command::message_command::MessageResp;
+use crate::error::CommonError;
+use crate::im_request_client::ImUrl;
+use crate::repository::im_message_read_receipt_repository::{self, ReadCount};
+
+/// 同一条消息的已读人数最短刷新间隔
+const READ_COUNT_REFRESH_INTERVAL_MS: i64 = 30_000;
+/// 只在后台刷新最近发送的消息
+const READ_COUNT_RECENT_WINDOW_MS: i64 = 3 * 24 * 60 * 60 * 1000;
+/// 已读成员列表的查询类型：1 已读，2 未读
+const READ_LIST_SEARCH_TYPE_READ: i64 = 1;
+
+/// 正在后台刷新的消息，避免翻页时重复请求
+static REFRESHING: Lazy<std::sync::Mutex<HashSet<String>>> =
+    Lazy::new(|| std::sync::Mutex::new(HashSet::new()));
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MsgReadCountResp {
+    pub msg_id: String,
+    #[serde(flatten)]
+    pub count: ReadCount,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReadReceiptsResp {
+    pub msg_id: String,
+    pub count: Option<ReadCount>,
+    pub read_uids: Vec<String>,
+    pub unread_uids: Vec<String>,
+}
+
+/// 服务端的 ID 可能是字符串或数字
+fn value_to_id(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn parse_read_counts(data: &Value) -> Vec<(String, ReadCount)> {
+    data.as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    let msg_id = value_to_id(item.get("msgId")?)?;
+                    let read_count = item.get("readCount").and_then(Value::as_u64).unwrap_or(0);
+                    let unread_count = item.get("unReadCount").and_then(Value::as_u64);
+                    Some((
+                        msg_id,
+                        ReadCount {
+                            read_count: read_count as u32,
+                            unread_count: unread_count.map(|c| c as u32),
+                        },
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 缓存 GetMsgReadCount 的返回结果
+pub async fn record_read_counts(state: &AppData, data: &Value) -> Result<(), CommonError> {
+    let counts = parse_read_counts(data);
+    if counts.is_empty() {
+        return Ok(());
+    }
+    let login_uid = state.user_info.lock().await.uid.clone();
+    let _guard = state.write_lock.lock().await;
+    im_message_read_receipt_repository::save_counts(state.db_conn.deref(), &login_uid, &counts)
+        .await
+}
+
+/// 缓存 GetMsgReadList 返回的一页已读或未读成员
+pub async fn record_read_list(
+    state: &AppData,
+    params: Option<&Value>,
+    data: &Value,
+) -> Result<(), CommonError> {
+    let Some(msg_id) = params.and_then(|p| p.get("msgId")).and_then(value_to_id) else {
+        return Ok(());
+    };
+    let is_read = params
+        .and_then(|p| p.get("searchType"))
+        .and_then(|v| v.as_i64().or_else(|| v.as_str()?.parse().ok()))
+        .is_none_or(|t| t == READ_LIST_SEARCH_TYPE_READ);
+    let reader_uids: Vec<String> = data
+        .get("list")
+        .and_then(Value::as_array)
+        .map(|list| {
+            list.iter()
+                .filter_map(|item| value_to_id(item.get("uid")?))
+                .collect()
+        })
+        .unwrap_or_default();
+    if reader_uids.is_empty() {
+        return Ok(());
+    }
+
+    let login_uid = state.user_info.lock().await.uid.clone();
+    let _guard = state.write_lock.lock().await;
+    im_message_read_receipt_repository::save_receipts(
+        state.db_conn.deref(),
+        &login_uid,
+        &msg_id,
+        &reader_uids,
+        is_read,
+    )
+    .await
+}
+
+/// 为自己发送的消息附加已缓存的已读人数，并在后台刷新其中较新且已过期的部分
+pub async fn attach_read_counts(
+    app_handle: &AppHandle,
+    state: &AppData,
+    login_uid: &str,
+    messages: &mut [MessageResp],
+) -> Result<(), CommonError> {
+    let own_ids: Vec<String> = messages
+        .iter()
+        .filter(|m| m.from_user.uid == login_uid)
+        .filter_map(|m| m.message.id.clone())
+        .collect();
+    if own_ids.is_empty() {
+        return Ok(());
+    }
+
+    let counts =
+        im_message_read_receipt_repository::load_counts(state.db_conn.deref(), login_uid, &own_ids)
+            .await?;
+    for message in messages.iter_mut() {
+        if let Some(count) = message.message.id.as_ref().and_then(|id| counts.get(id)) {
+            message.message.read_count = Some(count.read_count);
+            message.message.unread_count = count.unread_count;
+        }
+    }
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let recent_ids: Vec<String> = messages
+        .iter()
+        .filter(|m| m.from_user.uid == login_uid)
+        .filter(|m| {
+            m.message
+                .send_time
+                .is_some_and(|t| now - t <= READ_COUNT_RECENT_WINDOW_MS)
+        })
+        .filter_map(|m| m.message.id.clone())
+        .collect();
+    let stale_ids = im_message_read_receipt_repository::stale_message_ids(
+        state.db_conn.deref(),
+        login_uid,
+        &recent_ids,
+        now - READ_COUNT_REFRESH_INTERVAL_MS,
+    )
+    .await?;
+    spawn_read_count_refresh(app_handle.clone(), stale_ids);
+    Ok(())
+}
+
+/// 后台拉取已读人数，写入缓存后通过 msg-read-count-updated 事件通知前端
+fn spawn_read_count_refresh(app_handle: AppHandle, msg_ids: Vec<String>) {
+    let msg_ids: Vec<String> = {
+        let mut refreshing = REFRESHING.lock().unwrap_or_else(|e| e.into_inner());
+        msg_ids
+            .into_iter()
+            .filter(|id| refreshing.insert(id.clone()))
+            .collect()
+    };
+    if msg_ids.is_empty() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let state = app_handle.state::<AppData>();
+        if let Err(e) = refresh_read_counts(&app_handle, &state, &msg_ids).await {
+            warn!("Failed to refresh message read counts: {}", e);
+        }
+        let mut refreshing = REFRESHING.lock().unwrap_or_else(|e| e.into_inner());
+        for id in &msg_ids {
+            refreshing.remove(id);
+        }
+    });
+}
+
+async fn refresh_read_counts(
+    app_handle: &AppHandle,
+    state: &AppData,
+    msg_ids: &[String],
+) -> Result<(), CommonError> {
+    debug!("Refreshing read counts for {} messages", msg_ids.len());
+    let data: Option<Value> = state
+        .rc
+        .lock()
+        .await
+        .im_request(
+            ImUrl::GetMsgReadCount,
+            None::<Value>,
+            Some(json!({ "msgIds": msg_ids })),
+        )
+        .await?;
+    if let Some(data) = &data {
+        record_read_counts(state, data).await?;
+    }
+
+    // 服务端没有返回的消息同样记录获取时间，否则每次翻页都会重新请求
+    let login_uid = state.user_info.lock().await.uid.clone();
+    {
+        let _guard = state.write_lock.lock().await;
+        im_message_read_receipt_repository::mark_fetched(
+            state.db_conn.deref(),
+            &login_uid,
+            msg_ids,
+        )
+        .await?;
+    }
+    let counts =
+        im_message_read_receipt_repository::load_counts(state.db_conn.deref(), &login_uid, msg_ids)
+            .await?;
+    let payload: Vec<MsgReadCountResp> = counts
+        .into_iter()
+        .map(|(msg_id, count)| MsgReadCountResp { msg_id, count })
+        .collect();
+    if !payload.is_empty() {
+        let _ = app_handle.emit("msg-read-count-updated", &payload);
+    }
+    Ok(())
+}
+
+/// 查询消息已缓存的已读人数与已读/未读成员
+#[tauri::command]
+pub async fn get_message_read_receipts(
+    msg_id: String,
+    state: State<'_, AppData>,
+) -> Result<MessageReadReceiptsResp, String> {
+    let login_uid = state.user_info.lock().await.uid.clone();
+    let result: Result<MessageReadReceiptsResp, CommonError> = async {
+        let db = state.db_conn.deref();
+        let count = im_message_read_receipt_repository::load_counts(
+            db,
+            &login_uid,
+            std::slice::from_ref(&msg_id),
+        )
+        .await?
+        .remove(&msg_id);
+        let (read, unread): (Vec<im_message_read_receipt::Model>, Vec<_>) =
+            im_message_read_receipt_repository::list_receipts(db, &login_uid, &msg_id)
+                .await?
+                .into_iter()
+                .partition(|r| r.is_read);
+        Ok(MessageReadReceiptsResp {
+            msg_id: msg_id.clone(),
+            count,
+            read_uids: read.into_iter().map(|r| r.reader_uid).collect(),
+            unread_uids: unread.into_iter().map(|r| r.reader_uid).collect(),
+        })
+    }
+    .await;
+
+    result.map_err(|e| {
+        error!("Failed to get read receipts for message {}: {}", msg_id, e);
+        e.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_read_counts() {
+        let counts = parse_read_counts(&json!([
+            { "msgId": "1", "readCount": 2, "unReadCount": 3 },
+            { "msgId": 2, "readCount": 1, "unReadCount": null },
+            { "readCount": 1 }
+        ]));
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[0].1.unread_count, Some(3));
+        assert_eq!(counts[1].0, "2");
+        assert_eq!(counts[1].1.unread_count, None);
+    }
+}
@@ -4,9 +4,11 @@ use tracing::{error, info};
 
 use crate::{
     AppData,
-    command::message_command::check_user_init_and_fetch_messages,
+    command::{message_command::check_user_init_and_fetch_messages, read_receipt_command},
     im_request_client::{ImRequest, ImUrl},
-    repository::im_user_repository,
+    repository::{
+        im_message_mention_repository, im_room_read_cursor_repository, im_user_repository,
+    },
     vo::vo::{LoginReq, LoginResp, RefreshTokenReq},
 };
 
@@ -15,6 +17,11 @@ pub async fn login_command(
     data: LoginReq,
     state: State<'_, AppData>,
 ) -> Result<Option<LoginResp>, String> {
+    login(data, &state).await
+}
+
+/// 登录并保存 token，随后拉取消息；与 Tauri 运行时解耦，便于在测试中直接调用
+pub(crate) async fn login(data: LoginReq, state: &AppData) -> Result<Option<LoginResp>, String> {
     if data.is_auto_login {
         // 自动登录逻辑
         if let Some(uid) = &data.uid {
@@ -63,7 +70,7 @@ pub async fn login_command(
                                 uid: refresh_resp.uid,
                             };
 
-                            handle_login_success(&login_resp, &state, data.async_data).await?;
+                            handle_login_success(&login_resp, state, data.async_data).await?;
 
                             return Ok(Some(login_resp));
                         }
@@ -99,7 +106,7 @@ pub async fn login_command(
 
         // 登录成功后处理用户信息和token保存
         if let Some(login_resp) = &res {
-            handle_login_success(login_resp, &state, async_data).await?;
+            handle_login_success(login_resp, state, async_data).await?;
         }
 
         info!("Manual login successful");
@@ -109,7 +116,7 @@ pub async fn login_command(
 
 async fn handle_login_success(
     login_resp: &LoginResp,
-    state: &State<'_, AppData>,
+    state: &AppData,
     async_data: bool,
 ) -> Result<(), String> {
     info!("handle_login_success, login_resp: {:?}", login_resp);
@@ -152,11 +159,42 @@ pub async fn im_request_command(
     let mut rc = state.rc.lock().await;
 
     if let Ok(url) = url.parse::<ImUrl>() {
+        // 服务端标记已读成功后，同步前移本地已读游标并清除该房间的未读提及
+        let read_room_id = match url {
+            ImUrl::MarkMsgRead => body
+                .as_ref()
+                .and_then(|b| b.get("roomId"))
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            _ => None,
+        };
+        // 已读人数与已读成员列表写入本地缓存
+        let is_read_count = matches!(url, ImUrl::GetMsgReadCount);
+        let read_list_params = matches!(url, ImUrl::GetMsgReadList).then(|| params.clone());
         let result: Result<Option<serde_json::Value>, anyhow::Error> =
             rc.im_request(url, body, params).await;
 
         match result {
             Ok(data) => {
+                drop(rc);
+                if let Some(room_id) = read_room_id {
+                    sync_room_read(&state, &room_id).await;
+                }
+                if let Some(value) = data.as_ref() {
+                    let recorded = match read_list_params {
+                        Some(params) => {
+                            read_receipt_command::record_read_list(&state, params.as_ref(), value)
+                                .await
+                        }
+                        None if is_read_count => {
+                            read_receipt_command::record_read_counts(&state, value).await
+                        }
+                        None => Ok(()),
+                    };
+                    if let Err(e) = recorded {
+                        error!("Failed to cache message read receipts: {}", e);
+                    }
+                }
                 return Ok(data);
             }
             Err(e) => {
@@ -172,3 +210,16 @@ pub async fn im_request_command(
         return Err(format!("Invalid URL: {}", url));
     }
 }
+
+async fn sync_room_read(state: &State<'_, AppData>, room_id: &str) {
+    let login_uid = state.user_info.lock().await.uid.clone();
+    let _guard = state.write_lock.lock().await;
+    let db = state.db_conn.deref();
+    if let Err(e) = im_room_read_cursor_repository::advance_to_latest(db, &login_uid, room_id).await
+    {
+        error!("Failed to advance read cursor for room {}: {}", room_id, e);
+    }
+    if let Err(e) = im_message_mention_repository::mark_read(db, &login_uid, room_id, None).await {
+        error!("Failed to clear mentions for room {}: {}", room_id, e);
+    }
+}
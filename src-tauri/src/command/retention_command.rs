@@ -0,0 +1,51 @@
+use tauri::{AppHandle, State};
+use tracing::info;
+
+use crate::AppData;
+use crate::command::current_login_uid;
+use crate::retention::{self, PruneReport, RetentionSettings};
+
+/// 获取当前用户的消息保留策略
+#[tauri::command]
+pub async fn get_message_retention(state: State<'_, AppData>) -> Result<RetentionSettings, String> {
+    let login_uid = current_login_uid(&state).await?;
+    retention::load_settings(state.db_conn.as_ref(), &login_uid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 保存当前用户的消息保留策略，下一次清理时生效
+#[tauri::command]
+pub async fn set_message_retention(
+    settings: RetentionSettings,
+    state: State<'_, AppData>,
+) -> Result<(), String> {
+    let login_uid = current_login_uid(&state).await?;
+    info!("update message retention: {:?}", settings);
+    retention::save_settings(state.db_conn.as_ref(), &login_uid, &settings)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 立即按当前策略清理本地消息
+#[tauri::command]
+pub async fn prune_messages_now(
+    app_handle: AppHandle,
+    state: State<'_, AppData>,
+) -> Result<PruneReport, String> {
+    let login_uid = current_login_uid(&state).await?;
+    let settings = retention::load_settings(state.db_conn.as_ref(), &login_uid)
+        .await
+        .map_err(|e| e.to_string())?;
+    let report = retention::run_prune(
+        state.db_conn.as_ref(),
+        &state.write_lock,
+        &login_uid,
+        &settings,
+        &retention::thumbnail_roots(&app_handle),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    info!("Manual message prune finished: {:?}", report);
+    Ok(report)
+}
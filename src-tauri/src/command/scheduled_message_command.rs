@@ -0,0 +1,168 @@
+use chrono::Utc;
+use entity::im_scheduled_message;
+use tauri::State;
+use tracing::info;
+
+use crate::AppData;
+use crate::command::current_login_uid;
+use crate::repository::im_scheduled_message_repository::{
+    self as repository, EDITABLE_STATUSES, STATUS_CANCELLED, STATUS_PENDING,
+};
+use crate::scheduled_message::{self, ScheduledMessageResp, ScheduledMessageSettings};
+use crate::vo::vo::ChatMessageReq;
+
+fn validate(data: &ChatMessageReq, send_at: i64) -> Result<(String, String), String> {
+    let room_id = data
+        .room_id
+        .clone()
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| "定时消息缺少房间 ID".to_string())?;
+    if send_at <= Utc::now().timestamp_millis() {
+        return Err("发送时间必须晚于当前时间".to_string());
+    }
+    let request = serde_json::to_string(data).map_err(|e| e.to_string())?;
+    Ok((room_id, request))
+}
+
+/// 新建定时消息，data 与 send_msg 的参数相同，data.id 作为定时消息 ID
+#[tauri::command]
+pub async fn schedule_message(
+    data: ChatMessageReq,
+    send_at: i64,
+    state: State<'_, AppData>,
+) -> Result<ScheduledMessageResp, String> {
+    let login_uid = current_login_uid(&state).await?;
+    let (room_id, request) = validate(&data, send_at)?;
+    let now = Utc::now().timestamp_millis();
+    let model = im_scheduled_message::Model {
+        id: data.id.clone(),
+        login_uid,
+        room_id,
+        request,
+        send_at,
+        status: STATUS_PENDING.to_string(),
+        sent_msg_id: None,
+        error: None,
+        create_time: now,
+        update_time: now,
+    };
+    {
+        let _guard = state.write_lock.lock().await;
+        repository::insert(state.db_conn.as_ref(), model.clone())
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    info!("Scheduled message {} at {}", model.id, send_at);
+    scheduled_message::wake_scheduler();
+    Ok(model.into())
+}
+
+/// 列出定时消息，默认不包含已发送和已取消的
+#[tauri::command]
+pub async fn list_scheduled_messages(
+    room_id: Option<String>,
+    include_finished: Option<bool>,
+    state: State<'_, AppData>,
+) -> Result<Vec<ScheduledMessageResp>, String> {
+    let login_uid = current_login_uid(&state).await?;
+    let list = repository::list(
+        state.db_conn.as_ref(),
+        &login_uid,
+        room_id.as_deref(),
+        include_finished.unwrap_or(false),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(list.into_iter().map(ScheduledMessageResp::from).collect())
+}
+
+/// 修改定时消息的内容或发送时间；发送失败或错过的消息修改后重新进入待发送状态
+#[tauri::command]
+pub async fn update_scheduled_message(
+    id: String,
+    data: Option<ChatMessageReq>,
+    send_at: Option<i64>,
+    state: State<'_, AppData>,
+) -> Result<ScheduledMessageResp, String> {
+    let login_uid = current_login_uid(&state).await?;
+    let db = state.db_conn.as_ref();
+    let existing = repository::find(db, &login_uid, &id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "定时消息不存在".to_string())?;
+    if !EDITABLE_STATUSES.contains(&existing.status.as_str()) {
+        return Err("定时消息已发送或已取消，无法修改".to_string());
+    }
+
+    let mut data = match data {
+        Some(data) => data,
+        None => serde_json::from_str(&existing.request).map_err(|e| e.to_string())?,
+    };
+    // 定时消息 ID 不随内容变化
+    data.id = id.clone();
+    let send_at = send_at.unwrap_or(existing.send_at);
+    let (room_id, request) = validate(&data, send_at)?;
+
+    let updated = {
+        let _guard = state.write_lock.lock().await;
+        repository::reschedule(db, &login_uid, &id, &request, &room_id, send_at)
+            .await
+            .map_err(|e| e.to_string())?
+    };
+    if !updated {
+        return Err("定时消息已发送或已取消，无法修改".to_string());
+    }
+    scheduled_message::wake_scheduler();
+    repository::find(db, &login_uid, &id)
+        .await
+        .map_err(|e| e.to_string())?
+        .map(ScheduledMessageResp::from)
+        .ok_or_else(|| "定时消息不存在".to_string())
+}
+
+/// 取消尚未发送的定时消息
+#[tauri::command]
+pub async fn cancel_scheduled_message(id: String, state: State<'_, AppData>) -> Result<(), String> {
+    let login_uid = current_login_uid(&state).await?;
+    let cancelled = {
+        let _guard = state.write_lock.lock().await;
+        repository::transition(
+            state.db_conn.as_ref(),
+            &login_uid,
+            &id,
+            &EDITABLE_STATUSES,
+            STATUS_CANCELLED,
+            None,
+            None,
+        )
+        .await
+        .map_err(|e| e.to_string())?
+    };
+    if !cancelled {
+        return Err("定时消息已发送或已取消".to_string());
+    }
+    info!("Cancelled scheduled message {}", id);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_scheduled_message_settings(
+    state: State<'_, AppData>,
+) -> Result<ScheduledMessageSettings, String> {
+    let login_uid = current_login_uid(&state).await?;
+    scheduled_message::load_settings(state.db_conn.as_ref(), &login_uid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_scheduled_message_settings(
+    settings: ScheduledMessageSettings,
+    state: State<'_, AppData>,
+) -> Result<(), String> {
+    let login_uid = current_login_uid(&state).await?;
+    info!("update scheduled message settings: {:?}", settings);
+    scheduled_message::save_settings(state.db_conn.as_ref(), &login_uid, &settings)
+        .await
+        .map_err(|e| e.to_string())
+}
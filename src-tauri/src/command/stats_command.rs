@@ -0,0 +1,163 @@
+use moka::future::Cache;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::State;
+use tracing::debug;
+
+use crate::AppData;
+use crate::error::CommonError;
+use crate::repository::im_message_stats_repository::{
+    self as repository, ActivityCell, MessageTypeStat, RoomStat, SenderStat, StatsScope,
+};
+
+/// 图片、文件、语音、视频计入媒体统计
+const MEDIA_MESSAGE_TYPES: [u8; 4] = [3, 4, 5, 6];
+const DEFAULT_TOP_LIMIT: u32 = 10;
+const MAX_TOP_LIMIT: u32 = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct StatsCacheKey {
+    login_uid: String,
+    scope: StatsScope,
+    top_limit: u32,
+}
+
+/// 缓存的统计结果及计算时的数据版本号，版本号变化即视为失效
+static STATS_CACHE: Lazy<Cache<StatsCacheKey, (i64, Arc<ConversationStats>)>> = Lazy::new(|| {
+    Cache::builder()
+        .max_capacity(256)
+        .time_to_idle(Duration::from_secs(30 * 60))
+        .build()
+});
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationStatsParam {
+    /// 为空时统计所有房间
+    pub room_id: Option<String>,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    /// 发言排行和房间排行返回的条数
+    pub top_limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaStats {
+    pub count: u64,
+    pub total_size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationStats {
+    pub room_id: Option<String>,
+    pub total_messages: u64,
+    pub first_send_time: Option<i64>,
+    pub last_send_time: Option<i64>,
+    pub by_type: Vec<MessageTypeStat>,
+    pub media: MediaStats,
+    pub top_senders: Vec<SenderStat>,
+    /// 按本地时间的星期（0 为周日）和小时统计
+    pub activity: Vec<ActivityCell>,
+    /// 消息数最多的房间，仅统计所有房间时返回
+    pub top_rooms: Vec<RoomStat>,
+}
+
+async fn compute_stats(
+    state: &State<'_, AppData>,
+    key: &StatsCacheKey,
+) -> Result<ConversationStats, CommonError> {
+    let db = state.db_conn.deref();
+    let login_uid = key.login_uid.as_str();
+    let scope = &key.scope;
+
+    let by_type = repository::count_by_type(db, login_uid, scope).await?;
+    let top_senders = repository::top_senders(db, login_uid, scope, key.top_limit).await?;
+    let activity = repository::activity_heatmap(db, login_uid, scope).await?;
+    let top_rooms = match scope.room_id {
+        Some(_) => Vec::new(),
+        None => repository::top_rooms(db, login_uid, scope, key.top_limit).await?,
+    };
+
+    let media = by_type
+        .iter()
+        .filter(|stat| {
+            stat.message_type
+                .is_some_and(|t| MEDIA_MESSAGE_TYPES.contains(&t))
+        })
+        .fold(
+            MediaStats {
+                count: 0,
+                total_size: 0,
+            },
+            |acc, stat| MediaStats {
+                count: acc.count + stat.count,
+                total_size: acc.total_size + stat.total_size,
+            },
+        );
+
+    Ok(ConversationStats {
+        room_id: scope.room_id.clone(),
+        total_messages: by_type.iter().map(|stat| stat.count).sum(),
+        first_send_time: by_type.iter().filter_map(|stat| stat.first_send_time).min(),
+        last_send_time: by_type.iter().filter_map(|stat| stat.last_send_time).max(),
+        by_type,
+        media,
+        top_senders,
+        activity,
+        top_rooms,
+    })
+}
+
+/// 会话统计：各类型消息数、发言排行、活跃时段和媒体用量。
+/// 结果按数据版本号缓存，房间内有消息写入后下次查询重新计算
+#[tauri::command]
+pub async fn get_conversation_stats(
+    param: Option<ConversationStatsParam>,
+    state: State<'_, AppData>,
+) -> Result<ConversationStats, String> {
+    let login_uid = state.user_info.lock().await.uid.clone();
+    if login_uid.is_empty() {
+        return Err("用户未登录".to_string());
+    }
+    let param = param.unwrap_or_default();
+    let key = StatsCacheKey {
+        login_uid,
+        scope: StatsScope {
+            room_id: param.room_id.filter(|room_id| !room_id.is_empty()),
+            start_time: param.start_time,
+            end_time: param.end_time,
+        },
+        top_limit: param
+            .top_limit
+            .unwrap_or(DEFAULT_TOP_LIMIT)
+            .clamp(1, MAX_TOP_LIMIT),
+    };
+
+    // 先读版本号再计算，计算期间的写入会让版本号变化，下次查询不会命中旧结果
+    let version = repository::data_version(
+        state.db_conn.deref(),
+        &key.login_uid,
+        key.scope.room_id.as_deref(),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    if let Some((cached_version, stats)) = STATS_CACHE.get(&key).await {
+        if cached_version == version {
+            debug!("Conversation stats cache hit: {:?}", key.scope);
+            return Ok(stats.as_ref().clone());
+        }
+    }
+
+    let stats = compute_stats(&state, &key)
+        .await
+        .map_err(|e| e.to_string())?;
+    STATS_CACHE
+        .insert(key, (version, Arc::new(stats.clone())))
+        .await;
+    Ok(stats)
+}
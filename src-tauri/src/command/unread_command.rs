@@ -0,0 +1,39 @@
+use std::ops::Deref;
+use tauri::State;
+use tracing::info;
+
+use crate::AppData;
+use crate::command::current_login_uid;
+use crate::repository::im_room_read_cursor_repository::{self, RoomUnreadCount};
+
+/// 按本地已读游标计算所有会话的未读数
+#[tauri::command]
+pub async fn get_room_unread_counts(
+    state: State<'_, AppData>,
+) -> Result<Vec<RoomUnreadCount>, String> {
+    let login_uid = current_login_uid(&state).await?;
+    im_room_read_cursor_repository::list_unread_counts(state.db_conn.deref(), &login_uid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 总未读数（不含免打扰会话），用于托盘角标
+#[tauri::command]
+pub async fn get_total_unread_count(state: State<'_, AppData>) -> Result<u32, String> {
+    let login_uid = current_login_uid(&state).await?;
+    im_room_read_cursor_repository::total_unread(state.db_conn.deref(), &login_uid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 将会话标记为未读，下次标记已读时清除
+#[tauri::command]
+pub async fn mark_room_unread(room_id: String, state: State<'_, AppData>) -> Result<(), String> {
+    let login_uid = current_login_uid(&state).await?;
+    let _guard = state.write_lock.lock().await;
+    im_room_read_cursor_repository::mark_unread(state.db_conn.deref(), &login_uid, &room_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    info!("Marked room {} as unread", room_id);
+    Ok(())
+}
@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+use crate::error::CommonError;
+
+/// 应用自己生成的缩略图目录，备份和保留策略清理都只处理该目录下的文件
+pub fn thumbnail_dir(app_handle: &AppHandle) -> Result<PathBuf, CommonError> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| anyhow::anyhow!("获取 app_data_dir 失败: {}", e))?;
+    Ok(dir.join("thumbnails"))
+}
+
+/// 附件缓存根目录，与前端 PathUtil 中的 userData 目录保持一致
+pub fn user_data_root(app_handle: &AppHandle) -> Option<PathBuf> {
+    let base = if cfg!(mobile) {
+        app_handle.path().app_data_dir()
+    } else {
+        app_handle.path().resource_dir()
+    };
+    base.ok().map(|dir| dir.join("userData"))
+}
@@ -1,3 +1,4 @@
+pub mod app_paths;
 pub mod files_meta;
 pub mod init;
 pub mod sqlcipher;
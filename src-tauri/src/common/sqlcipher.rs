@@ -102,7 +102,7 @@ fn is_plaintext_sqlite_file(db_path: &Path) -> Result<bool, CommonError> {
     Ok(bytes_read >= SQLITE_HEADER.len() && header.starts_with(SQLITE_HEADER))
 }
 
-fn cleanup_sqlite_sidecar_files(db_path: &Path) {
+pub(crate) fn cleanup_sqlite_sidecar_files(db_path: &Path) {
     let Some(file_name) = db_path.file_name().and_then(|v| v.to_str()) else {
         return;
     };
@@ -113,7 +113,7 @@ fn cleanup_sqlite_sidecar_files(db_path: &Path) {
     }
 }
 
-fn escape_sqlite_single_quoted(value: &str) -> String {
+pub(crate) fn escape_sqlite_single_quoted(value: &str) -> String {
     value.replace('\'', "''")
 }
 
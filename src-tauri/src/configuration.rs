@@ -1,5 +1,7 @@
+use crate::backup;
 use crate::common::sqlcipher;
 use crate::error::CommonError;
+use crate::websocket::codec::WireFormat;
 use sea_orm::{ConnectOptions, Database, DatabaseConnection};
 use std::path::PathBuf;
 use std::time::Duration;
@@ -28,6 +30,12 @@ pub struct DatabaseSettings {
 pub struct BackendSettings {
     pub base_url: String,
     pub ws_url: String,
+    /// 服务端支持的 WebSocket 线路格式，按优先级排列；默认为空，不协商子协议
+    #[serde(default)]
+    pub ws_wire_formats: Vec<WireFormat>,
+    /// 服务端是否提供消息编辑（`im/chat/msg/edit` 接口和 `msgEdit` 推送）；默认关闭
+    #[serde(default)]
+    pub message_edit: bool,
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
@@ -68,6 +76,34 @@ pub enum Environment {
 }
 
 impl DatabaseSettings {
+    /// 数据库文件路径
+    /// 桌面开发环境使用项目根目录，其余情况使用 app_data_dir
+    pub fn database_path(&self, app_handle: &AppHandle) -> Result<PathBuf, CommonError> {
+        if cfg!(debug_assertions) && cfg!(desktop) {
+            // 桌面端开发环境：使用项目根目录
+            let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push("db.sqlite");
+            return Ok(path);
+        }
+
+        // SQLite 无法连接 asset://localhost/ 这样的虚拟协议，必须使用真实文件系统路径
+        match app_handle.path().app_data_dir() {
+            Ok(app_data_dir) => {
+                if let Err(create_err) = std::fs::create_dir_all(&app_data_dir) {
+                    tracing::warn!("Failed to create app_data_dir: {}", create_err);
+                }
+                let db_path = app_data_dir.join("db.sqlite");
+                info!("Mobile: Using app_data_dir database path: {:?}", db_path);
+                Ok(db_path)
+            }
+            Err(e) => {
+                let error_msg = format!("Mobile: Failed to get app_data_dir: {}", e);
+                tracing::error!("{}", error_msg);
+                Err(CommonError::RequestError(error_msg))
+            }
+        }
+    }
+
     /// 创建数据库连接
     /// 根据不同的运行环境（桌面开发、移动端、桌面生产）选择合适的数据库路径
     /// 并配置数据库连接选项，返回数据库连接实例
@@ -82,34 +118,13 @@ impl DatabaseSettings {
         &self,
         app_handle: &AppHandle,
     ) -> Result<DatabaseConnection, CommonError> {
-        // 数据库路径配置：
-        let db_path = if cfg!(debug_assertions) && cfg!(desktop) {
-            // 桌面端开发环境：使用项目根目录
-            let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-            path.push("db.sqlite");
-            path
-        } else {
-            // SQLite 无法连接 asset://localhost/ 这样的虚拟协议，必须使用真实文件系统路径
-            match app_handle.path().app_data_dir() {
-                Ok(app_data_dir) => {
-                    if let Err(create_err) = std::fs::create_dir_all(&app_data_dir) {
-                        tracing::warn!("Failed to create app_data_dir: {}", create_err);
-                    }
-                    let db_path = app_data_dir.join("db.sqlite");
-                    info!("Mobile: Using app_data_dir database path: {:?}", db_path);
-                    db_path
-                }
-                Err(e) => {
-                    let error_msg = format!("Mobile: Failed to get app_data_dir: {}", e);
-                    tracing::error!("{}", error_msg);
-                    return Err(CommonError::RequestError(error_msg).into());
-                }
-            }
-        };
+        let db_path = self.database_path(app_handle)?;
         info!("Database path: {:?}", db_path);
 
         // 设备绑定的 SQLCipher 密钥（存储在系统安全存储/Keychain/Keystore）
         let sqlcipher_key = sqlcipher::get_or_create_sqlcipher_key(app_handle).await?;
+        // 上次恢复备份时暂存的数据库，在建立连接前替换
+        backup::apply_staged_restore(&db_path)?;
         // 兼容旧版本明文库：首次启动时自动迁移为加密库
         sqlcipher::ensure_sqlite_encrypted(&db_path, &sqlcipher_key).await?;
 
@@ -370,6 +370,7 @@ pub enum ImUrl {
     DeleteEmoji,
     AddEmoji,
     RecallMsg,
+    EditMsg,
     BlockUser,
     MarkMsg,
     SetUserBadge,
@@ -646,6 +647,7 @@ impl ImUrl {
 
             // 消息相关
             ImUrl::RecallMsg => (http::Method::PUT, "im/chat/msg/recall"),
+            ImUrl::EditMsg => (http::Method::PUT, "im/chat/msg/edit"),
             ImUrl::MarkMsg => (http::Method::PUT, "im/chat/msg/mark"),
             ImUrl::GetMsgPage => (http::Method::GET, "im/chat/msg/page"),
             ImUrl::GetMsgList => (http::Method::POST, "im/chat/msg/list"),
@@ -942,6 +944,7 @@ impl ImUrl {
 
             // 消息相关
             "recallMsg" => Ok(ImUrl::RecallMsg),
+            "editMsg" => Ok(ImUrl::EditMsg),
             "markMsg" => Ok(ImUrl::MarkMsg),
             "getMsgList" => Ok(ImUrl::GetMsgList),
             "getMsgPage" => Ok(ImUrl::GetMsgPage),
@@ -1155,6 +1158,7 @@ mod test {
 
     use crate::{
         im_request_client::{ImRequest, ImRequestClient},
+        mock_server::{MockScenario, MockServer},
         vo::vo::{LoginReq, LoginResp},
     };
     // #[tokio::test]
@@ -1174,18 +1178,22 @@ mod test {
 
     #[tokio::test]
     async fn test_login() -> Result<(), anyhow::Error> {
-        let mut request_client = ImRequestClient::new("http://192.168.1.14:18760".to_string())?;
+        let server = MockServer::start(MockScenario::default()).await?;
+        let mut request_client = ImRequestClient::new(server.base_url.clone())?;
         let login_req = json!({
             "grantType": "PASSWORD",
             "systemType": "2",
             "deviceType": "MOBILE",
-            "account": "ql",
-            "client_id": "testClientId",
-            "password": "123456"
+            "account": "hula",
+            "clientId": "testClientId",
+            "password": "123456",
+            "asyncData": false
         });
         let login_req: LoginReq = serde_json::from_value(login_req)?;
         let result: Option<LoginResp> = request_client.login(login_req).await?;
         println!("{:?}", serde_json::json!(result).to_string());
+        assert_eq!(result.map(|r| r.uid), Some("10001".to_string()));
+        assert!(request_client.token.is_some());
         Ok(())
     }
 }
@@ -26,13 +26,22 @@ use init::DesktopCustomInit;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use tauri_plugin_fs::FsExt;
+pub mod backup;
+pub mod chat_export;
+pub mod chat_import;
 pub mod command;
 pub mod common;
 pub mod configuration;
 pub mod error;
 mod im_request_client;
+pub mod link_preview;
+#[cfg(any(test, feature = "mock-server"))]
+pub mod mock_server;
+pub mod outbox;
 pub mod pojo;
 pub mod repository;
+pub mod retention;
+pub mod scheduled_message;
 pub mod timeout_config;
 pub mod utils;
 mod vo;
@@ -76,16 +85,47 @@ pub struct AppData {
 
 pub(crate) static APP_STATE_READY: AtomicBool = AtomicBool::new(false);
 
-use crate::command::chat_history_command::query_chat_history;
+use crate::command::backup_command::{backup_local_data, restore_local_data};
+use crate::command::chat_history_command::{
+    query_chat_history, search_all_messages, search_chat_messages,
+};
 use crate::command::contact_command::{hide_contact_command, list_contacts_command};
+use crate::command::draft_command::{clear_draft, load_draft, save_draft};
+use crate::command::export_command::{cancel_chat_export, export_chat_history};
+use crate::command::favorite_command::{
+    add_favorite_message, list_favorite_messages, list_favorite_tags, remove_favorite_messages,
+    set_favorite_tags,
+};
 use crate::command::file_manager_command::{
-    debug_message_stats, get_navigation_items, query_files,
+    get_navigation_items, query_files, query_media_gallery,
+};
+use crate::command::import_command::import_chat_history;
+use crate::command::link_preview_command::{
+    get_link_preview_settings, get_link_previews, set_link_preview_settings,
 };
+use crate::command::mention_command::{
+    get_unread_mention_counts, list_unread_mentions, mark_mentions_read,
+};
+#[cfg(debug_assertions)]
+use crate::command::message_command::debug_benchmark_message_write;
 use crate::command::message_command::{
-    delete_message, delete_room_messages, page_msg, save_msg, send_msg, sync_messages,
-    update_message_recall_status,
+    delete_message, delete_room_messages, edit_message, get_message_revisions, get_message_thread,
+    page_msg, page_msg_around, save_msg, send_msg, sync_messages, update_message_recall_status,
 };
 use crate::command::message_mark_command::save_message_mark;
+use crate::command::outbox_command::{cancel_outbox_message, list_outbox, resend_message};
+use crate::command::read_receipt_command::get_message_read_receipts;
+use crate::command::retention_command::{
+    get_message_retention, prune_messages_now, set_message_retention,
+};
+use crate::command::scheduled_message_command::{
+    cancel_scheduled_message, get_scheduled_message_settings, list_scheduled_messages,
+    schedule_message, set_scheduled_message_settings, update_scheduled_message,
+};
+use crate::command::stats_command::get_conversation_stats;
+use crate::command::unread_command::{
+    get_room_unread_counts, get_total_unread_count, mark_room_unread,
+};
 
 #[cfg(desktop)]
 use tauri::Listener;
@@ -159,6 +199,18 @@ async fn initialize_app_data(
             anyhow::anyhow!("Failed to load configuration: {}", e)
         })?));
 
+    // 设置 HULA_MOCK_SERVER 时改为连接进程内模拟服务端，便于离线开发
+    #[cfg(feature = "mock-server")]
+    if std::env::var("HULA_MOCK_SERVER").is_ok() {
+        let (base_url, ws_url) = mock_server::start_dev_server()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to start mock server: {}", e))?;
+        info!("Using mock server, http: {}, ws: {}", base_url, ws_url);
+        let mut settings = configuration.lock().await;
+        settings.backend.base_url = base_url;
+        settings.backend.ws_url = ws_url;
+    }
+
     // 初始化数据库连接
     let db: Arc<DatabaseConnection> = Arc::new(
         configuration
@@ -220,6 +272,9 @@ pub async fn build_request_client() -> Result<reqwest::Client, CommonError> {
 pub async fn handle_logout_windows(app_handle: &tauri::AppHandle) {
     tracing::info!("[LOGOUT] Starting to close windows and preserve capture/checkupdate windows");
 
+    // 窗口销毁前写入尚在防抖中的草稿
+    command::draft_command::flush_pending_drafts(app_handle).await;
+
     let all_windows = app_handle.webview_windows();
     tracing::info!("[LOGOUT] Found {} windows", all_windows.len());
 
@@ -361,6 +416,16 @@ fn common_setup(app_handle: AppHandle) -> Result<(), Box<dyn std::error::Error>>
                 write_lock: Arc::new(Mutex::new(())),
                 stream_tasks: Arc::new(Mutex::new(std::collections::HashMap::new())),
             });
+            if let Some(state) = app_handle.try_state::<AppData>() {
+                retention::spawn_pruner(
+                    app_handle.clone(),
+                    state.db_conn.clone(),
+                    state.write_lock.clone(),
+                    state.user_info.clone(),
+                );
+                scheduled_message::spawn_scheduler(app_handle.clone());
+                outbox::spawn_worker(app_handle.clone());
+            }
             APP_STATE_READY.store(true, Ordering::SeqCst);
             if let Err(e) = app_handle.emit("app-state-ready", ()) {
                 tracing::warn!("Failed to emit app-state-ready event: {}", e);
@@ -393,8 +458,8 @@ fn get_invoke_handlers() -> impl Fn(tauri::ipc::Invoke<tauri::Wry>) -> bool + Se
     #[cfg(mobile)]
     use crate::mobiles::splash::hide_splash_screen;
     use crate::websocket::commands::{
-        ws_disconnect, ws_force_reconnect, ws_get_app_background_state, ws_get_health,
-        ws_get_state, ws_init_connection, ws_is_connected, ws_send_message,
+        ws_disconnect, ws_force_reconnect, ws_get_app_background_state, ws_get_diagnostics,
+        ws_get_health, ws_get_state, ws_init_connection, ws_is_connected, ws_send_message,
         ws_set_app_background_state, ws_update_config,
     };
 
@@ -442,25 +507,72 @@ fn get_invoke_handlers() -> impl Fn(tauri::ipc::Invoke<tauri::Wry>) -> bool + Se
         list_contacts_command,
         hide_contact_command,
         page_msg,
+        page_msg_around,
         sync_messages,
         send_msg,
         save_msg,
         delete_message,
         delete_room_messages,
+        #[cfg(debug_assertions)]
+        debug_benchmark_message_write,
         update_message_recall_status,
+        edit_message,
+        get_message_revisions,
+        get_message_thread,
         save_message_mark,
+        get_unread_mention_counts,
+        list_unread_mentions,
+        mark_mentions_read,
+        get_room_unread_counts,
+        get_total_unread_count,
+        mark_room_unread,
+        get_message_read_receipts,
+        save_draft,
+        load_draft,
+        clear_draft,
+        schedule_message,
+        list_scheduled_messages,
+        update_scheduled_message,
+        cancel_scheduled_message,
+        get_scheduled_message_settings,
+        set_scheduled_message_settings,
+        resend_message,
+        cancel_outbox_message,
+        list_outbox,
+        add_favorite_message,
+        remove_favorite_messages,
+        set_favorite_tags,
+        list_favorite_messages,
+        list_favorite_tags,
+        get_conversation_stats,
+        get_link_previews,
+        get_link_preview_settings,
+        set_link_preview_settings,
         // 聊天历史相关命令
         query_chat_history,
+        search_chat_messages,
+        search_all_messages,
+        export_chat_history,
+        cancel_chat_export,
+        import_chat_history,
+        // 本地数据备份与恢复
+        backup_local_data,
+        restore_local_data,
         // 文件管理相关命令
         query_files,
+        query_media_gallery,
         get_navigation_items,
-        debug_message_stats,
+        // 消息保留策略相关命令
+        get_message_retention,
+        set_message_retention,
+        prune_messages_now,
         // WebSocket 相关命令
         ws_init_connection,
         ws_disconnect,
         ws_send_message,
         ws_get_state,
         ws_get_health,
+        ws_get_diagnostics,
         ws_force_reconnect,
         ws_update_config,
         ws_is_connected,
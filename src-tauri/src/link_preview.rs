@@ -0,0 +1,837 @@
+//! 文本消息的链接预览
+//!
+//! 从文本消息中提取链接，后台抓取网页的 Open Graph / Twitter Card 元数据和站点图标，
+//! 结果缓存在 im_link_preview 中。分页时从缓存注入 body.urlContentMap，
+//! 缓存未命中的链接抓取完成后广播给所有窗口。
+//!
+//! 抓取会向链接所在站点暴露本机 IP 和在线时间，因此只自动抓取自己发送的消息中的链接；
+//! 他人消息中的链接需要用户在 LinkPreviewSettings 中开启 fetch_incoming 后才会抓取。
+//!
+//! 抓取只允许 http/https，域名解析出的地址必须全部是公网地址，并固定连接到校验过的地址；
+//! 重定向逐跳重新校验，响应限制大小和总耗时。
+
+use crate::AppData;
+use crate::command::message_command::{Message, MessageResp, UrlInfo};
+use crate::error::CommonError;
+use crate::repository::im_config_repository;
+use crate::repository::im_link_preview_repository as repository;
+use chrono::Utc;
+use entity::im_link_preview;
+use once_cell::sync::Lazy;
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use reqwest::redirect::Policy;
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::sync::Mutex as StdMutex;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+use url::{Host, Url};
+
+/// 预览抓取完成后广播的事件
+pub const LINK_PREVIEW_EVENT: &str = "link-preview-updated";
+pub const LINK_PREVIEW_CONFIG_KEY: &str = "linkPreview";
+
+/// 单次抓取的总耗时上限，包含域名解析和重定向
+const FETCH_TIMEOUT: Duration = Duration::from_secs(8);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// 最多读取的响应字节数，元数据一般在 head 中，超出部分直接丢弃
+const MAX_BODY_BYTES: usize = 512 * 1024;
+const MAX_REDIRECTS: usize = 3;
+/// 同时进行的抓取数
+const MAX_CONCURRENT_FETCHES: usize = 4;
+/// 单条消息最多预览的链接数
+const MAX_URLS_PER_MESSAGE: usize = 3;
+const MAX_URL_LEN: usize = 2048;
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_DESCRIPTION_CHARS: usize = 500;
+/// 抓取成功的缓存有效期
+const CACHE_TTL_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+/// 抓取失败的缓存有效期，期间不再重试
+const FAILURE_TTL_MS: i64 = 60 * 60 * 1000;
+/// 清理过期缓存的最小间隔
+const CLEANUP_INTERVAL_MS: i64 = 60 * 60 * 1000;
+const USER_AGENT: &str = "Mozilla/5.0 (compatible; HuLaLinkPreview/1.0)";
+
+/// 正在抓取的链接，避免多个窗口同时分页时重复抓取
+static INFLIGHT: Lazy<StdMutex<HashSet<String>>> = Lazy::new(|| StdMutex::new(HashSet::new()));
+static FETCH_PERMITS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(MAX_CONCURRENT_FETCHES));
+static LAST_CLEANUP_AT: AtomicI64 = AtomicI64::new(0);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkPreviewEvent {
+    pub url: String,
+    pub preview: UrlInfo,
+}
+
+impl From<im_link_preview::Model> for UrlInfo {
+    fn from(model: im_link_preview::Model) -> Self {
+        Self {
+            title: model.title,
+            description: model.description,
+            image: model.image,
+            site_name: model.site_name,
+            favicon: model.favicon,
+        }
+    }
+}
+
+/// 提取文本中的 http/https 链接，规则与前端 Text.vue 的 URL_REGEX 一致，
+/// 保证返回的链接可以直接作为 urlContentMap 的 key
+pub fn extract_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = find_scheme(rest) {
+        let candidate = &rest[start..];
+        let run_end = candidate
+            .find(|c: char| c.is_whitespace() || c == '<')
+            .unwrap_or(candidate.len());
+        let url = candidate[..run_end].trim_end_matches(['.', ',', ':', ';', '"', '\'', ')', ']']);
+        rest = &candidate[run_end..];
+
+        if is_preview_url(url) && !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+            if urls.len() >= MAX_URLS_PER_MESSAGE {
+                break;
+            }
+        }
+    }
+    urls
+}
+
+/// 是否为可以预览的 http/https 链接
+pub fn is_preview_url(url: &str) -> bool {
+    url.len() <= MAX_URL_LEN
+        && Url::parse(url).is_ok_and(|parsed| {
+            matches!(parsed.scheme(), "http" | "https") && parsed.host_str().is_some()
+        })
+}
+
+fn find_scheme(text: &str) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(pos) = text[offset..].find("http") {
+        let start = offset + pos;
+        let tail = &text[start + 4..];
+        if tail.starts_with("://") || tail.starts_with("s://") {
+            return Some(start);
+        }
+        offset = start + 4;
+    }
+    None
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LinkPreviewSettings {
+    /// 是否自动抓取他人消息中的链接，默认关闭
+    pub fetch_incoming: bool,
+}
+
+pub async fn load_settings(
+    db: &DatabaseConnection,
+    login_uid: &str,
+) -> Result<LinkPreviewSettings, CommonError> {
+    let config =
+        im_config_repository::get_config_by_key(db, LINK_PREVIEW_CONFIG_KEY, login_uid).await?;
+    match config.and_then(|c| c.config_value) {
+        Some(value) => serde_json::from_str(&value)
+            .map_err(|e| anyhow::anyhow!("解析链接预览设置失败: {}", e).into()),
+        None => Ok(LinkPreviewSettings::default()),
+    }
+}
+
+pub async fn save_settings(
+    db: &DatabaseConnection,
+    login_uid: &str,
+    settings: &LinkPreviewSettings,
+) -> Result<(), CommonError> {
+    let value = serde_json::to_string(settings)
+        .map_err(|e| anyhow::anyhow!("序列化链接预览设置失败: {}", e))?;
+    im_config_repository::save_or_update_config(db, LINK_PREVIEW_CONFIG_KEY, Some(value), login_uid)
+        .await
+}
+
+/// 文本消息中需要预览的链接
+fn message_urls(message: &Message) -> Vec<String> {
+    if message.message_type != Some(1) {
+        return Vec::new();
+    }
+    message
+        .body
+        .as_ref()
+        .and_then(|body| body.get("content"))
+        .and_then(|content| content.as_str())
+        .map(extract_urls)
+        .unwrap_or_default()
+}
+
+/// 从缓存为文本消息注入链接预览，服务端已下发的预览不覆盖；
+/// 未缓存的链接在后台抓取，完成后通过 LINK_PREVIEW_EVENT 通知。
+/// 他人消息中的链接只在开启 fetch_incoming 时抓取，已缓存的预览照常注入
+pub async fn attach_previews(
+    app_handle: &AppHandle,
+    db: &DatabaseConnection,
+    login_uid: &str,
+    messages: &mut [MessageResp],
+) -> Result<(), CommonError> {
+    let mut urls_by_message = Vec::new();
+    let mut all_urls: Vec<String> = Vec::new();
+    let mut own_urls: HashSet<String> = HashSet::new();
+    for (index, resp) in messages.iter().enumerate() {
+        let urls = message_urls(&resp.message);
+        if urls.is_empty() {
+            continue;
+        }
+        let is_own = resp.from_user.uid == login_uid;
+        for url in &urls {
+            if !all_urls.contains(url) {
+                all_urls.push(url.clone());
+            }
+            if is_own {
+                own_urls.insert(url.clone());
+            }
+        }
+        urls_by_message.push((index, urls));
+    }
+    if all_urls.is_empty() {
+        return Ok(());
+    }
+
+    let cached = repository::find_valid(db, &all_urls, Utc::now().timestamp_millis()).await?;
+    for (index, urls) in urls_by_message {
+        let Some(body) = messages[index]
+            .message
+            .body
+            .as_mut()
+            .and_then(|body| body.as_object_mut())
+        else {
+            continue;
+        };
+        let Some(url_map) = body
+            .entry("urlContentMap")
+            .or_insert_with(|| serde_json::json!({}))
+            .as_object_mut()
+        else {
+            continue;
+        };
+        for url in urls {
+            if let Some(model) = cached.get(&url).filter(|model| model.success) {
+                let preview = UrlInfo::from(model.clone());
+                url_map
+                    .entry(url)
+                    .or_insert_with(|| serde_json::to_value(preview).unwrap_or_default());
+            }
+        }
+        if url_map.is_empty() {
+            body.remove("urlContentMap");
+        }
+    }
+
+    let mut missing: Vec<String> = all_urls
+        .into_iter()
+        .filter(|url| !cached.contains_key(url))
+        .collect();
+    if missing.iter().any(|url| !own_urls.contains(url))
+        && !load_settings(db, login_uid).await?.fetch_incoming
+    {
+        missing.retain(|url| own_urls.contains(url));
+    }
+    schedule_fetch(app_handle, missing);
+    Ok(())
+}
+
+/// 保存新消息时预先抓取未缓存的链接，打开会话时即可从缓存读取；
+/// 他人发送的消息只在开启 fetch_incoming 时抓取
+pub async fn prefetch_previews(
+    app_handle: &AppHandle,
+    db: &DatabaseConnection,
+    login_uid: &str,
+    message: &MessageResp,
+) -> Result<(), CommonError> {
+    let urls = message_urls(&message.message);
+    if urls.is_empty() {
+        return Ok(());
+    }
+    if message.from_user.uid != login_uid && !load_settings(db, login_uid).await?.fetch_incoming {
+        return Ok(());
+    }
+    let cached = repository::find_valid(db, &urls, Utc::now().timestamp_millis()).await?;
+    let missing = urls
+        .into_iter()
+        .filter(|url| !cached.contains_key(url))
+        .collect();
+    schedule_fetch(app_handle, missing);
+    Ok(())
+}
+
+/// 在后台抓取链接预览，已在抓取中的链接会被跳过
+pub fn schedule_fetch(app_handle: &AppHandle, urls: Vec<String>) {
+    for url in urls {
+        if !INFLIGHT
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(url.clone())
+        {
+            continue;
+        }
+        let app_handle = app_handle.clone();
+        tauri::async_runtime::spawn(async move {
+            let _permit = FETCH_PERMITS.acquire().await;
+            let result = tokio::time::timeout(FETCH_TIMEOUT, fetch_preview(&url))
+                .await
+                .unwrap_or_else(|_| Err(CommonError::RequestError("fetch timed out".to_string())));
+            if let Err(e) = store_preview(&app_handle, &url, result).await {
+                warn!("Failed to save link preview for {}: {}", url, e);
+            }
+            INFLIGHT
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&url);
+        });
+    }
+}
+
+async fn store_preview(
+    app_handle: &AppHandle,
+    url: &str,
+    result: Result<UrlInfo, CommonError>,
+) -> Result<(), CommonError> {
+    let now = Utc::now().timestamp_millis();
+    let (preview, expires_at) = match result {
+        Ok(preview) => (Some(preview), now + CACHE_TTL_MS),
+        Err(e) => {
+            debug!("Link preview unavailable for {}: {}", url, e);
+            (None, now + FAILURE_TTL_MS)
+        }
+    };
+    let model = match preview.clone() {
+        Some(preview) => im_link_preview::Model {
+            url: url.to_string(),
+            title: preview.title,
+            description: preview.description,
+            image: preview.image,
+            site_name: preview.site_name,
+            favicon: preview.favicon,
+            success: true,
+            fetched_at: now,
+            expires_at,
+        },
+        None => im_link_preview::Model {
+            url: url.to_string(),
+            title: None,
+            description: None,
+            image: None,
+            site_name: None,
+            favicon: None,
+            success: false,
+            fetched_at: now,
+            expires_at,
+        },
+    };
+
+    let state = app_handle.state::<AppData>();
+    {
+        let _guard = state.write_lock.lock().await;
+        let db = state.db_conn.as_ref();
+        repository::upsert(db, model).await?;
+        if now - LAST_CLEANUP_AT.load(Ordering::Relaxed) >= CLEANUP_INTERVAL_MS {
+            LAST_CLEANUP_AT.store(now, Ordering::Relaxed);
+            let deleted = repository::delete_expired(db, now).await?;
+            if deleted > 0 {
+                debug!("Deleted {} expired link previews", deleted);
+            }
+        }
+    }
+
+    if let Some(preview) = preview {
+        let event = LinkPreviewEvent {
+            url: url.to_string(),
+            preview,
+        };
+        if let Err(e) = app_handle.emit(LINK_PREVIEW_EVENT, &event) {
+            warn!("Failed to emit link preview event: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn request_error(e: impl std::fmt::Display) -> CommonError {
+    CommonError::RequestError(e.to_string())
+}
+
+/// 抓取网页并解析预览，重定向由这里逐跳处理以便重新校验目标地址
+async fn fetch_preview(url: &str) -> Result<UrlInfo, CommonError> {
+    let mut current = Url::parse(url).map_err(request_error)?;
+    for _ in 0..=MAX_REDIRECTS {
+        let client = pinned_client(&current).await?;
+        let mut response = client
+            .get(current.clone())
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| request_error("redirect without location"))?;
+            current = current.join(location).map_err(request_error)?;
+            continue;
+        }
+        if !response.status().is_success() {
+            return Err(request_error(format!("status {}", response.status())));
+        }
+        let is_html = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.to_ascii_lowercase().contains("text/html"));
+        if !is_html {
+            return Err(request_error("not an html page"));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(request_error)? {
+            let remaining = MAX_BODY_BYTES - body.len();
+            body.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+            if body.len() >= MAX_BODY_BYTES {
+                break;
+            }
+        }
+        let html = String::from_utf8_lossy(&body);
+        return build_preview(parse_html(&html), &current)
+            .ok_or_else(|| request_error("no preview metadata"));
+    }
+    Err(request_error("too many redirects"))
+}
+
+/// 校验目标地址并创建只会连接到这些地址的客户端，防止解析结果在校验后被替换（DNS rebinding）
+async fn pinned_client(url: &Url) -> Result<reqwest::Client, CommonError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(request_error(format!(
+            "unsupported scheme {}",
+            url.scheme()
+        )));
+    }
+    let builder = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(FETCH_TIMEOUT)
+        .redirect(Policy::none())
+        .no_proxy()
+        .user_agent(USER_AGENT);
+
+    let builder = match url.host() {
+        Some(Host::Ipv4(ip)) => {
+            ensure_public(&[IpAddr::V4(ip)])?;
+            builder
+        }
+        Some(Host::Ipv6(ip)) => {
+            ensure_public(&[IpAddr::V6(ip)])?;
+            builder
+        }
+        Some(Host::Domain(domain)) => {
+            let host = domain.to_string();
+            let port = url.port_or_known_default().unwrap_or(80);
+            let addrs: Vec<SocketAddr> = tauri::async_runtime::spawn_blocking(move || {
+                (host.as_str(), port)
+                    .to_socket_addrs()
+                    .map(|addrs| addrs.collect())
+            })
+            .await
+            .map_err(request_error)?
+            .map_err(request_error)?;
+            let ips: Vec<IpAddr> = addrs.iter().map(|addr| addr.ip()).collect();
+            ensure_public(&ips)?;
+            builder.resolve_to_addrs(domain, &addrs)
+        }
+        None => return Err(request_error("missing host")),
+    };
+    builder.build().map_err(request_error)
+}
+
+fn ensure_public(ips: &[IpAddr]) -> Result<(), CommonError> {
+    if ips.is_empty() {
+        return Err(request_error("host not resolved"));
+    }
+    match ips.iter().find(|ip| !is_public_ip(**ip)) {
+        Some(ip) => Err(request_error(format!("blocked non-public address {}", ip))),
+        None => Ok(()),
+    }
+}
+
+/// 是否为可访问的公网地址，内网、回环、链路本地、保留和组播地址都不允许
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(ipv4) = ip.to_ipv4_mapped() {
+                return is_public_ipv4(ipv4);
+            }
+            let segments = ip.segments();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // IPv4 兼容地址 ::a.b.c.d
+                || segments[..6] == [0; 6]
+                // 唯一本地地址 fc00::/7
+                || (segments[0] & 0xfe00) == 0xfc00
+                // 链路本地 fe80::/10 和已废弃的站点本地 fec0::/10
+                || (segments[0] & 0xffc0) == 0xfe80
+                || (segments[0] & 0xffc0) == 0xfec0
+                // 文档地址 2001:db8::/32
+                || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+                // NAT64 64:ff9b::/96 可能映射到内网 IPv4
+                || (segments[0] == 0x0064 && segments[1] == 0xff9b))
+        }
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // 0.0.0.0/8 和 240.0.0.0/4
+        || a == 0
+        || a >= 240
+        // 运营商级 NAT 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        // IETF 协议分配 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // 基准测试 198.18.0.0/15
+        || (a == 198 && (b & 0xfe) == 18))
+}
+
+/// 网页中与预览有关的信息
+#[derive(Debug, Default)]
+struct PageMeta {
+    /// meta 标签，key 为小写的 property 或 name，重复时保留第一个
+    meta: HashMap<String, String>,
+    title: Option<String>,
+    icon: Option<String>,
+}
+
+/// 解析 meta、title 和 link 标签，跳过注释、script 和 style
+fn parse_html(html: &str) -> PageMeta {
+    // ASCII 小写不改变字节位置，用于不区分大小写的查找
+    let lower = html.to_ascii_lowercase();
+    let mut page = PageMeta::default();
+    let mut pos = 0;
+    while let Some(offset) = html[pos..].find('<') {
+        let start = pos + offset + 1;
+        if lower[start..].starts_with("!--") {
+            match lower[start..].find("-->") {
+                Some(end) => pos = start + end + 3,
+                None => break,
+            }
+            continue;
+        }
+        let name_end = lower[start..]
+            .find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+            .map_or(html.len(), |i| start + i);
+        let name = &lower[start..name_end];
+        if name.is_empty() {
+            if lower[start..].starts_with("/head") {
+                break;
+            }
+            pos = start;
+            continue;
+        }
+        let (attrs, tag_end) = parse_attrs(html, name_end);
+        pos = tag_end;
+
+        match name {
+            "meta" => {
+                let key = attr(&attrs, "property").or_else(|| attr(&attrs, "name"));
+                if let (Some(key), Some(content)) = (key, attr(&attrs, "content")) {
+                    page.meta
+                        .entry(key.to_ascii_lowercase())
+                        .or_insert_with(|| content.to_string());
+                }
+            }
+            "title" => {
+                let end = lower[pos..].find("</title").map_or(html.len(), |i| pos + i);
+                if page.title.is_none() {
+                    page.title = Some(html[pos..end].to_string());
+                }
+                pos = end;
+            }
+            "link" if page.icon.is_none() => {
+                let is_icon = attr(&attrs, "rel").is_some_and(|rel| {
+                    rel.split_ascii_whitespace()
+                        .any(|r| r.eq_ignore_ascii_case("icon"))
+                });
+                if is_icon {
+                    page.icon = attr(&attrs, "href").map(str::to_string);
+                }
+            }
+            "script" | "style" => {
+                let closing = format!("</{}", name);
+                pos = lower[pos..].find(&closing).map_or(html.len(), |i| pos + i);
+            }
+            _ => {}
+        }
+    }
+    page
+}
+
+/// 解析标签属性，返回小写的属性名、原始属性值和标签结束后的位置
+fn parse_attrs(html: &str, mut pos: usize) -> (Vec<(String, String)>, usize) {
+    let bytes = html.as_bytes();
+    let len = bytes.len();
+    let mut attrs = Vec::new();
+    loop {
+        while pos < len && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'/') {
+            pos += 1;
+        }
+        if pos >= len {
+            return (attrs, len);
+        }
+        if bytes[pos] == b'>' {
+            return (attrs, pos + 1);
+        }
+
+        let name_start = pos;
+        while pos < len
+            && !bytes[pos].is_ascii_whitespace()
+            && !matches!(bytes[pos], b'=' | b'>' | b'/')
+        {
+            pos += 1;
+        }
+        let name = html[name_start..pos].to_ascii_lowercase();
+        while pos < len && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+
+        let mut value = String::new();
+        if pos < len && bytes[pos] == b'=' {
+            pos += 1;
+            while pos < len && bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos < len && matches!(bytes[pos], b'"' | b'\'') {
+                let quote = bytes[pos] as char;
+                let value_start = pos + 1;
+                let value_end = html[value_start..]
+                    .find(quote)
+                    .map_or(len, |i| value_start + i);
+                value = html[value_start..value_end].to_string();
+                pos = (value_end + 1).min(len);
+            } else {
+                let value_start = pos;
+                while pos < len && !bytes[pos].is_ascii_whitespace() && bytes[pos] != b'>' {
+                    pos += 1;
+                }
+                value = html[value_start..pos].to_string();
+            }
+        }
+        attrs.push((name, value));
+    }
+}
+
+fn attr<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// 按优先级选取元数据，没有标题、描述和图片时视为无法预览
+fn build_preview(page: PageMeta, base: &Url) -> Option<UrlInfo> {
+    let first_meta = |keys: &[&str]| {
+        keys.iter()
+            .filter_map(|key| page.meta.get(*key))
+            .find(|value| !value.trim().is_empty())
+            .cloned()
+    };
+
+    let title = first_meta(&["og:title", "twitter:title"])
+        .or_else(|| page.title.clone())
+        .and_then(|title| clean_text(&title, MAX_TITLE_CHARS));
+    let description = first_meta(&["og:description", "twitter:description", "description"])
+        .and_then(|description| clean_text(&description, MAX_DESCRIPTION_CHARS));
+    let image = first_meta(&[
+        "og:image:secure_url",
+        "og:image",
+        "og:image:url",
+        "twitter:image",
+        "twitter:image:src",
+    ])
+    .and_then(|image| resolve_url(base, &image));
+    if title.is_none() && description.is_none() && image.is_none() {
+        return None;
+    }
+
+    let site_name = first_meta(&["og:site_name", "application-name"])
+        .and_then(|name| clean_text(&name, MAX_TITLE_CHARS))
+        .or_else(|| base.host_str().map(str::to_string));
+    let favicon = page
+        .icon
+        .as_deref()
+        .and_then(|href| resolve_url(base, href))
+        .or_else(|| base.join("/favicon.ico").ok().map(String::from));
+    Some(UrlInfo {
+        title,
+        description,
+        image,
+        site_name,
+        favicon,
+    })
+}
+
+/// 相对地址按网页地址补全，只保留 http/https
+fn resolve_url(base: &Url, href: &str) -> Option<String> {
+    let href = decode_entities(href.trim());
+    let url = base.join(&href).ok()?;
+    if !matches!(url.scheme(), "http" | "https") || url.as_str().len() > MAX_URL_LEN {
+        return None;
+    }
+    Some(url.into())
+}
+
+/// 解码实体、合并空白并按字符数截断
+fn clean_text(text: &str, max_chars: usize) -> Option<String> {
+    let text = decode_entities(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => Some(format!("{}…", &text[..end])),
+        None => Some(text),
+    }
+}
+
+/// 解码常见的命名实体和数字实体，无法识别的保持原样
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let ch = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => {
+                let code = entity.strip_prefix('#')?;
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (entity, ch) {
+            (Some(entity), Some(ch)) => {
+                decoded.push(ch);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_urls() {
+        let text = "看这个 https://example.com/a?b=1 还有 (http://example.org/x). 重复 https://example.com/a?b=1 ftp://x https://";
+        assert_eq!(
+            extract_urls(text),
+            vec!["https://example.com/a?b=1", "http://example.org/x"]
+        );
+        // 与前端一致，链接后紧跟的中文也算在链接内
+        assert_eq!(
+            extract_urls("链接https://a.cn/x，看看"),
+            vec!["https://a.cn/x，看看"]
+        );
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:192.168.1.1",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700:4700::1111"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_parse_html_preview() {
+        let html = r#"<!doctype html><html><head>
+            <!-- <meta property="og:title" content="comment"> -->
+            <title> Fallback   Title </title>
+            <script>var s = "<meta property='og:title' content='script'>";</script>
+            <META Property="og:title" Content="Tom &amp; Jerry &#x1F600;">
+            <meta name="twitter:description" content='A "quoted" description'>
+            <meta property="og:image" content="/img/cover.png">
+            <link rel="shortcut icon" href="//cdn.example.com/favicon.png">
+            </head><body><meta property="og:site_name" content="ignored"></body></html>"#;
+        let base = Url::parse("https://example.com/post/1").unwrap();
+        let preview = build_preview(parse_html(html), &base).unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Tom & Jerry 😀"));
+        assert_eq!(
+            preview.description.as_deref(),
+            Some(r#"A "quoted" description"#)
+        );
+        assert_eq!(
+            preview.image.as_deref(),
+            Some("https://example.com/img/cover.png")
+        );
+        assert_eq!(preview.site_name.as_deref(), Some("example.com"));
+        assert_eq!(
+            preview.favicon.as_deref(),
+            Some("https://cdn.example.com/favicon.png")
+        );
+
+        let page = parse_html("<html><head><title>Only &lt;title&gt;</title></head></html>");
+        let preview = build_preview(page, &base).unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Only <title>"));
+        assert_eq!(
+            preview.favicon.as_deref(),
+            Some("https://example.com/favicon.ico")
+        );
+        assert!(build_preview(parse_html("<html></html>"), &base).is_none());
+    }
+}
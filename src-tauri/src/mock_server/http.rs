@@ -0,0 +1,292 @@
+use super::{MockState, WsCommand};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+/// 解析后的 HTTP 请求
+struct MockRequest {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    token: Option<String>,
+    body: Value,
+}
+
+pub(super) async fn serve(listener: TcpListener, state: Arc<Mutex<MockState>>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_connection(stream, state.clone()));
+            }
+            Err(e) => {
+                warn!("Mock http accept failed: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// 处理一个 keep-alive 连接上的所有请求
+async fn handle_connection(stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        let request = match read_request(&mut reader).await {
+            Ok(Some(request)) => request,
+            Ok(None) => return,
+            Err(e) => {
+                debug!("Mock http connection closed: {}", e);
+                return;
+            }
+        };
+
+        let response = route(&state, request).await.to_string();
+        let raw = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: keep-alive\r\n\r\n{}",
+            response.len(),
+            response
+        );
+        if writer.write_all(raw.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn read_request<R>(reader: &mut BufReader<R>) -> std::io::Result<Option<MockRequest>>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).await? == 0 {
+        return Ok(None);
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0usize;
+    let mut token = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.parse().unwrap_or(0),
+                "token" => token = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).await?;
+
+    let (path, query) = target.split_once('?').unwrap_or((target.as_str(), ""));
+    Ok(Some(MockRequest {
+        method,
+        path: path.trim_start_matches('/').to_string(),
+        query: url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect(),
+        token,
+        body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+    }))
+}
+
+fn ok(data: Value) -> Value {
+    json!({ "success": true, "code": 200, "msg": "ok", "data": data })
+}
+
+fn fail(code: i32, msg: &str) -> Value {
+    json!({ "success": false, "code": code, "msg": msg, "data": null })
+}
+
+/// 校验 token，返回对应 uid；失败时返回错误响应
+fn authenticate(state: &mut MockState, token: Option<&str>) -> Result<String, Value> {
+    let Some(info) = token.and_then(|t| state.tokens.get_mut(t)) else {
+        return Err(fail(401, "未登录"));
+    };
+
+    if info.expired || info.remaining == Some(0) {
+        info.expired = true;
+        return Err(fail(406, "token已过期"));
+    }
+    if let Some(remaining) = info.remaining.as_mut() {
+        *remaining -= 1;
+    }
+    Ok(info.uid.clone())
+}
+
+async fn route(state: &Arc<Mutex<MockState>>, request: MockRequest) -> Value {
+    let mut state = state.lock().await;
+    state
+        .request_log
+        .push(format!("{} {}", request.method, request.path));
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "oauth/anyTenant/login") => login(&mut state, &request.body),
+        ("POST", "oauth/anyTenant/refresh") => refresh(&mut state, &request.body),
+        (method, path) => {
+            let uid = match authenticate(&mut state, request.token.as_deref()) {
+                Ok(uid) => uid,
+                Err(response) => return response,
+            };
+
+            match (method, path) {
+                ("GET", "im/chat/contact/list") => ok(json!(state.scenario.contacts)),
+                ("POST", "im/chat/msg/list") => ok(json!(state.messages)),
+                ("GET", "im/chat/msg/page") => page_messages(&state, &request.query),
+                ("POST", "im/chat/msg") => send_message(&mut state, &uid, &request.body),
+                ("GET", "im/room/group/listMember") => {
+                    let room_id = request.query.get("roomId").cloned().unwrap_or_default();
+                    ok(json!(
+                        state
+                            .scenario
+                            .room_members
+                            .get(&room_id)
+                            .cloned()
+                            .unwrap_or_default()
+                    ))
+                }
+                _ => fail(404, &format!("mock server: unhandled {} {}", method, path)),
+            }
+        }
+    }
+}
+
+fn login(state: &mut MockState, body: &Value) -> Value {
+    let account = body["account"].as_str().unwrap_or_default();
+    let password = body["password"].as_str().unwrap_or_default();
+
+    let Some(user) = state
+        .scenario
+        .users
+        .iter()
+        .find(|u| u.account == account && u.password == password)
+        .cloned()
+    else {
+        return fail(500, "账号或密码错误");
+    };
+
+    let (token, refresh_token) = state.issue_tokens(&user.uid);
+    ok(json!({
+        "token": token,
+        "client": "mock",
+        "refreshToken": refresh_token,
+        "uid": user.uid,
+        "expire": "7200"
+    }))
+}
+
+fn refresh(state: &mut MockState, body: &Value) -> Value {
+    let refresh_token = body["refreshToken"].as_str().unwrap_or_default();
+    let Some(uid) = state.refresh_tokens.remove(refresh_token) else {
+        return fail(401, "refreshToken无效");
+    };
+
+    state.refresh_count += 1;
+    let (token, refresh_token) = state.issue_tokens(&uid);
+    ok(json!({
+        "token": token,
+        "refreshToken": refresh_token,
+        "expire": "7200",
+        "uid": uid
+    }))
+}
+
+fn message_id(message: &Value) -> u64 {
+    message["message"]["id"]
+        .as_str()
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(0)
+}
+
+fn page_messages(state: &MockState, query: &HashMap<String, String>) -> Value {
+    let room_id = query.get("roomId").map(String::as_str).unwrap_or_default();
+    let page_size = query
+        .get("pageSize")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(20);
+    let cursor = query
+        .get("cursor")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(u64::MAX);
+
+    let mut list: Vec<&Value> = state
+        .messages
+        .iter()
+        .filter(|m| m["message"]["roomId"].as_str() == Some(room_id))
+        .collect();
+    let total = list.len();
+    list.retain(|m| message_id(m) < cursor);
+    list.sort_by_key(|m| std::cmp::Reverse(message_id(m)));
+
+    let is_last = list.len() <= page_size;
+    list.truncate(page_size);
+    let next_cursor = list
+        .last()
+        .map(|m| message_id(m).to_string())
+        .unwrap_or_default();
+
+    ok(json!({
+        "cursor": next_cursor,
+        "isLast": is_last,
+        "list": list,
+        "total": total
+    }))
+}
+
+fn send_message(state: &mut MockState, uid: &str, body: &Value) -> Value {
+    let Some(room_id) = body["roomId"].as_str() else {
+        return fail(400, "roomId不能为空");
+    };
+
+    let id = state.next_id;
+    state.next_id += 1;
+    let now = chrono::Utc::now().timestamp_millis();
+    let nickname = state
+        .scenario
+        .users
+        .iter()
+        .find(|u| u.uid == uid)
+        .map(|u| u.name.clone())
+        .unwrap_or_default();
+
+    let message = json!({
+        "createId": uid,
+        "createTime": now,
+        "updateId": uid,
+        "updateTime": now,
+        "fromUser": { "uid": uid, "nickname": nickname },
+        "message": {
+            "id": id.to_string(),
+            "roomId": room_id,
+            "type": body["msgType"],
+            "body": body["body"],
+            "messageMarks": {},
+            "sendTime": now
+        },
+        "oldMsgId": body["id"],
+        "timeBlock": null
+    });
+
+    state.messages.push(message.clone());
+    state.broadcast(WsCommand::Send(json!({
+        "type": "receiveMessage",
+        "data": message
+    })));
+    ok(message)
+}
@@ -0,0 +1,543 @@
+/// 进程内模拟 HuLa 服务端
+///
+/// 在本地端口上提供 `ImUrl` 中核心 REST 接口（登录/刷新 token、会话列表、消息列表/分页、
+/// 发送消息、群成员）以及 WebSocket 协议（线路格式协商、心跳、ACK、推送），由 `MockScenario` 脚本驱动，
+/// 用于离线开发和端到端测试。
+mod http;
+mod scenario;
+mod ws;
+
+pub use scenario::{MockScenario, MockUser, WsStep, contact, room_member, text_message};
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+#[cfg(feature = "mock-server")]
+use std::sync::OnceLock;
+use tokio::net::TcpListener;
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// 发往单个 WebSocket 连接的指令
+#[derive(Debug, Clone)]
+pub(crate) enum WsCommand {
+    Send(Value),
+    Drop,
+    Close(u16),
+    StopHeartbeat,
+}
+
+#[derive(Debug)]
+pub(crate) struct TokenInfo {
+    pub uid: String,
+    /// 剩余可用次数，None 表示不限
+    pub remaining: Option<u32>,
+    pub expired: bool,
+}
+
+/// 模拟服务端运行状态
+#[derive(Debug)]
+pub(crate) struct MockState {
+    pub scenario: MockScenario,
+    pub tokens: HashMap<String, TokenInfo>,
+    pub refresh_tokens: HashMap<String, String>,
+    pub messages: Vec<Value>,
+    pub next_id: u64,
+    pub acks: Vec<String>,
+    pub request_log: Vec<String>,
+    pub refresh_count: u32,
+    pub ws_clients: Vec<mpsc::UnboundedSender<WsCommand>>,
+    /// 累计建立的 WebSocket 连接数
+    pub ws_connect_count: u32,
+}
+
+impl MockState {
+    fn new(scenario: MockScenario) -> Self {
+        let messages = scenario.messages.clone();
+        let next_id = messages
+            .iter()
+            .filter_map(|m| m["message"]["id"].as_str()?.parse::<u64>().ok())
+            .max()
+            .unwrap_or(0)
+            + 1;
+
+        Self {
+            scenario,
+            tokens: HashMap::new(),
+            refresh_tokens: HashMap::new(),
+            messages,
+            next_id,
+            acks: Vec::new(),
+            request_log: Vec::new(),
+            refresh_count: 0,
+            ws_clients: Vec::new(),
+            ws_connect_count: 0,
+        }
+    }
+
+    pub fn issue_tokens(&mut self, uid: &str) -> (String, String) {
+        let token = format!("mock-token-{}", uuid::Uuid::new_v4());
+        let refresh_token = format!("mock-refresh-{}", uuid::Uuid::new_v4());
+        self.tokens.insert(
+            token.clone(),
+            TokenInfo {
+                uid: uid.to_string(),
+                remaining: self.scenario.token_ttl_requests,
+                expired: false,
+            },
+        );
+        self.refresh_tokens
+            .insert(refresh_token.clone(), uid.to_string());
+        (token, refresh_token)
+    }
+
+    pub fn expire_all_tokens(&mut self) {
+        for info in self.tokens.values_mut() {
+            info.expired = true;
+        }
+    }
+
+    /// 向所有 WebSocket 连接广播，顺便清理已断开的连接
+    pub fn broadcast(&mut self, command: WsCommand) {
+        self.ws_clients
+            .retain(|client| client.send(command.clone()).is_ok());
+    }
+}
+
+/// 模拟服务端句柄，drop 时停止服务
+pub struct MockServer {
+    pub base_url: String,
+    pub ws_url: String,
+    state: Arc<Mutex<MockState>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// 在随机端口上启动模拟服务端
+    pub async fn start(scenario: MockScenario) -> std::io::Result<Self> {
+        let state = Arc::new(Mutex::new(MockState::new(scenario)));
+
+        let http_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let base_url = format!("http://{}", http_listener.local_addr()?);
+        let ws_url = format!("ws://{}/websocket", ws_listener.local_addr()?);
+
+        let tasks = vec![
+            tokio::spawn(http::serve(http_listener, state.clone())),
+            tokio::spawn(ws::serve(ws_listener, state.clone())),
+        ];
+
+        info!("Mock server started, http: {}, ws: {}", base_url, ws_url);
+        Ok(Self {
+            base_url,
+            ws_url,
+            state,
+            tasks,
+        })
+    }
+
+    /// 服务端收到的 ACK 消息 ID
+    pub async fn received_acks(&self) -> Vec<String> {
+        self.state.lock().await.acks.clone()
+    }
+
+    /// 服务端收到的请求，格式为 `METHOD path`
+    pub async fn request_log(&self) -> Vec<String> {
+        self.state.lock().await.request_log.clone()
+    }
+
+    /// 刷新 token 的次数
+    pub async fn refresh_count(&self) -> u32 {
+        self.state.lock().await.refresh_count
+    }
+
+    /// 服务端当前保存的消息（包含客户端发送的消息）
+    pub async fn messages(&self) -> Vec<Value> {
+        self.state.lock().await.messages.clone()
+    }
+
+    /// 令所有已签发的 token 立即过期
+    pub async fn expire_tokens(&self) {
+        self.state.lock().await.expire_all_tokens();
+    }
+
+    /// 向所有 WebSocket 连接推送消息
+    pub async fn push(&self, message: Value) {
+        self.state.lock().await.broadcast(WsCommand::Send(message));
+    }
+
+    /// 断开所有 WebSocket 连接（不发送关闭帧）
+    pub async fn drop_connections(&self) {
+        self.state.lock().await.broadcast(WsCommand::Drop);
+    }
+
+    /// 累计建立的 WebSocket 连接数（包含重连）
+    pub async fn ws_connect_count(&self) -> u32 {
+        self.state.lock().await.ws_connect_count
+    }
+
+    /// 当前 WebSocket 连接数
+    pub async fn ws_connection_count(&self) -> usize {
+        let mut state = self.state.lock().await;
+        state.ws_clients.retain(|client| !client.is_closed());
+        state.ws_clients.len()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(feature = "mock-server")]
+static DEV_SERVER: OnceLock<MockServer> = OnceLock::new();
+
+/// 启动供本地开发使用的全局模拟服务端，返回 (base_url, ws_url)
+#[cfg(feature = "mock-server")]
+pub async fn start_dev_server() -> std::io::Result<(String, String)> {
+    if let Some(server) = DEV_SERVER.get() {
+        return Ok((server.base_url.clone(), server.ws_url.clone()));
+    }
+
+    let server = MockServer::start(MockScenario::demo()).await?;
+    let urls = (server.base_url.clone(), server.ws_url.clone());
+    let _ = DEV_SERVER.set(server);
+    Ok(urls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::message_command::fetch_all_messages;
+    use crate::command::request_command::login;
+    use crate::configuration::Settings;
+    use crate::im_request_client::{ImRequest, ImRequestClient, ImUrl};
+    use crate::repository::im_user_repository;
+    use crate::repository::test_support::migrated_db;
+    use crate::vo::vo::LoginReq;
+    use crate::websocket::codec::{self, DecodedFrame, WireFormat};
+    use crate::websocket::diagnostics::DisconnectCause;
+    use crate::websocket::{ConnectionState, WebSocketClient, WebSocketConfig};
+    use crate::{AppData, UserInfo};
+    use futures_util::{SinkExt, StreamExt};
+    use sea_orm::{ConnectionTrait, EntityTrait, PaginatorTrait};
+    use serde_json::json;
+    use std::time::Duration;
+    use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+
+    fn login_req(account: &str, password: &str) -> LoginReq {
+        serde_json::from_value(json!({
+            "grantType": "PASSWORD",
+            "systemType": "2",
+            "deviceType": "PC",
+            "clientId": "testClientId",
+            "account": account,
+            "password": password,
+            "asyncData": false
+        }))
+        .unwrap()
+    }
+
+    /// 构造指向模拟服务端的应用状态，数据库为内存库
+    async fn app_data(server: &MockServer) -> Result<AppData, anyhow::Error> {
+        let db = migrated_db().await;
+
+        let settings: Settings = serde_json::from_value(json!({
+            "database": { "sqlite_file": ":memory:" },
+            "backend": { "base_url": server.base_url, "ws_url": server.ws_url }
+        }))?;
+        Ok(AppData {
+            db_conn: Arc::new(db),
+            user_info: Arc::new(Mutex::new(UserInfo {
+                token: String::new(),
+                refresh_token: String::new(),
+                uid: String::new(),
+            })),
+            rc: Arc::new(Mutex::new(ImRequestClient::new(server.base_url.clone())?)),
+            config: Arc::new(Mutex::new(settings)),
+            frontend_task: Mutex::new(false),
+            backend_task: Mutex::new(true),
+            write_lock: Arc::new(Mutex::new(())),
+            stream_tasks: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// 心跳和重连间隔都缩短到毫秒级的客户端配置
+    async fn ws_config(server: &MockServer) -> Result<WebSocketConfig, anyhow::Error> {
+        let mut client = ImRequestClient::new(server.base_url.clone())?;
+        let resp = client.login(login_req("hula", "123456")).await?.unwrap();
+        Ok(WebSocketConfig {
+            server_url: server.ws_url.clone(),
+            token: Some(resp.token),
+            client_id: "test".to_string(),
+            heartbeat_interval: 50,
+            heartbeat_timeout: 100,
+            reconnect_delay_ms: 50,
+            ..Default::default()
+        })
+    }
+
+    /// 等待服务端累计收到 count 个连接，且客户端处于已连接状态
+    async fn wait_for_connects(
+        server: &MockServer,
+        client: &WebSocketClient<tauri::test::MockRuntime>,
+        count: u32,
+    ) {
+        for _ in 0..250 {
+            if server.ws_connect_count().await >= count
+                && client.get_state().await == ConnectionState::Connected
+            {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("timed out waiting for ws connection #{}", count);
+    }
+
+    async fn next_json(
+        stream: &mut (impl StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>>
+                  + Unpin),
+    ) -> Value {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), stream.next())
+                .await
+                .expect("timed out waiting for ws message")
+                .expect("ws stream ended")
+                .expect("ws error");
+            if let Message::Text(text) = message {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_and_refresh_expired_token() -> Result<(), anyhow::Error> {
+        let scenario = MockScenario {
+            contacts: MockScenario::demo().contacts,
+            token_ttl_requests: Some(1),
+            ..Default::default()
+        };
+        let server = MockServer::start(scenario).await?;
+        let mut client = ImRequestClient::new(server.base_url.clone())?;
+
+        assert!(client.login(login_req("hula", "wrong")).await.is_err());
+        let login = client.login(login_req("hula", "123456")).await?.unwrap();
+        assert_eq!(login.uid, "10001");
+
+        // 第一次请求消耗掉 token，第二次触发 406 并自动刷新
+        for _ in 0..2 {
+            let contacts: Option<Vec<Value>> = client
+                .im_request(
+                    ImUrl::GetContactList,
+                    None::<Value>,
+                    None::<Value>,
+                )
+                .await?;
+            assert_eq!(contacts.map(|c| c.len()), Some(2));
+        }
+        assert_eq!(server.refresh_count().await, 1);
+        assert_ne!(client.token.as_deref(), Some(login.token.as_str()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_all_messages_into_sqlite() -> Result<(), anyhow::Error> {
+        let server = MockServer::start(MockScenario::demo()).await?;
+        let mut client = ImRequestClient::new(server.base_url.clone())?;
+        client.login(login_req("hula", "123456")).await?;
+
+        let db = migrated_db().await;
+        db.execute_unprepared("INSERT INTO im_user (id, is_init) VALUES ('10001', 1)")
+            .await?;
+
+        fetch_all_messages(&mut client, &db, "10001", false).await?;
+
+        let count = entity::im_message::Entity::find().count(&db).await?;
+        assert_eq!(count as usize, server.messages().await.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_msg_pushes_and_collects_ack() -> Result<(), anyhow::Error> {
+        let server = MockServer::start(MockScenario::default()).await?;
+        let mut client = ImRequestClient::new(server.base_url.clone())?;
+        let login = client.login(login_req("hula", "123456")).await?.unwrap();
+
+        let url = format!("{}?clientId=test&Token={}", server.ws_url, login.token);
+        let (ws, _) = connect_async(url).await?;
+        let (mut sink, mut stream) = ws.split();
+
+        // 心跳
+        sink.send(Message::Text(json!({ "type": "2" }).to_string().into()))
+            .await?;
+        assert_eq!(next_json(&mut stream).await["type"], "3");
+
+        // 发送消息后服务端通过 WS 回推 receiveMessage
+        let sent: Option<Value> = client
+            .im_request(
+                ImUrl::SendMsg,
+                Some(json!({ "id": "T1", "roomId": "1", "msgType": 1, "body": { "content": "hi" } })),
+                None::<Value>,
+            )
+            .await?;
+        let msg_id = sent.unwrap()["message"]["id"].as_str().unwrap().to_string();
+
+        let pushed = next_json(&mut stream).await;
+        assert_eq!(pushed["type"], "receiveMessage");
+        assert_eq!(pushed["data"]["message"]["id"], msg_id.as_str());
+
+        sink.send(Message::Text(
+            json!({ "type": "15", "data": { "msgId": msg_id, "timestamp": 0 } })
+                .to_string()
+                .into(),
+        ))
+        .await?;
+
+        // 等待服务端处理 ACK
+        for _ in 0..50 {
+            if !server.received_acks().await.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(server.received_acks().await, vec![msg_id]);
+
+        // 模拟网络中断
+        server.drop_connections().await;
+        let ended = tokio::time::timeout(Duration::from_secs(5), stream.next()).await?;
+        assert!(!matches!(ended, Some(Ok(Message::Text(_)))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_login_command_saves_tokens_and_fetches_messages() -> Result<(), anyhow::Error> {
+        let server = MockServer::start(MockScenario::demo()).await?;
+        let state = app_data(&server).await?;
+
+        assert!(login(login_req("hula", "wrong"), &state).await.is_err());
+        let resp = login(login_req("hula", "123456"), &state)
+            .await
+            .map_err(anyhow::Error::msg)?
+            .unwrap();
+        assert_eq!(resp.uid, "10001");
+        assert_eq!(state.user_info.lock().await.uid, "10001");
+        assert_eq!(
+            im_user_repository::get_user_tokens(state.db_conn.as_ref(), "10001").await?,
+            Some((resp.token.clone(), resp.refresh_token.clone()))
+        );
+        let count = entity::im_message::Entity::find()
+            .count(state.db_conn.as_ref())
+            .await?;
+        assert_eq!(count as usize, server.messages().await.len());
+
+        // 自动登录使用保存的 refresh_token 换取新 token
+        let mut auto_login = login_req("", "");
+        auto_login.is_auto_login = true;
+        auto_login.uid = Some("10001".to_string());
+        let refreshed = login(auto_login, &state)
+            .await
+            .map_err(anyhow::Error::msg)?
+            .unwrap();
+        assert_ne!(refreshed.token, resp.token);
+        assert_eq!(server.refresh_count().await, 1);
+        assert_eq!(
+            im_user_repository::get_user_tokens(state.db_conn.as_ref(), "10001").await?,
+            Some((refreshed.token, refreshed.refresh_token))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_negotiates_wire_format() -> Result<(), anyhow::Error> {
+        let server = MockServer::start(MockScenario::default()).await?;
+        let config = ws_config(&server).await?;
+        let url = format!(
+            "{}?clientId=test&Token={}",
+            server.ws_url,
+            config.token.unwrap()
+        );
+
+        let offer = [WireFormat::MessagePack, WireFormat::Json];
+        let (ws, response) = connect_async(codec::build_request(&url, &offer)?).await?;
+        let format = codec::negotiated_format(&response);
+        assert_eq!(format, WireFormat::MessagePack);
+
+        let (mut sink, mut stream) = ws.split();
+        sink.send(codec::encode(format, &json!({ "type": "2" }))?)
+            .await?;
+        let pong = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await?
+            .expect("ws stream ended")?;
+        assert!(matches!(pong, Message::Binary(_)));
+        match codec::decode(format, pong)? {
+            Some(DecodedFrame::Json(value)) => assert_eq!(value["type"], "3"),
+            other => panic!("unexpected frame: {:?}", other),
+        }
+
+        // 不支持子协议的旧版服务端不回应，客户端握手失败后应回退
+        let legacy = MockServer::start(MockScenario {
+            wire_formats: Vec::new(),
+            ..Default::default()
+        })
+        .await?;
+        let config = ws_config(&legacy).await?;
+        let url = format!(
+            "{}?clientId=test&Token={}",
+            legacy.ws_url,
+            config.token.unwrap()
+        );
+        let err = connect_async(codec::build_request(&url, &offer)?)
+            .await
+            .unwrap_err();
+        assert!(codec::is_subprotocol_rejected(&err));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ws_client_reconnects_after_drop() -> Result<(), anyhow::Error> {
+        let server = MockServer::start(MockScenario::default()).await?;
+        let config = ws_config(&server).await?;
+        let app = tauri::test::mock_app();
+        let client = WebSocketClient::new(app.handle().clone());
+        let connection = tokio::spawn({
+            let client = client.clone();
+            async move { client.connect(config).await }
+        });
+
+        wait_for_connects(&server, &client, 1).await;
+        server.drop_connections().await;
+        wait_for_connects(&server, &client, 2).await;
+
+        client.internal_disconnect(DisconnectCause::Manual).await;
+        connection.await??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ws_client_reconnects_after_heartbeat_timeout() -> Result<(), anyhow::Error> {
+        // 连接保持但服务端不再回应心跳，客户端应判定超时并重连
+        let scenario = MockScenario {
+            ws_script: vec![WsStep::Wait(200), WsStep::StopHeartbeat],
+            ..Default::default()
+        };
+        let server = MockServer::start(scenario).await?;
+        let config = ws_config(&server).await?;
+        let app = tauri::test::mock_app();
+        let client = WebSocketClient::new(app.handle().clone());
+        let connection = tokio::spawn({
+            let client = client.clone();
+            async move { client.connect(config).await }
+        });
+
+        wait_for_connects(&server, &client, 1).await;
+        wait_for_connects(&server, &client, 2).await;
+
+        client.internal_disconnect(DisconnectCause::Manual).await;
+        connection.await??;
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+use crate::websocket::codec::WireFormat;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+/// 模拟账号
+#[derive(Debug, Clone)]
+pub struct MockUser {
+    pub account: String,
+    pub password: String,
+    pub uid: String,
+    pub name: String,
+}
+
+/// WebSocket 连接建立后按顺序执行的脚本步骤
+#[derive(Debug, Clone)]
+pub enum WsStep {
+    /// 推送一条消息，格式与真实服务端一致：`{"type": "...", "data": ...}`
+    Push(Value),
+    /// 等待指定毫秒数
+    Wait(u64),
+    /// 直接断开连接，不发送关闭帧（模拟网络中断）
+    Drop,
+    /// 发送关闭帧
+    Close(u16),
+    /// 令所有 token 过期并推送 tokenExpired
+    ExpireToken,
+    /// 此后不再响应心跳，连接保持但已假死（模拟心跳超时）
+    StopHeartbeat,
+}
+
+/// 模拟服务端场景
+#[derive(Debug, Clone)]
+pub struct MockScenario {
+    pub users: Vec<MockUser>,
+    /// 每个 token 可用于多少次鉴权请求，超过后返回 406；None 表示永不过期
+    pub token_ttl_requests: Option<u32>,
+    /// 会话列表（`im/chat/contact/list` 的返回）
+    pub contacts: Vec<Value>,
+    /// 服务端已有消息，格式为 `MessageResp`
+    pub messages: Vec<Value>,
+    /// 房间成员（`im/room/group/listMember` 的返回），按 roomId 分组
+    pub room_members: HashMap<String, Vec<Value>>,
+    /// 每次 WebSocket 连接建立后执行的脚本
+    pub ws_script: Vec<WsStep>,
+    /// 是否响应心跳，关闭后可用于模拟心跳超时
+    pub reply_heartbeat: bool,
+    /// 支持的线路格式，按客户端在 `Sec-WebSocket-Protocol` 中的顺序选择；
+    /// 为空时模拟不支持子协议协商的旧版服务端
+    pub wire_formats: Vec<WireFormat>,
+}
+
+impl Default for MockScenario {
+    fn default() -> Self {
+        Self {
+            users: vec![MockUser {
+                account: "hula".to_string(),
+                password: "123456".to_string(),
+                uid: "10001".to_string(),
+                name: "HuLa".to_string(),
+            }],
+            token_ttl_requests: None,
+            contacts: Vec::new(),
+            messages: Vec::new(),
+            room_members: HashMap::new(),
+            ws_script: Vec::new(),
+            reply_heartbeat: true,
+            wire_formats: vec![
+                WireFormat::Json,
+                WireFormat::JsonDeflate,
+                WireFormat::MessagePack,
+            ],
+        }
+    }
+}
+
+impl MockScenario {
+    /// 本地开发使用的演示数据：一个单聊和一个群聊，各带若干条消息
+    pub fn demo() -> Self {
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut scenario = Self::default();
+
+        scenario.contacts = vec![
+            contact("1", "10002", 2, "小管家", now),
+            contact("2", "1", 1, "HuLa 交流群", now),
+        ];
+
+        let mut id = 1000;
+        for (room_id, uid, nickname) in [
+            ("1", "10002", "小管家"),
+            ("2", "10003", "群友"),
+            ("2", "10001", "HuLa"),
+        ] {
+            for i in 0..5 {
+                id += 1;
+                let send_time = now - (5 - i) * 60_000;
+                scenario.messages.push(text_message(
+                    &id.to_string(),
+                    room_id,
+                    uid,
+                    nickname,
+                    &format!("来自 {} 的第 {} 条消息", nickname, i + 1),
+                    send_time,
+                ));
+            }
+        }
+
+        scenario.room_members.insert(
+            "2".to_string(),
+            vec![
+                room_member("2", "10001", "HuLa", now),
+                room_member("2", "10003", "群友", now),
+            ],
+        );
+
+        scenario
+    }
+}
+
+/// 构造一条 `MessageResp` 格式的文本消息
+pub fn text_message(
+    id: &str,
+    room_id: &str,
+    uid: &str,
+    nickname: &str,
+    content: &str,
+    send_time: i64,
+) -> Value {
+    json!({
+        "createId": uid,
+        "createTime": send_time,
+        "updateId": uid,
+        "updateTime": send_time,
+        "fromUser": { "uid": uid, "nickname": nickname },
+        "message": {
+            "id": id,
+            "roomId": room_id,
+            "type": 1,
+            "body": { "content": content },
+            "messageMarks": {},
+            "sendTime": send_time
+        },
+        "oldMsgId": null,
+        "timeBlock": null
+    })
+}
+
+/// 构造一条会话记录
+pub fn contact(room_id: &str, detail_id: &str, contact_type: u32, name: &str, now: i64) -> Value {
+    json!({
+        "id": room_id,
+        "detailId": detail_id,
+        "roomId": room_id,
+        "type": contact_type,
+        "hotFlag": 0,
+        "top": false,
+        "muteNotification": 0,
+        "hide": false,
+        "activeTime": now,
+        "shield": false,
+        "avatar": null,
+        "name": name,
+        "text": "",
+        "unreadCount": 0,
+        "createTime": now,
+        "updateTime": now
+    })
+}
+
+/// 构造一条群成员记录
+pub fn room_member(room_id: &str, uid: &str, name: &str, now: i64) -> Value {
+    json!({
+        "id": format!("{}-{}", room_id, uid),
+        "roomId": room_id,
+        "uid": uid,
+        "account": uid,
+        "myName": null,
+        "activeStatus": 1,
+        "roleId": 3,
+        "locPlace": null,
+        "lastOptTime": now,
+        "createTime": now,
+        "name": name,
+        "avatar": null
+    })
+}
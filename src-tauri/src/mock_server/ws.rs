@@ -0,0 +1,205 @@
+use super::{MockState, WsCommand, WsStep};
+use crate::websocket::codec::{self, DecodedFrame, WireFormat};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex, mpsc};
+use tokio::time::{Duration, sleep};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::HeaderValue,
+    protocol::{CloseFrame, Message, frame::coding::CloseCode},
+};
+use tracing::{debug, info, warn};
+
+pub(super) async fn serve(listener: TcpListener, state: Arc<Mutex<MockState>>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_connection(stream, state.clone()));
+            }
+            Err(e) => {
+                warn!("Mock ws accept failed: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// 从握手请求的查询参数中提取 Token
+fn token_from_request(request: &Request) -> Option<String> {
+    let query = request.uri().query()?;
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "Token")
+        .map(|(_, value)| value.into_owned())
+}
+
+/// 按客户端提供的顺序选出第一个服务端支持的线路格式
+fn select_wire_format(request: &Request, supported: &[WireFormat]) -> Option<WireFormat> {
+    request
+        .headers()
+        .get_all(codec::SUBPROTOCOL_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(WireFormat::from_subprotocol)
+        .find(|format| supported.contains(format))
+}
+
+async fn handle_connection(stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let supported = state.lock().await.scenario.wire_formats.clone();
+    let mut token = None;
+    let mut negotiated = None;
+    let callback = |request: &Request, mut response: Response| {
+        token = token_from_request(request);
+        // 回应选中的子协议，未选中时不回应，客户端会按旧版服务端处理
+        negotiated = select_wire_format(request, &supported);
+        if let Some(format) = negotiated {
+            response.headers_mut().insert(
+                codec::SUBPROTOCOL_HEADER,
+                HeaderValue::from_static(format.subprotocol()),
+            );
+        }
+        Ok::<Response, ErrorResponse>(response)
+    };
+    let ws = match tokio_tungstenite::accept_hdr_async(stream, callback).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            debug!("Mock ws handshake failed: {}", e);
+            return;
+        }
+    };
+    let wire_format = negotiated.unwrap_or(WireFormat::Json);
+    let (mut sink, mut stream) = ws.split();
+
+    let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+    let (authorized, script, mut reply_heartbeat) = {
+        let mut state = state.lock().await;
+        let authorized = token
+            .as_deref()
+            .and_then(|t| state.tokens.get(t))
+            .is_some_and(|info| !info.expired);
+        if authorized {
+            state.ws_clients.push(command_tx.clone());
+            state.ws_connect_count += 1;
+        }
+        (
+            authorized,
+            state.scenario.ws_script.clone(),
+            state.scenario.reply_heartbeat,
+        )
+    };
+
+    if !authorized {
+        if let Ok(message) = codec::encode(
+            wire_format,
+            &json!({ "type": "tokenExpired", "data": null }),
+        ) {
+            let _ = sink.send(message).await;
+        }
+        let _ = sink.close().await;
+        return;
+    }
+    info!("Mock ws client connected, wire format: {:?}", wire_format);
+
+    // 按脚本依次执行
+    let script_task = tokio::spawn({
+        let state = state.clone();
+        let command_tx = command_tx.clone();
+        async move {
+            for step in script {
+                let command = match step {
+                    WsStep::Push(message) => WsCommand::Send(message),
+                    WsStep::Wait(ms) => {
+                        sleep(Duration::from_millis(ms)).await;
+                        continue;
+                    }
+                    WsStep::Drop => WsCommand::Drop,
+                    WsStep::Close(code) => WsCommand::Close(code),
+                    WsStep::StopHeartbeat => WsCommand::StopHeartbeat,
+                    WsStep::ExpireToken => {
+                        state.lock().await.expire_all_tokens();
+                        WsCommand::Send(json!({ "type": "tokenExpired", "data": null }))
+                    }
+                };
+                if command_tx.send(command).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    loop {
+        tokio::select! {
+            incoming = stream.next() => {
+                let message = match incoming {
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(message)) => message,
+                };
+                let value = match codec::decode(wire_format, message) {
+                    Ok(Some(DecodedFrame::Json(value))) => value,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        debug!("Mock ws failed to decode frame: {}", e);
+                        continue;
+                    }
+                };
+
+                match value["type"].as_str() {
+                    Some("2") if reply_heartbeat => {
+                        let pong = json!({
+                            "type": "3",
+                            "timestamp": chrono::Utc::now().timestamp_millis()
+                        });
+                        if !send_json(&mut sink, wire_format, &pong).await {
+                            break;
+                        }
+                    }
+                    Some("15") => {
+                        if let Some(msg_id) = value["data"]["msgId"].as_str() {
+                            state.lock().await.acks.push(msg_id.to_string());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            command = command_rx.recv() => {
+                match command {
+                    Some(WsCommand::Send(message)) => {
+                        if !send_json(&mut sink, wire_format, &message).await {
+                            break;
+                        }
+                    }
+                    Some(WsCommand::StopHeartbeat) => reply_heartbeat = false,
+                    Some(WsCommand::Close(code)) => {
+                        let frame = CloseFrame {
+                            code: CloseCode::from(code),
+                            reason: "mock close".into(),
+                        };
+                        let _ = sink.send(Message::Close(Some(frame))).await;
+                        break;
+                    }
+                    Some(WsCommand::Drop) | None => break,
+                }
+            }
+        }
+    }
+
+    script_task.abort();
+    info!("Mock ws client disconnected");
+}
+
+/// 按协商的线路格式发送，连接已断开时返回 false
+async fn send_json<S>(sink: &mut S, format: WireFormat, value: &Value) -> bool
+where
+    S: SinkExt<Message> + Unpin,
+{
+    match codec::encode(format, value) {
+        Ok(message) => sink.send(message).await.is_ok(),
+        Err(e) => {
+            warn!("Mock ws failed to encode message: {}", e);
+            true
+        }
+    }
+}
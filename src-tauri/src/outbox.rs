@@ -0,0 +1,383 @@
+//! 发件箱
+//!
+//! send_msg 在保存本地消息的同一事务中把请求写入 im_outbox，由后台任务按入队顺序发送：同一房间内
+//! 前一条消息发送完成后才会发送下一条；各房间的队首消息轮流逐条发送（请求共用同一个 HTTP 客户端），
+//! 某个房间的消息等待重试时不影响其他房间。发送失败按指数退避自动重试，超过次数后
+//! 移出队列标记为失败，由用户手动重发或取消。WebSocket 断开期间暂停发送，重连后立即重试。
+//! 队列保存在数据库中，应用重启后继续发送。
+
+use crate::AppData;
+use crate::command::message_command::{self, MessageResp};
+use crate::error::CommonError;
+use crate::repository::im_outbox_repository::{self as repository, STATUS_QUEUED, STATUS_SENDING};
+use crate::repository::{im_message_client_id_repository, im_message_repository};
+use crate::scheduled_message;
+use crate::vo::vo::ChatMessageReq;
+use crate::websocket::commands::get_websocket_client_container;
+use chrono::Utc;
+use entity::im_outbox;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex as StdMutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// 状态变化时推送给前端的事件
+pub const OUTBOX_STATUS_EVENT: &str = "outbox-status";
+
+/// 自动发送的最大尝试次数，超过后标记为失败
+const MAX_AUTO_ATTEMPTS: i32 = 8;
+/// 首次重试的等待时间，之后每次翻倍
+const RETRY_BASE_DELAY_MS: i64 = 1000;
+/// 重试等待时间上限
+const RETRY_MAX_DELAY_MS: i64 = 60_000;
+/// 队列为空或离线时的检查间隔
+const WORKER_IDLE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 入队、重发或网络恢复后唤醒发送任务
+static WORKER_WAKE: Lazy<Notify> = Lazy::new(Notify::new);
+/// 网络恢复后跳过退避等待
+static RETRY_NOW: AtomicBool = AtomicBool::new(false);
+/// 发起发送的窗口注册的通道，key 为本地消息 id
+static SUBSCRIBERS: Lazy<StdMutex<HashMap<String, OutboxSubscriber>>> =
+    Lazy::new(|| StdMutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OutboxStatus {
+    Queued,
+    Sending,
+    Sent,
+    Failed,
+    Cancelled,
+}
+
+impl OutboxStatus {
+    fn is_final(self) -> bool {
+        matches!(self, Self::Sent | Self::Failed | Self::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxEvent {
+    pub msg_id: String,
+    pub room_id: String,
+    pub status: OutboxStatus,
+    /// 已尝试发送的次数
+    pub attempts: i32,
+    /// 排队等待重试时的下一次发送时间
+    pub next_attempt_at: Option<i64>,
+    pub error: Option<String>,
+    /// 发送成功或最终失败后的消息
+    pub message: Option<MessageResp>,
+}
+
+impl OutboxEvent {
+    pub fn new(msg_id: &str, room_id: &str, status: OutboxStatus) -> Self {
+        Self {
+            msg_id: msg_id.to_string(),
+            room_id: room_id.to_string(),
+            status,
+            attempts: 0,
+            next_attempt_at: None,
+            error: None,
+            message: None,
+        }
+    }
+
+    fn from_model(model: &im_outbox::Model, status: OutboxStatus) -> Self {
+        Self {
+            attempts: model.attempts,
+            error: model.last_error.clone(),
+            ..Self::new(&model.id, &model.room_id, status)
+        }
+    }
+}
+
+/// 发送完成后回调的通道。success_channel 收到最终的消息（含发送失败），
+/// error_channel 在无法更新本地消息时收到消息 id
+pub struct OutboxSubscriber {
+    pub success_channel: Option<Channel<MessageResp>>,
+    pub error_channel: Option<Channel<String>>,
+    pub status_channel: Option<Channel<OutboxEvent>>,
+}
+
+pub fn subscribe(msg_id: &str, subscriber: OutboxSubscriber) {
+    SUBSCRIBERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(msg_id.to_string(), subscriber);
+}
+
+/// 推送状态变化；进入最终状态后通知并移除该消息的订阅
+pub fn emit(app_handle: &AppHandle, event: OutboxEvent) {
+    let _ = app_handle.emit_to("home", OUTBOX_STATUS_EVENT, &event);
+    if event.status.is_final() {
+        scheduled_message::on_outbox_finished(app_handle, &event);
+    }
+
+    let mut subscribers = SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner());
+    if !event.status.is_final() {
+        if let Some(channel) = subscribers
+            .get(&event.msg_id)
+            .and_then(|s| s.status_channel.as_ref())
+        {
+            let _ = channel.send(event);
+        }
+        return;
+    }
+    let Some(subscriber) = subscribers.remove(&event.msg_id) else {
+        return;
+    };
+    drop(subscribers);
+
+    if let Some(channel) = &subscriber.status_channel {
+        let _ = channel.send(event.clone());
+    }
+    match (event.status, event.message) {
+        (OutboxStatus::Sent | OutboxStatus::Failed, Some(message)) => {
+            if let Some(channel) = &subscriber.success_channel {
+                let _ = channel.send(message);
+            }
+        }
+        (OutboxStatus::Sent | OutboxStatus::Failed, None) => {
+            if let Some(channel) = &subscriber.error_channel {
+                let _ = channel.send(event.msg_id);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn wake() {
+    WORKER_WAKE.notify_one();
+}
+
+/// WebSocket 重新连接后调用，排队中的消息跳过退避立即重试
+pub fn notify_connectivity_restored() {
+    RETRY_NOW.store(true, Ordering::SeqCst);
+    WORKER_WAKE.notify_one();
+}
+
+/// 第 attempts 次失败后的重试等待时间
+fn retry_delay_ms(attempts: i32) -> i64 {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    (RETRY_BASE_DELAY_MS << exponent).min(RETRY_MAX_DELAY_MS)
+}
+
+/// WebSocket 客户端已创建但未连接时视为离线
+async fn is_online() -> bool {
+    let container = get_websocket_client_container().read().await;
+    container
+        .as_ref()
+        .is_none_or(|client| client.is_connected())
+}
+
+/// 启动发件箱发送任务
+pub fn spawn_worker(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppData>();
+        match repository::requeue_interrupted(state.db_conn.as_ref()).await {
+            Ok(0) => {}
+            Ok(count) => info!("Requeued {} interrupted outbox messages", count),
+            Err(e) => warn!("Failed to requeue interrupted outbox messages: {}", e),
+        }
+
+        loop {
+            let login_uid = state.user_info.lock().await.uid.clone();
+            let mut wait = WORKER_IDLE_INTERVAL;
+            if !login_uid.is_empty() && is_online().await {
+                if let Err(e) = drain(&app_handle, &state, &login_uid).await {
+                    warn!("Failed to deliver outbox messages: {}", e);
+                }
+                match repository::next_attempt_at(state.db_conn.as_ref(), &login_uid).await {
+                    Ok(Some(next)) => {
+                        let delay = (next - Utc::now().timestamp_millis()).max(0) as u64;
+                        wait = wait.min(Duration::from_millis(delay));
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Failed to query next outbox attempt: {}", e),
+                }
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = WORKER_WAKE.notified() => {}
+            }
+        }
+    });
+}
+
+/// 逐条发送各房间队首的消息，直到没有可发送的消息或网络断开；
+/// 等待重试的消息不在 ready_heads 中，不会阻塞其他房间
+async fn drain(
+    app_handle: &AppHandle,
+    state: &AppData,
+    login_uid: &str,
+) -> Result<(), CommonError> {
+    let db = state.db_conn.as_ref();
+    if RETRY_NOW.swap(false, Ordering::SeqCst) {
+        repository::retry_now(db, login_uid, Utc::now().timestamp_millis()).await?;
+    }
+
+    loop {
+        let heads = repository::ready_heads(db, login_uid, Utc::now().timestamp_millis()).await?;
+        if heads.is_empty() {
+            return Ok(());
+        }
+        for item in heads {
+            if !is_online().await {
+                return Ok(());
+            }
+            deliver(app_handle, state, login_uid, item).await?;
+        }
+    }
+}
+
+async fn deliver(
+    app_handle: &AppHandle,
+    state: &AppData,
+    login_uid: &str,
+    mut item: im_outbox::Model,
+) -> Result<(), CommonError> {
+    let db = state.db_conn.as_ref();
+    let claimed = {
+        let _guard = state.write_lock.lock().await;
+        repository::transition(db, login_uid, &item.id, &[STATUS_QUEUED], STATUS_SENDING).await?
+    };
+    if !claimed {
+        return Ok(());
+    }
+
+    // 上一次发送的结果丢失，但服务端回推已确认送达
+    let server_id = im_message_client_id_repository::find(db, login_uid, &item.id)
+        .await?
+        .and_then(|mapping| mapping.server_id);
+    if let Some(server_id) = server_id {
+        let record =
+            im_message_repository::find_message_with_thumbnail(db, login_uid, &server_id).await?;
+        {
+            let _guard = state.write_lock.lock().await;
+            repository::remove(db, login_uid, &item.id, &[]).await?;
+        }
+        let mut event = OutboxEvent::from_model(&item, OutboxStatus::Sent);
+        event.error = None;
+        event.message =
+            record.map(|record| message_command::convert_message_to_resp(record, Some(item.id)));
+        emit(app_handle, event);
+        return Ok(());
+    }
+
+    item.attempts += 1;
+    emit(
+        app_handle,
+        OutboxEvent::from_model(&item, OutboxStatus::Sending),
+    );
+
+    let Some(record) =
+        im_message_repository::find_message_with_thumbnail(db, login_uid, &item.id).await?
+    else {
+        // 本地消息已被删除，不再发送
+        let _guard = state.write_lock.lock().await;
+        repository::remove(db, login_uid, &item.id, &[]).await?;
+        emit(
+            app_handle,
+            OutboxEvent::new(&item.id, &item.room_id, OutboxStatus::Cancelled),
+        );
+        return Ok(());
+    };
+
+    let (resp, error, retry_at) = match serde_json::from_str::<ChatMessageReq>(&item.request) {
+        Ok(request) => match message_command::request_send(&state.rc, request).await {
+            Ok(resp) => (Some(resp), None, None),
+            Err(e) => {
+                let retry_at = (item.attempts < MAX_AUTO_ATTEMPTS)
+                    .then(|| Utc::now().timestamp_millis() + retry_delay_ms(item.attempts));
+                (None, Some(e.to_string()), retry_at)
+            }
+        },
+        Err(e) => (None, Some(format!("消息内容无效: {}", e)), None),
+    };
+
+    if let (Some(error), Some(retry_at)) = (&error, retry_at) {
+        warn!(
+            "Outbox message {} attempt {} failed, retrying: {}",
+            item.id, item.attempts, error
+        );
+        {
+            let _guard = state.write_lock.lock().await;
+            repository::record_attempt(
+                db,
+                login_uid,
+                &item.id,
+                item.attempts,
+                Some(retry_at),
+                error,
+            )
+            .await?;
+        }
+        let mut event = OutboxEvent::new(&item.id, &item.room_id, OutboxStatus::Queued);
+        event.attempts = item.attempts;
+        event.next_attempt_at = Some(retry_at);
+        event.error = Some(error.clone());
+        emit(app_handle, event);
+        return Ok(());
+    }
+
+    let delivered = resp.is_some();
+    let delivery = {
+        let _guard = state.write_lock.lock().await;
+        match &error {
+            Some(error) => {
+                repository::record_attempt(db, login_uid, &item.id, item.attempts, None, error)
+                    .await?
+            }
+            None => {
+                repository::remove(db, login_uid, &item.id, &[]).await?;
+            }
+        }
+        message_command::finish_delivery(db, login_uid.to_string(), record, resp).await
+    };
+
+    let mut event = OutboxEvent::new(
+        &item.id,
+        &item.room_id,
+        if delivered {
+            OutboxStatus::Sent
+        } else {
+            OutboxStatus::Failed
+        },
+    );
+    event.attempts = item.attempts;
+    event.error = error;
+    match delivery {
+        Ok(delivery) => event.message = Some(delivery.message),
+        Err(e) => {
+            warn!("Failed to update outbox message {}: {}", item.id, e);
+            event.error.get_or_insert_with(|| e.to_string());
+        }
+    }
+    info!(
+        "Outbox message {} finished after {} attempts, delivered: {}",
+        item.id, item.attempts, delivered
+    );
+    emit(app_handle, event);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay_backoff() {
+        assert_eq!(retry_delay_ms(1), 1000);
+        assert_eq!(retry_delay_ms(3), 4000);
+        assert_eq!(retry_delay_ms(MAX_AUTO_ATTEMPTS), RETRY_MAX_DELAY_MS);
+    }
+}
@@ -95,3 +95,21 @@ pub async fn update_contact_hide(
 
     Ok(())
 }
+
+/// 按房间 ID 批量查询会话
+pub async fn list_contact_by_room_ids(
+    db: &DatabaseConnection,
+    login_uid: &str,
+    room_ids: &[String],
+) -> Result<Vec<im_contact::Model>, CommonError> {
+    if room_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let list = im_contact::Entity::find()
+        .filter(im_contact::Column::LoginUid.eq(login_uid))
+        .filter(im_contact::Column::RoomId.is_in(room_ids.iter().cloned()))
+        .all(db)
+        .await?;
+    Ok(list)
+}
@@ -0,0 +1,335 @@
+use crate::error::CommonError;
+use crate::repository::im_message_fts_repository::escape_like;
+use entity::im_favorite_message;
+use sea_orm::prelude::Expr;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, EntityTrait, IntoActiveModel, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, Statement,
+};
+use serde::Serialize;
+
+/// 收藏列表的过滤条件，各条件之间为"且"的关系
+#[derive(Debug, Clone, Default)]
+pub struct FavoriteFilter {
+    /// 按空白拆分的关键词，匹配消息文本、文件名和发送人昵称
+    pub keyword: Option<String>,
+    pub tag: Option<String>,
+    pub message_types: Vec<u8>,
+    pub room_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FavoriteTagCount {
+    pub tag: String,
+    pub count: u32,
+}
+
+/// 读取消息当前可检索的文本，提取规则与全文索引共用 im_message_search_source 视图
+pub async fn extract_search_text<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+) -> Result<String, CommonError> {
+    let sql = r#"
+        SELECT trim(COALESCE(s.text_content, '') || ' ' || COALESCE(s.file_name, '') || ' ' ||
+            COALESCE(s.extra, '')) AS search_text
+        FROM im_message m
+        JOIN im_message_search_source s ON s.message_rowid = m.rowid
+        WHERE m.login_uid = ? AND m.id = ?
+    "#;
+    let row = db
+        .query_one(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            [login_uid.into(), message_id.into()],
+        ))
+        .await?;
+    match row {
+        Some(row) => Ok(row.try_get("", "search_text")?),
+        None => Ok(String::new()),
+    }
+}
+
+/// 保存收藏。已收藏时刷新快照，保留原有的收藏时间和标签
+pub async fn upsert<C: ConnectionTrait>(
+    db: &C,
+    model: im_favorite_message::Model,
+) -> Result<(), CommonError> {
+    im_favorite_message::Entity::insert(model.into_active_model())
+        .on_conflict(
+            OnConflict::columns([
+                im_favorite_message::Column::MessageId,
+                im_favorite_message::Column::LoginUid,
+            ])
+            .update_columns([
+                im_favorite_message::Column::RoomId,
+                im_favorite_message::Column::FromUid,
+                im_favorite_message::Column::FromNickname,
+                im_favorite_message::Column::FromAvatar,
+                im_favorite_message::Column::MessageType,
+                im_favorite_message::Column::Body,
+                im_favorite_message::Column::ThumbnailPath,
+                im_favorite_message::Column::SendTime,
+                im_favorite_message::Column::SearchText,
+                im_favorite_message::Column::UpdateTime,
+            ])
+            .to_owned(),
+        )
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+pub async fn find<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+) -> Result<Option<im_favorite_message::Model>, CommonError> {
+    let model =
+        im_favorite_message::Entity::find_by_id((message_id.to_string(), login_uid.to_string()))
+            .one(db)
+            .await?;
+    Ok(model)
+}
+
+/// 替换标签，返回是否更新
+pub async fn set_tags<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+    tags: &[String],
+    update_time: i64,
+) -> Result<bool, CommonError> {
+    let tags = serde_json::to_string(tags).map_err(|e| anyhow::anyhow!(e))?;
+    let result = im_favorite_message::Entity::update_many()
+        .col_expr(im_favorite_message::Column::Tags, Expr::value(tags))
+        .col_expr(
+            im_favorite_message::Column::UpdateTime,
+            Expr::value(update_time),
+        )
+        .filter(im_favorite_message::Column::LoginUid.eq(login_uid))
+        .filter(im_favorite_message::Column::MessageId.eq(message_id))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// 取消收藏，返回删除条数
+pub async fn remove<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_ids: &[String],
+) -> Result<u64, CommonError> {
+    let result = im_favorite_message::Entity::delete_many()
+        .filter(im_favorite_message::Column::LoginUid.eq(login_uid))
+        .filter(im_favorite_message::Column::MessageId.is_in(message_ids.iter().cloned()))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+fn filter_condition(login_uid: &str, filter: &FavoriteFilter) -> Condition {
+    let mut condition = Condition::all().add(im_favorite_message::Column::LoginUid.eq(login_uid));
+    if let Some(room_id) = &filter.room_id {
+        condition = condition.add(im_favorite_message::Column::RoomId.eq(room_id.as_str()));
+    }
+    if !filter.message_types.is_empty() {
+        condition = condition.add(
+            im_favorite_message::Column::MessageType.is_in(filter.message_types.iter().copied()),
+        );
+    }
+    if let Some(tag) = &filter.tag {
+        condition = condition.add(Expr::cust_with_values(
+            r#"EXISTS (SELECT 1 FROM json_each("im_favorite_message"."tags") WHERE value = ?)"#,
+            [tag.as_str()],
+        ));
+    }
+    for term in filter
+        .keyword
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+    {
+        let pattern = format!("%{}%", escape_like(term));
+        condition = condition.add(Expr::cust_with_values(
+            r#"("im_favorite_message"."search_text" LIKE ? ESCAPE '\'
+                OR COALESCE("im_favorite_message"."from_nickname", '') LIKE ? ESCAPE '\')"#,
+            [pattern.clone(), pattern],
+        ));
+    }
+    condition
+}
+
+/// 按收藏时间倒序分页，before 为上一页最后一条的 (收藏时间, 消息 ID)
+pub async fn list<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    filter: &FavoriteFilter,
+    before: Option<(i64, String)>,
+    limit: u64,
+) -> Result<Vec<im_favorite_message::Model>, CommonError> {
+    let mut condition = filter_condition(login_uid, filter);
+    if let Some((create_time, message_id)) = before {
+        condition = condition.add(Expr::cust_with_values(
+            r#"("im_favorite_message"."create_time", "im_favorite_message"."message_id") < (?, ?)"#,
+            [
+                sea_orm::Value::from(create_time),
+                sea_orm::Value::from(message_id),
+            ],
+        ));
+    }
+
+    let favorites = im_favorite_message::Entity::find()
+        .filter(condition)
+        .order_by_desc(im_favorite_message::Column::CreateTime)
+        .order_by_desc(im_favorite_message::Column::MessageId)
+        .limit(limit)
+        .all(db)
+        .await?;
+    Ok(favorites)
+}
+
+/// 符合过滤条件的收藏总数
+pub async fn count<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    filter: &FavoriteFilter,
+) -> Result<u64, CommonError> {
+    let count = im_favorite_message::Entity::find()
+        .filter(filter_condition(login_uid, filter))
+        .count(db)
+        .await?;
+    Ok(count)
+}
+
+/// 统计各标签的收藏数，按数量降序
+pub async fn list_tags<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+) -> Result<Vec<FavoriteTagCount>, CommonError> {
+    let sql = r#"
+        SELECT t.value AS tag, COUNT(*) AS tag_count
+        FROM im_favorite_message f, json_each(f.tags) t
+        WHERE f.login_uid = ?
+        GROUP BY t.value
+        ORDER BY tag_count DESC, tag
+    "#;
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            [login_uid.into()],
+        ))
+        .await?;
+    let mut tags = Vec::with_capacity(rows.len());
+    for row in rows {
+        tags.push(FavoriteTagCount {
+            tag: row.try_get("", "tag")?,
+            count: row.try_get::<i64>("", "tag_count")? as u32,
+        });
+    }
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::im_message_repository::{self, MessageWithThumbnail};
+    use crate::repository::test_support::migrated_db;
+    use entity::im_message;
+
+    fn favorite(
+        message_id: &str,
+        search_text: &str,
+        create_time: i64,
+    ) -> im_favorite_message::Model {
+        im_favorite_message::Model {
+            message_id: message_id.to_string(),
+            login_uid: "10001".to_string(),
+            room_id: "1".to_string(),
+            from_uid: "10002".to_string(),
+            from_nickname: Some("Alice".to_string()),
+            from_avatar: None,
+            message_type: Some(1),
+            body: None,
+            thumbnail_path: None,
+            send_time: Some(0),
+            search_text: search_text.to_string(),
+            tags: "[]".to_string(),
+            create_time,
+            update_time: create_time,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_favorites_survive_message_delete() {
+        let db = migrated_db().await;
+
+        im_message_repository::save_all(
+            &db,
+            vec![MessageWithThumbnail::from(im_message::Model {
+                id: "1".to_string(),
+                uid: "10002".to_string(),
+                nickname: None,
+                room_id: "1".to_string(),
+                send_time: Some(0),
+                message_type: Some(4),
+                body: Some(r#"{"fileName":"报告.pdf","url":"https://x"}"#.to_string()),
+                message_marks: None,
+                create_time: None,
+                update_time: None,
+                login_uid: "10001".to_string(),
+                send_status: "success".to_string(),
+                time_block: None,
+                edited_at: None,
+                reply_to_id: None,
+            })],
+        )
+        .await
+        .unwrap();
+        let text = extract_search_text(&db, "10001", "1").await.unwrap();
+        assert_eq!(text, "报告.pdf");
+
+        upsert(&db, favorite("1", &text, 10)).await.unwrap();
+        upsert(&db, favorite("2", "hello world", 20)).await.unwrap();
+        set_tags(&db, "10001", "1", &["工作".to_string()], 30)
+            .await
+            .unwrap();
+        // 重新收藏只刷新快照，不覆盖标签和收藏时间
+        upsert(&db, favorite("1", &text, 40)).await.unwrap();
+        im_message::Entity::delete_many().exec(&db).await.unwrap();
+
+        let filter = FavoriteFilter {
+            tag: Some("工作".to_string()),
+            ..Default::default()
+        };
+        let tagged = list(&db, "10001", &filter, None, 10).await.unwrap();
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(tagged[0].create_time, 10);
+
+        let filter = FavoriteFilter {
+            keyword: Some("alice 报告".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            list(&db, "10001", &filter, None, 10).await.unwrap().len(),
+            1
+        );
+        assert_eq!(count(&db, "10001", &filter).await.unwrap(), 1);
+
+        let page = list(
+            &db,
+            "10001",
+            &FavoriteFilter::default(),
+            Some((20, "2".to_string())),
+            10,
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].message_id, "1");
+        assert_eq!(list_tags(&db, "10001").await.unwrap()[0].count, 1);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::error::CommonError;
+use crate::repository::SQLITE_MAX_VARIABLES;
+use entity::im_link_preview;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, IntoActiveModel, QueryFilter};
+use std::collections::HashMap;
+
+/// 查询未过期的缓存（含抓取失败的记录），key 为 URL
+pub async fn find_valid<C: ConnectionTrait>(
+    db: &C,
+    urls: &[String],
+    now: i64,
+) -> Result<HashMap<String, im_link_preview::Model>, CommonError> {
+    let mut previews = HashMap::new();
+    for chunk in urls.chunks(SQLITE_MAX_VARIABLES - 1) {
+        let models = im_link_preview::Entity::find()
+            .filter(im_link_preview::Column::Url.is_in(chunk.iter().cloned()))
+            .filter(im_link_preview::Column::ExpiresAt.gt(now))
+            .all(db)
+            .await?;
+        previews.extend(models.into_iter().map(|model| (model.url.clone(), model)));
+    }
+    Ok(previews)
+}
+
+pub async fn upsert<C: ConnectionTrait>(
+    db: &C,
+    model: im_link_preview::Model,
+) -> Result<(), CommonError> {
+    im_link_preview::Entity::insert(model.into_active_model())
+        .on_conflict(
+            OnConflict::column(im_link_preview::Column::Url)
+                .update_columns([
+                    im_link_preview::Column::Title,
+                    im_link_preview::Column::Description,
+                    im_link_preview::Column::Image,
+                    im_link_preview::Column::SiteName,
+                    im_link_preview::Column::Favicon,
+                    im_link_preview::Column::Success,
+                    im_link_preview::Column::FetchedAt,
+                    im_link_preview::Column::ExpiresAt,
+                ])
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// 删除过期的缓存，返回删除条数
+pub async fn delete_expired<C: ConnectionTrait>(db: &C, now: i64) -> Result<u64, CommonError> {
+    let result = im_link_preview::Entity::delete_many()
+        .filter(im_link_preview::Column::ExpiresAt.lte(now))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+
+    fn preview(url: &str, title: &str, expires_at: i64) -> im_link_preview::Model {
+        im_link_preview::Model {
+            url: url.to_string(),
+            title: Some(title.to_string()),
+            description: None,
+            image: None,
+            site_name: None,
+            favicon: None,
+            success: true,
+            fetched_at: 0,
+            expires_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_expires() {
+        let db = migrated_db().await;
+
+        upsert(&db, preview("https://a.com", "A", 100))
+            .await
+            .unwrap();
+        upsert(&db, preview("https://b.com", "B", 10))
+            .await
+            .unwrap();
+        upsert(&db, preview("https://a.com", "A2", 100))
+            .await
+            .unwrap();
+
+        let urls = vec!["https://a.com".to_string(), "https://b.com".to_string()];
+        let valid = find_valid(&db, &urls, 50).await.unwrap();
+        assert_eq!(valid.len(), 1);
+        assert_eq!(valid["https://a.com"].title.as_deref(), Some("A2"));
+        assert_eq!(delete_expired(&db, 50).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::error::CommonError;
+use crate::repository::SQLITE_MAX_VARIABLES;
+use chrono::Utc;
+use entity::im_message_client_id;
+use sea_orm::sea_query::{OnConflict, Value};
+use sea_orm::{ConnectionTrait, EntityTrait, Set, Statement};
+use std::collections::HashMap;
+
+/// 发送前登记客户端 ID，已登记时不做修改
+pub async fn register<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    client_id: &str,
+    room_id: &str,
+) -> Result<(), CommonError> {
+    im_message_client_id::Entity::insert(im_message_client_id::ActiveModel {
+        client_id: Set(client_id.to_string()),
+        login_uid: Set(login_uid.to_string()),
+        server_id: Set(None),
+        room_id: Set(room_id.to_string()),
+        create_time: Set(Utc::now().timestamp_millis()),
+    })
+    .on_conflict(
+        OnConflict::columns([
+            im_message_client_id::Column::ClientId,
+            im_message_client_id::Column::LoginUid,
+        ])
+        .do_nothing()
+        .to_owned(),
+    )
+    .do_nothing()
+    .exec(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn find<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    client_id: &str,
+) -> Result<Option<im_message_client_id::Model>, CommonError> {
+    let model =
+        im_message_client_id::Entity::find_by_id((client_id.to_string(), login_uid.to_string()))
+            .one(db)
+            .await?;
+    Ok(model)
+}
+
+/// 记录客户端 ID 对应的服务端 ID，并把本地以客户端 ID 保存的消息合并到服务端 ID 上
+pub async fn bind<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    client_id: &str,
+    server_id: &str,
+    room_id: &str,
+) -> Result<(), CommonError> {
+    if client_id == server_id {
+        return Ok(());
+    }
+    im_message_client_id::Entity::insert(im_message_client_id::ActiveModel {
+        client_id: Set(client_id.to_string()),
+        login_uid: Set(login_uid.to_string()),
+        server_id: Set(Some(server_id.to_string())),
+        room_id: Set(room_id.to_string()),
+        create_time: Set(Utc::now().timestamp_millis()),
+    })
+    .on_conflict(
+        OnConflict::columns([
+            im_message_client_id::Column::ClientId,
+            im_message_client_id::Column::LoginUid,
+        ])
+        .update_column(im_message_client_id::Column::ServerId)
+        .to_owned(),
+    )
+    .exec(db)
+    .await?;
+    merge_message(db, login_uid, client_id, server_id).await
+}
+
+/// 批量查询已确认的客户端 ID 对应的服务端 ID
+pub async fn resolve<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    client_ids: &[&str],
+) -> Result<HashMap<String, String>, CommonError> {
+    let mut resolved = HashMap::new();
+    for chunk in client_ids.chunks(SQLITE_MAX_VARIABLES - 1) {
+        let sql = format!(
+            "SELECT client_id, server_id FROM im_message_client_id \
+             WHERE login_uid = ? AND server_id IS NOT NULL AND client_id IN ({})",
+            vec!["?"; chunk.len()].join(", ")
+        );
+        let mut values = vec![Value::from(login_uid.to_string())];
+        values.extend(chunk.iter().map(|id| Value::from(id.to_string())));
+        let rows = db
+            .query_all(Statement::from_sql_and_values(
+                db.get_database_backend(),
+                sql,
+                values,
+            ))
+            .await?;
+        for row in rows {
+            resolved.insert(row.try_get("", "client_id")?, row.try_get("", "server_id")?);
+        }
+    }
+    Ok(resolved)
+}
+
+/// 保存服务端消息前调用：server_ids 中已知映射且本地仍有客户端 ID 消息的，先合并到服务端 ID 上，
+/// 避免同一条消息以两个 ID 各存一份。返回合并的条数
+pub async fn merge_known<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    server_ids: &[&str],
+) -> Result<usize, CommonError> {
+    let mut pending = HashMap::new();
+    for chunk in server_ids.chunks(SQLITE_MAX_VARIABLES - 1) {
+        let sql = format!(
+            "SELECT c.client_id, c.server_id FROM im_message_client_id c \
+             JOIN im_message m ON m.login_uid = c.login_uid AND m.id = c.client_id \
+             WHERE c.login_uid = ? AND c.server_id IN ({})",
+            vec!["?"; chunk.len()].join(", ")
+        );
+        let mut values = vec![Value::from(login_uid.to_string())];
+        values.extend(chunk.iter().map(|id| Value::from(id.to_string())));
+        let rows = db
+            .query_all(Statement::from_sql_and_values(
+                db.get_database_backend(),
+                sql,
+                values,
+            ))
+            .await?;
+        for row in rows {
+            let client_id: String = row.try_get("", "client_id")?;
+            let server_id: String = row.try_get("", "server_id")?;
+            pending.insert(client_id, server_id);
+        }
+    }
+
+    for (client_id, server_id) in &pending {
+        merge_message(db, login_uid, client_id, server_id).await?;
+    }
+    Ok(pending.len())
+}
+
+/// 服务端 ID 的消息已存在时删除客户端 ID 的消息（保留其缩略图），否则把客户端 ID 的消息改为服务端 ID
+async fn merge_message<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    client_id: &str,
+    server_id: &str,
+) -> Result<(), CommonError> {
+    let backend = db.get_database_backend();
+    db.execute(Statement::from_sql_and_values(
+        backend,
+        r#"
+            UPDATE im_message SET thumbnail_path = (
+                SELECT c.thumbnail_path FROM im_message c WHERE c.login_uid = ? AND c.id = ?
+            )
+            WHERE login_uid = ? AND id = ? AND thumbnail_path IS NULL
+        "#,
+        [
+            login_uid.into(),
+            client_id.into(),
+            login_uid.into(),
+            server_id.into(),
+        ],
+    ))
+    .await?;
+    db.execute(Statement::from_sql_and_values(
+        backend,
+        r#"
+            DELETE FROM im_message WHERE login_uid = ? AND id = ?
+                AND EXISTS (SELECT 1 FROM im_message s WHERE s.login_uid = ? AND s.id = ?)
+        "#,
+        [
+            login_uid.into(),
+            client_id.into(),
+            login_uid.into(),
+            server_id.into(),
+        ],
+    ))
+    .await?;
+    db.execute(Statement::from_sql_and_values(
+        backend,
+        "UPDATE im_message SET id = ? WHERE login_uid = ? AND id = ?",
+        [server_id.into(), login_uid.into(), client_id.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::im_message_repository::{self, MessageWithThumbnail};
+    use crate::repository::test_support::migrated_db;
+    use entity::im_message;
+    use sea_orm::PaginatorTrait;
+
+    fn message(id: &str, send_status: &str, thumbnail_path: Option<&str>) -> MessageWithThumbnail {
+        MessageWithThumbnail::new(
+            im_message::Model {
+                id: id.to_string(),
+                uid: "10001".to_string(),
+                nickname: None,
+                room_id: "1".to_string(),
+                send_time: Some(1),
+                message_type: Some(3),
+                body: Some(r#"{"url":"a.png"}"#.to_string()),
+                message_marks: None,
+                create_time: None,
+                update_time: None,
+                login_uid: "10001".to_string(),
+                send_status: send_status.to_string(),
+                time_block: None,
+                edited_at: None,
+                reply_to_id: None,
+            },
+            thumbnail_path.map(str::to_string),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_echo_before_send_result_is_merged() {
+        let db = migrated_db().await;
+
+        register(&db, "10001", "T1", "1").await.unwrap();
+        im_message_repository::save_all(&db, vec![message("T1", "pending", Some("/tmp/a.png"))])
+            .await
+            .unwrap();
+
+        // 服务端回推先于发送结果到达
+        bind(&db, "10001", "T1", "100", "1").await.unwrap();
+        im_message_repository::save_all(&db, vec![message("100", "success", None)])
+            .await
+            .unwrap();
+        assert_eq!(im_message::Entity::find().count(&db).await.unwrap(), 1);
+
+        // 已确认的客户端 ID 再次写入时改用服务端 ID，不会产生重复消息
+        register(&db, "10001", "T2", "1").await.unwrap();
+        bind(&db, "10001", "T2", "200", "1").await.unwrap();
+        im_message_repository::save_all(&db, vec![message("T2", "pending", Some("/tmp/b.png"))])
+            .await
+            .unwrap();
+        im_message_repository::save_all(&db, vec![message("200", "success", None)])
+            .await
+            .unwrap();
+
+        let saved = im_message_repository::find_message_with_thumbnail(&db, "10001", "200")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(saved.thumbnail_path.as_deref(), Some("/tmp/b.png"));
+        assert_eq!(im_message::Entity::find().count(&db).await.unwrap(), 2);
+        let mapping = find(&db, "10001", "T2").await.unwrap().unwrap();
+        assert_eq!(mapping.server_id.as_deref(), Some("200"));
+    }
+}
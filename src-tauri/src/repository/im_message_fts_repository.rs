@@ -0,0 +1,798 @@
+use crate::error::CommonError;
+use crate::repository::im_message_repository::{
+    MessageKey, MessageWithThumbnail, enrich_models_with_thumbnails,
+};
+use entity::im_message;
+use sea_orm::prelude::Expr;
+use sea_orm::sea_query::{SimpleExpr, Value};
+use sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryResult, Statement};
+use std::collections::HashMap;
+use tracing::debug;
+
+/// trigram 分词器要求关键词至少 3 个字符才能走 MATCH
+const TRIGRAM_MIN_CHARS: usize = 3;
+/// 摘要中关键词前后保留的字符数
+const SNIPPET_CONTEXT_CHARS: usize = 16;
+/// snippet() 返回的高亮标记，转义后再替换为 <mark>，避免消息内容中的 HTML 被前端渲染
+const MARK_START: char = '\u{2}';
+const MARK_END: char = '\u{3}';
+/// bm25 列权重：正文、文件名、回复/引用
+const BM25_WEIGHTS: &str = "1.0, 2.0, 0.5";
+
+/// 解析后的全文检索关键词，多个关键词之间为"且"的关系
+#[derive(Debug, Clone, PartialEq)]
+pub struct FtsQuery {
+    terms: Vec<String>,
+}
+
+impl FtsQuery {
+    /// 按空白拆分关键词；关键词为空时返回 None
+    pub fn parse(keyword: &str) -> Option<Self> {
+        let terms: Vec<String> = keyword.split_whitespace().map(str::to_string).collect();
+        if terms.is_empty() {
+            None
+        } else {
+            Some(Self { terms })
+        }
+    }
+
+    /// 所有关键词都不少于 3 个字符时走 FTS5 MATCH，否则在索引列上逐个 LIKE
+    fn uses_match(&self) -> bool {
+        self.terms
+            .iter()
+            .all(|term| term.chars().count() >= TRIGRAM_MIN_CHARS)
+    }
+
+    /// 每个关键词作为短语处理，避免其中的 AND/OR/* 等被当作查询语法
+    fn match_expr(&self) -> String {
+        self.terms
+            .iter()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// 生成 im_message_fts 上的 WHERE 子句及参数
+    fn where_clause(&self) -> (String, Vec<Value>) {
+        if self.uses_match() {
+            return (
+                "im_message_fts MATCH ?".to_string(),
+                vec![Value::from(self.match_expr())],
+            );
+        }
+
+        let mut clauses = Vec::with_capacity(self.terms.len());
+        let mut values = Vec::with_capacity(self.terms.len() * 3);
+        for term in &self.terms {
+            let pattern = format!("%{}%", escape_like(term));
+            clauses.push(
+                "(im_message_fts.text_content LIKE ? ESCAPE '\\' \
+                 OR im_message_fts.file_name LIKE ? ESCAPE '\\' \
+                 OR im_message_fts.extra LIKE ? ESCAPE '\\')",
+            );
+            for _ in 0..3 {
+                values.push(Value::from(pattern.clone()));
+            }
+        }
+        (clauses.join(" AND "), values)
+    }
+
+    /// 用于 im_message 查询的过滤条件
+    pub fn condition(&self) -> SimpleExpr {
+        let (clause, values) = self.where_clause();
+        Expr::cust_with_values(
+            format!(
+                "\"im_message\".rowid IN (SELECT rowid FROM im_message_fts WHERE {})",
+                clause
+            ),
+            values,
+        )
+    }
+}
+
+pub(crate) fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 将带有 MARK_START/MARK_END 标记的文本转义为 HTML 高亮片段
+fn render_marked(text: &str) -> String {
+    escape_html(text)
+        .replace(MARK_START, "<mark>")
+        .replace(MARK_END, "</mark>")
+}
+
+/// 在文本中查找关键词（ASCII 不区分大小写，与 SQLite LIKE 一致）并截取高亮摘要
+fn highlight(text: &str, terms: &[String]) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let terms: Vec<Vec<char>> = terms
+        .iter()
+        .map(|term| term.chars().map(|c| c.to_ascii_lowercase()).collect())
+        .filter(|term: &Vec<char>| !term.is_empty())
+        .collect();
+
+    let match_at = |pos: usize| {
+        terms
+            .iter()
+            .filter(|term| lower[pos..].starts_with(term))
+            .map(|term| term.len())
+            .max()
+    };
+
+    let first = (0..lower.len()).find(|&pos| match_at(pos).is_some())?;
+    let start = first.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let end = (first + SNIPPET_CONTEXT_CHARS * 2).min(chars.len());
+
+    let mut marked = String::new();
+    if start > 0 {
+        marked.push('…');
+    }
+    let mut pos = start;
+    while pos < end {
+        match match_at(pos) {
+            Some(len) => {
+                let stop = (pos + len).min(chars.len());
+                marked.push(MARK_START);
+                marked.extend(&chars[pos..stop]);
+                marked.push(MARK_END);
+                pos = stop;
+            }
+            None => {
+                marked.push(chars[pos]);
+                pos += 1;
+            }
+        }
+    }
+    if pos < chars.len() {
+        marked.push('…');
+    }
+
+    Some(render_marked(&marked))
+}
+
+/// 单条检索结果
+#[derive(Debug, Clone)]
+pub struct MessageSearchHit {
+    pub message: MessageWithThumbnail,
+    /// 已转义的 HTML 摘要，关键词以 <mark> 包裹
+    pub snippet: String,
+    /// bm25 得分，越小越相关；LIKE 检索时为 None
+    pub rank: Option<f64>,
+}
+
+struct RawHit {
+    id: String,
+    snippet: String,
+    rank: Option<f64>,
+}
+
+/// 查询摘要所需的列，需与 im_message_fts 联表
+fn snippet_select(query: &FtsQuery) -> String {
+    if query.uses_match() {
+        format!(
+            "bm25(im_message_fts, {}) AS score, \
+             snippet(im_message_fts, -1, char(2), char(3), '…', 24) AS snippet",
+            BM25_WEIGHTS
+        )
+    } else {
+        "NULL AS score, im_message_fts.text_content AS text_content, \
+         im_message_fts.file_name AS file_name, im_message_fts.extra AS extra"
+            .to_string()
+    }
+}
+
+fn read_hit(row: &QueryResult, query: &FtsQuery) -> Result<RawHit, CommonError> {
+    let id: String = row.try_get("", "id")?;
+    let rank: Option<f64> = row.try_get("", "score")?;
+    let snippet = if query.uses_match() {
+        let snippet: Option<String> = row.try_get("", "snippet")?;
+        render_marked(&snippet.unwrap_or_default())
+    } else {
+        let mut snippet = None;
+        for column in ["text_content", "file_name", "extra"] {
+            let text: Option<String> = row.try_get("", column)?;
+            if let Some(found) = text.and_then(|t| highlight(&t, &query.terms)) {
+                snippet = Some(found);
+                break;
+            }
+        }
+        snippet.unwrap_or_default()
+    };
+    Ok(RawHit { id, snippet, rank })
+}
+
+/// 加载命中的消息，保持命中顺序
+async fn load_hits<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    hits: Vec<RawHit>,
+) -> Result<Vec<MessageSearchHit>, CommonError> {
+    if hits.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ids: Vec<String> = hits.iter().map(|hit| hit.id.clone()).collect();
+    let models = im_message::Entity::find()
+        .filter(im_message::Column::LoginUid.eq(login_uid))
+        .filter(im_message::Column::Id.is_in(ids))
+        .all(db)
+        .await?;
+
+    let mut messages: HashMap<String, MessageWithThumbnail> =
+        enrich_models_with_thumbnails(db, models)
+            .await?
+            .into_iter()
+            .map(|record| (record.message.id.clone(), record))
+            .collect();
+
+    Ok(hits
+        .into_iter()
+        .filter_map(|hit| {
+            messages.remove(&hit.id).map(|message| MessageSearchHit {
+                message,
+                snippet: hit.snippet,
+                rank: hit.rank,
+            })
+        })
+        .collect())
+}
+
+async fn query_hits<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: Option<&str>,
+    query: &FtsQuery,
+    ids: Option<&[String]>,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<RawHit>, CommonError> {
+    let (fts_clause, mut values) = query.where_clause();
+    let mut sql = format!(
+        "SELECT m.id AS id, {} FROM im_message_fts \
+         JOIN im_message m ON m.rowid = im_message_fts.rowid \
+         WHERE {} AND m.login_uid = ?",
+        snippet_select(query),
+        fts_clause
+    );
+    values.push(Value::from(login_uid.to_string()));
+
+    if let Some(room_id) = room_id {
+        sql.push_str(" AND m.room_id = ?");
+        values.push(Value::from(room_id.to_string()));
+    }
+    if let Some(ids) = ids {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        sql.push_str(&format!(
+            " AND m.id IN ({})",
+            vec!["?"; ids.len()].join(", ")
+        ));
+        values.extend(ids.iter().map(|id| Value::from(id.clone())));
+    }
+
+    sql.push_str(if query.uses_match() {
+        " ORDER BY score"
+    } else {
+        " ORDER BY m.send_time DESC"
+    });
+    sql.push_str(" LIMIT ? OFFSET ?");
+    values.push(Value::from(limit));
+    values.push(Value::from(offset));
+
+    let stmt = Statement::from_sql_and_values(db.get_database_backend(), sql, values);
+    db.query_all(stmt)
+        .await?
+        .iter()
+        .map(|row| read_hit(row, query))
+        .collect()
+}
+
+/// 获取指定消息的高亮摘要，key 为消息 ID
+pub async fn fetch_snippets<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    query: &FtsQuery,
+    message_ids: &[String],
+) -> Result<HashMap<String, String>, CommonError> {
+    let hits = query_hits(
+        db,
+        login_uid,
+        None,
+        query,
+        Some(message_ids),
+        message_ids.len() as u32,
+        0,
+    )
+    .await?;
+
+    Ok(hits
+        .into_iter()
+        .filter(|hit| !hit.snippet.is_empty())
+        .map(|hit| (hit.id, hit.snippet))
+        .collect())
+}
+
+/// 按相关度检索消息，room_id 为空时跨房间检索
+pub async fn search_messages<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: Option<&str>,
+    query: &FtsQuery,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<MessageSearchHit>, CommonError> {
+    let hits = query_hits(db, login_uid, room_id, query, None, limit, offset).await?;
+    debug!("Full-text search returned {} hits", hits.len());
+    load_hits(db, login_uid, hits).await
+}
+
+/// 删除 im_message 中已不存在的索引行，返回删除数量
+pub async fn remove_orphan_fts_rows<C: ConnectionTrait>(db: &C) -> Result<u64, CommonError> {
+    let result = db
+        .execute_unprepared(
+            "DELETE FROM im_message_fts WHERE rowid NOT IN (SELECT rowid FROM im_message)",
+        )
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// 重建全文索引
+///
+/// im_message 没有 INTEGER PRIMARY KEY，VACUUM 后 rowid 可能被重新分配，需要重建
+pub async fn rebuild_message_fts<C: ConnectionTrait>(db: &C) -> Result<(), CommonError> {
+    db.execute_unprepared("DELETE FROM im_message_fts").await?;
+    db.execute_unprepared(
+        "INSERT INTO im_message_fts (rowid, text_content, file_name, extra) \
+         SELECT message_rowid, text_content, file_name, extra FROM im_message_search_source",
+    )
+    .await?;
+    Ok(())
+}
+
+/// 跨房间检索的过滤条件，各条件之间为"且"的关系
+#[derive(Debug, Clone, Default)]
+pub struct GlobalSearchFilter {
+    pub query: Option<FtsQuery>,
+    /// 发送人 uid，为空表示不限
+    pub sender_uids: Vec<String>,
+    /// 消息类型，为空表示不限
+    pub message_types: Vec<u8>,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    /// 仅返回 @ 了当前用户（含 @全体成员）的消息，以 im_message_mention 为准
+    pub mentions_me: bool,
+}
+
+impl GlobalSearchFilter {
+    pub fn is_empty(&self) -> bool {
+        self.query.is_none()
+            && self.sender_uids.is_empty()
+            && self.message_types.is_empty()
+            && self.start_time.is_none()
+            && self.end_time.is_none()
+            && !self.mentions_me
+    }
+
+    /// 生成 FROM 与 WHERE 子句（不含游标条件），im_message 别名为 m
+    fn from_where(&self, login_uid: &str) -> (String, Vec<Value>) {
+        let mut values = Vec::new();
+        let mut sql = match &self.query {
+            Some(query) => {
+                let (clause, fts_values) = query.where_clause();
+                values.extend(fts_values);
+                format!(
+                    "FROM im_message_fts JOIN im_message m ON m.rowid = im_message_fts.rowid \
+                     WHERE {} AND m.login_uid = ?",
+                    clause
+                )
+            }
+            // 无关键词时不经过索引，需自行排除撤回消息
+            None => "FROM im_message m WHERE m.login_uid = ? \
+                     AND (m.message_type IS NULL OR m.message_type <> 2)"
+                .to_string(),
+        };
+        values.push(Value::from(login_uid.to_string()));
+
+        if !self.sender_uids.is_empty() {
+            sql.push_str(&format!(
+                " AND m.uid IN ({})",
+                vec!["?"; self.sender_uids.len()].join(", ")
+            ));
+            values.extend(self.sender_uids.iter().map(|uid| Value::from(uid.clone())));
+        }
+        if !self.message_types.is_empty() {
+            sql.push_str(&format!(
+                " AND m.message_type IN ({})",
+                vec!["?"; self.message_types.len()].join(", ")
+            ));
+            values.extend(self.message_types.iter().map(|t| Value::from(*t)));
+        }
+        if let Some(start_time) = self.start_time {
+            sql.push_str(" AND m.send_time >= ?");
+            values.push(Value::from(start_time));
+        }
+        if let Some(end_time) = self.end_time {
+            sql.push_str(" AND m.send_time <= ?");
+            values.push(Value::from(end_time));
+        }
+        // 提及的判定只在 im_message_mention 的触发器中维护一份
+        if self.mentions_me {
+            sql.push_str(
+                " AND EXISTS (SELECT 1 FROM im_message_mention mm \
+                 WHERE mm.message_id = m.id AND mm.login_uid = m.login_uid)",
+            );
+        }
+
+        (sql, values)
+    }
+}
+
+/// 跨房间检索的一页结果
+#[derive(Debug, Clone)]
+pub struct GlobalSearchPage {
+    pub hits: Vec<MessageSearchHit>,
+    /// 本页涉及房间在全部结果中的命中数
+    pub room_hit_counts: HashMap<String, u64>,
+    /// 下一页游标（见 [`MessageKey::to_cursor`]），None 表示已是最后一页
+    pub next_cursor: Option<String>,
+}
+
+/// 跨房间检索当前用户的消息，按 (send_time, id) 倒序分页，与房间内分页的排序一致；
+/// cursor 为上一页最后一条消息的位置，新到达的消息只会出现在第一页之前，不影响后续翻页
+pub async fn search_global<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    filter: &GlobalSearchFilter,
+    cursor: Option<&MessageKey>,
+    page_size: u32,
+) -> Result<GlobalSearchPage, CommonError> {
+    let backend = db.get_database_backend();
+    let (from_where, base_values) = filter.from_where(login_uid);
+
+    let select = match &filter.query {
+        Some(query) => format!(
+            "m.id AS id, m.room_id AS room_id, {}",
+            snippet_select(query)
+        ),
+        None => "m.id AS id, m.room_id AS room_id, NULL AS score".to_string(),
+    };
+    let mut sql = format!(
+        "SELECT {}, COALESCE(m.send_time, 0) AS sort_time {}",
+        select, from_where
+    );
+    let mut values = base_values.clone();
+    if let Some(cursor) = cursor {
+        sql.push_str(" AND (COALESCE(m.send_time, 0), m.id) < (?, ?)");
+        values.push(Value::from(cursor.send_time));
+        values.push(Value::from(cursor.id.clone()));
+    }
+    // 多取一条用于判断是否还有下一页
+    sql.push_str(" ORDER BY sort_time DESC, m.id DESC LIMIT ?");
+    values.push(Value::from(page_size + 1));
+
+    let mut rows = db
+        .query_all(Statement::from_sql_and_values(backend, sql, values))
+        .await?;
+    let has_more = rows.len() > page_size as usize;
+    rows.truncate(page_size as usize);
+
+    let next_cursor = match rows.last() {
+        Some(row) if has_more => Some(
+            MessageKey {
+                send_time: row.try_get("", "sort_time")?,
+                id: row.try_get("", "id")?,
+            }
+            .to_cursor(),
+        ),
+        _ => None,
+    };
+
+    let mut room_ids: Vec<String> = Vec::new();
+    let mut hits = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let room_id: String = row.try_get("", "room_id")?;
+        if !room_ids.contains(&room_id) {
+            room_ids.push(room_id);
+        }
+        hits.push(match &filter.query {
+            Some(query) => read_hit(row, query)?,
+            None => RawHit {
+                id: row.try_get("", "id")?,
+                snippet: String::new(),
+                rank: None,
+            },
+        });
+    }
+
+    // 统计本页涉及房间的总命中数
+    let mut room_hit_counts = HashMap::new();
+    if !room_ids.is_empty() {
+        let sql = format!(
+            "SELECT m.room_id AS room_id, COUNT(*) AS hit_count {} AND m.room_id IN ({}) \
+             GROUP BY m.room_id",
+            from_where,
+            vec!["?"; room_ids.len()].join(", ")
+        );
+        let mut values = base_values;
+        values.extend(room_ids.iter().map(|id| Value::from(id.clone())));
+        for row in db
+            .query_all(Statement::from_sql_and_values(backend, sql, values))
+            .await?
+        {
+            let room_id: String = row.try_get("", "room_id")?;
+            let count: i64 = row.try_get("", "hit_count")?;
+            room_hit_counts.insert(room_id, count as u64);
+        }
+    }
+
+    Ok(GlobalSearchPage {
+        hits: load_hits(db, login_uid, hits).await?,
+        room_hit_counts,
+        next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+    use sea_orm::DatabaseConnection;
+
+    async fn insert_message(db: &DatabaseConnection, id: &str, message_type: u8, body: &str) {
+        insert_room_message(db, id, "1", "10001", message_type, body).await;
+    }
+
+    async fn insert_room_message(
+        db: &DatabaseConnection,
+        id: &str,
+        room_id: &str,
+        uid: &str,
+        message_type: u8,
+        body: &str,
+    ) {
+        let stmt = Statement::from_sql_and_values(
+            db.get_database_backend(),
+            "INSERT INTO im_message (id, uid, room_id, login_uid, message_type, body, send_time) \
+             VALUES (?, ?, ?, '10001', ?, ?, ?)",
+            vec![
+                Value::from(id.to_string()),
+                Value::from(uid.to_string()),
+                Value::from(room_id.to_string()),
+                Value::from(message_type),
+                Value::from(body.to_string()),
+                Value::from(id.parse::<i64>().unwrap()),
+            ],
+        );
+        db.execute(stmt).await.unwrap();
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(FtsQuery::parse("   "), None);
+
+        let query = FtsQuery::parse(" 季度报告  a\"b\"c ").unwrap();
+        assert!(query.uses_match());
+        assert_eq!(query.match_expr(), "\"季度报告\" \"a\"\"b\"\"c\"");
+
+        let query = FtsQuery::parse("季度 报告单").unwrap();
+        assert!(!query.uses_match());
+        let (clause, values) = query.where_clause();
+        assert_eq!(clause.matches("LIKE").count(), 6);
+        assert_eq!(values.len(), 6);
+    }
+
+    #[test]
+    fn test_highlight_escapes_html() {
+        let snippet = highlight("<b>Hello</b> world", &["hello".to_string()]).unwrap();
+        assert_eq!(snippet, "&lt;b&gt;<mark>Hello</mark>&lt;/b&gt; world");
+        assert!(highlight("nothing here", &["xyz".to_string()]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_search_tracks_insert_recall_and_delete() {
+        let db = migrated_db().await;
+        insert_message(&db, "1", 1, r#"{"content":"下周发布季度报告"}"#).await;
+        insert_message(
+            &db,
+            "2",
+            4,
+            r#"{"fileName":"季度报告.pdf","url":"http://a/content"}"#,
+        )
+        .await;
+        insert_message(&db, "3", 9, r#"{"content":["张三:季度总结","李四:好的"]}"#).await;
+
+        // 文件名权重更高，排在前面；JSON 键名和 URL 不会被命中
+        let query = FtsQuery::parse("季度报告").unwrap();
+        let hits = search_messages(&db, "10001", None, &query, 10, 0)
+            .await
+            .unwrap();
+        let ids: Vec<&str> = hits.iter().map(|h| h.message.message.id.as_str()).collect();
+        assert_eq!(ids, vec!["2", "1"]);
+        assert!(hits[1].snippet.contains("<mark>季度报告</mark>"));
+        assert!(
+            search_messages(
+                &db,
+                "10001",
+                None,
+                &FtsQuery::parse("content").unwrap(),
+                10,
+                0
+            )
+            .await
+            .unwrap()
+            .is_empty()
+        );
+
+        // 两个字的关键词走 LIKE，同样能命中合并转发内容
+        let query = FtsQuery::parse("季度").unwrap();
+        let hits = search_messages(&db, "10001", Some("1"), &query, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 3);
+        assert!(hits[0].snippet.contains("<mark>季度</mark>总结"));
+
+        // 撤回和删除后从索引中移除
+        db.execute_unprepared(
+            "UPDATE im_message SET message_type = 2, body = '{\"content\":\"季度报告\"}' WHERE id = '1'",
+        )
+        .await
+        .unwrap();
+        db.execute_unprepared("DELETE FROM im_message WHERE id = '2'")
+            .await
+            .unwrap();
+        let query = FtsQuery::parse("季度报告").unwrap();
+        let snippets = fetch_snippets(&db, "10001", &query, &["1".to_string(), "2".to_string()])
+            .await
+            .unwrap();
+        assert!(snippets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_file_name_indexes_every_name_field() {
+        let db = migrated_db().await;
+        insert_message(
+            &db,
+            "1",
+            4,
+            r#"{"fileName":"a.pdf","original_name":"年度预算草案.xlsx","downloadUrl":"https://cdn/files/合同扫描件.pdf"}"#,
+        )
+        .await;
+        insert_message(
+            &db,
+            "2",
+            4,
+            r#"{"fileTitle":"会议纪要终版","url":"https://cdn/2"}"#,
+        )
+        .await;
+
+        for (keyword, expected) in [("年度预算", "1"), ("a.pdf", "1"), ("会议纪要", "2")] {
+            let query = FtsQuery::parse(keyword).unwrap();
+            let hits = search_messages(&db, "10001", None, &query, 10, 0)
+                .await
+                .unwrap();
+            let ids: Vec<&str> = hits.iter().map(|h| h.message.message.id.as_str()).collect();
+            assert_eq!(ids, vec![expected], "keyword {}", keyword);
+        }
+
+        // 下载地址中的文件名不索引
+        let query = FtsQuery::parse("合同扫描件").unwrap();
+        assert!(
+            search_messages(&db, "10001", None, &query, 10, 0)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_global_search_filters_and_keyset_paging() {
+        let db = migrated_db().await;
+        for (id, room_id, uid, body) in [
+            ("1", "1", "10002", r#"{"content":"invoice for march"}"#),
+            (
+                "2",
+                "2",
+                "10003",
+                r#"{"content":"@HuLa invoice for april","atUidList":["10001"]}"#,
+            ),
+            ("3", "2", "10001", r#"{"content":"invoice paid"}"#),
+            ("4", "3", "10002", r#"{"content":"unrelated"}"#),
+            ("5", "1", "10002", r#"{"content":"another invoice"}"#),
+        ] {
+            insert_room_message(&db, id, room_id, uid, 1, body).await;
+        }
+
+        let filter = GlobalSearchFilter {
+            query: FtsQuery::parse("invoice"),
+            ..Default::default()
+        };
+        let page = search_global(&db, "10001", &filter, None, 2).await.unwrap();
+        let ids: Vec<&str> = page
+            .hits
+            .iter()
+            .map(|h| h.message.message.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["5", "3"]);
+        assert_eq!(page.room_hit_counts.get("1"), Some(&2));
+        assert_eq!(page.room_hit_counts.get("2"), Some(&2));
+        assert_eq!(page.next_cursor.as_deref(), Some("3_3"));
+
+        // 新消息到达后，继续翻页不受影响
+        insert_room_message(&db, "6", "1", "10002", 1, r#"{"content":"new invoice"}"#).await;
+        let cursor = page.next_cursor.as_deref().and_then(MessageKey::parse);
+        let page = search_global(&db, "10001", &filter, cursor.as_ref(), 2)
+            .await
+            .unwrap();
+        let ids: Vec<&str> = page
+            .hits
+            .iter()
+            .map(|h| h.message.message.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["2", "1"]);
+        assert!(page.next_cursor.is_none());
+
+        let filter = GlobalSearchFilter {
+            mentions_me: true,
+            ..Default::default()
+        };
+        let page = search_global(&db, "10001", &filter, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(page.hits.len(), 1);
+        assert_eq!(page.hits[0].message.message.id, "2");
+
+        // @全体成员同样算作提及
+        insert_room_message(
+            &db,
+            "7",
+            "3",
+            "10002",
+            1,
+            r#"{"content":"@all","atUidList":[0]}"#,
+        )
+        .await;
+        let page = search_global(&db, "10001", &filter, None, 10)
+            .await
+            .unwrap();
+        let ids: Vec<&str> = page
+            .hits
+            .iter()
+            .map(|h| h.message.message.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["7", "2"]);
+
+        let filter = GlobalSearchFilter {
+            query: FtsQuery::parse("invoice"),
+            sender_uids: vec!["10002".to_string()],
+            start_time: Some(2),
+            ..Default::default()
+        };
+        let page = search_global(&db, "10001", &filter, None, 10)
+            .await
+            .unwrap();
+        let ids: Vec<&str> = page
+            .hits
+            .iter()
+            .map(|h| h.message.message.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["6", "5"]);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::error::CommonError;
+use crate::repository::im_message_repository::{self, MessageWithThumbnail};
+use entity::{im_message, im_message_mention};
+use sea_orm::prelude::Expr;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Statement,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// 房间的未读提及统计
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomMentionCount {
+    pub room_id: String,
+    pub unread_count: u32,
+    /// 其中单独 @ 当前用户（非 @全体成员）的条数
+    pub direct_count: u32,
+    pub latest_message_id: String,
+    pub latest_send_time: Option<i64>,
+}
+
+/// 按房间统计未读提及
+pub async fn count_unread_by_room<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+) -> Result<Vec<RoomMentionCount>, CommonError> {
+    // SQLite 的 MAX 聚合会让同一行的其他列取自最大值所在行
+    let sql = r#"
+        SELECT room_id,
+            COUNT(*) AS unread_count,
+            SUM(CASE WHEN mention_all THEN 0 ELSE 1 END) AS direct_count,
+            message_id AS latest_message_id,
+            MAX(COALESCE(send_time, 0)) AS latest_send_time
+        FROM im_message_mention
+        WHERE login_uid = ? AND is_read = 0
+        GROUP BY room_id
+        ORDER BY latest_send_time DESC
+    "#;
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            [login_uid.into()],
+        ))
+        .await?;
+
+    let mut counts = Vec::with_capacity(rows.len());
+    for row in rows {
+        counts.push(RoomMentionCount {
+            room_id: row.try_get("", "room_id")?,
+            unread_count: row.try_get::<i64>("", "unread_count")? as u32,
+            direct_count: row.try_get::<i64>("", "direct_count")? as u32,
+            latest_message_id: row.try_get("", "latest_message_id")?,
+            latest_send_time: row.try_get("", "latest_send_time")?,
+        });
+    }
+    Ok(counts)
+}
+
+/// 按发送时间倒序列出所有房间的未读提及
+pub async fn list_unread<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    limit: u64,
+) -> Result<Vec<(im_message_mention::Model, MessageWithThumbnail)>, CommonError> {
+    let mentions = im_message_mention::Entity::find()
+        .filter(im_message_mention::Column::LoginUid.eq(login_uid))
+        .filter(im_message_mention::Column::IsRead.eq(false))
+        .order_by_desc(im_message_mention::Column::SendTime)
+        .limit(limit)
+        .all(db)
+        .await?;
+    if mentions.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let messages = im_message::Entity::find()
+        .filter(im_message::Column::LoginUid.eq(login_uid))
+        .filter(im_message::Column::Id.is_in(mentions.iter().map(|m| m.message_id.clone())))
+        .all(db)
+        .await?;
+    let mut by_id: HashMap<String, MessageWithThumbnail> =
+        im_message_repository::enrich_models_with_thumbnails(db, messages)
+            .await?
+            .into_iter()
+            .map(|record| (record.message.id.clone(), record))
+            .collect();
+
+    Ok(mentions
+        .into_iter()
+        .filter_map(|mention| {
+            let record = by_id.remove(&mention.message_id)?;
+            Some((mention, record))
+        })
+        .collect())
+}
+
+/// 将房间内发送时间不晚于 read_until 的提及标记为已读，read_until 为空时全部标记，返回更新条数
+pub async fn mark_read<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+    read_until: Option<i64>,
+) -> Result<u64, CommonError> {
+    let mut update = im_message_mention::Entity::update_many()
+        .col_expr(im_message_mention::Column::IsRead, Expr::value(true))
+        .filter(im_message_mention::Column::LoginUid.eq(login_uid))
+        .filter(im_message_mention::Column::RoomId.eq(room_id))
+        .filter(im_message_mention::Column::IsRead.eq(false));
+    if let Some(read_until) = read_until {
+        update = update.filter(Expr::cust_with_values(
+            r#"COALESCE("im_message_mention"."send_time", 0) <= ?"#,
+            [read_until],
+        ));
+    }
+    let result = update.exec(db).await?;
+    Ok(result.rows_affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+
+    fn message(id: &str, uid: &str, message_type: u8, body: &str) -> MessageWithThumbnail {
+        MessageWithThumbnail::from(im_message::Model {
+            id: id.to_string(),
+            uid: uid.to_string(),
+            nickname: None,
+            room_id: "1".to_string(),
+            send_time: id.parse().ok(),
+            message_type: Some(message_type),
+            body: Some(body.to_string()),
+            message_marks: None,
+            create_time: None,
+            update_time: None,
+            login_uid: "10001".to_string(),
+            send_status: "success".to_string(),
+            time_block: None,
+            edited_at: None,
+            reply_to_id: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_mentions_tracked_on_write() {
+        let db = migrated_db().await;
+
+        im_message_repository::save_all(
+            &db,
+            vec![
+                message(
+                    "1",
+                    "10002",
+                    15,
+                    r#"{"content":"@me","atUidList":["10001"]}"#,
+                ),
+                message("2", "10002", 15, r#"{"content":"@all","atUidList":[0]}"#),
+                message(
+                    "3",
+                    "10002",
+                    15,
+                    r#"{"content":"@other","atUidList":["10003"]}"#,
+                ),
+                message("4", "10001", 15, r#"{"content":"@all","atUidList":["0"]}"#),
+                message("5", "10002", 1, "not json"),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let counts = count_unread_by_room(&db, "10001").await.unwrap();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].unread_count, 2);
+        assert_eq!(counts[0].direct_count, 1);
+        assert_eq!(counts[0].latest_message_id, "2");
+
+        // 撤回后不再计入
+        im_message_repository::save_all(&db, vec![message("2", "10002", 2, r#""撤回""#)])
+            .await
+            .unwrap();
+        let unread = list_unread(&db, "10001", 10).await.unwrap();
+        assert_eq!(unread.len(), 1);
+        assert_eq!(unread[0].1.message.id, "1");
+
+        assert_eq!(mark_read(&db, "10001", "1", Some(1)).await.unwrap(), 1);
+        assert!(count_unread_by_room(&db, "10001").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,276 @@
+use crate::error::CommonError;
+use chrono::Utc;
+use entity::im_message_prune_record;
+use sea_orm::sea_query::{Expr, OnConflict, Value};
+use sea_orm::{ConnectionTrait, EntityTrait, Set, Statement};
+use std::collections::HashMap;
+
+/// 未发送成功的消息不参与清理，避免丢失用户尚未送达的内容
+const PRUNABLE_CONDITION: &str = "send_time IS NOT NULL AND send_status NOT IN ('pending', 'fail')";
+
+/// 一批被清理的消息
+#[derive(Debug, Default)]
+pub struct PrunedBatch {
+    pub deleted: u64,
+    /// 被删除消息引用的缩略图路径
+    pub thumbnail_paths: Vec<String>,
+    /// 各房间被删除消息中最晚的发送时间
+    pub pruned_until_by_room: HashMap<String, i64>,
+}
+
+/// 数据库页使用情况
+#[derive(Debug, Clone, Copy)]
+pub struct DbUsage {
+    pub page_size: i64,
+    pub page_count: i64,
+    pub freelist_count: i64,
+}
+
+impl DbUsage {
+    /// 文件大小
+    pub fn file_bytes(&self) -> u64 {
+        (self.page_size * self.page_count).max(0) as u64
+    }
+
+    /// 实际占用的大小（不含空闲页）
+    pub fn used_bytes(&self) -> u64 {
+        (self.page_size * (self.page_count - self.freelist_count)).max(0) as u64
+    }
+}
+
+/// 当前用户有消息的房间
+pub async fn list_message_room_ids<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+) -> Result<Vec<String>, CommonError> {
+    let stmt = Statement::from_sql_and_values(
+        db.get_database_backend(),
+        "SELECT DISTINCT room_id FROM im_message WHERE login_uid = ?",
+        vec![Value::from(login_uid.to_string())],
+    );
+    db.query_all(stmt)
+        .await?
+        .iter()
+        .map(|row| row.try_get::<String>("", "room_id").map_err(Into::into))
+        .collect()
+}
+
+/// 房间保留最新 keep 条消息时，需要清理的消息中最晚的发送时间；不足 keep 条时返回 None
+pub async fn find_count_cutoff<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+    keep: u32,
+) -> Result<Option<i64>, CommonError> {
+    let stmt = Statement::from_sql_and_values(
+        db.get_database_backend(),
+        format!(
+            "SELECT send_time FROM im_message WHERE login_uid = ? AND room_id = ? AND {} \
+             ORDER BY send_time DESC LIMIT 1 OFFSET ?",
+            PRUNABLE_CONDITION
+        ),
+        vec![
+            Value::from(login_uid.to_string()),
+            Value::from(room_id.to_string()),
+            Value::from(keep),
+        ],
+    );
+    match db.query_one(stmt).await? {
+        Some(row) => Ok(Some(row.try_get("", "send_time")?)),
+        None => Ok(None),
+    }
+}
+
+async fn delete_batch<C: ConnectionTrait>(
+    db: &C,
+    condition: &str,
+    mut values: Vec<Value>,
+    batch_size: u32,
+) -> Result<PrunedBatch, CommonError> {
+    values.push(Value::from(batch_size));
+    let stmt = Statement::from_sql_and_values(
+        db.get_database_backend(),
+        format!(
+            "DELETE FROM im_message WHERE rowid IN (\
+             SELECT rowid FROM im_message WHERE {} AND {} ORDER BY send_time LIMIT ?) \
+             RETURNING room_id, send_time, thumbnail_path",
+            condition, PRUNABLE_CONDITION
+        ),
+        values,
+    );
+
+    let mut batch = PrunedBatch::default();
+    for row in db.query_all(stmt).await? {
+        let room_id: String = row.try_get("", "room_id")?;
+        let send_time: i64 = row.try_get("", "send_time")?;
+        let thumbnail_path: Option<String> = row.try_get("", "thumbnail_path")?;
+
+        batch.deleted += 1;
+        if let Some(path) = thumbnail_path.filter(|p| !p.is_empty()) {
+            batch.thumbnail_paths.push(path);
+        }
+        let until = batch
+            .pruned_until_by_room
+            .entry(room_id)
+            .or_insert(send_time);
+        *until = (*until).max(send_time);
+    }
+    Ok(batch)
+}
+
+/// 删除房间中发送时间不晚于 cutoff 的一批消息
+pub async fn prune_room_batch<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+    cutoff: i64,
+    batch_size: u32,
+) -> Result<PrunedBatch, CommonError> {
+    delete_batch(
+        db,
+        "login_uid = ? AND room_id = ? AND send_time <= ?",
+        vec![
+            Value::from(login_uid.to_string()),
+            Value::from(room_id.to_string()),
+            Value::from(cutoff),
+        ],
+        batch_size,
+    )
+    .await
+}
+
+/// 跨房间删除最旧的一批消息，用于数据库大小超限时
+pub async fn prune_oldest_batch<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    batch_size: u32,
+) -> Result<PrunedBatch, CommonError> {
+    delete_batch(
+        db,
+        "login_uid = ?",
+        vec![Value::from(login_uid.to_string())],
+        batch_size,
+    )
+    .await
+}
+
+/// 记录房间已清理的范围，只会向后推进
+pub async fn record_prune<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+    pruned_until: i64,
+) -> Result<(), CommonError> {
+    let record = im_message_prune_record::ActiveModel {
+        room_id: Set(room_id.to_string()),
+        login_uid: Set(login_uid.to_string()),
+        pruned_until: Set(pruned_until),
+        pruned_at: Set(Utc::now().timestamp_millis()),
+    };
+    im_message_prune_record::Entity::insert(record)
+        .on_conflict(
+            OnConflict::columns([
+                im_message_prune_record::Column::RoomId,
+                im_message_prune_record::Column::LoginUid,
+            ])
+            .value(
+                im_message_prune_record::Column::PrunedUntil,
+                Expr::cust("MAX(im_message_prune_record.pruned_until, excluded.pruned_until)"),
+            )
+            .update_column(im_message_prune_record::Column::PrunedAt)
+            .to_owned(),
+        )
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// 缩略图是否仍被其他消息引用
+pub async fn is_thumbnail_referenced<C: ConnectionTrait>(
+    db: &C,
+    path: &str,
+) -> Result<bool, CommonError> {
+    let stmt = Statement::from_sql_and_values(
+        db.get_database_backend(),
+        "SELECT 1 FROM im_message WHERE thumbnail_path = ? LIMIT 1",
+        vec![Value::from(path.to_string())],
+    );
+    Ok(db.query_one(stmt).await?.is_some())
+}
+
+async fn pragma_i64<C: ConnectionTrait>(db: &C, name: &str) -> Result<i64, CommonError> {
+    let stmt = Statement::from_string(db.get_database_backend(), format!("PRAGMA {}", name));
+    match db.query_one(stmt).await? {
+        Some(row) => Ok(row.try_get_by_index(0)?),
+        None => Ok(0),
+    }
+}
+
+pub async fn db_usage<C: ConnectionTrait>(db: &C) -> Result<DbUsage, CommonError> {
+    Ok(DbUsage {
+        page_size: pragma_i64(db, "page_size").await?,
+        page_count: pragma_i64(db, "page_count").await?,
+        freelist_count: pragma_i64(db, "freelist_count").await?,
+    })
+}
+
+pub async fn vacuum<C: ConnectionTrait>(db: &C) -> Result<(), CommonError> {
+    db.execute_unprepared("VACUUM").await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::im_message_repository::should_skip_message_insert;
+    use crate::repository::test_support::migrated_db;
+
+    #[tokio::test]
+    async fn test_prune_room_keeps_newest_and_blocks_reimport() {
+        let db = migrated_db().await;
+
+        for (id, send_time, status) in [
+            ("1", 100, "success"),
+            ("2", 200, "fail"),
+            ("3", 300, "success"),
+            ("4", 400, "success"),
+        ] {
+            db.execute_unprepared(&format!(
+                "INSERT INTO im_message (id, uid, room_id, send_time, body, login_uid, send_status) \
+                 VALUES ('{}', '1', '1', {}, 'hello', '10001', '{}')",
+                id, send_time, status
+            ))
+            .await
+            .unwrap();
+        }
+
+        // 未发送成功的消息不计入保留条数
+        let cutoff = find_count_cutoff(&db, "10001", "1", 1)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cutoff, 300);
+
+        let batch = prune_room_batch(&db, "10001", "1", cutoff, 500)
+            .await
+            .unwrap();
+        assert_eq!(batch.deleted, 2);
+        record_prune(&db, "10001", "1", batch.pruned_until_by_room["1"])
+            .await
+            .unwrap();
+        record_prune(&db, "10001", "1", 100).await.unwrap();
+
+        let remaining = list_message_room_ids(&db, "10001").await.unwrap();
+        assert_eq!(remaining, vec!["1".to_string()]);
+        assert!(
+            should_skip_message_insert(&db, "3", "1", "10001", Some(300))
+                .await
+                .unwrap()
+        );
+        assert!(
+            !should_skip_message_insert(&db, "5", "1", "10001", Some(500))
+                .await
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use crate::error::CommonError;
+use crate::repository::SQLITE_MAX_VARIABLES;
+use chrono::Utc;
+use entity::{im_message_read_count, im_message_read_receipt};
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// 消息的已读/未读人数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadCount {
+    pub read_count: u32,
+    #[serde(rename = "unReadCount")]
+    pub unread_count: Option<u32>,
+}
+
+/// 保存服务端返回的已读人数，key 为消息 ID
+pub async fn save_counts<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    counts: &[(String, ReadCount)],
+) -> Result<(), CommonError> {
+    let now = Utc::now().timestamp_millis();
+    // 每行 5 个变量
+    for chunk in counts.chunks(SQLITE_MAX_VARIABLES / 5) {
+        let models = chunk
+            .iter()
+            .map(|(message_id, count)| im_message_read_count::ActiveModel {
+                message_id: Set(message_id.clone()),
+                login_uid: Set(login_uid.to_string()),
+                read_count: Set(count.read_count as i32),
+                unread_count: Set(count.unread_count.map(|c| c as i32)),
+                fetched_at: Set(now),
+            });
+        im_message_read_count::Entity::insert_many(models)
+            .on_conflict(
+                OnConflict::columns([
+                    im_message_read_count::Column::MessageId,
+                    im_message_read_count::Column::LoginUid,
+                ])
+                .update_columns([
+                    im_message_read_count::Column::ReadCount,
+                    im_message_read_count::Column::UnreadCount,
+                    im_message_read_count::Column::FetchedAt,
+                ])
+                .to_owned(),
+            )
+            .exec(db)
+            .await?;
+    }
+    Ok(())
+}
+
+/// 记录这些消息的已读人数已向服务端查询过。服务端没有返回的消息按 0 人已读缓存，
+/// 已有的人数保持不变，只更新 fetched_at，避免每次翻页都重新请求
+pub async fn mark_fetched<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_ids: &[String],
+) -> Result<(), CommonError> {
+    let now = Utc::now().timestamp_millis();
+    // 每行 5 个变量
+    for chunk in message_ids.chunks(SQLITE_MAX_VARIABLES / 5) {
+        let models = chunk
+            .iter()
+            .map(|message_id| im_message_read_count::ActiveModel {
+                message_id: Set(message_id.clone()),
+                login_uid: Set(login_uid.to_string()),
+                read_count: Set(0),
+                unread_count: Set(None),
+                fetched_at: Set(now),
+            });
+        im_message_read_count::Entity::insert_many(models)
+            .on_conflict(
+                OnConflict::columns([
+                    im_message_read_count::Column::MessageId,
+                    im_message_read_count::Column::LoginUid,
+                ])
+                .update_column(im_message_read_count::Column::FetchedAt)
+                .to_owned(),
+            )
+            .exec(db)
+            .await?;
+    }
+    Ok(())
+}
+
+/// 保存一页已读或未读成员
+pub async fn save_receipts<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+    reader_uids: &[String],
+    is_read: bool,
+) -> Result<(), CommonError> {
+    let now = Utc::now().timestamp_millis();
+    for chunk in reader_uids.chunks(SQLITE_MAX_VARIABLES / 5) {
+        let models = chunk
+            .iter()
+            .map(|reader_uid| im_message_read_receipt::ActiveModel {
+                message_id: Set(message_id.to_string()),
+                login_uid: Set(login_uid.to_string()),
+                reader_uid: Set(reader_uid.clone()),
+                is_read: Set(is_read),
+                update_time: Set(Some(now)),
+            });
+        im_message_read_receipt::Entity::insert_many(models)
+            .on_conflict(
+                OnConflict::columns([
+                    im_message_read_receipt::Column::MessageId,
+                    im_message_read_receipt::Column::LoginUid,
+                    im_message_read_receipt::Column::ReaderUid,
+                ])
+                .update_columns([
+                    im_message_read_receipt::Column::IsRead,
+                    im_message_read_receipt::Column::UpdateTime,
+                ])
+                .to_owned(),
+            )
+            .exec(db)
+            .await?;
+    }
+    Ok(())
+}
+
+/// 批量读取已读人数，综合服务端人数与已缓存的成员回执；没有任何缓存的消息不返回
+pub async fn load_counts<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_ids: &[String],
+) -> Result<HashMap<String, ReadCount>, CommonError> {
+    let mut aggregates: HashMap<String, im_message_read_count::Model> = HashMap::new();
+    let mut receipts: HashMap<String, (u32, u32)> = HashMap::new();
+    for chunk in message_ids.chunks(SQLITE_MAX_VARIABLES - 1) {
+        aggregates.extend(
+            im_message_read_count::Entity::find()
+                .filter(im_message_read_count::Column::LoginUid.eq(login_uid))
+                .filter(im_message_read_count::Column::MessageId.is_in(chunk.iter().cloned()))
+                .all(db)
+                .await?
+                .into_iter()
+                .map(|m| (m.message_id.clone(), m)),
+        );
+
+        let rows: Vec<(String, bool, i64)> = im_message_read_receipt::Entity::find()
+            .select_only()
+            .column(im_message_read_receipt::Column::MessageId)
+            .column(im_message_read_receipt::Column::IsRead)
+            .column_as(
+                im_message_read_receipt::Column::ReaderUid.count(),
+                "reader_count",
+            )
+            .filter(im_message_read_receipt::Column::LoginUid.eq(login_uid))
+            .filter(im_message_read_receipt::Column::MessageId.is_in(chunk.iter().cloned()))
+            .group_by(im_message_read_receipt::Column::MessageId)
+            .group_by(im_message_read_receipt::Column::IsRead)
+            .into_tuple()
+            .all(db)
+            .await?;
+        for (message_id, is_read, count) in rows {
+            let entry = receipts.entry(message_id).or_default();
+            if is_read {
+                entry.0 = count as u32;
+            } else {
+                entry.1 = count as u32;
+            }
+        }
+    }
+
+    let mut counts = HashMap::new();
+    for message_id in message_ids {
+        let (receipt_read, receipt_unread) = receipts.get(message_id).copied().unwrap_or_default();
+        let count = match aggregates.get(message_id) {
+            // 成员回执比人数更新时，已读人数取较大值，并相应减少未读人数
+            Some(aggregate) => {
+                let aggregate_read = aggregate.read_count.max(0) as u32;
+                let read_count = aggregate_read.max(receipt_read);
+                ReadCount {
+                    read_count,
+                    // 服务端未给出未读人数时退回成员回执
+                    unread_count: aggregate
+                        .unread_count
+                        .map(|c| (c.max(0) as u32).saturating_sub(read_count - aggregate_read))
+                        .or((receipt_unread > 0).then_some(receipt_unread)),
+                }
+            }
+            None if receipt_read + receipt_unread > 0 => ReadCount {
+                read_count: receipt_read,
+                unread_count: (receipt_unread > 0).then_some(receipt_unread),
+            },
+            None => continue,
+        };
+        counts.insert(message_id.clone(), count);
+    }
+    Ok(counts)
+}
+
+/// 筛选出已读人数缺失或在 fresh_after 之前获取的消息
+pub async fn stale_message_ids<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_ids: &[String],
+    fresh_after: i64,
+) -> Result<Vec<String>, CommonError> {
+    let mut fresh: HashSet<String> = HashSet::new();
+    for chunk in message_ids.chunks(SQLITE_MAX_VARIABLES - 2) {
+        let ids: Vec<String> = im_message_read_count::Entity::find()
+            .select_only()
+            .column(im_message_read_count::Column::MessageId)
+            .filter(im_message_read_count::Column::LoginUid.eq(login_uid))
+            .filter(im_message_read_count::Column::MessageId.is_in(chunk.iter().cloned()))
+            .filter(im_message_read_count::Column::FetchedAt.gte(fresh_after))
+            .into_tuple()
+            .all(db)
+            .await?;
+        fresh.extend(ids);
+    }
+    Ok(message_ids
+        .iter()
+        .filter(|id| !fresh.contains(*id))
+        .cloned()
+        .collect())
+}
+
+/// 列出消息已缓存的成员回执
+pub async fn list_receipts<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+) -> Result<Vec<im_message_read_receipt::Model>, CommonError> {
+    let receipts = im_message_read_receipt::Entity::find()
+        .filter(im_message_read_receipt::Column::LoginUid.eq(login_uid))
+        .filter(im_message_read_receipt::Column::MessageId.eq(message_id))
+        .order_by_desc(im_message_read_receipt::Column::UpdateTime)
+        .all(db)
+        .await?;
+    Ok(receipts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+    use entity::im_message;
+
+    #[tokio::test]
+    async fn test_counts_merge_receipts() {
+        let db = migrated_db().await;
+
+        im_message::Entity::insert(im_message::ActiveModel {
+            id: Set("1".to_string()),
+            uid: Set("10001".to_string()),
+            room_id: Set("1".to_string()),
+            login_uid: Set("10001".to_string()),
+            send_status: Set("success".to_string()),
+            ..Default::default()
+        })
+        .exec(&db)
+        .await
+        .unwrap();
+
+        let ids = vec!["1".to_string(), "2".to_string()];
+        let count = ReadCount {
+            read_count: 1,
+            unread_count: Some(3),
+        };
+        save_counts(&db, "10001", &[("1".to_string(), count)])
+            .await
+            .unwrap();
+        assert_eq!(
+            stale_message_ids(&db, "10001", &ids, 0).await.unwrap(),
+            vec!["2".to_string()]
+        );
+
+        // 服务端没有返回 "2" 时同样记录获取时间，已有的人数不被覆盖
+        mark_fetched(&db, "10001", &ids).await.unwrap();
+        assert!(
+            stale_message_ids(&db, "10001", &ids, 0)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(load_counts(&db, "10001", &ids).await.unwrap()["1"], count);
+
+        // 成员回执比人数更新
+        let readers = vec!["10002".to_string(), "10003".to_string()];
+        save_receipts(&db, "10001", "1", &readers, true)
+            .await
+            .unwrap();
+        save_receipts(&db, "10001", "2", &readers, false)
+            .await
+            .unwrap();
+        let counts = load_counts(&db, "10001", &ids).await.unwrap();
+        assert_eq!(
+            counts["1"],
+            ReadCount {
+                read_count: 2,
+                unread_count: Some(2)
+            }
+        );
+        assert_eq!(counts["2"].unread_count, Some(2));
+
+        im_message::Entity::delete_by_id(("1".to_string(), "10001".to_string()))
+            .exec(&db)
+            .await
+            .unwrap();
+        assert!(list_receipts(&db, "10001", "1").await.unwrap().is_empty());
+    }
+}
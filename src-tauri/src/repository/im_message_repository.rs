@@ -1,29 +1,51 @@
 use crate::error::CommonError;
 use crate::pojo::common::{CursorPageParam, CursorPageResp};
+use crate::repository::im_message_fts_repository::FtsQuery;
+use crate::repository::{
+    SQLITE_MAX_VARIABLES, im_message_client_id_repository, im_message_revision_repository,
+    im_message_thread_repository,
+};
 use chrono::Utc;
-use entity::im_message;
-use lazy_static::lazy_static;
+use entity::{im_deleted_message, im_message, im_message_prune_record, im_room_clear_record};
 use sea_orm::prelude::Expr;
-use sea_orm::sea_query::{Alias, Value};
+use sea_orm::sea_query::{Alias, OnConflict, Value};
 use sea_orm::{
     ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DatabaseTransaction, EntityTrait,
-    IntoActiveModel, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set, Statement,
+    IntoActiveModel, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Select, Set, Statement,
     TryIntoModel,
 };
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
 
 use tracing::{debug, error, info};
 
-lazy_static! {
-    static ref DELETED_TABLE_INITIALIZED: AtomicBool = AtomicBool::new(false);
-    static ref ROOM_CLEAR_TABLE_INITIALIZED: AtomicBool = AtomicBool::new(false);
-}
+/// 批量写入 im_message 的列，顺序需与 upsert_values 一致
+const UPSERT_COLUMNS: [&str; 16] = [
+    "id",
+    "uid",
+    "nickname",
+    "room_id",
+    "send_time",
+    "message_type",
+    "body",
+    "message_marks",
+    "create_time",
+    "update_time",
+    "login_uid",
+    "send_status",
+    "time_block",
+    "thumbnail_path",
+    "edited_at",
+    "reply_to_id",
+];
 
 #[derive(Clone)]
 pub struct MessageWithThumbnail {
     pub message: im_message::Model,
     pub thumbnail_path: Option<String>,
+    /// 编辑次数，由 enrich_models_with_thumbnails 填充
+    pub edit_count: u32,
+    /// 回复数，由 enrich_models_with_thumbnails 填充
+    pub reply_count: u32,
 }
 
 impl MessageWithThumbnail {
@@ -31,6 +53,8 @@ impl MessageWithThumbnail {
         Self {
             message,
             thumbnail_path,
+            edit_count: 0,
+            reply_count: 0,
         }
     }
 
@@ -44,6 +68,8 @@ impl From<im_message::Model> for MessageWithThumbnail {
         Self {
             message,
             thumbnail_path: None,
+            edit_count: 0,
+            reply_count: 0,
         }
     }
 }
@@ -52,113 +78,128 @@ fn parse_message_id(id: &str) -> Option<i64> {
     id.parse::<i64>().ok()
 }
 
-async fn ensure_deleted_message_table<C: ConnectionTrait>(conn: &C) -> Result<(), CommonError> {
-    if DELETED_TABLE_INITIALIZED.load(Ordering::SeqCst) {
-        return Ok(());
+pub(crate) async fn should_skip_message_insert<C: ConnectionTrait>(
+    conn: &C,
+    message_id: &str,
+    room_id: &str,
+    login_uid: &str,
+    send_time: Option<i64>,
+) -> Result<bool, CommonError> {
+    let deleted =
+        im_deleted_message::Entity::find_by_id((message_id.to_string(), login_uid.to_string()))
+            .one(conn)
+            .await?;
+    if deleted.is_some() {
+        return Ok(true);
     }
 
-    let backend = conn.get_database_backend();
-    let stmt = Statement::from_string(
-        backend,
-        "CREATE TABLE IF NOT EXISTS im_deleted_message (
-            id TEXT NOT NULL,
-            room_id TEXT NOT NULL,
-            login_uid TEXT NOT NULL,
-            deleted_at INTEGER NOT NULL,
-            PRIMARY KEY (id, login_uid)
-        )"
-        .to_string(),
-    );
-    conn.execute(stmt)
-        .await
-        .map_err(CommonError::DatabaseError)?;
-    DELETED_TABLE_INITIALIZED.store(true, Ordering::SeqCst);
-    Ok(())
-}
+    let clear_record =
+        im_room_clear_record::Entity::find_by_id((room_id.to_string(), login_uid.to_string()))
+            .one(conn)
+            .await?;
 
-async fn ensure_room_clear_table<C: ConnectionTrait>(conn: &C) -> Result<(), CommonError> {
-    if ROOM_CLEAR_TABLE_INITIALIZED.load(Ordering::SeqCst) {
-        return Ok(());
+    if clear_record.is_some_and(|record| is_cleared_by(&record, message_id, send_time)) {
+        return Ok(true);
     }
 
-    let backend = conn.get_database_backend();
-    let stmt = Statement::from_string(
-        backend,
-        "CREATE TABLE IF NOT EXISTS im_room_clear_record (
-            room_id TEXT NOT NULL,
-            login_uid TEXT NOT NULL,
-            cleared_at INTEGER NOT NULL,
-            last_cleared_msg_id TEXT,
-            PRIMARY KEY (room_id, login_uid)
-        )"
-        .to_string(),
-    );
-    conn.execute(stmt)
-        .await
-        .map_err(CommonError::DatabaseError)?;
-    ROOM_CLEAR_TABLE_INITIALIZED.store(true, Ordering::SeqCst);
-    Ok(())
+    let prune_record =
+        im_message_prune_record::Entity::find_by_id((room_id.to_string(), login_uid.to_string()))
+            .one(conn)
+            .await?;
+
+    Ok(prune_record.is_some_and(|record| is_pruned_by(&record, send_time)))
 }
 
-async fn should_skip_message_insert<C: ConnectionTrait>(
-    conn: &C,
+/// 判断消息是否在按保留策略清理过的范围内
+fn is_pruned_by(record: &im_message_prune_record::Model, send_time: Option<i64>) -> bool {
+    send_time.is_some_and(|send_time| send_time <= record.pruned_until)
+}
+
+/// 判断消息是否在房间清空范围内：发送时间不晚于清空时间，或 ID 不大于清空时的最后一条消息
+fn is_cleared_by(
+    record: &im_room_clear_record::Model,
     message_id: &str,
-    room_id: &str,
-    login_uid: &str,
     send_time: Option<i64>,
-) -> Result<bool, CommonError> {
-    ensure_deleted_message_table(conn).await?;
-    let backend = conn.get_database_backend();
-    let deleted_stmt = Statement::from_sql_and_values(
-        backend,
-        "SELECT 1 FROM im_deleted_message WHERE id = ? AND login_uid = ? LIMIT 1",
-        vec![
-            Value::from(message_id.to_string()),
-            Value::from(login_uid.to_string()),
-        ],
-    );
-    if conn.query_one(deleted_stmt).await?.is_some() {
-        return Ok(true);
+) -> bool {
+    if send_time.is_some_and(|send_time| send_time <= record.cleared_at) {
+        return true;
     }
 
-    ensure_room_clear_table(conn).await?;
-    let clear_stmt = Statement::from_sql_and_values(
-        backend,
-        "SELECT cleared_at, last_cleared_msg_id FROM im_room_clear_record WHERE room_id = ? AND login_uid = ? LIMIT 1",
-        vec![
-            Value::from(room_id.to_string()),
-            Value::from(login_uid.to_string()),
-        ],
-    );
+    match (
+        parse_message_id(message_id),
+        record
+            .last_cleared_msg_id
+            .as_deref()
+            .and_then(parse_message_id),
+    ) {
+        (Some(current), Some(threshold)) => current <= threshold,
+        _ => false,
+    }
+}
 
-    if let Some(row) = conn.query_one(clear_stmt).await? {
-        let cleared_at: i64 = row.try_get("", "cleared_at")?;
-        if let Some(send_time) = send_time {
-            if send_time <= cleared_at {
-                return Ok(true);
-            }
+/// 批量过滤已删除、已被清空或已按保留策略清理的消息，按登录用户分组批量查询
+pub(crate) async fn filter_skipped_messages<C: ConnectionTrait>(
+    conn: &C,
+    messages: Vec<MessageWithThumbnail>,
+) -> Result<Vec<MessageWithThumbnail>, CommonError> {
+    let mut by_login_uid: HashMap<String, Vec<MessageWithThumbnail>> = HashMap::new();
+    for message in messages {
+        by_login_uid
+            .entry(message.message.login_uid.clone())
+            .or_default()
+            .push(message);
+    }
+
+    let mut kept = Vec::new();
+    for (login_uid, messages) in by_login_uid {
+        let ids: Vec<String> = messages.iter().map(|m| m.message.id.clone()).collect();
+        let mut deleted_ids = std::collections::HashSet::new();
+        for chunk in ids.chunks(SQLITE_MAX_VARIABLES - 1) {
+            let found: Vec<String> = im_deleted_message::Entity::find()
+                .select_only()
+                .column(im_deleted_message::Column::Id)
+                .filter(im_deleted_message::Column::LoginUid.eq(&login_uid))
+                .filter(im_deleted_message::Column::Id.is_in(chunk.iter().cloned()))
+                .into_tuple()
+                .all(conn)
+                .await?;
+            deleted_ids.extend(found);
         }
 
-        let last_id: Option<String> = row.try_get("", "last_cleared_msg_id")?;
-        if let Some(last_id) = last_id {
-            if let (Some(current), Ok(threshold)) =
-                (parse_message_id(message_id), last_id.parse::<i64>())
-            {
-                if current <= threshold {
-                    return Ok(true);
-                }
-            }
+        let mut room_ids: Vec<String> =
+            messages.iter().map(|m| m.message.room_id.clone()).collect();
+        room_ids.sort();
+        room_ids.dedup();
+        let mut clear_records = HashMap::new();
+        let mut prune_records = HashMap::new();
+        for chunk in room_ids.chunks(SQLITE_MAX_VARIABLES - 1) {
+            let records = im_room_clear_record::Entity::find()
+                .filter(im_room_clear_record::Column::LoginUid.eq(&login_uid))
+                .filter(im_room_clear_record::Column::RoomId.is_in(chunk.iter().cloned()))
+                .all(conn)
+                .await?;
+            clear_records.extend(records.into_iter().map(|r| (r.room_id.clone(), r)));
+
+            let records = im_message_prune_record::Entity::find()
+                .filter(im_message_prune_record::Column::LoginUid.eq(&login_uid))
+                .filter(im_message_prune_record::Column::RoomId.is_in(chunk.iter().cloned()))
+                .all(conn)
+                .await?;
+            prune_records.extend(records.into_iter().map(|r| (r.room_id.clone(), r)));
         }
-    }
 
-    Ok(false)
-}
+        kept.extend(messages.into_iter().filter(|m| {
+            !deleted_ids.contains(&m.message.id)
+                && !clear_records
+                    .get(&m.message.room_id)
+                    .is_some_and(|record| is_cleared_by(record, &m.message.id, m.message.send_time))
+                && !prune_records
+                    .get(&m.message.room_id)
+                    .is_some_and(|record| is_pruned_by(record, m.message.send_time))
+        }));
+    }
 
-fn collect_message_keys(messages: &[MessageWithThumbnail]) -> Vec<(String, String)> {
-    messages
-        .iter()
-        .map(|msg| (msg.message.id.clone(), msg.message.login_uid.clone()))
-        .collect()
+    Ok(kept)
 }
 
 async fn fetch_thumbnail_map<C: ConnectionTrait>(
@@ -246,7 +287,7 @@ async fn update_thumbnail_path<C: ConnectionTrait>(
     Ok(())
 }
 
-async fn enrich_models_with_thumbnails<C: ConnectionTrait>(
+pub(crate) async fn enrich_models_with_thumbnails<C: ConnectionTrait>(
     conn: &C,
     messages: Vec<im_message::Model>,
 ) -> Result<Vec<MessageWithThumbnail>, CommonError> {
@@ -260,12 +301,22 @@ async fn enrich_models_with_thumbnails<C: ConnectionTrait>(
         .collect();
 
     let thumbnail_map = fetch_thumbnail_map(conn, &keys).await?;
+    let edited_keys: Vec<(String, String)> = messages
+        .iter()
+        .filter(|msg| msg.edited_at.is_some())
+        .map(|msg| (msg.id.clone(), msg.login_uid.clone()))
+        .collect();
+    let edit_counts = im_message_revision_repository::count_revisions(conn, &edited_keys).await?;
+    let reply_counts = im_message_thread_repository::count_replies(conn, &keys).await?;
     let enriched = messages
         .into_iter()
         .map(|message| {
             let key = (message.id.clone(), message.login_uid.clone());
             let path = thumbnail_map.get(&key).cloned();
-            MessageWithThumbnail::new(message, path)
+            let mut record = MessageWithThumbnail::new(message, path);
+            record.edit_count = edit_counts.get(&key).copied().unwrap_or(0);
+            record.reply_count = reply_counts.get(&key).copied().unwrap_or(0);
+            record
         })
         .collect();
 
@@ -297,139 +348,178 @@ async fn save_all_internal<C>(
 where
     C: ConnectionTrait,
 {
-    // SQLite 的变量限制通常是 999，为了安全起见，我们设置批次大小为 50
-    // 考虑到需要先查询再删除再插入，减少批次大小以避免变量限制
-    const BATCH_SIZE: usize = 50;
-
-    // 如果数据量小于批次大小，直接处理
-    if messages.len() <= BATCH_SIZE {
-        if !messages.is_empty() {
-            let count = messages.len();
-            process_message_batch(db, messages).await?;
-            info!("Message processing completed, total {} items", count);
-        }
-    } else {
-        // 分批处理
-        for (batch_index, chunk) in messages.chunks(BATCH_SIZE).enumerate() {
-            info!(
-                "Processing batch {} of messages, total {} items",
-                batch_index + 1,
-                chunk.len()
-            );
+    if messages.is_empty() {
+        return Ok(());
+    }
 
-            process_message_batch(db, chunk.to_vec())
-                .await
-                .map_err(|e| {
-                    anyhow::anyhow!(
-                        "Failed to process batch {} of messages: {}",
-                        batch_index + 1,
-                        e
-                    )
-                })?;
-        }
+    let total = messages.len();
+    let mut messages = filter_skipped_messages(db, messages).await?;
+    debug!(
+        "Skipped {} deleted or cleared messages",
+        total - messages.len()
+    );
+    merge_client_messages(db, &mut messages).await?;
 
+    let rows_per_batch = SQLITE_MAX_VARIABLES / UPSERT_COLUMNS.len();
+    for (batch_index, chunk) in messages.chunks(rows_per_batch).enumerate() {
         info!(
-            "All message batch processing completed, total {} items",
-            messages.len()
+            "Upserting batch {} of messages, total {} items",
+            batch_index + 1,
+            chunk.len()
         );
+        upsert_message_batch(db, chunk).await.map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to process batch {} of messages: {}",
+                batch_index + 1,
+                e
+            )
+        })?;
     }
+
+    info!(
+        "Message processing completed, total {} items, {} saved",
+        total,
+        messages.len()
+    );
     Ok(())
 }
 
-/// 处理单批消息：检查存在性，删除已存在的，然后插入新的
-async fn process_message_batch<C>(
+/// 按客户端 ID 与服务端 ID 的映射合并消息：以已确认的客户端 ID 写入的消息改用服务端 ID，
+/// 写入服务端消息前把本地仍以客户端 ID 保存的同一条消息合并过来
+async fn merge_client_messages<C: ConnectionTrait>(
     db: &C,
-    messages: Vec<MessageWithThumbnail>,
-) -> Result<(), CommonError>
-where
-    C: ConnectionTrait,
-{
-    if messages.is_empty() {
-        return Ok(());
-    }
-
-    let mut filtered_messages = Vec::with_capacity(messages.len());
-    for message in messages.into_iter() {
-        let skip = should_skip_message_insert(
-            db,
-            &message.message.id,
-            &message.message.room_id,
-            &message.message.login_uid,
-            message.message.send_time,
-        )
-        .await?;
-        if !skip {
-            filtered_messages.push(message);
-        }
-    }
-
-    if filtered_messages.is_empty() {
-        return Ok(());
+    messages: &mut [MessageWithThumbnail],
+) -> Result<(), CommonError> {
+    let mut by_login_uid: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, record) in messages.iter().enumerate() {
+        by_login_uid
+            .entry(record.message.login_uid.clone())
+            .or_default()
+            .push(index);
     }
-
-    let mut messages = filtered_messages;
-
-    let message_keys = collect_message_keys(&messages);
-    let existing_thumbnail_map = fetch_thumbnail_map(db, &message_keys).await?;
-
-    for message in &mut messages {
-        if message.thumbnail_path.is_none() {
-            if let Some(path) = existing_thumbnail_map.get(&message.key()) {
-                message.thumbnail_path = Some(path.clone());
+    for (login_uid, indexes) in by_login_uid {
+        let ids: Vec<&str> = indexes
+            .iter()
+            .map(|&i| messages[i].message.id.as_str())
+            .collect();
+        let resolved = im_message_client_id_repository::resolve(db, &login_uid, &ids).await?;
+        for &i in &indexes {
+            if let Some(server_id) = resolved.get(&messages[i].message.id) {
+                messages[i].message.id = server_id.clone();
             }
         }
-    }
 
-    // 查询已存在的消息
-    let mut condition = sea_orm::Condition::any();
-    for (id, login_uid) in &message_keys {
-        condition = condition.add(
-            sea_orm::Condition::all()
-                .add(im_message::Column::Id.eq(id.clone()))
-                .add(im_message::Column::LoginUid.eq(login_uid.clone())),
-        );
-    }
-
-    let existing_messages = im_message::Entity::find()
-        .filter(condition)
-        .all(db)
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to query existing messages: {}", e))?;
-
-    if !existing_messages.is_empty() {
-        let mut delete_condition = sea_orm::Condition::any();
-        for msg in &existing_messages {
-            delete_condition = delete_condition.add(
-                sea_orm::Condition::all()
-                    .add(im_message::Column::Id.eq(msg.id.clone()))
-                    .add(im_message::Column::LoginUid.eq(msg.login_uid.clone())),
-            );
+        let ids: Vec<&str> = indexes
+            .iter()
+            .map(|&i| messages[i].message.id.as_str())
+            .collect();
+        let merged = im_message_client_id_repository::merge_known(db, &login_uid, &ids).await?;
+        if merged > 0 {
+            debug!("Merged {} local messages into server messages", merged);
         }
+    }
+    Ok(())
+}
 
-        im_message::Entity::delete_many()
-            .filter(delete_condition)
-            .exec(db)
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to delete existing messages: {}", e))?;
+fn upsert_values(record: &MessageWithThumbnail) -> [Value; UPSERT_COLUMNS.len()] {
+    let message = &record.message;
+    [
+        Value::from(message.id.clone()),
+        Value::from(message.uid.clone()),
+        Value::from(message.nickname.clone()),
+        Value::from(message.room_id.clone()),
+        Value::from(message.send_time),
+        Value::from(message.message_type),
+        Value::from(message.body.clone()),
+        Value::from(message.message_marks.clone()),
+        Value::from(message.create_time),
+        Value::from(message.update_time),
+        Value::from(message.login_uid.clone()),
+        Value::from(message.send_status.clone()),
+        Value::from(message.time_block),
+        Value::from(record.thumbnail_path.clone()),
+        Value::from(message.edited_at),
+        Value::from(
+            message
+                .reply_to_id
+                .clone()
+                .or_else(|| message.body.as_deref().and_then(extract_reply_to_id)),
+        ),
+    ]
+}
 
-        debug!("Deleted {} existing messages", existing_messages.len());
+/// 从消息体中提取被回复的消息 ID：服务端消息为 reply.id，本地发送的消息为 replyMsgId
+pub(crate) fn extract_reply_to_id(body: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let id = value
+        .get("reply")
+        .and_then(|reply| reply.get("id"))
+        .filter(|id| !id.is_null())
+        .or_else(|| value.get("replyMsgId").filter(|id| !id.is_null()))?;
+    match id {
+        serde_json::Value::String(id) if !id.is_empty() => Some(id.clone()),
+        serde_json::Value::Number(id) => Some(id.to_string()),
+        _ => None,
     }
+}
 
-    // 插入新消息
-    let active_models: Vec<im_message::ActiveModel> = messages
+/// 生成批量 upsert 语句，冲突时覆盖服务端字段，thumbnail_path、edited_at 仅在传入非空值时覆盖；
+/// 内容未变化的行不会被重写，避免无意义的写入和全文索引更新
+fn build_upsert_sql(rows: usize) -> String {
+    let placeholders = format!("({})", vec!["?"; UPSERT_COLUMNS.len()].join(", "));
+    let updatable = UPSERT_COLUMNS
         .iter()
-        .map(|message| message.message.clone().into_active_model())
+        .filter(|col| !matches!(**col, "id" | "login_uid" | "thumbnail_path" | "edited_at"));
+
+    let mut assignments: Vec<String> = updatable
+        .clone()
+        .map(|col| format!("{col} = excluded.{col}"))
         .collect();
+    assignments.push(
+        "thumbnail_path = COALESCE(excluded.thumbnail_path, im_message.thumbnail_path)".to_string(),
+    );
+    assignments.push("edited_at = COALESCE(excluded.edited_at, im_message.edited_at)".to_string());
 
-    im_message::Entity::insert_many(active_models)
-        .exec(db)
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to batch insert messages: {}", e))?;
+    let mut changed: Vec<String> = updatable
+        .map(|col| format!("im_message.{col} IS NOT excluded.{col}"))
+        .collect();
+    changed.push(
+        "(excluded.thumbnail_path IS NOT NULL AND im_message.thumbnail_path IS NOT excluded.thumbnail_path)"
+            .to_string(),
+    );
+    changed.push(
+        "(excluded.edited_at IS NOT NULL AND im_message.edited_at IS NOT excluded.edited_at)"
+            .to_string(),
+    );
+
+    format!(
+        "INSERT INTO im_message ({}) VALUES {} ON CONFLICT(id, login_uid) DO UPDATE SET {} WHERE {}",
+        UPSERT_COLUMNS.join(", "),
+        vec![placeholders; rows].join(", "),
+        assignments.join(", "),
+        changed.join(" OR ")
+    )
+}
 
-    for message in &messages {
-        update_thumbnail_path(db, &message.key(), message.thumbnail_path.as_deref()).await?;
+/// 以单条 INSERT ... ON CONFLICT DO UPDATE 写入一批消息
+async fn upsert_message_batch<C>(
+    db: &C,
+    messages: &[MessageWithThumbnail],
+) -> Result<(), CommonError>
+where
+    C: ConnectionTrait,
+{
+    if messages.is_empty() {
+        return Ok(());
     }
 
+    let values: Vec<Value> = messages.iter().flat_map(upsert_values).collect();
+    let stmt = Statement::from_sql_and_values(
+        db.get_database_backend(),
+        build_upsert_sql(messages.len()),
+        values,
+    );
+    db.execute(stmt).await?;
     Ok(())
 }
 
@@ -499,6 +589,187 @@ pub async fn cursor_page_messages(
     })
 }
 
+fn room_range_condition(
+    login_uid: &str,
+    room_id: &str,
+    start_time: Option<i64>,
+    end_time: Option<i64>,
+) -> Condition {
+    let mut condition = Condition::all()
+        .add(im_message::Column::RoomId.eq(room_id))
+        .add(im_message::Column::LoginUid.eq(login_uid));
+    if let Some(start_time) = start_time {
+        condition = condition.add(im_message::Column::SendTime.gte(start_time));
+    }
+    if let Some(end_time) = end_time {
+        condition = condition.add(im_message::Column::SendTime.lte(end_time));
+    }
+    condition
+}
+
+/// 统计房间在时间范围内的消息数
+pub async fn count_room_messages(
+    db: &DatabaseConnection,
+    login_uid: &str,
+    room_id: &str,
+    start_time: Option<i64>,
+    end_time: Option<i64>,
+) -> Result<u64, CommonError> {
+    let total = im_message::Entity::find()
+        .filter(room_range_condition(
+            login_uid, room_id, start_time, end_time,
+        ))
+        .count(db)
+        .await?;
+    Ok(total)
+}
+
+/// 按发送时间升序分批读取房间消息，after 为上一批最后一条消息的 (send_time, id)
+pub async fn list_room_messages_after(
+    db: &DatabaseConnection,
+    login_uid: &str,
+    room_id: &str,
+    start_time: Option<i64>,
+    end_time: Option<i64>,
+    after: Option<(i64, i64)>,
+    limit: u64,
+) -> Result<Vec<MessageWithThumbnail>, CommonError> {
+    let mut condition = room_range_condition(login_uid, room_id, start_time, end_time);
+    if let Some((send_time, id)) = after {
+        condition = condition.add(Expr::cust_with_values(
+            r#"(COALESCE("im_message"."send_time", 0), CAST("im_message"."id" AS INTEGER)) > (?, ?)"#,
+            [send_time, id],
+        ));
+    }
+
+    let messages = im_message::Entity::find()
+        .filter(condition)
+        .order_by_asc(Expr::cust(r#"COALESCE("im_message"."send_time", 0)"#))
+        .order_by_asc(Expr::col(im_message::Column::Id).cast_as(Alias::new("INTEGER")))
+        .limit(limit)
+        .all(db)
+        .await?;
+    enrich_models_with_thumbnails(db, messages).await
+}
+
+/// 锚点分页的方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageDirection {
+    /// 更早的消息
+    Before,
+    /// 更新的消息
+    After,
+}
+
+/// 消息在 (send_time, id) 排序中的位置，用作锚点分页的游标。
+/// send_time 相同时按 id 字符串排序，只要求顺序稳定，不依赖 id 能否转为整数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageKey {
+    pub send_time: i64,
+    pub id: String,
+}
+
+impl MessageKey {
+    /// 游标格式为 "sendTime_id"
+    pub fn parse(cursor: &str) -> Option<Self> {
+        let (send_time, id) = cursor.split_once('_')?;
+        Some(Self {
+            send_time: send_time.parse().ok()?,
+            id: id.to_string(),
+        })
+    }
+
+    pub fn to_cursor(&self) -> String {
+        format!("{}_{}", self.send_time, self.id)
+    }
+}
+
+impl From<&im_message::Model> for MessageKey {
+    fn from(message: &im_message::Model) -> Self {
+        Self {
+            send_time: message.send_time.unwrap_or(0),
+            id: message.id.clone(),
+        }
+    }
+}
+
+/// 查询房间内消息的排序位置，消息不在本地时返回 None
+pub async fn find_message_key<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+    message_id: &str,
+) -> Result<Option<MessageKey>, CommonError> {
+    let message = im_message::Entity::find()
+        .filter(im_message::Column::LoginUid.eq(login_uid))
+        .filter(im_message::Column::RoomId.eq(room_id))
+        .filter(im_message::Column::Id.eq(message_id))
+        .one(db)
+        .await?;
+    Ok(message.as_ref().map(MessageKey::from))
+}
+
+/// 房间内本地最早一条已发送成功的消息
+pub async fn find_oldest_message_key<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+) -> Result<Option<MessageKey>, CommonError> {
+    let message = im_message::Entity::find()
+        .filter(im_message::Column::LoginUid.eq(login_uid))
+        .filter(im_message::Column::RoomId.eq(room_id))
+        .filter(im_message::Column::SendStatus.eq("success"))
+        .order_by_asc(Expr::cust(r#"COALESCE("im_message"."send_time", 0)"#))
+        .order_by_asc(im_message::Column::Id)
+        .one(db)
+        .await?;
+    Ok(message.as_ref().map(MessageKey::from))
+}
+
+/// 从 key 开始按 direction 方向取最多 limit 条消息，结果按 (send_time, id) 升序返回。
+/// inclusive 为 true 时包含 key 所在的消息
+pub async fn page_messages_from_key<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+    key: &MessageKey,
+    direction: PageDirection,
+    inclusive: bool,
+    limit: u64,
+) -> Result<Vec<MessageWithThumbnail>, CommonError> {
+    let operator = match (direction, inclusive) {
+        (PageDirection::Before, false) => "<",
+        (PageDirection::Before, true) => "<=",
+        (PageDirection::After, false) => ">",
+        (PageDirection::After, true) => ">=",
+    };
+    let mut query = im_message::Entity::find()
+        .filter(im_message::Column::RoomId.eq(room_id))
+        .filter(im_message::Column::LoginUid.eq(login_uid))
+        .filter(Expr::cust_with_values(
+            format!(
+                r#"(COALESCE("im_message"."send_time", 0), "im_message"."id") {} (?, ?)"#,
+                operator
+            ),
+            [Value::from(key.send_time), Value::from(key.id.clone())],
+        ))
+        .limit(limit);
+    query = match direction {
+        PageDirection::Before => query
+            .order_by_desc(Expr::cust(r#"COALESCE("im_message"."send_time", 0)"#))
+            .order_by_desc(im_message::Column::Id),
+        PageDirection::After => query
+            .order_by_asc(Expr::cust(r#"COALESCE("im_message"."send_time", 0)"#))
+            .order_by_asc(im_message::Column::Id),
+    };
+
+    let mut messages = query.all(db).await?;
+    if direction == PageDirection::Before {
+        messages.reverse();
+    }
+    enrich_models_with_thumbnails(db, messages).await
+}
+
 /// 保存单个消息到数据库
 pub async fn save_message(
     db: &DatabaseTransaction,
@@ -516,31 +787,13 @@ pub async fn save_message(
         return Ok(record);
     }
 
-    // 根据消息主键查找是否已存在
-    let existing_message = im_message::Entity::find_by_id((
-        record.message.id.clone(),
-        record.message.login_uid.clone(),
-    ))
-    .one(db)
-    .await
-    .map_err(|e| anyhow::anyhow!("Failed to find message: {}", e))?;
+    merge_client_messages(db, std::slice::from_mut(&mut record)).await?;
 
     if record.thumbnail_path.is_none() {
         record.thumbnail_path =
             fetch_thumbnail_path(db, &record.message.id, &record.message.login_uid).await?;
     }
 
-    // 如果已存在，则先删除
-    if existing_message.is_some() {
-        im_message::Entity::delete_by_id((
-            record.message.id.clone(),
-            record.message.login_uid.clone(),
-        ))
-        .exec(db)
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to delete existing message: {}", e))?;
-    }
-
     // 如果缺少，就填充time_block，使用统一的计算函数
     if record.message.time_block.is_none() {
         if let Some(current_send_time) = record.message.send_time {
@@ -556,8 +809,8 @@ pub async fn save_message(
         }
     }
 
-    let active_model = record.message.clone().into_active_model();
-    im_message::Entity::insert(active_model).exec(db).await?;
+    // 已存在时原地更新，保留 rowid 与编辑记录
+    upsert_message_batch(db, std::slice::from_ref(&record)).await?;
     update_thumbnail_path(db, &record.key(), record.thumbnail_path.as_deref()).await?;
     Ok(record)
 }
@@ -631,21 +884,26 @@ pub async fn record_deleted_message(
     room_id: &str,
     login_uid: &str,
 ) -> Result<(), CommonError> {
-    ensure_deleted_message_table(db).await?;
-    let backend = db.get_database_backend();
-    let stmt = Statement::from_sql_and_values(
-        backend,
-        "INSERT INTO im_deleted_message (id, room_id, login_uid, deleted_at)
-         VALUES (?, ?, ?, ?)
-         ON CONFLICT(id, login_uid) DO UPDATE SET room_id = excluded.room_id, deleted_at = excluded.deleted_at",
-        vec![
-            Value::from(message_id.to_string()),
-            Value::from(room_id.to_string()),
-            Value::from(login_uid.to_string()),
-            Value::from(Utc::now().timestamp_millis()),
-        ],
-    );
-    db.execute(stmt).await.map_err(CommonError::DatabaseError)?;
+    let record = im_deleted_message::ActiveModel {
+        id: Set(message_id.to_string()),
+        room_id: Set(room_id.to_string()),
+        login_uid: Set(login_uid.to_string()),
+        deleted_at: Set(Utc::now().timestamp_millis()),
+    };
+    im_deleted_message::Entity::insert(record)
+        .on_conflict(
+            OnConflict::columns([
+                im_deleted_message::Column::Id,
+                im_deleted_message::Column::LoginUid,
+            ])
+            .update_columns([
+                im_deleted_message::Column::RoomId,
+                im_deleted_message::Column::DeletedAt,
+            ])
+            .to_owned(),
+        )
+        .exec(db)
+        .await?;
     Ok(())
 }
 
@@ -655,24 +913,26 @@ pub async fn record_room_clear(
     login_uid: &str,
     last_cleared_msg_id: Option<String>,
 ) -> Result<(), CommonError> {
-    ensure_room_clear_table(db).await?;
-    let backend = db.get_database_backend();
-    let stmt = Statement::from_sql_and_values(
-        backend,
-        "INSERT INTO im_room_clear_record (room_id, login_uid, cleared_at, last_cleared_msg_id)
-         VALUES (?, ?, ?, ?)
-         ON CONFLICT(room_id, login_uid) DO UPDATE SET cleared_at = excluded.cleared_at, last_cleared_msg_id = excluded.last_cleared_msg_id",
-        vec![
-            Value::from(room_id.to_string()),
-            Value::from(login_uid.to_string()),
-            Value::from(Utc::now().timestamp_millis()),
-            match last_cleared_msg_id {
-                Some(id) => Value::from(id),
-                None => Value::String(None),
-            },
-        ],
-    );
-    db.execute(stmt).await.map_err(CommonError::DatabaseError)?;
+    let record = im_room_clear_record::ActiveModel {
+        room_id: Set(room_id.to_string()),
+        login_uid: Set(login_uid.to_string()),
+        cleared_at: Set(Utc::now().timestamp_millis()),
+        last_cleared_msg_id: Set(last_cleared_msg_id),
+    };
+    im_room_clear_record::Entity::insert(record)
+        .on_conflict(
+            OnConflict::columns([
+                im_room_clear_record::Column::RoomId,
+                im_room_clear_record::Column::LoginUid,
+            ])
+            .update_columns([
+                im_room_clear_record::Column::ClearedAt,
+                im_room_clear_record::Column::LastClearedMsgId,
+            ])
+            .to_owned(),
+        )
+        .exec(db)
+        .await?;
     Ok(())
 }
 
@@ -718,6 +978,96 @@ where
     Ok(None)
 }
 
+/// 按 ID 批量查询当前用户已有的消息
+pub async fn find_messages_by_ids<C>(
+    db: &C,
+    login_uid: &str,
+    ids: &[String],
+) -> Result<HashMap<String, im_message::Model>, CommonError>
+where
+    C: ConnectionTrait,
+{
+    let mut found = HashMap::new();
+    for chunk in ids.chunks(SQLITE_MAX_VARIABLES - 1) {
+        let messages = im_message::Entity::find()
+            .filter(im_message::Column::LoginUid.eq(login_uid))
+            .filter(im_message::Column::Id.is_in(chunk.iter().cloned()))
+            .all(db)
+            .await?;
+        found.extend(messages.into_iter().map(|m| (m.id.clone(), m)));
+    }
+    Ok(found)
+}
+
+/// 查询单条消息并附带缩略图路径
+pub async fn find_message_with_thumbnail<C>(
+    db: &C,
+    login_uid: &str,
+    id: &str,
+) -> Result<Option<MessageWithThumbnail>, CommonError>
+where
+    C: ConnectionTrait,
+{
+    let Some(message) = im_message::Entity::find_by_id((id.to_string(), login_uid.to_string()))
+        .one(db)
+        .await?
+    else {
+        return Ok(None);
+    };
+    Ok(enrich_models_with_thumbnails(db, vec![message])
+        .await?
+        .into_iter()
+        .next())
+}
+
+/// 重新计算房间内 send_time 不早于 from_send_time 的消息的 time_block，规则与 calculate_time_block 一致。
+/// 用于批量插入历史消息后修正前后相邻消息的分隔时间，返回更新的行数
+pub async fn recompute_time_blocks<C>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+    from_send_time: i64,
+) -> Result<u64, CommonError>
+where
+    C: ConnectionTrait,
+{
+    // 时间间隔阈值：10分钟
+    const TIME_BLOCK_THRESHOLD_MS: i64 = 1000 * 60 * 10;
+    let sql = r#"
+        UPDATE im_message SET time_block = t.block
+        FROM (
+            SELECT rid, send_time,
+                CASE
+                    WHEN prev_time IS NULL THEN 1
+                    WHEN send_time - prev_time >= ? THEN send_time - prev_time
+                    ELSE NULL
+                END AS block
+            FROM (
+                SELECT rowid AS rid, send_time,
+                    LAG(send_time) OVER (ORDER BY send_time, CAST(id AS INTEGER)) AS prev_time
+                FROM im_message
+                WHERE login_uid = ? AND room_id = ? AND send_time IS NOT NULL
+            )
+        ) AS t
+        WHERE im_message.rowid = t.rid
+            AND t.send_time >= ?
+            AND im_message.time_block IS NOT t.block
+    "#;
+    let result = db
+        .execute(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            [
+                TIME_BLOCK_THRESHOLD_MS.into(),
+                login_uid.into(),
+                room_id.into(),
+                from_send_time.into(),
+            ],
+        ))
+        .await?;
+    Ok(result.rows_affected())
+}
+
 /// 更新消息发送状态
 pub async fn update_message_status(
     db: &DatabaseConnection,
@@ -776,6 +1126,25 @@ pub async fn update_message_status(
     Ok(record)
 }
 
+/// 只更新消息的发送状态，返回是否更新
+pub async fn update_send_status<C>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+    status: &str,
+) -> Result<bool, CommonError>
+where
+    C: ConnectionTrait,
+{
+    let result = im_message::Entity::update_many()
+        .col_expr(im_message::Column::SendStatus, Expr::value(status))
+        .filter(im_message::Column::Id.eq(message_id))
+        .filter(im_message::Column::LoginUid.eq(login_uid))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
 /// 更新消息撤回状态
 pub async fn update_message_recall_status(
     db: &DatabaseConnection,
@@ -846,28 +1215,13 @@ pub async fn query_chat_history(
         conditions = conditions.add(type_condition);
     }
 
-    // 关键词搜索（支持消息内容与文件名等字段）
-    if let Some(ref keyword) = condition.search_keyword {
-        let trimmed = keyword.trim();
-        if !trimmed.is_empty() {
-            let keyword_pattern = format!("%{}%", trimmed);
-            let keyword_pattern_lower = format!("%{}%", trimmed.to_lowercase());
-
-            let mut keyword_condition = Condition::any();
-            for json_path in ["$.content", "$.fileName", "$.url"] {
-                keyword_condition = keyword_condition.add(Expr::cust_with_values(
-                    &format!("JSON_EXTRACT(body, '{}') LIKE ?", json_path),
-                    [Value::from(keyword_pattern.clone())],
-                ));
-            }
-
-            keyword_condition = keyword_condition.add(Expr::cust_with_values(
-                "LOWER(body) LIKE ?",
-                [Value::from(keyword_pattern_lower)],
-            ));
-
-            conditions = conditions.add(keyword_condition);
-        }
+    // 关键词搜索（全文索引，覆盖消息内容、文件名及回复/合并转发内容）
+    if let Some(query) = condition
+        .search_keyword
+        .as_deref()
+        .and_then(FtsQuery::parse)
+    {
+        conditions = conditions.add(query.condition());
     }
 
     // 日期范围筛选
@@ -942,46 +1296,9 @@ pub async fn query_file_messages(
         conditions = conditions.add(type_condition);
     }
 
-    // 关键词搜索（搜索文件名、来源等关键信息）
-    if let Some(keyword) = search_keyword {
-        let trimmed_keyword = keyword.trim();
-        if !trimmed_keyword.is_empty() {
-            let keyword_lower = trimmed_keyword.to_lowercase();
-            let keyword_pattern = format!("%{}%", keyword_lower);
-            use sea_orm::sea_query::Value;
-
-            let json_paths = [
-                "$.fileName",
-                "$.file_name",
-                "$.name",
-                "$.originalFileName",
-                "$.originalName",
-                "$.original_name",
-                "$.title",
-                "$.fileTitle",
-                "$.url",
-                "$.downloadUrl",
-                "$.content",
-            ];
-
-            let mut keyword_condition = Condition::any();
-
-            for path in json_paths {
-                let expr = format!("LOWER(JSON_EXTRACT(body, '{}')) LIKE ?", path);
-                keyword_condition = keyword_condition.add(Expr::cust_with_values(
-                    expr,
-                    [Value::from(keyword_pattern.clone())],
-                ));
-            }
-
-            // 兼容 JSON 中缺少字段时直接在 body 中检索
-            keyword_condition = keyword_condition.add(Expr::cust_with_values(
-                "LOWER(body) LIKE ?",
-                [Value::from(keyword_pattern.clone())],
-            ));
-
-            conditions = conditions.add(keyword_condition);
-        }
+    // 关键词搜索（全文索引，覆盖文件名及消息内容）
+    if let Some(query) = search_keyword.and_then(FtsQuery::parse) {
+        conditions = conditions.add(query.condition());
     }
 
     // 构建分页查询
@@ -1002,3 +1319,283 @@ pub async fn query_file_messages(
 
     enrich_models_with_thumbnails(db, messages).await
 }
+
+/// 媒体库查询，由 idx_im_message_login_type_send_time_room 按类型和游标定位
+fn media_query(
+    login_uid: &str,
+    room_id: Option<&str>,
+    message_types: &[u8],
+    before: Option<&MessageKey>,
+    limit: u64,
+) -> Select<im_message::Entity> {
+    let mut condition = Condition::all()
+        .add(im_message::Column::LoginUid.eq(login_uid))
+        .add(im_message::Column::MessageType.is_in(message_types.iter().copied()))
+        .add(im_message::Column::SendTime.is_not_null());
+    if let Some(room_id) = room_id {
+        condition = condition.add(im_message::Column::RoomId.eq(room_id));
+    }
+    if let Some(key) = before {
+        condition = condition.add(Expr::cust_with_values(
+            r#"("im_message"."send_time", "im_message"."id") < (?, ?)"#,
+            [Value::from(key.send_time), Value::from(key.id.clone())],
+        ));
+    }
+
+    im_message::Entity::find()
+        .filter(condition)
+        .order_by_desc(im_message::Column::SendTime)
+        .order_by_desc(im_message::Column::Id)
+        .limit(limit)
+}
+
+/// 媒体库分页：按 (send_time, id) 倒序取指定类型的消息，before 为上一页最后一条消息的位置。
+/// 未确定发送时间的消息（如发送中）不参与排序，不会出现在结果中
+pub async fn page_media_messages<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: Option<&str>,
+    message_types: &[u8],
+    before: Option<&MessageKey>,
+    limit: u64,
+) -> Result<Vec<MessageWithThumbnail>, CommonError> {
+    let messages = media_query(login_uid, room_id, message_types, before, limit)
+        .all(db)
+        .await?;
+    enrich_models_with_thumbnails(db, messages).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::{memory_db, migrated_db};
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::{DbBackend, QueryTrait};
+
+    #[tokio::test]
+    async fn test_migration_keeps_legacy_deletion_records() {
+        let db = memory_db().await;
+
+        // 旧版本运行时创建的表及数据
+        db.execute_unprepared(
+            "CREATE TABLE im_deleted_message (
+                id TEXT NOT NULL,
+                room_id TEXT NOT NULL,
+                login_uid TEXT NOT NULL,
+                deleted_at INTEGER NOT NULL,
+                PRIMARY KEY (id, login_uid)
+            )",
+        )
+        .await
+        .unwrap();
+        db.execute_unprepared("INSERT INTO im_deleted_message VALUES ('100', '1', '10001', 0)")
+            .await
+            .unwrap();
+
+        Migrator::up(&db, None).await.unwrap();
+
+        assert!(
+            should_skip_message_insert(&db, "100", "1", "10001", None)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !should_skip_message_insert(&db, "101", "1", "10001", Some(10))
+                .await
+                .unwrap()
+        );
+
+        record_room_clear(&db, "1", "10001", Some("200".to_string()))
+            .await
+            .unwrap();
+        record_room_clear(&db, "1", "10001", Some("150".to_string()))
+            .await
+            .unwrap();
+        assert!(
+            should_skip_message_insert(&db, "150", "1", "10001", None)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !should_skip_message_insert(&db, "151", "1", "10001", None)
+                .await
+                .unwrap()
+        );
+    }
+
+    fn test_record(id: &str, body: &str, thumbnail_path: Option<&str>) -> MessageWithThumbnail {
+        MessageWithThumbnail::new(
+            im_message::Model {
+                id: id.to_string(),
+                uid: "10002".to_string(),
+                nickname: None,
+                room_id: "1".to_string(),
+                send_time: id.parse().ok(),
+                message_type: Some(1),
+                body: Some(body.to_string()),
+                message_marks: None,
+                create_time: None,
+                update_time: None,
+                login_uid: "10001".to_string(),
+                send_status: "success".to_string(),
+                time_block: None,
+                edited_at: None,
+                reply_to_id: None,
+            },
+            thumbnail_path.map(str::to_string),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_save_all_upserts_and_keeps_thumbnail() {
+        let db = migrated_db().await;
+
+        record_deleted_message(&db, "2", "1", "10001")
+            .await
+            .unwrap();
+        save_all(
+            &db,
+            vec![
+                test_record("1", "a", Some("/thumb/1.png")),
+                test_record("2", "b", None),
+                test_record("3", "c", None),
+            ],
+        )
+        .await
+        .unwrap();
+
+        // 再次同步时不带缩略图，已有的本地缩略图不应被覆盖
+        save_all(&db, vec![test_record("1", "a2", None)])
+            .await
+            .unwrap();
+
+        let saved = enrich_models_with_thumbnails(
+            &db,
+            im_message::Entity::find()
+                .order_by_asc(im_message::Column::Id)
+                .all(&db)
+                .await
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        let ids: Vec<&str> = saved.iter().map(|m| m.message.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "3"]);
+        assert_eq!(saved[0].message.body.as_deref(), Some("a2"));
+        assert_eq!(saved[0].thumbnail_path.as_deref(), Some("/thumb/1.png"));
+    }
+
+    #[tokio::test]
+    async fn test_page_messages_from_key() {
+        let db = migrated_db().await;
+
+        // "9" 与 "10" 发送时间相同，按 id 字符串排序
+        let mut records: Vec<MessageWithThumbnail> = ["8", "9", "10", "11"]
+            .iter()
+            .map(|id| test_record(id, "a", None))
+            .collect();
+        records[2].message.send_time = Some(9);
+        save_all(&db, records).await.unwrap();
+
+        let ids = |list: Vec<MessageWithThumbnail>| -> Vec<String> {
+            list.into_iter().map(|m| m.message.id).collect()
+        };
+        let key = find_message_key(&db, "10001", "1", "9")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(MessageKey::parse(&key.to_cursor()), Some(key.clone()));
+
+        let before =
+            page_messages_from_key(&db, "10001", "1", &key, PageDirection::Before, true, 3)
+                .await
+                .unwrap();
+        assert_eq!(ids(before), vec!["8", "10", "9"]);
+        let after = page_messages_from_key(&db, "10001", "1", &key, PageDirection::After, false, 5)
+            .await
+            .unwrap();
+        assert_eq!(ids(after), vec!["11"]);
+
+        // 按时间定位时 id 为空，同一时间的消息都排在锚点之后
+        let key = MessageKey {
+            send_time: 9,
+            id: String::new(),
+        };
+        let after = page_messages_from_key(&db, "10001", "1", &key, PageDirection::After, true, 5)
+            .await
+            .unwrap();
+        assert_eq!(ids(after), vec!["10", "9", "11"]);
+        assert_eq!(
+            find_oldest_message_key(&db, "10001", "1")
+                .await
+                .unwrap()
+                .map(|k| k.id),
+            Some("8".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_page_media_messages_pages_across_types() {
+        let db = migrated_db().await;
+
+        // 图片、视频、文件分布在两个房间，"9" 与 "10" 发送时间相同；文本消息不应出现
+        let records = [
+            ("7", 3, "1"),
+            ("8", 5, "2"),
+            ("9", 7, "1"),
+            ("10", 3, "2"),
+            ("11", 5, "1"),
+            ("12", 1, "1"),
+        ]
+        .into_iter()
+        .map(|(id, message_type, room_id)| {
+            let mut record = test_record(id, r#"{"url":"a.png"}"#, None);
+            record.message.message_type = Some(message_type);
+            record.message.room_id = room_id.to_string();
+            if id == "10" {
+                record.message.send_time = Some(9);
+            }
+            record
+        })
+        .collect();
+        save_all(&db, records).await.unwrap();
+
+        let ids = |list: Vec<MessageWithThumbnail>| -> Vec<String> {
+            list.into_iter().map(|m| m.message.id).collect()
+        };
+        let media = page_media_messages(&db, "10001", None, &[3, 5, 7], None, 3)
+            .await
+            .unwrap();
+        let last = MessageKey::from(&media[2].message);
+        assert_eq!(ids(media), vec!["11", "9", "10"]);
+        let media = page_media_messages(&db, "10001", None, &[3, 5, 7], Some(&last), 3)
+            .await
+            .unwrap();
+        assert_eq!(ids(media), vec!["8", "7"]);
+        let media = page_media_messages(&db, "10001", Some("1"), &[3, 5, 7], None, 10)
+            .await
+            .unwrap();
+        assert_eq!(ids(media), vec!["11", "9", "7"]);
+
+        // 跨房间查询按类型和游标由媒体库索引定位
+        let statement =
+            media_query("10001", None, &[3, 5, 7], Some(&last), 3).build(DbBackend::Sqlite);
+        let plan = db
+            .query_all(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                format!("EXPLAIN QUERY PLAN {}", statement.sql),
+                statement.values.map(|v| v.0).unwrap_or_default(),
+            ))
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.try_get::<String>("", "detail").unwrap())
+            .collect::<Vec<_>>();
+        assert!(
+            plan.iter()
+                .any(|detail| detail.contains("idx_im_message_login_type_send_time_room")),
+            "{:?}",
+            plan
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use crate::error::CommonError;
+use crate::repository::SQLITE_MAX_VARIABLES;
+use chrono::Utc;
+use entity::{im_message, im_message_revision};
+use sea_orm::prelude::Expr;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use std::collections::HashMap;
+
+/// 一次编辑的结果
+#[derive(Debug, Clone)]
+pub struct AppliedEdit {
+    pub message: im_message::Model,
+    /// 本次编辑新增的版本号；内容未变化或编辑事件已过期时为空
+    pub revision: Option<i32>,
+    pub edit_count: u32,
+}
+
+async fn latest_revision<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+) -> Result<Option<im_message_revision::Model>, CommonError> {
+    let revision = im_message_revision::Entity::find()
+        .filter(im_message_revision::Column::LoginUid.eq(login_uid))
+        .filter(im_message_revision::Column::MessageId.eq(message_id))
+        .order_by_desc(im_message_revision::Column::Revision)
+        .one(db)
+        .await?;
+    Ok(revision)
+}
+
+async fn count_message_revisions<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+) -> Result<u32, CommonError> {
+    let key = (message_id.to_string(), login_uid.to_string());
+    let counts = count_revisions(db, std::slice::from_ref(&key)).await?;
+    Ok(counts.get(&key).copied().unwrap_or(0))
+}
+
+/// 应用一次编辑：保存编辑前的消息体为新版本，并更新消息内容与 edited_at。
+/// edited_at 不晚于本地记录的编辑事件视为过期，不做修改；
+/// 消息不存在，或发送者、房间与本地记录不一致时返回 None
+pub async fn apply_edit<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+    uid: &str,
+    room_id: &str,
+    body: &str,
+    edited_at: i64,
+) -> Result<Option<AppliedEdit>, CommonError> {
+    let Some(message) =
+        im_message::Entity::find_by_id((message_id.to_string(), login_uid.to_string()))
+            .one(db)
+            .await?
+    else {
+        return Ok(None);
+    };
+    if message.uid != uid || message.room_id != room_id {
+        return Ok(None);
+    }
+
+    if message.edited_at.is_some_and(|local| local >= edited_at) {
+        let edit_count = count_message_revisions(db, login_uid, message_id).await?;
+        return Ok(Some(AppliedEdit {
+            message,
+            revision: None,
+            edit_count,
+        }));
+    }
+
+    let mut revision = None;
+    if message.body.as_deref() != Some(body) {
+        let next = latest_revision(db, login_uid, message_id)
+            .await?
+            .map_or(1, |r| r.revision + 1);
+        im_message_revision::Entity::insert(im_message_revision::ActiveModel {
+            message_id: Set(message_id.to_string()),
+            login_uid: Set(login_uid.to_string()),
+            revision: Set(next),
+            room_id: Set(message.room_id.clone()),
+            body: Set(message.body.clone()),
+            previous_edited_at: Set(message.edited_at),
+            edited_at: Set(edited_at),
+        })
+        .exec(db)
+        .await?;
+        revision = Some(next);
+    }
+
+    let mut active_model = message.into_active_model();
+    active_model.body = Set(Some(body.to_string()));
+    active_model.edited_at = Set(Some(edited_at));
+    active_model.update_time = Set(Some(Utc::now().timestamp_millis()));
+    let message = im_message::Entity::update(active_model).exec(db).await?;
+    let edit_count = count_message_revisions(db, login_uid, message_id).await?;
+
+    Ok(Some(AppliedEdit {
+        message,
+        revision,
+        edit_count,
+    }))
+}
+
+/// 撤销一次本地编辑（服务端拒绝时回滚），只能撤销最新的版本，返回是否已撤销
+pub async fn revert_edit<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+    revision: i32,
+) -> Result<bool, CommonError> {
+    let Some(latest) = latest_revision(db, login_uid, message_id).await? else {
+        return Ok(false);
+    };
+    if latest.revision != revision {
+        return Ok(false);
+    }
+
+    im_message::Entity::update_many()
+        .col_expr(im_message::Column::Body, Expr::value(latest.body.clone()))
+        .col_expr(
+            im_message::Column::EditedAt,
+            Expr::value(latest.previous_edited_at),
+        )
+        .filter(im_message::Column::Id.eq(message_id))
+        .filter(im_message::Column::LoginUid.eq(login_uid))
+        .exec(db)
+        .await?;
+    im_message_revision::Entity::delete_by_id((
+        message_id.to_string(),
+        login_uid.to_string(),
+        revision,
+    ))
+    .exec(db)
+    .await?;
+    Ok(true)
+}
+
+/// 按版本号升序列出消息的编辑记录
+pub async fn list_revisions<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+) -> Result<Vec<im_message_revision::Model>, CommonError> {
+    let revisions = im_message_revision::Entity::find()
+        .filter(im_message_revision::Column::LoginUid.eq(login_uid))
+        .filter(im_message_revision::Column::MessageId.eq(message_id))
+        .order_by_asc(im_message_revision::Column::Revision)
+        .all(db)
+        .await?;
+    Ok(revisions)
+}
+
+/// 批量统计消息的编辑次数，key 为 (message_id, login_uid)
+pub async fn count_revisions<C: ConnectionTrait>(
+    db: &C,
+    keys: &[(String, String)],
+) -> Result<HashMap<(String, String), u32>, CommonError> {
+    let mut by_login_uid: HashMap<&str, Vec<String>> = HashMap::new();
+    for (message_id, login_uid) in keys {
+        by_login_uid
+            .entry(login_uid.as_str())
+            .or_default()
+            .push(message_id.clone());
+    }
+
+    let mut counts = HashMap::new();
+    for (login_uid, message_ids) in by_login_uid {
+        for chunk in message_ids.chunks(SQLITE_MAX_VARIABLES - 1) {
+            let rows: Vec<(String, i64)> = im_message_revision::Entity::find()
+                .select_only()
+                .column(im_message_revision::Column::MessageId)
+                .column_as(im_message_revision::Column::Revision.count(), "edit_count")
+                .filter(im_message_revision::Column::LoginUid.eq(login_uid))
+                .filter(im_message_revision::Column::MessageId.is_in(chunk.iter().cloned()))
+                .group_by(im_message_revision::Column::MessageId)
+                .into_tuple()
+                .all(db)
+                .await?;
+            counts
+                .extend(rows.into_iter().map(|(message_id, count)| {
+                    ((message_id, login_uid.to_string()), count as u32)
+                }));
+        }
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+
+    #[tokio::test]
+    async fn test_edit_revert_and_cascade_delete() {
+        let db = migrated_db().await;
+
+        im_message::Entity::insert(im_message::ActiveModel {
+            id: Set("1".to_string()),
+            uid: Set("10001".to_string()),
+            room_id: Set("1".to_string()),
+            send_time: Set(Some(0)),
+            message_type: Set(Some(1)),
+            body: Set(Some("v0".to_string())),
+            login_uid: Set("10001".to_string()),
+            send_status: Set("success".to_string()),
+            ..Default::default()
+        })
+        .exec(&db)
+        .await
+        .unwrap();
+
+        // 发送者或房间不一致的编辑不生效
+        assert!(
+            apply_edit(&db, "10001", "1", "10002", "1", "forged", 5)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            apply_edit(&db, "10001", "1", "10001", "2", "forged", 5)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let first = apply_edit(&db, "10001", "1", "10001", "1", "v1", 10)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.revision, Some(1));
+        let second = apply_edit(&db, "10001", "1", "10001", "1", "v2", 20)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.edit_count, 2);
+
+        // 过期的编辑事件不覆盖新内容
+        let stale = apply_edit(&db, "10001", "1", "10001", "1", "v1", 15)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stale.revision, None);
+        assert_eq!(stale.message.body.as_deref(), Some("v2"));
+
+        assert!(!revert_edit(&db, "10001", "1", 1).await.unwrap());
+        assert!(revert_edit(&db, "10001", "1", 2).await.unwrap());
+        let message = im_message::Entity::find_by_id(("1".to_string(), "10001".to_string()))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.body.as_deref(), Some("v1"));
+        assert_eq!(message.edited_at, Some(10));
+
+        im_message::Entity::delete_by_id(("1".to_string(), "10001".to_string()))
+            .exec(&db)
+            .await
+            .unwrap();
+        assert!(list_revisions(&db, "10001", "1").await.unwrap().is_empty());
+    }
+}
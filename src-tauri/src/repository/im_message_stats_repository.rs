@@ -0,0 +1,318 @@
+use crate::error::CommonError;
+use sea_orm::sea_query::Value;
+use sea_orm::{ConnectionTrait, Statement};
+use serde::Serialize;
+
+/// 系统消息不计入发言排行和活跃时段
+const SYSTEM_MESSAGE_TYPE: u8 = 8;
+
+/// 统计范围：room_id 为空时统计所有房间，时间为毫秒时间戳且包含边界
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct StatsScope {
+    pub room_id: Option<String>,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageTypeStat {
+    pub message_type: Option<u8>,
+    pub count: u64,
+    /// 消息体中 size 字段之和（字节），仅图片、文件、视频等消息有值
+    pub total_size: u64,
+    pub first_send_time: Option<i64>,
+    pub last_send_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SenderStat {
+    pub uid: String,
+    pub nickname: Option<String>,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityCell {
+    /// 0 为周日
+    pub weekday: u8,
+    pub hour: u8,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomStat {
+    pub room_id: String,
+    pub count: u64,
+}
+
+/// 生成统计范围的 WHERE 条件，m 为 im_message 的别名
+fn scope_clause(login_uid: &str, scope: &StatsScope) -> (String, Vec<Value>) {
+    let mut clause = String::from("m.login_uid = ?");
+    let mut values = vec![Value::from(login_uid.to_string())];
+    if let Some(room_id) = &scope.room_id {
+        clause.push_str(" AND m.room_id = ?");
+        values.push(Value::from(room_id.clone()));
+    }
+    if let Some(start_time) = scope.start_time {
+        clause.push_str(" AND m.send_time >= ?");
+        values.push(Value::from(start_time));
+    }
+    if let Some(end_time) = scope.end_time {
+        clause.push_str(" AND m.send_time <= ?");
+        values.push(Value::from(end_time));
+    }
+    (clause, values)
+}
+
+/// 消息数据的版本号，由触发器在消息写入时递增；版本号不变时统计结果不变
+pub async fn data_version<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: Option<&str>,
+) -> Result<i64, CommonError> {
+    let mut sql = String::from(
+        "SELECT COALESCE(SUM(version), 0) AS version FROM im_message_stats_version WHERE login_uid = ?",
+    );
+    let mut values = vec![Value::from(login_uid.to_string())];
+    if let Some(room_id) = room_id {
+        sql.push_str(" AND room_id = ?");
+        values.push(Value::from(room_id.to_string()));
+    }
+    let row = db
+        .query_one(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            values,
+        ))
+        .await?;
+    match row {
+        Some(row) => Ok(row.try_get("", "version")?),
+        None => Ok(0),
+    }
+}
+
+/// 按消息类型统计条数、文件大小和时间范围
+pub async fn count_by_type<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    scope: &StatsScope,
+) -> Result<Vec<MessageTypeStat>, CommonError> {
+    let (clause, values) = scope_clause(login_uid, scope);
+    let sql = format!(
+        r#"
+        SELECT m.message_type AS message_type, COUNT(*) AS message_count,
+            SUM(CASE WHEN json_valid(m.body)
+                THEN COALESCE(CAST(json_extract(m.body, '$.size') AS INTEGER), 0) ELSE 0 END) AS total_size,
+            MIN(m.send_time) AS first_send_time, MAX(m.send_time) AS last_send_time
+        FROM im_message m
+        WHERE {clause}
+        GROUP BY m.message_type
+        ORDER BY message_count DESC
+        "#
+    );
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            values,
+        ))
+        .await?;
+    let mut stats = Vec::with_capacity(rows.len());
+    for row in rows {
+        stats.push(MessageTypeStat {
+            message_type: row
+                .try_get::<Option<i32>>("", "message_type")?
+                .map(|t| t as u8),
+            count: row.try_get::<i64>("", "message_count")? as u64,
+            total_size: row
+                .try_get::<Option<i64>>("", "total_size")?
+                .unwrap_or(0)
+                .max(0) as u64,
+            first_send_time: row.try_get("", "first_send_time")?,
+            last_send_time: row.try_get("", "last_send_time")?,
+        });
+    }
+    Ok(stats)
+}
+
+/// 发言最多的成员
+pub async fn top_senders<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    scope: &StatsScope,
+    limit: u32,
+) -> Result<Vec<SenderStat>, CommonError> {
+    let (clause, mut values) = scope_clause(login_uid, scope);
+    let sql = format!(
+        r#"
+        SELECT m.uid AS uid, MAX(m.nickname) AS nickname, COUNT(*) AS message_count
+        FROM im_message m
+        WHERE {clause} AND COALESCE(m.message_type, 0) <> ?
+        GROUP BY m.uid
+        ORDER BY message_count DESC, m.uid
+        LIMIT ?
+        "#
+    );
+    values.push(Value::from(SYSTEM_MESSAGE_TYPE as i32));
+    values.push(Value::from(limit));
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            values,
+        ))
+        .await?;
+    let mut senders = Vec::with_capacity(rows.len());
+    for row in rows {
+        senders.push(SenderStat {
+            uid: row.try_get("", "uid")?,
+            nickname: row.try_get("", "nickname")?,
+            count: row.try_get::<i64>("", "message_count")? as u64,
+        });
+    }
+    Ok(senders)
+}
+
+/// 按本地时间的星期和小时统计消息数，没有消息的时段不返回
+pub async fn activity_heatmap<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    scope: &StatsScope,
+) -> Result<Vec<ActivityCell>, CommonError> {
+    let (clause, mut values) = scope_clause(login_uid, scope);
+    let sql = format!(
+        r#"
+        SELECT CAST(strftime('%w', m.send_time / 1000, 'unixepoch', 'localtime') AS INTEGER) AS weekday,
+            CAST(strftime('%H', m.send_time / 1000, 'unixepoch', 'localtime') AS INTEGER) AS hour,
+            COUNT(*) AS message_count
+        FROM im_message m
+        WHERE {clause} AND m.send_time IS NOT NULL AND COALESCE(m.message_type, 0) <> ?
+        GROUP BY weekday, hour
+        ORDER BY weekday, hour
+        "#
+    );
+    values.push(Value::from(SYSTEM_MESSAGE_TYPE as i32));
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            values,
+        ))
+        .await?;
+    let mut cells = Vec::with_capacity(rows.len());
+    for row in rows {
+        cells.push(ActivityCell {
+            weekday: row.try_get::<i32>("", "weekday")? as u8,
+            hour: row.try_get::<i32>("", "hour")? as u8,
+            count: row.try_get::<i64>("", "message_count")? as u64,
+        });
+    }
+    Ok(cells)
+}
+
+/// 消息数最多的房间，scope 中的 room_id 不参与过滤
+pub async fn top_rooms<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    scope: &StatsScope,
+    limit: u32,
+) -> Result<Vec<RoomStat>, CommonError> {
+    let scope = StatsScope {
+        room_id: None,
+        ..scope.clone()
+    };
+    let (clause, mut values) = scope_clause(login_uid, &scope);
+    let sql = format!(
+        r#"
+        SELECT m.room_id AS room_id, COUNT(*) AS message_count
+        FROM im_message m
+        WHERE {clause}
+        GROUP BY m.room_id
+        ORDER BY message_count DESC, m.room_id
+        LIMIT ?
+        "#
+    );
+    values.push(Value::from(limit));
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            values,
+        ))
+        .await?;
+    let mut rooms = Vec::with_capacity(rows.len());
+    for row in rows {
+        rooms.push(RoomStat {
+            room_id: row.try_get("", "room_id")?,
+            count: row.try_get::<i64>("", "message_count")? as u64,
+        });
+    }
+    Ok(rooms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+    use entity::im_message;
+    use sea_orm::{EntityTrait, Set};
+
+    async fn insert(db: &sea_orm::DatabaseConnection, id: &str, uid: &str, message_type: u8) {
+        im_message::Entity::insert(im_message::ActiveModel {
+            id: Set(id.to_string()),
+            uid: Set(uid.to_string()),
+            room_id: Set("1".to_string()),
+            login_uid: Set("10001".to_string()),
+            message_type: Set(Some(message_type)),
+            body: Set(Some(r#"{"size":100}"#.to_string())),
+            send_time: Set(Some(0)),
+            send_status: Set("success".to_string()),
+            ..Default::default()
+        })
+        .exec(db)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stats_and_version() {
+        let db = migrated_db().await;
+
+        insert(&db, "1", "10002", 1).await;
+        insert(&db, "2", "10002", 4).await;
+        insert(&db, "3", "10003", 4).await;
+        insert(&db, "4", "0", SYSTEM_MESSAGE_TYPE).await;
+        let version = data_version(&db, "10001", Some("1")).await.unwrap();
+        assert_eq!(version, 4);
+
+        let scope = StatsScope::default();
+        let by_type = count_by_type(&db, "10001", &scope).await.unwrap();
+        assert_eq!(by_type[0].message_type, Some(4));
+        assert_eq!((by_type[0].count, by_type[0].total_size), (2, 200));
+
+        let senders = top_senders(&db, "10001", &scope, 10).await.unwrap();
+        assert_eq!(senders.len(), 2);
+        assert_eq!((senders[0].uid.as_str(), senders[0].count), ("10002", 2));
+        let heatmap = activity_heatmap(&db, "10001", &scope).await.unwrap();
+        assert_eq!(heatmap.iter().map(|c| c.count).sum::<u64>(), 3);
+        assert_eq!(
+            top_rooms(&db, "10001", &scope, 10).await.unwrap()[0].count,
+            4
+        );
+
+        // 只改 time_block 不影响统计，版本号不变；删除消息后版本号递增
+        db.execute_unprepared("UPDATE im_message SET time_block = 1")
+            .await
+            .unwrap();
+        assert_eq!(data_version(&db, "10001", None).await.unwrap(), version);
+        im_message::Entity::delete_by_id(("1".to_string(), "10001".to_string()))
+            .exec(&db)
+            .await
+            .unwrap();
+        assert!(data_version(&db, "10001", None).await.unwrap() > version);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::error::CommonError;
+use crate::repository::SQLITE_MAX_VARIABLES;
+use crate::repository::im_message_repository::{self, MessageWithThumbnail};
+use entity::im_message;
+use sea_orm::sea_query::Value;
+use sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, Statement};
+use std::collections::HashMap;
+
+/// 回复链的最大深度，防止异常数据形成环
+const MAX_THREAD_DEPTH: u32 = 100;
+
+/// 批量统计消息的回复数（不含已撤回的回复），key 为 (message_id, login_uid)
+pub async fn count_replies<C: ConnectionTrait>(
+    db: &C,
+    keys: &[(String, String)],
+) -> Result<HashMap<(String, String), u32>, CommonError> {
+    let mut by_login_uid: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (message_id, login_uid) in keys {
+        by_login_uid
+            .entry(login_uid.as_str())
+            .or_default()
+            .push(message_id.as_str());
+    }
+
+    let mut counts = HashMap::new();
+    for (login_uid, message_ids) in by_login_uid {
+        for chunk in message_ids.chunks(SQLITE_MAX_VARIABLES - 1) {
+            let sql = format!(
+                "SELECT reply_to_id, COUNT(*) AS reply_count FROM im_message \
+                 WHERE login_uid = ? AND reply_to_id IN ({}) \
+                 AND (message_type IS NULL OR message_type <> 2) \
+                 GROUP BY reply_to_id",
+                vec!["?"; chunk.len()].join(", ")
+            );
+            let mut values = vec![Value::from(login_uid.to_string())];
+            values.extend(chunk.iter().map(|id| Value::from(id.to_string())));
+            let rows = db
+                .query_all(Statement::from_sql_and_values(
+                    db.get_database_backend(),
+                    sql,
+                    values,
+                ))
+                .await?;
+            for row in rows {
+                let message_id: String = row.try_get("", "reply_to_id")?;
+                let count: i64 = row.try_get("", "reply_count")?;
+                counts.insert((message_id, login_uid.to_string()), count as u32);
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// 沿回复链向上查找本地存在的最早一条消息，作为线程的根
+pub async fn find_thread_root<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    message_id: &str,
+) -> Result<Option<String>, CommonError> {
+    let sql = r#"
+        WITH RECURSIVE ancestors(id, reply_to_id, depth) AS (
+            SELECT id, reply_to_id, 0 FROM im_message WHERE login_uid = ? AND id = ?
+            UNION ALL
+            SELECT m.id, m.reply_to_id, a.depth + 1
+            FROM im_message m
+            JOIN ancestors a ON m.id = a.reply_to_id
+            WHERE m.login_uid = ? AND a.depth < ?
+        )
+        SELECT id FROM ancestors ORDER BY depth DESC LIMIT 1
+    "#;
+    let row = db
+        .query_one(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            [
+                login_uid.into(),
+                message_id.into(),
+                login_uid.into(),
+                MAX_THREAD_DEPTH.into(),
+            ],
+        ))
+        .await?;
+    match row {
+        Some(row) => Ok(Some(row.try_get("", "id")?)),
+        None => Ok(None),
+    }
+}
+
+/// 查询以 root_id 为根的整个回复线程（含根消息），按发送时间升序，最多 limit 条
+pub async fn list_thread<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    root_id: &str,
+    limit: u64,
+) -> Result<Vec<MessageWithThumbnail>, CommonError> {
+    let sql = r#"
+        WITH RECURSIVE thread(id, depth) AS (
+            SELECT ?, 0
+            UNION
+            SELECT m.id, t.depth + 1
+            FROM im_message m
+            JOIN thread t ON m.reply_to_id = t.id
+            WHERE m.login_uid = ? AND t.depth < ?
+        )
+        SELECT DISTINCT id FROM thread LIMIT ?
+    "#;
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            [
+                root_id.into(),
+                login_uid.into(),
+                MAX_THREAD_DEPTH.into(),
+                (limit as i64).into(),
+            ],
+        ))
+        .await?;
+    let ids = rows
+        .iter()
+        .map(|row| row.try_get::<String>("", "id"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut messages = Vec::with_capacity(ids.len());
+    for chunk in ids.chunks(SQLITE_MAX_VARIABLES - 1) {
+        messages.extend(
+            im_message::Entity::find()
+                .filter(im_message::Column::LoginUid.eq(login_uid))
+                .filter(im_message::Column::Id.is_in(chunk.iter().cloned()))
+                .order_by_asc(im_message::Column::SendTime)
+                .all(db)
+                .await?,
+        );
+    }
+    messages.sort_by_key(|m| m.send_time.unwrap_or(0));
+    im_message_repository::enrich_models_with_thumbnails(db, messages).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+
+    fn message(id: &str, body: &str) -> MessageWithThumbnail {
+        MessageWithThumbnail::from(im_message::Model {
+            id: id.to_string(),
+            uid: "10001".to_string(),
+            nickname: None,
+            room_id: "1".to_string(),
+            send_time: id.parse().ok(),
+            message_type: Some(1),
+            body: Some(body.to_string()),
+            message_marks: None,
+            create_time: None,
+            update_time: None,
+            login_uid: "10001".to_string(),
+            send_status: "success".to_string(),
+            time_block: None,
+            edited_at: None,
+            reply_to_id: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_thread_from_reply_bodies() {
+        let db = migrated_db().await;
+
+        im_message_repository::save_all(
+            &db,
+            vec![
+                message("1", r#"{"content":"root"}"#),
+                message("2", r#"{"content":"a","reply":{"id":"1","username":"x"}}"#),
+                message("3", r#"{"content":"b","replyMsgId":2}"#),
+                message("4", r#"{"content":"c","reply":{"id":"1"}}"#),
+                message("5", r#"{"content":"other"}"#),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let root = find_thread_root(&db, "10001", "3").await.unwrap();
+        assert_eq!(root.as_deref(), Some("1"));
+
+        let thread = list_thread(&db, "10001", "1", 100).await.unwrap();
+        let ids: Vec<&str> = thread.iter().map(|m| m.message.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "2", "3", "4"]);
+        assert_eq!(thread[0].reply_count, 2);
+        assert_eq!(thread[1].reply_count, 1);
+        assert_eq!(thread[2].message.reply_to_id.as_deref(), Some("2"));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::error::CommonError;
+use chrono::Utc;
+use entity::im_outbox;
+use sea_orm::prelude::Expr;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Statement,
+};
+
+pub const STATUS_QUEUED: &str = "queued";
+pub const STATUS_SENDING: &str = "sending";
+pub const STATUS_FAILED: &str = "failed";
+
+/// 加入发件箱队尾，序号取当前用户已有的最大序号加一
+pub async fn enqueue<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    id: &str,
+    room_id: &str,
+    request: &str,
+) -> Result<(), CommonError> {
+    let now = Utc::now().timestamp_millis();
+    let sql = r#"
+        INSERT INTO im_outbox (id, login_uid, room_id, seq, request, status, attempts,
+            next_attempt_at, last_error, create_time, update_time)
+        SELECT ?, ?, ?, COALESCE(MAX(seq), 0) + 1, ?, ?, 0, ?, NULL, ?, ?
+        FROM im_outbox WHERE login_uid = ?
+    "#;
+    db.execute(Statement::from_sql_and_values(
+        db.get_database_backend(),
+        sql,
+        [
+            id.into(),
+            login_uid.into(),
+            room_id.into(),
+            request.into(),
+            STATUS_QUEUED.into(),
+            now.into(),
+            now.into(),
+            now.into(),
+            login_uid.into(),
+        ],
+    ))
+    .await?;
+    Ok(())
+}
+
+pub async fn find<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    id: &str,
+) -> Result<Option<im_outbox::Model>, CommonError> {
+    let model = im_outbox::Entity::find_by_id((id.to_string(), login_uid.to_string()))
+        .one(db)
+        .await?;
+    Ok(model)
+}
+
+/// 按入队顺序列出发件箱中的消息
+pub async fn list<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: Option<&str>,
+) -> Result<Vec<im_outbox::Model>, CommonError> {
+    let mut query = im_outbox::Entity::find()
+        .filter(im_outbox::Column::LoginUid.eq(login_uid))
+        .order_by_asc(im_outbox::Column::Seq);
+    if let Some(room_id) = room_id {
+        query = query.filter(im_outbox::Column::RoomId.eq(room_id));
+    }
+    Ok(query.all(db).await?)
+}
+
+/// 各房间队首且已到重试时间的消息。同一房间内排在前面的消息未发送完成（含退避等待中）时，
+/// 后面的消息不会被取出；发送失败的消息已移出队列，不阻塞后续消息
+pub async fn ready_heads<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    now: i64,
+) -> Result<Vec<im_outbox::Model>, CommonError> {
+    let heads = im_outbox::Entity::find()
+        .filter(im_outbox::Column::LoginUid.eq(login_uid))
+        .filter(im_outbox::Column::Status.eq(STATUS_QUEUED))
+        .filter(im_outbox::Column::NextAttemptAt.lte(now))
+        .filter(Expr::cust_with_values(
+            r#"NOT EXISTS (SELECT 1 FROM im_outbox o WHERE o.login_uid = "im_outbox"."login_uid"
+                AND o.room_id = "im_outbox"."room_id" AND o.seq < "im_outbox"."seq"
+                AND o.status IN (?, ?))"#,
+            [STATUS_QUEUED, STATUS_SENDING],
+        ))
+        .order_by_asc(im_outbox::Column::Seq)
+        .all(db)
+        .await?;
+    Ok(heads)
+}
+
+/// 最早一条排队消息的重试时间
+pub async fn next_attempt_at<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+) -> Result<Option<i64>, CommonError> {
+    let next: Option<Option<i64>> = im_outbox::Entity::find()
+        .select_only()
+        .column_as(im_outbox::Column::NextAttemptAt.min(), "next_attempt_at")
+        .filter(im_outbox::Column::LoginUid.eq(login_uid))
+        .filter(im_outbox::Column::Status.eq(STATUS_QUEUED))
+        .into_tuple()
+        .one(db)
+        .await?;
+    Ok(next.flatten())
+}
+
+/// 仅当当前状态属于 from 时更新状态，返回是否更新成功；用于避免与取消、重发并发
+pub async fn transition<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    id: &str,
+    from: &[&str],
+    to: &str,
+) -> Result<bool, CommonError> {
+    let result = im_outbox::Entity::update_many()
+        .col_expr(im_outbox::Column::Status, Expr::value(to))
+        .col_expr(
+            im_outbox::Column::UpdateTime,
+            Expr::value(Utc::now().timestamp_millis()),
+        )
+        .filter(im_outbox::Column::LoginUid.eq(login_uid))
+        .filter(im_outbox::Column::Id.eq(id))
+        .filter(im_outbox::Column::Status.is_in(from.iter().copied()))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// 发送失败后记录尝试次数：retry_at 为空时移出队列并标记为失败，否则在 retry_at 后重试
+pub async fn record_attempt<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    id: &str,
+    attempts: i32,
+    retry_at: Option<i64>,
+    error: &str,
+) -> Result<(), CommonError> {
+    let now = Utc::now().timestamp_millis();
+    let status = if retry_at.is_some() {
+        STATUS_QUEUED
+    } else {
+        STATUS_FAILED
+    };
+    im_outbox::Entity::update_many()
+        .col_expr(im_outbox::Column::Status, Expr::value(status))
+        .col_expr(im_outbox::Column::Attempts, Expr::value(attempts))
+        .col_expr(
+            im_outbox::Column::NextAttemptAt,
+            Expr::value(retry_at.unwrap_or(now)),
+        )
+        .col_expr(im_outbox::Column::LastError, Expr::value(error))
+        .col_expr(im_outbox::Column::UpdateTime, Expr::value(now))
+        .filter(im_outbox::Column::LoginUid.eq(login_uid))
+        .filter(im_outbox::Column::Id.eq(id))
+        .filter(im_outbox::Column::Status.eq(STATUS_SENDING))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// 将失败的消息重新放到队尾并清零尝试次数，返回是否成功
+pub async fn requeue_failed<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    id: &str,
+) -> Result<bool, CommonError> {
+    let now = Utc::now().timestamp_millis();
+    let sql = r#"
+        UPDATE im_outbox
+        SET status = ?, attempts = 0, next_attempt_at = ?, last_error = NULL, update_time = ?,
+            seq = (SELECT COALESCE(MAX(seq), 0) + 1 FROM im_outbox WHERE login_uid = ?)
+        WHERE login_uid = ? AND id = ? AND status = ?
+    "#;
+    let result = db
+        .execute(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            sql,
+            [
+                STATUS_QUEUED.into(),
+                now.into(),
+                now.into(),
+                login_uid.into(),
+                login_uid.into(),
+                id.into(),
+                STATUS_FAILED.into(),
+            ],
+        ))
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// 网络恢复后让所有退避中的消息立即重试
+pub async fn retry_now<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    now: i64,
+) -> Result<u64, CommonError> {
+    let result = im_outbox::Entity::update_many()
+        .col_expr(im_outbox::Column::NextAttemptAt, Expr::value(now))
+        .filter(im_outbox::Column::LoginUid.eq(login_uid))
+        .filter(im_outbox::Column::Status.eq(STATUS_QUEUED))
+        .filter(im_outbox::Column::NextAttemptAt.gt(now))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+/// 从发件箱删除，from 为空时不限状态，返回是否删除
+pub async fn remove<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    id: &str,
+    from: &[&str],
+) -> Result<bool, CommonError> {
+    let mut delete = im_outbox::Entity::delete_many()
+        .filter(im_outbox::Column::LoginUid.eq(login_uid))
+        .filter(im_outbox::Column::Id.eq(id));
+    if !from.is_empty() {
+        delete = delete.filter(im_outbox::Column::Status.is_in(from.iter().copied()));
+    }
+    Ok(delete.exec(db).await?.rows_affected > 0)
+}
+
+/// 上次运行时发送中断的消息重新排队。无法确认服务端是否已收到，重发可能产生重复消息
+pub async fn requeue_interrupted<C: ConnectionTrait>(db: &C) -> Result<u64, CommonError> {
+    let result = im_outbox::Entity::update_many()
+        .col_expr(im_outbox::Column::Status, Expr::value(STATUS_QUEUED))
+        .filter(im_outbox::Column::Status.eq(STATUS_SENDING))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+
+    fn ids(models: &[im_outbox::Model]) -> Vec<&str> {
+        models.iter().map(|m| m.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_ordered_heads_and_retry() {
+        let db = migrated_db().await;
+
+        enqueue(&db, "10001", "a1", "a", "{}").await.unwrap();
+        enqueue(&db, "10001", "b1", "b", "{}").await.unwrap();
+        enqueue(&db, "10001", "a2", "a", "{}").await.unwrap();
+        let now = Utc::now().timestamp_millis();
+
+        // 每个房间只取队首
+        let heads = ready_heads(&db, "10001", now).await.unwrap();
+        assert_eq!(ids(&heads), vec!["a1", "b1"]);
+
+        // 队首退避等待时，同房间后续消息继续等待
+        assert!(
+            transition(&db, "10001", "a1", &[STATUS_QUEUED], STATUS_SENDING)
+                .await
+                .unwrap()
+        );
+        record_attempt(&db, "10001", "a1", 1, Some(now + 60_000), "timeout")
+            .await
+            .unwrap();
+        assert_eq!(
+            ids(&ready_heads(&db, "10001", now).await.unwrap()),
+            vec!["b1"]
+        );
+        assert_eq!(retry_now(&db, "10001", now).await.unwrap(), 1);
+
+        // 失败后移出队列，不再阻塞后续消息；重发时排到队尾
+        transition(&db, "10001", "a1", &[STATUS_QUEUED], STATUS_SENDING)
+            .await
+            .unwrap();
+        record_attempt(&db, "10001", "a1", 6, None, "timeout")
+            .await
+            .unwrap();
+        assert_eq!(
+            ids(&ready_heads(&db, "10001", now).await.unwrap()),
+            vec!["b1", "a2"]
+        );
+        assert!(requeue_failed(&db, "10001", "a1").await.unwrap());
+        assert_eq!(
+            ids(&list(&db, "10001", Some("a")).await.unwrap()),
+            vec!["a2", "a1"]
+        );
+
+        transition(&db, "10001", "b1", &[STATUS_QUEUED], STATUS_SENDING)
+            .await
+            .unwrap();
+        assert_eq!(requeue_interrupted(&db).await.unwrap(), 1);
+        assert!(remove(&db, "10001", "b1", &[]).await.unwrap());
+        assert!(!remove(&db, "10001", "a2", &[STATUS_FAILED]).await.unwrap());
+    }
+}
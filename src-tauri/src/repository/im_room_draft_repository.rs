@@ -0,0 +1,98 @@
+use crate::error::CommonError;
+use entity::im_room_draft;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, IntoActiveModel, QueryFilter};
+
+/// 保存草稿，已存在时整体覆盖
+pub async fn save_draft<C: ConnectionTrait>(
+    db: &C,
+    draft: im_room_draft::Model,
+) -> Result<(), CommonError> {
+    im_room_draft::Entity::insert(draft.into_active_model())
+        .on_conflict(
+            OnConflict::columns([
+                im_room_draft::Column::RoomId,
+                im_room_draft::Column::LoginUid,
+            ])
+            .update_columns([
+                im_room_draft::Column::Content,
+                im_room_draft::Column::ReplyToId,
+                im_room_draft::Column::Mentions,
+                im_room_draft::Column::Attachments,
+                im_room_draft::Column::UpdateTime,
+            ])
+            .to_owned(),
+        )
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_draft<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+) -> Result<Option<im_room_draft::Model>, CommonError> {
+    let draft = im_room_draft::Entity::find_by_id((room_id.to_string(), login_uid.to_string()))
+        .one(db)
+        .await?;
+    Ok(draft)
+}
+
+pub async fn delete_draft<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+) -> Result<(), CommonError> {
+    im_room_draft::Entity::delete_by_id((room_id.to_string(), login_uid.to_string()))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// 列出当前用户的所有草稿
+pub async fn list_drafts<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+) -> Result<Vec<im_room_draft::Model>, CommonError> {
+    let drafts = im_room_draft::Entity::find()
+        .filter(im_room_draft::Column::LoginUid.eq(login_uid))
+        .all(db)
+        .await?;
+    Ok(drafts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+
+    fn draft(room_id: &str, content: &str, update_time: i64) -> im_room_draft::Model {
+        im_room_draft::Model {
+            room_id: room_id.to_string(),
+            login_uid: "10001".to_string(),
+            content: Some(content.to_string()),
+            reply_to_id: None,
+            mentions: None,
+            attachments: None,
+            update_time,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_draft_overwrite_and_delete() {
+        let db = migrated_db().await;
+
+        save_draft(&db, draft("1", "hello", 1)).await.unwrap();
+        let mut second = draft("1", "hello world", 2);
+        second.reply_to_id = Some("100".to_string());
+        save_draft(&db, second.clone()).await.unwrap();
+        save_draft(&db, draft("2", "other", 3)).await.unwrap();
+
+        assert_eq!(get_draft(&db, "10001", "1").await.unwrap(), Some(second));
+        assert!(get_draft(&db, "10002", "1").await.unwrap().is_none());
+
+        delete_draft(&db, "10001", "2").await.unwrap();
+        assert_eq!(list_drafts(&db, "10001").await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::error::CommonError;
+use chrono::Utc;
+use entity::{im_message, im_room_read_cursor};
+use sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, Statement};
+use serde::Serialize;
+
+// 游标只允许前移；前移时清除手动标记的未读
+const UPSERT_CURSOR: &str = r#"
+    INSERT INTO im_room_read_cursor (room_id, login_uid, last_read_msg_id, last_read_time, marked_unread, update_time)
+    VALUES (?, ?, ?, ?, 0, ?)
+    ON CONFLICT(room_id, login_uid) DO UPDATE SET
+        last_read_msg_id = excluded.last_read_msg_id,
+        last_read_time = excluded.last_read_time,
+        marked_unread = 0,
+        update_time = excluded.update_time
+    WHERE excluded.last_read_time >= im_room_read_cursor.last_read_time
+"#;
+
+// 服务端返回未读数为 0 的会话说明已在其他设备读过，将游标前移到会话活跃时间之前的最新消息，
+// 保留本地手动标记的未读
+const RECONCILE_WITH_CONTACTS: &str = r#"
+    INSERT INTO im_room_read_cursor (room_id, login_uid, last_read_msg_id, last_read_time, marked_unread, update_time)
+    SELECT c.room_id, c.login_uid, m.id, m.send_time, 0, ?
+    FROM im_contact c
+    JOIN im_message m ON m.login_uid = c.login_uid AND m.id = (
+        SELECT id FROM im_message
+        WHERE room_id = c.room_id AND login_uid = c.login_uid
+            AND send_time <= COALESCE(c.active_time, 9223372036854775807)
+        ORDER BY send_time DESC
+        LIMIT 1
+    )
+    WHERE c.login_uid = ? AND COALESCE(c.unread_count, 0) = 0
+    ON CONFLICT(room_id, login_uid) DO UPDATE SET
+        last_read_msg_id = excluded.last_read_msg_id,
+        last_read_time = excluded.last_read_time,
+        update_time = excluded.update_time
+    WHERE excluded.last_read_time > im_room_read_cursor.last_read_time
+"#;
+
+// 有游标的会话按本地消息计算未读（不含自己发送和已撤回的消息），
+// 没有游标的会话沿用服务端返回的未读数
+const UNREAD_COUNTS: &str = r#"
+    SELECT c.room_id,
+        c.unread_count AS server_unread,
+        (COALESCE(c.mute_notification, 0) <> 0 OR COALESCE(c.shield, 0) <> 0) AS muted,
+        r.last_read_msg_id,
+        r.last_read_time,
+        COALESCE(r.marked_unread, 0) AS marked_unread,
+        CASE WHEN r.room_id IS NULL THEN NULL ELSE (
+            SELECT COUNT(*) FROM im_message m
+            WHERE m.room_id = c.room_id AND m.login_uid = c.login_uid
+                AND m.uid <> c.login_uid
+                AND (m.message_type IS NULL OR m.message_type <> 2)
+                AND m.send_time > r.last_read_time
+        ) END AS local_unread
+    FROM im_contact c
+    LEFT JOIN im_room_read_cursor r ON r.room_id = c.room_id AND r.login_uid = c.login_uid
+    WHERE c.login_uid = ?
+"#;
+
+/// 会话的未读状态
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomUnreadCount {
+    pub room_id: String,
+    pub unread_count: u32,
+    /// 免打扰、已退群或屏蔽的会话，不计入总未读数
+    pub muted: bool,
+    pub marked_unread: bool,
+    pub last_read_msg_id: Option<String>,
+    pub last_read_time: Option<i64>,
+}
+
+/// 将房间的已读游标前移到指定消息，早于当前游标时不做修改
+pub async fn advance<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+    last_read_msg_id: Option<&str>,
+    last_read_time: i64,
+) -> Result<(), CommonError> {
+    db.execute(Statement::from_sql_and_values(
+        db.get_database_backend(),
+        UPSERT_CURSOR,
+        [
+            room_id.into(),
+            login_uid.into(),
+            last_read_msg_id.map(str::to_string).into(),
+            last_read_time.into(),
+            Utc::now().timestamp_millis().into(),
+        ],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// 将房间的已读游标前移到本地最新一条消息，房间没有消息时不做修改
+pub async fn advance_to_latest<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+) -> Result<(), CommonError> {
+    let latest = im_message::Entity::find()
+        .filter(im_message::Column::LoginUid.eq(login_uid))
+        .filter(im_message::Column::RoomId.eq(room_id))
+        .filter(im_message::Column::SendTime.is_not_null())
+        .order_by_desc(im_message::Column::SendTime)
+        .one(db)
+        .await?;
+    if let Some(message) = latest {
+        advance(
+            db,
+            login_uid,
+            room_id,
+            Some(&message.id),
+            message.send_time.unwrap_or(0),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// 将房间标记为未读；没有游标时以本地最新消息建立游标
+pub async fn mark_unread<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: &str,
+) -> Result<(), CommonError> {
+    let exists =
+        im_room_read_cursor::Entity::find_by_id((room_id.to_string(), login_uid.to_string()))
+            .one(db)
+            .await?
+            .is_some();
+    if !exists {
+        advance_to_latest(db, login_uid, room_id).await?;
+    }
+    db.execute(Statement::from_sql_and_values(
+        db.get_database_backend(),
+        r#"
+            INSERT INTO im_room_read_cursor (room_id, login_uid, last_read_msg_id, last_read_time, marked_unread, update_time)
+            VALUES (?, ?, NULL, 0, 1, ?)
+            ON CONFLICT(room_id, login_uid) DO UPDATE SET
+                marked_unread = 1,
+                update_time = excluded.update_time
+        "#,
+        [
+            room_id.into(),
+            login_uid.into(),
+            Utc::now().timestamp_millis().into(),
+        ],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// 根据服务端会话列表前移已读游标，返回更新的房间数
+pub async fn reconcile_with_contacts<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+) -> Result<u64, CommonError> {
+    let result = db
+        .execute(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            RECONCILE_WITH_CONTACTS,
+            [Utc::now().timestamp_millis().into(), login_uid.into()],
+        ))
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// 计算当前用户所有会话的未读数
+pub async fn list_unread_counts<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+) -> Result<Vec<RoomUnreadCount>, CommonError> {
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            UNREAD_COUNTS,
+            [login_uid.into()],
+        ))
+        .await?;
+
+    let mut counts = Vec::with_capacity(rows.len());
+    for row in rows {
+        let server_unread: Option<i64> = row.try_get("", "server_unread")?;
+        let local_unread: Option<i64> = row.try_get("", "local_unread")?;
+        let marked_unread: bool = row.try_get("", "marked_unread")?;
+        let mut unread_count = local_unread.or(server_unread).unwrap_or(0).max(0) as u32;
+        if marked_unread {
+            unread_count = unread_count.max(1);
+        }
+        counts.push(RoomUnreadCount {
+            room_id: row.try_get("", "room_id")?,
+            unread_count,
+            muted: row.try_get("", "muted")?,
+            marked_unread,
+            last_read_msg_id: row.try_get("", "last_read_msg_id")?,
+            last_read_time: row.try_get("", "last_read_time")?,
+        });
+    }
+    Ok(counts)
+}
+
+/// 计算总未读数（不含免打扰会话），用于托盘角标
+pub async fn total_unread<C: ConnectionTrait>(db: &C, login_uid: &str) -> Result<u32, CommonError> {
+    let counts = list_unread_counts(db, login_uid).await?;
+    Ok(counts
+        .iter()
+        .filter(|c| !c.muted)
+        .map(|c| c.unread_count)
+        .sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+    use entity::im_contact;
+    use sea_orm::Set;
+
+    async fn insert_message(db: &impl ConnectionTrait, id: &str, room_id: &str, uid: &str) {
+        im_message::Entity::insert(im_message::ActiveModel {
+            id: Set(id.to_string()),
+            uid: Set(uid.to_string()),
+            room_id: Set(room_id.to_string()),
+            send_time: Set(id.parse().ok()),
+            message_type: Set(Some(1)),
+            login_uid: Set("10001".to_string()),
+            send_status: Set("success".to_string()),
+            ..Default::default()
+        })
+        .exec(db)
+        .await
+        .unwrap();
+    }
+
+    async fn insert_contact(db: &impl ConnectionTrait, room_id: &str, unread: u32, mute: u32) {
+        im_contact::Entity::insert(im_contact::ActiveModel {
+            id: Set(room_id.to_string()),
+            detail_id: Set(room_id.to_string()),
+            room_id: Set(room_id.to_string()),
+            mute_notification: Set(Some(mute)),
+            unread_count: Set(Some(unread)),
+            login_uid: Set("10001".to_string()),
+            ..Default::default()
+        })
+        .exec(db)
+        .await
+        .unwrap();
+    }
+
+    fn unread_of(counts: &[RoomUnreadCount], room_id: &str) -> u32 {
+        counts
+            .iter()
+            .find(|c| c.room_id == room_id)
+            .map(|c| c.unread_count)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cursor_unread_counts() {
+        let db = migrated_db().await;
+
+        insert_contact(&db, "1", 5, 0).await;
+        insert_contact(&db, "2", 0, 0).await;
+        insert_contact(&db, "3", 4, 1).await;
+        for (id, room_id, uid) in [
+            ("1", "1", "10002"),
+            ("2", "1", "10002"),
+            ("3", "1", "10001"),
+            ("4", "2", "10002"),
+        ] {
+            insert_message(&db, id, room_id, uid).await;
+        }
+
+        // 没有游标时使用服务端未读数；服务端为 0 的会话建立游标
+        assert_eq!(reconcile_with_contacts(&db, "10001").await.unwrap(), 1);
+        let counts = list_unread_counts(&db, "10001").await.unwrap();
+        assert_eq!(unread_of(&counts, "1"), 5);
+        assert_eq!(unread_of(&counts, "2"), 0);
+        assert_eq!(total_unread(&db, "10001").await.unwrap(), 5);
+
+        // 自己的消息不计入未读，游标不会后退
+        advance(&db, "10001", "1", Some("1"), 1).await.unwrap();
+        advance(&db, "10001", "1", None, 0).await.unwrap();
+        let counts = list_unread_counts(&db, "10001").await.unwrap();
+        assert_eq!(unread_of(&counts, "1"), 1);
+
+        insert_message(&db, "5", "2", "10002").await;
+        mark_unread(&db, "10001", "1").await.unwrap();
+        advance_to_latest(&db, "10001", "1").await.unwrap();
+        mark_unread(&db, "10001", "3").await.unwrap();
+        let counts = list_unread_counts(&db, "10001").await.unwrap();
+        assert_eq!(unread_of(&counts, "1"), 0);
+        assert_eq!(unread_of(&counts, "2"), 1);
+        assert_eq!(unread_of(&counts, "3"), 1);
+        assert_eq!(total_unread(&db, "10001").await.unwrap(), 1);
+    }
+}
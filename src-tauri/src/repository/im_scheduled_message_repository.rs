@@ -0,0 +1,254 @@
+use crate::error::CommonError;
+use chrono::Utc;
+use entity::im_scheduled_message;
+use sea_orm::prelude::Expr;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+    QuerySelect, Statement,
+};
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_SENDING: &str = "sending";
+pub const STATUS_SENT: &str = "sent";
+pub const STATUS_FAILED: &str = "failed";
+pub const STATUS_MISSED: &str = "missed";
+pub const STATUS_CANCELLED: &str = "cancelled";
+
+/// 可以修改后重新排期的状态
+pub const EDITABLE_STATUSES: [&str; 3] = [STATUS_PENDING, STATUS_FAILED, STATUS_MISSED];
+
+pub async fn insert<C: ConnectionTrait>(
+    db: &C,
+    model: im_scheduled_message::Model,
+) -> Result<(), CommonError> {
+    im_scheduled_message::Entity::insert(model.into_active_model())
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+pub async fn find<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    id: &str,
+) -> Result<Option<im_scheduled_message::Model>, CommonError> {
+    let model = im_scheduled_message::Entity::find_by_id((id.to_string(), login_uid.to_string()))
+        .one(db)
+        .await?;
+    Ok(model)
+}
+
+/// 按发送时间升序列出定时消息；include_finished 为 false 时只返回待发送和发送失败/错过的
+pub async fn list<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    room_id: Option<&str>,
+    include_finished: bool,
+) -> Result<Vec<im_scheduled_message::Model>, CommonError> {
+    let mut query = im_scheduled_message::Entity::find()
+        .filter(im_scheduled_message::Column::LoginUid.eq(login_uid))
+        .order_by_asc(im_scheduled_message::Column::SendAt);
+    if let Some(room_id) = room_id {
+        query = query.filter(im_scheduled_message::Column::RoomId.eq(room_id));
+    }
+    if !include_finished {
+        query = query.filter(
+            im_scheduled_message::Column::Status
+                .is_in(EDITABLE_STATUSES.into_iter().chain([STATUS_SENDING])),
+        );
+    }
+    Ok(query.all(db).await?)
+}
+
+/// 最早一条待发送消息的发送时间
+pub async fn next_send_at<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+) -> Result<Option<i64>, CommonError> {
+    let send_at: Option<Option<i64>> = im_scheduled_message::Entity::find()
+        .select_only()
+        .column_as(im_scheduled_message::Column::SendAt.min(), "next_send_at")
+        .filter(im_scheduled_message::Column::LoginUid.eq(login_uid))
+        .filter(im_scheduled_message::Column::Status.eq(STATUS_PENDING))
+        .into_tuple()
+        .one(db)
+        .await?;
+    Ok(send_at.flatten())
+}
+
+/// 已到发送时间的待发送消息
+pub async fn list_due<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    now: i64,
+) -> Result<Vec<im_scheduled_message::Model>, CommonError> {
+    let due = im_scheduled_message::Entity::find()
+        .filter(im_scheduled_message::Column::LoginUid.eq(login_uid))
+        .filter(im_scheduled_message::Column::Status.eq(STATUS_PENDING))
+        .filter(im_scheduled_message::Column::SendAt.lte(now))
+        .order_by_asc(im_scheduled_message::Column::SendAt)
+        .all(db)
+        .await?;
+    Ok(due)
+}
+
+/// 仅当当前状态属于 from 时更新状态，返回是否更新成功；用于避免与编辑、取消并发时重复发送
+pub async fn transition<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    id: &str,
+    from: &[&str],
+    to: &str,
+    sent_msg_id: Option<String>,
+    error: Option<String>,
+) -> Result<bool, CommonError> {
+    let result = im_scheduled_message::Entity::update_many()
+        .col_expr(im_scheduled_message::Column::Status, Expr::value(to))
+        .col_expr(
+            im_scheduled_message::Column::SentMsgId,
+            Expr::value(sent_msg_id),
+        )
+        .col_expr(im_scheduled_message::Column::Error, Expr::value(error))
+        .col_expr(
+            im_scheduled_message::Column::UpdateTime,
+            Expr::value(Utc::now().timestamp_millis()),
+        )
+        .filter(im_scheduled_message::Column::LoginUid.eq(login_uid))
+        .filter(im_scheduled_message::Column::Id.eq(id))
+        .filter(im_scheduled_message::Column::Status.is_in(from.iter().copied()))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// 修改内容或发送时间并重新进入待发送状态，返回是否修改成功
+pub async fn reschedule<C: ConnectionTrait>(
+    db: &C,
+    login_uid: &str,
+    id: &str,
+    request: &str,
+    room_id: &str,
+    send_at: i64,
+) -> Result<bool, CommonError> {
+    let result = im_scheduled_message::Entity::update_many()
+        .col_expr(im_scheduled_message::Column::Request, Expr::value(request))
+        .col_expr(im_scheduled_message::Column::RoomId, Expr::value(room_id))
+        .col_expr(im_scheduled_message::Column::SendAt, Expr::value(send_at))
+        .col_expr(
+            im_scheduled_message::Column::Status,
+            Expr::value(STATUS_PENDING),
+        )
+        .col_expr(
+            im_scheduled_message::Column::Error,
+            Expr::value(None::<String>),
+        )
+        .col_expr(
+            im_scheduled_message::Column::UpdateTime,
+            Expr::value(Utc::now().timestamp_millis()),
+        )
+        .filter(im_scheduled_message::Column::LoginUid.eq(login_uid))
+        .filter(im_scheduled_message::Column::Id.eq(id))
+        .filter(im_scheduled_message::Column::Status.is_in(EDITABLE_STATUSES))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// 处理上次运行时停在发送中的消息：仍在发件箱中的由发件箱继续发送；已拿到服务端 ID 的
+/// 标记为已发送；其余无法确认是否已送达，标记为失败由用户决定是否重发。返回标记为失败的条数
+pub async fn resolve_interrupted<C: ConnectionTrait>(db: &C) -> Result<u64, CommonError> {
+    let now = Utc::now().timestamp_millis();
+    db.execute(Statement::from_sql_and_values(
+        db.get_database_backend(),
+        r#"
+        UPDATE im_scheduled_message
+        SET status = ?, error = NULL, update_time = ?,
+            sent_msg_id = (
+                SELECT c.server_id FROM im_message_client_id c
+                WHERE c.client_id = im_scheduled_message.id
+                    AND c.login_uid = im_scheduled_message.login_uid
+            )
+        WHERE status = ? AND EXISTS (
+            SELECT 1 FROM im_message_client_id c
+            WHERE c.client_id = im_scheduled_message.id
+                AND c.login_uid = im_scheduled_message.login_uid
+                AND c.server_id IS NOT NULL
+        )
+        "#,
+        [STATUS_SENT.into(), now.into(), STATUS_SENDING.into()],
+    ))
+    .await?;
+
+    let result = db
+        .execute(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            r#"
+            UPDATE im_scheduled_message
+            SET status = ?, error = ?, update_time = ?
+            WHERE status = ? AND NOT EXISTS (
+                SELECT 1 FROM im_outbox o
+                WHERE o.id = im_scheduled_message.id
+                    AND o.login_uid = im_scheduled_message.login_uid
+            )
+            "#,
+            [
+                STATUS_FAILED.into(),
+                "发送过程中应用退出".into(),
+                now.into(),
+                STATUS_SENDING.into(),
+            ],
+        ))
+        .await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_support::migrated_db;
+
+    fn scheduled(id: &str, send_at: i64) -> im_scheduled_message::Model {
+        im_scheduled_message::Model {
+            id: id.to_string(),
+            login_uid: "10001".to_string(),
+            room_id: "1".to_string(),
+            request: "{}".to_string(),
+            send_at,
+            status: STATUS_PENDING.to_string(),
+            sent_msg_id: None,
+            error: None,
+            create_time: 0,
+            update_time: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_schedule_claim_and_reschedule() {
+        let db = migrated_db().await;
+
+        insert(&db, scheduled("a", 100)).await.unwrap();
+        insert(&db, scheduled("b", 200)).await.unwrap();
+        assert_eq!(next_send_at(&db, "10001").await.unwrap(), Some(100));
+
+        let due = list_due(&db, "10001", 150).await.unwrap();
+        assert_eq!(due.len(), 1);
+        let pending = [STATUS_PENDING];
+        assert!(
+            transition(&db, "10001", "a", &pending, STATUS_SENDING, None, None)
+                .await
+                .unwrap()
+        );
+        // 发送中的消息不能再次领取或修改
+        assert!(
+            !transition(&db, "10001", "a", &pending, STATUS_SENDING, None, None)
+                .await
+                .unwrap()
+        );
+        assert!(!reschedule(&db, "10001", "a", "{}", "1", 300).await.unwrap());
+
+        assert_eq!(resolve_interrupted(&db).await.unwrap(), 1);
+        assert!(reschedule(&db, "10001", "a", "{}", "1", 300).await.unwrap());
+        assert_eq!(next_send_at(&db, "10001").await.unwrap(), Some(200));
+        assert_eq!(list(&db, "10001", None, false).await.unwrap().len(), 2);
+    }
+}
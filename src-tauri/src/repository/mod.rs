@@ -1,5 +1,43 @@
 pub mod im_config_repository;
 pub mod im_contact_repository;
+pub mod im_favorite_message_repository;
+pub mod im_link_preview_repository;
+pub mod im_message_client_id_repository;
+pub mod im_message_fts_repository;
+pub mod im_message_mention_repository;
+pub mod im_message_prune_repository;
+pub mod im_message_read_receipt_repository;
 pub mod im_message_repository;
+pub mod im_message_revision_repository;
+pub mod im_message_stats_repository;
+pub mod im_message_thread_repository;
+pub mod im_outbox_repository;
+pub mod im_room_draft_repository;
 pub mod im_room_member_repository;
+pub mod im_room_read_cursor_repository;
+pub mod im_scheduled_message_repository;
 pub mod im_user_repository;
+
+/// SQLite 3.32 起单条语句允许的最大变量数，批量 IN 查询和批量插入按此分块
+pub(crate) const SQLITE_MAX_VARIABLES: usize = 32766;
+
+/// 测试共用的内存数据库
+#[cfg(test)]
+pub(crate) mod test_support {
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+
+    /// 未执行迁移的内存库；内存库只能使用单连接，否则每个连接各自拥有一份独立的数据库
+    pub(crate) async fn memory_db() -> DatabaseConnection {
+        let mut opt = ConnectOptions::new("sqlite::memory:");
+        opt.max_connections(1).sqlx_logging(false);
+        Database::connect(opt).await.unwrap()
+    }
+
+    /// 已执行全部迁移的内存库
+    pub(crate) async fn migrated_db() -> DatabaseConnection {
+        let db = memory_db().await;
+        Migrator::up(&db, None).await.unwrap();
+        db
+    }
+}
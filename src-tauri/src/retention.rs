@@ -0,0 +1,349 @@
+//! 本地消息保留策略与后台清理
+//!
+//! 策略按用户保存在 im_config 中，房间级策略覆盖全局策略。已清理的范围记录在
+//! im_message_prune_record 中，之后同步时不会再导入这部分消息；放宽策略也不会恢复已清理的历史。
+
+use crate::UserInfo;
+use crate::common::app_paths;
+use crate::error::CommonError;
+use crate::repository::{
+    im_config_repository, im_message_fts_repository, im_message_prune_repository,
+};
+use chrono::Utc;
+use sea_orm::{DatabaseConnection, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::AppHandle;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// im_config 中保存策略的配置键
+pub const RETENTION_CONFIG_KEY: &str = "messageRetention";
+
+/// 每批删除的消息数，批次之间释放写锁，避免长时间阻塞消息写入
+const PRUNE_BATCH_SIZE: u32 = 500;
+/// 启动后首次清理的延迟
+const PRUNE_INITIAL_DELAY: Duration = Duration::from_secs(120);
+/// 后台清理间隔
+const PRUNE_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+/// 允许随消息一起删除的缩略图扩展名
+const THUMBNAIL_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "webp", "gif"];
+
+/// 单个保留策略，字段为空表示不限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionPolicy {
+    /// 仅保留最近 N 天的消息
+    pub max_age_days: Option<u32>,
+    /// 每个房间最多保留的消息条数
+    pub max_messages_per_room: Option<u32>,
+}
+
+impl RetentionPolicy {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_age_days.is_none() && self.max_messages_per_room.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetentionSettings {
+    pub global: RetentionPolicy,
+    /// 房间级策略，key 为房间 ID
+    pub rooms: HashMap<String, RetentionPolicy>,
+    /// 数据库大小上限（MB），超出时跨房间删除最旧的消息
+    pub max_db_size_mb: Option<u64>,
+    /// 清理后执行 VACUUM 回收磁盘空间
+    pub vacuum_after_prune: bool,
+}
+
+impl RetentionSettings {
+    /// 房间生效的策略
+    pub fn policy_for(&self, room_id: &str) -> RetentionPolicy {
+        self.rooms.get(room_id).copied().unwrap_or(self.global)
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.global.is_unlimited()
+            && self.rooms.values().all(RetentionPolicy::is_unlimited)
+            && self.max_db_size_mb.is_none()
+    }
+}
+
+/// 一次清理的结果
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneReport {
+    pub deleted_messages: u64,
+    pub rooms_pruned: usize,
+    pub thumbnails_removed: usize,
+    pub fts_orphans_removed: u64,
+    pub vacuumed: bool,
+    pub db_size_before: u64,
+    pub db_size_after: u64,
+}
+
+/// 读取用户的保留策略，未配置时返回不限制
+pub async fn load_settings(
+    db: &DatabaseConnection,
+    login_uid: &str,
+) -> Result<RetentionSettings, CommonError> {
+    let config =
+        im_config_repository::get_config_by_key(db, RETENTION_CONFIG_KEY, login_uid).await?;
+    match config.and_then(|c| c.config_value) {
+        Some(value) => serde_json::from_str(&value)
+            .map_err(|e| anyhow::anyhow!("解析消息保留策略失败: {}", e).into()),
+        None => Ok(RetentionSettings::default()),
+    }
+}
+
+pub async fn save_settings(
+    db: &DatabaseConnection,
+    login_uid: &str,
+    settings: &RetentionSettings,
+) -> Result<(), CommonError> {
+    let value = serde_json::to_string(settings)
+        .map_err(|e| anyhow::anyhow!("序列化消息保留策略失败: {}", e))?;
+    im_config_repository::save_or_update_config(db, RETENTION_CONFIG_KEY, Some(value), login_uid)
+        .await
+}
+
+/// 删除一批消息并推进清理记录，在同一事务中完成
+async fn prune_in_txn<F, Fut>(
+    db: &DatabaseConnection,
+    write_lock: &Arc<Mutex<()>>,
+    login_uid: &str,
+    delete: F,
+) -> Result<im_message_prune_repository::PrunedBatch, CommonError>
+where
+    F: FnOnce(sea_orm::DatabaseTransaction) -> Fut,
+    Fut: Future<
+        Output = Result<
+            (
+                sea_orm::DatabaseTransaction,
+                im_message_prune_repository::PrunedBatch,
+            ),
+            CommonError,
+        >,
+    >,
+{
+    let _guard = write_lock.lock().await;
+    let txn = db.begin().await?;
+    let (txn, batch) = delete(txn).await?;
+    for (room_id, pruned_until) in &batch.pruned_until_by_room {
+        im_message_prune_repository::record_prune(&txn, login_uid, room_id, *pruned_until).await?;
+    }
+    txn.commit().await?;
+    Ok(batch)
+}
+
+/// 按策略执行一次清理
+pub async fn run_prune(
+    db: &DatabaseConnection,
+    write_lock: &Arc<Mutex<()>>,
+    login_uid: &str,
+    settings: &RetentionSettings,
+    thumbnail_roots: &[PathBuf],
+) -> Result<PruneReport, CommonError> {
+    let mut report = PruneReport {
+        db_size_before: im_message_prune_repository::db_usage(db)
+            .await?
+            .file_bytes(),
+        ..Default::default()
+    };
+    let mut thumbnails = Vec::new();
+    let mut pruned_rooms = std::collections::HashSet::new();
+    let now = Utc::now().timestamp_millis();
+
+    for room_id in im_message_prune_repository::list_message_room_ids(db, login_uid).await? {
+        let policy = settings.policy_for(&room_id);
+        let age_cutoff = policy
+            .max_age_days
+            .map(|days| now - i64::from(days) * 24 * 60 * 60 * 1000);
+        let count_cutoff = match policy.max_messages_per_room {
+            Some(keep) => {
+                im_message_prune_repository::find_count_cutoff(db, login_uid, &room_id, keep)
+                    .await?
+            }
+            None => None,
+        };
+        let Some(cutoff) = age_cutoff.into_iter().chain(count_cutoff).max() else {
+            continue;
+        };
+
+        loop {
+            let batch = prune_in_txn(db, write_lock, login_uid, |txn| {
+                let room_id = room_id.clone();
+                async move {
+                    let batch = im_message_prune_repository::prune_room_batch(
+                        &txn,
+                        login_uid,
+                        &room_id,
+                        cutoff,
+                        PRUNE_BATCH_SIZE,
+                    )
+                    .await?;
+                    Ok((txn, batch))
+                }
+            })
+            .await?;
+            if batch.deleted == 0 {
+                break;
+            }
+            report.deleted_messages += batch.deleted;
+            pruned_rooms.extend(batch.pruned_until_by_room.into_keys());
+            thumbnails.extend(batch.thumbnail_paths);
+            if batch.deleted < u64::from(PRUNE_BATCH_SIZE) {
+                break;
+            }
+        }
+    }
+
+    if let Some(max_mb) = settings.max_db_size_mb {
+        let limit = max_mb.saturating_mul(1024 * 1024);
+        while im_message_prune_repository::db_usage(db)
+            .await?
+            .used_bytes()
+            > limit
+        {
+            let batch = prune_in_txn(db, write_lock, login_uid, |txn| async move {
+                let batch = im_message_prune_repository::prune_oldest_batch(
+                    &txn,
+                    login_uid,
+                    PRUNE_BATCH_SIZE,
+                )
+                .await?;
+                Ok((txn, batch))
+            })
+            .await?;
+            if batch.deleted == 0 {
+                warn!(
+                    "Database still exceeds {} MB but no prunable messages remain",
+                    max_mb
+                );
+                break;
+            }
+            report.deleted_messages += batch.deleted;
+            pruned_rooms.extend(batch.pruned_until_by_room.into_keys());
+            thumbnails.extend(batch.thumbnail_paths);
+        }
+    }
+    report.rooms_pruned = pruned_rooms.len();
+
+    if report.deleted_messages > 0 {
+        {
+            let _guard = write_lock.lock().await;
+            report.fts_orphans_removed =
+                im_message_fts_repository::remove_orphan_fts_rows(db).await?;
+        }
+        report.thumbnails_removed =
+            remove_orphan_thumbnails(db, thumbnails, thumbnail_roots).await?;
+
+        if settings.vacuum_after_prune {
+            let _guard = write_lock.lock().await;
+            im_message_prune_repository::vacuum(db).await?;
+            im_message_fts_repository::rebuild_message_fts(db).await?;
+            report.vacuumed = true;
+        }
+    }
+
+    report.db_size_after = im_message_prune_repository::db_usage(db)
+        .await?
+        .file_bytes();
+    Ok(report)
+}
+
+/// 缩略图路径可能来自服务端消息体，只删除应用缩略图目录下、且不再被引用的图片文件
+async fn remove_orphan_thumbnails(
+    db: &DatabaseConnection,
+    paths: Vec<String>,
+    roots: &[PathBuf],
+) -> Result<usize, CommonError> {
+    let mut removed = 0;
+    let mut seen = std::collections::HashSet::new();
+    for path in paths {
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        let Some(file) = managed_thumbnail_path(Path::new(&path), roots) else {
+            continue;
+        };
+        if im_message_prune_repository::is_thumbnail_referenced(db, &path).await? {
+            continue;
+        }
+        match tokio::fs::remove_file(&file).await {
+            Ok(()) => removed += 1,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove thumbnail {}: {}", file.display(), e),
+        }
+    }
+    Ok(removed)
+}
+
+fn managed_thumbnail_path(path: &Path, roots: &[PathBuf]) -> Option<PathBuf> {
+    let is_image = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            THUMBNAIL_EXTENSIONS
+                .iter()
+                .any(|allowed| ext.eq_ignore_ascii_case(allowed))
+        });
+    if !is_image {
+        return None;
+    }
+    let canonical = path.canonicalize().ok()?;
+    roots
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .any(|root| canonical.starts_with(root))
+        .then_some(canonical)
+}
+
+/// 可以清理缩略图的目录。消息体中的路径可能由其他用户指定，只清理应用自己生成缩略图的目录，
+/// 临时目录和其他应用目录下的文件一律跳过
+pub fn thumbnail_roots(app_handle: &AppHandle) -> Vec<PathBuf> {
+    app_paths::thumbnail_dir(app_handle).into_iter().collect()
+}
+
+/// 启动后台清理任务
+pub fn spawn_pruner(
+    app_handle: AppHandle,
+    db: Arc<DatabaseConnection>,
+    write_lock: Arc<Mutex<()>>,
+    user_info: Arc<Mutex<UserInfo>>,
+) {
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(PRUNE_INITIAL_DELAY).await;
+        let roots = thumbnail_roots(&app_handle);
+        loop {
+            let login_uid = user_info.lock().await.uid.clone();
+            if !login_uid.is_empty() {
+                match prune_for_user(&db, &write_lock, &login_uid, &roots).await {
+                    Ok(Some(report)) => info!("Message retention prune finished: {:?}", report),
+                    Ok(None) => {}
+                    Err(e) => warn!("Message retention prune failed: {}", e),
+                }
+            }
+            tokio::time::sleep(PRUNE_INTERVAL).await;
+        }
+    });
+}
+
+async fn prune_for_user(
+    db: &DatabaseConnection,
+    write_lock: &Arc<Mutex<()>>,
+    login_uid: &str,
+    roots: &[PathBuf],
+) -> Result<Option<PruneReport>, CommonError> {
+    let settings = load_settings(db, login_uid).await?;
+    if settings.is_unlimited() {
+        return Ok(None);
+    }
+    run_prune(db, write_lock, login_uid, &settings, roots)
+        .await
+        .map(Some)
+}
@@ -0,0 +1,306 @@
+//! 定时发送消息
+//!
+//! 定时消息以 send_msg 使用的 ChatMessageReq 保存在 im_scheduled_message 中，到期后由后台调度器
+//! 加入发件箱，与普通消息一起按房间顺序发送，发送结果由发件箱事件回写。
+//! 应用未运行期间到期的消息，按用户策略在下次启动后补发或标记为错过。
+
+use crate::AppData;
+use crate::command::message_command::{self, MessageResp};
+use crate::error::CommonError;
+use crate::outbox::{OutboxEvent, OutboxStatus};
+use crate::repository::im_config_repository;
+use crate::repository::im_scheduled_message_repository::{
+    self as repository, STATUS_CANCELLED, STATUS_FAILED, STATUS_MISSED, STATUS_PENDING,
+    STATUS_SENDING, STATUS_SENT,
+};
+use crate::vo::vo::ChatMessageReq;
+use chrono::Utc;
+use entity::im_scheduled_message;
+use once_cell::sync::Lazy;
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// im_config 中保存策略的配置键
+pub const SCHEDULED_CONFIG_KEY: &str = "scheduledMessage";
+
+/// 没有待发送消息时的检查间隔
+const SCHEDULER_IDLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 新增、修改定时消息后唤醒调度器重新计算下一次发送时间
+static SCHEDULER_WAKE: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// 应用未运行期间到期的消息的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MissedPolicy {
+    /// 下次启动后立即发送
+    #[default]
+    SendOnLaunch,
+    /// 标记为错过，由用户决定是否重新排期
+    MarkMissed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScheduledMessageSettings {
+    pub missed_policy: MissedPolicy,
+    /// 超过发送时间不到该分钟数时，无论策略如何都照常发送
+    pub grace_minutes: u32,
+}
+
+impl Default for ScheduledMessageSettings {
+    fn default() -> Self {
+        Self {
+            missed_policy: MissedPolicy::default(),
+            grace_minutes: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledMessageResp {
+    pub id: String,
+    pub room_id: String,
+    /// 与 send_msg 参数相同的 ChatMessageReq
+    pub request: serde_json::Value,
+    pub send_at: i64,
+    pub status: String,
+    pub sent_msg_id: Option<String>,
+    pub error: Option<String>,
+    pub create_time: i64,
+    pub update_time: i64,
+}
+
+impl From<im_scheduled_message::Model> for ScheduledMessageResp {
+    fn from(model: im_scheduled_message::Model) -> Self {
+        Self {
+            request: serde_json::from_str(&model.request).unwrap_or(serde_json::Value::Null),
+            id: model.id,
+            room_id: model.room_id,
+            send_at: model.send_at,
+            status: model.status,
+            sent_msg_id: model.sent_msg_id,
+            error: model.error,
+            create_time: model.create_time,
+            update_time: model.update_time,
+        }
+    }
+}
+
+/// 定时消息状态变化时推送给前端，发送后附带生成的消息
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScheduledMessageEvent {
+    scheduled: ScheduledMessageResp,
+    message: Option<MessageResp>,
+}
+
+pub async fn load_settings(
+    db: &DatabaseConnection,
+    login_uid: &str,
+) -> Result<ScheduledMessageSettings, CommonError> {
+    let config =
+        im_config_repository::get_config_by_key(db, SCHEDULED_CONFIG_KEY, login_uid).await?;
+    match config.and_then(|c| c.config_value) {
+        Some(value) => serde_json::from_str(&value)
+            .map_err(|e| anyhow::anyhow!("解析定时消息设置失败: {}", e).into()),
+        None => Ok(ScheduledMessageSettings::default()),
+    }
+}
+
+pub async fn save_settings(
+    db: &DatabaseConnection,
+    login_uid: &str,
+    settings: &ScheduledMessageSettings,
+) -> Result<(), CommonError> {
+    let value = serde_json::to_string(settings)
+        .map_err(|e| anyhow::anyhow!("序列化定时消息设置失败: {}", e))?;
+    im_config_repository::save_or_update_config(db, SCHEDULED_CONFIG_KEY, Some(value), login_uid)
+        .await
+}
+
+pub fn wake_scheduler() {
+    SCHEDULER_WAKE.notify_one();
+}
+
+/// 启动定时消息调度器
+pub fn spawn_scheduler(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppData>();
+        match repository::resolve_interrupted(state.db_conn.as_ref()).await {
+            Ok(0) => {}
+            Ok(count) => warn!("Marked {} interrupted scheduled messages as failed", count),
+            Err(e) => warn!("Failed to reset interrupted scheduled messages: {}", e),
+        }
+
+        loop {
+            let login_uid = state.user_info.lock().await.uid.clone();
+            let mut wait = SCHEDULER_IDLE_INTERVAL;
+            if !login_uid.is_empty() {
+                if let Err(e) = run_due(&app_handle, &state, &login_uid).await {
+                    warn!("Failed to send scheduled messages: {}", e);
+                }
+                match repository::next_send_at(state.db_conn.as_ref(), &login_uid).await {
+                    Ok(Some(send_at)) => {
+                        let delay = (send_at - Utc::now().timestamp_millis()).max(0) as u64;
+                        wait = wait.min(Duration::from_millis(delay));
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Failed to query next scheduled message: {}", e),
+                }
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = SCHEDULER_WAKE.notified() => {}
+            }
+        }
+    });
+}
+
+/// 发送所有已到期的定时消息
+async fn run_due(
+    app_handle: &AppHandle,
+    state: &AppData,
+    login_uid: &str,
+) -> Result<(), CommonError> {
+    let db = state.db_conn.as_ref();
+    let settings = load_settings(db, login_uid).await?;
+    let now = Utc::now().timestamp_millis();
+    let grace_ms = settings.grace_minutes as i64 * 60 * 1000;
+
+    for item in repository::list_due(db, login_uid, now).await? {
+        if settings.missed_policy == MissedPolicy::MarkMissed && now - item.send_at > grace_ms {
+            let marked = {
+                let _guard = state.write_lock.lock().await;
+                repository::transition(
+                    db,
+                    login_uid,
+                    &item.id,
+                    &[STATUS_PENDING],
+                    STATUS_MISSED,
+                    None,
+                    Some("应用未运行，已错过发送时间".to_string()),
+                )
+                .await?
+            };
+            if marked {
+                info!("Scheduled message {} missed its send time", item.id);
+                emit_update(app_handle, db, login_uid, &item.id, None).await;
+            }
+            continue;
+        }
+
+        // 先标记为发送中，避免与编辑、取消并发时重复发送
+        let claimed = {
+            let _guard = state.write_lock.lock().await;
+            repository::transition(
+                db,
+                login_uid,
+                &item.id,
+                &[STATUS_PENDING],
+                STATUS_SENDING,
+                None,
+                None,
+            )
+            .await?
+        };
+        if !claimed {
+            continue;
+        }
+
+        // 加入发件箱后保持发送中，最终结果由 on_outbox_finished 回写
+        let outcome = match serde_json::from_str::<ChatMessageReq>(&item.request) {
+            Ok(request) => message_command::enqueue_message(state, app_handle, request).await,
+            Err(e) => Err(format!("定时消息内容无效: {}", e)),
+        };
+        match outcome {
+            Ok(()) => info!("Scheduled message {} handed to outbox", item.id),
+            Err(error) => {
+                warn!("Failed to enqueue scheduled message {}: {}", item.id, error);
+                let _guard = state.write_lock.lock().await;
+                repository::transition(
+                    db,
+                    login_uid,
+                    &item.id,
+                    &[STATUS_SENDING],
+                    STATUS_FAILED,
+                    None,
+                    Some(error),
+                )
+                .await?;
+            }
+        }
+        emit_update(app_handle, db, login_uid, &item.id, None).await;
+    }
+    Ok(())
+}
+
+/// 发件箱中的消息发送完成、最终失败或被取消时回写对应的定时消息。
+/// 定时消息 ID 与消息 ID 相同，不是定时消息时更新不到任何记录
+pub fn on_outbox_finished(app_handle: &AppHandle, event: &OutboxEvent) {
+    let (to, from): (&str, &[&str]) = match event.status {
+        OutboxStatus::Sent => (STATUS_SENT, &[STATUS_SENDING, STATUS_FAILED][..]),
+        OutboxStatus::Failed => (STATUS_FAILED, &[STATUS_SENDING][..]),
+        OutboxStatus::Cancelled => (STATUS_CANCELLED, &[STATUS_SENDING, STATUS_FAILED][..]),
+        OutboxStatus::Queued | OutboxStatus::Sending => return,
+    };
+    let app_handle = app_handle.clone();
+    let event = event.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppData>();
+        let login_uid = state.user_info.lock().await.uid.clone();
+        let db = state.db_conn.as_ref();
+        let sent_msg_id = event
+            .message
+            .as_ref()
+            .filter(|_| to == STATUS_SENT)
+            .and_then(|message| message.message.id.clone());
+        let error = (to == STATUS_FAILED).then(|| {
+            event
+                .error
+                .clone()
+                .unwrap_or_else(|| "服务端发送失败".to_string())
+        });
+        let updated = {
+            let _guard = state.write_lock.lock().await;
+            repository::transition(db, &login_uid, &event.msg_id, from, to, sent_msg_id, error)
+                .await
+        };
+        match updated {
+            Ok(true) => {
+                info!(
+                    "Scheduled message {} finished with status {}",
+                    event.msg_id, to
+                );
+                emit_update(&app_handle, db, &login_uid, &event.msg_id, event.message).await;
+            }
+            Ok(false) => {}
+            Err(e) => warn!("Failed to update scheduled message {}: {}", event.msg_id, e),
+        }
+    });
+}
+
+async fn emit_update(
+    app_handle: &AppHandle,
+    db: &DatabaseConnection,
+    login_uid: &str,
+    id: &str,
+    message: Option<MessageResp>,
+) {
+    match repository::find(db, login_uid, id).await {
+        Ok(Some(model)) => {
+            let event = ScheduledMessageEvent {
+                scheduled: model.into(),
+                message,
+            };
+            let _ = app_handle.emit_to("home", "scheduled-message-updated", &event);
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Failed to load scheduled message {}: {}", id, e),
+    }
+}
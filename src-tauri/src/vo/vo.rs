@@ -19,6 +19,18 @@ pub struct ChatMessageReq {
     pub skip: Option<bool>,
     pub is_temp: Option<bool>,
     pub is_push_message: Option<bool>,
+    /// 客户端消息 ID，重发时保持不变，供服务端和本地去重；为空时使用 id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_msg_id: Option<String>,
+}
+
+/// 编辑消息请求
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EditMessageReq {
+    pub msg_id: String,
+    pub room_id: String,
+    pub body: serde_json::Value,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -1,7 +1,11 @@
 use crate::AppData;
-use crate::command::message_command::{SyncMessagesParam, sync_messages};
+use crate::command::message_command::{
+    MessageEditEvent, SyncMessagesParam, apply_message_edit_event, sync_messages,
+};
 use crate::websocket::commands::get_websocket_client_container;
 
+use super::codec::{self, DecodedFrame};
+use super::diagnostics::{DisconnectCause, get_connection_diagnostics};
 use super::types::*;
 use anyhow::Result;
 use chrono::Utc;
@@ -9,7 +13,7 @@ use futures_util::{sink::SinkExt, stream::StreamExt};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
-use tauri::{AppHandle, Emitter, Manager, State};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State, Wry};
 use tokio::sync::{Mutex, RwLock, mpsc};
 use tokio::task::JoinHandle;
 use tokio::time::{Duration, interval, sleep};
@@ -34,12 +38,11 @@ impl AckMessage {
     }
 }
 
-/// WebSocket 客户端
-#[derive(Clone)]
-pub struct WebSocketClient {
+/// WebSocket 客户端，运行时参数仅用于在测试中替换为模拟运行时
+pub struct WebSocketClient<R: Runtime = Wry> {
     config: Arc<RwLock<WebSocketConfig>>,
     state: Arc<RwLock<ConnectionState>>,
-    app_handle: AppHandle,
+    app_handle: AppHandle<R>,
 
     // 心跳相关
     last_pong_time: Arc<AtomicU64>,
@@ -50,8 +53,8 @@ pub struct WebSocketClient {
     reconnect_attempts: Arc<AtomicU32>,
     is_reconnecting: Arc<AtomicBool>,
 
-    // 消息队列
-    message_sender: Arc<RwLock<Option<mpsc::UnboundedSender<Message>>>>,
+    // 消息队列（编码在发送任务中按协商的线路格式完成）
+    message_sender: Arc<RwLock<Option<mpsc::UnboundedSender<serde_json::Value>>>>,
     pending_messages: Arc<RwLock<Vec<serde_json::Value>>>,
 
     // 连接控制
@@ -64,6 +67,11 @@ pub struct WebSocketClient {
 
     // 连接状态标记
     is_ws_connected: Arc<AtomicBool>,
+    // 当前连接在诊断记录中的会话编号
+    session_id: Arc<AtomicU64>,
+
+    // 服务端不支持子协议协商时置位，后续重连直接使用 JSON 文本
+    legacy_server: Arc<AtomicBool>,
 
     // 连接互斥锁，防止并发连接
     connection_mutex: Arc<Mutex<()>>,
@@ -75,8 +83,36 @@ pub struct WebSocketClient {
     close_sender: Arc<RwLock<Option<mpsc::UnboundedSender<()>>>>,
 }
 
-impl WebSocketClient {
-    pub fn new(app_handle: AppHandle) -> Self {
+// 手动实现 Clone，derive 会要求运行时类型本身实现 Clone
+impl<R: Runtime> Clone for WebSocketClient<R> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            state: self.state.clone(),
+            app_handle: self.app_handle.clone(),
+            last_pong_time: self.last_pong_time.clone(),
+            consecutive_failures: self.consecutive_failures.clone(),
+            heartbeat_active: self.heartbeat_active.clone(),
+            reconnect_attempts: self.reconnect_attempts.clone(),
+            is_reconnecting: self.is_reconnecting.clone(),
+            message_sender: self.message_sender.clone(),
+            pending_messages: self.pending_messages.clone(),
+            should_stop: self.should_stop.clone(),
+            is_app_in_background: self.is_app_in_background.clone(),
+            last_foreground_time: self.last_foreground_time.clone(),
+            background_heartbeat_failures: self.background_heartbeat_failures.clone(),
+            is_ws_connected: self.is_ws_connected.clone(),
+            session_id: self.session_id.clone(),
+            legacy_server: self.legacy_server.clone(),
+            connection_mutex: self.connection_mutex.clone(),
+            task_handles: self.task_handles.clone(),
+            close_sender: self.close_sender.clone(),
+        }
+    }
+}
+
+impl<R: Runtime> WebSocketClient<R> {
+    pub fn new(app_handle: AppHandle<R>) -> Self {
         Self {
             config: Arc::new(RwLock::new(WebSocketConfig::default())),
             state: Arc::new(RwLock::new(ConnectionState::Disconnected)),
@@ -95,6 +131,8 @@ impl WebSocketClient {
             )),
             background_heartbeat_failures: Arc::new(AtomicU32::new(0)),
             is_ws_connected: Arc::new(AtomicBool::new(false)),
+            session_id: Arc::new(AtomicU64::new(0)),
+            legacy_server: Arc::new(AtomicBool::new(false)),
             connection_mutex: Arc::new(Mutex::new(())),
             task_handles: Arc::new(RwLock::new(Vec::new())),
             close_sender: Arc::new(RwLock::new(None)),
@@ -129,13 +167,14 @@ impl WebSocketClient {
     /// 断开连接
     pub async fn disconnect(&self) {
         let _lock = self.connection_mutex.lock().await;
-        self.internal_disconnect().await;
+        self.internal_disconnect(DisconnectCause::Manual).await;
     }
 
-    /// 内部断开连接方法（不获取锁）
-    pub async fn internal_disconnect(&self) {
-        info!("Disconnecting WebSocket connection");
+    /// 内部断开连接方法（不获取锁），`cause` 决定诊断日志中记录的断开原因
+    pub async fn internal_disconnect(&self, cause: DisconnectCause) {
+        info!("Disconnecting WebSocket connection ({:?})", cause);
         self.should_stop.store(true, Ordering::SeqCst);
+        get_connection_diagnostics().record_disconnect(cause);
 
         // 更新连接状态
         self.is_ws_connected.store(false, Ordering::SeqCst);
@@ -182,11 +221,11 @@ impl WebSocketClient {
                 let sender = self.message_sender.read().await;
 
                 if let Some(sender) = sender.as_ref() {
-                    let message = Message::Text(data.to_string().into());
-                    sender.send(message.clone()).map_err(|e| {
+                    sender.send(data.clone()).map_err(|e| {
                         anyhow::anyhow!("Failed to queue message for sending: {}", e)
                     })?;
-                    info!("Message sent {:?}", message);
+                    // 此时只是交给发送任务，实际写入失败会在发送任务中记录
+                    info!("Message queued for sending {}", data);
                     Ok(())
                 } else {
                     warn!("Connection state is Connected but sender not ready, message queued");
@@ -254,7 +293,7 @@ impl WebSocketClient {
                 Some(last_pong)
             },
             consecutive_failures: failures,
-            round_trip_time: None, // 可以在心跳时计算
+            round_trip_time: get_connection_diagnostics().last_rtt(),
         }
     }
 
@@ -271,7 +310,7 @@ impl WebSocketClient {
         self.reconnect_attempts.store(0, Ordering::SeqCst);
 
         // 先断开当前连接
-        self.internal_disconnect().await;
+        self.internal_disconnect(DisconnectCause::Reconnect).await;
 
         // 重新连接
         let config = self.config.read().await.clone();
@@ -402,10 +441,45 @@ impl WebSocketClient {
         info!("Connecting to WebSocket: {}", url_str);
         self.update_state(ConnectionState::Connecting, false).await;
 
-        // 建立连接
-        let (ws_stream, _) = connect_async(url_str)
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to connect to WebSocket '{}': {}", url_str, e))?;
+        // 诊断日志中只记录主机，避免 Token 落盘
+        let diagnostics = get_connection_diagnostics();
+        diagnostics.record_connect_attempt(url.host_str().map(|host| host.to_string()));
+
+        // 建立连接，旧版服务端不支持子协议时回退为不协商
+        let offer = if self.legacy_server.load(Ordering::SeqCst) {
+            Vec::new()
+        } else {
+            config.wire_formats.clone()
+        };
+        let mut result = connect_async(codec::build_request(url_str, &offer)?).await;
+        if let Err(e) = &result {
+            if codec::is_subprotocol_rejected(e) {
+                warn!("Server does not support wire format negotiation, falling back to JSON text");
+                self.legacy_server.store(true, Ordering::SeqCst);
+                result = connect_async(codec::build_request(url_str, &[])?).await;
+            }
+        }
+
+        let (ws_stream, response) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                diagnostics.record_connect_failed(e.to_string());
+                return Err(anyhow::anyhow!(
+                    "Failed to connect to WebSocket '{}': {}",
+                    url_str,
+                    e
+                ));
+            }
+        };
+        let wire_format = codec::negotiated_format(&response);
+        info!("WebSocket wire format: {:?}", wire_format);
+        let session_id =
+            diagnostics.record_connected(Some(format!("wire format: {:?}", wire_format)));
+        self.session_id.store(session_id, Ordering::SeqCst);
+
+        // 新连接重新计算心跳超时，否则沿用上一条连接的超时状态会在首次心跳时立即再次断开
+        self.last_pong_time.store(0, Ordering::SeqCst);
+        self.consecutive_failures.store(0, Ordering::SeqCst);
 
         let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
@@ -428,6 +502,7 @@ impl WebSocketClient {
 
         // 标记为已连接
         self.is_ws_connected.store(true, Ordering::SeqCst);
+        crate::outbox::notify_connectivity_restored();
 
         // 发送待发消息
         self.send_pending_messages().await?;
@@ -442,7 +517,14 @@ impl WebSocketClient {
             tokio::spawn(async move {
                 while !should_stop.load(Ordering::SeqCst) {
                     tokio::select! {
-                        Some(message) = msg_receiver.recv() => {
+                        Some(value) = msg_receiver.recv() => {
+                            let message = match codec::encode(wire_format, &value) {
+                                Ok(message) => message,
+                                Err(e) => {
+                                    error!(" Failed to encode message: {}", e);
+                                    continue;
+                                }
+                            };
                             if let Err(e) = ws_sender.send(message).await {
                                 error!(" Failed to send message: {}", e);
                                 is_ws_connected.store(false, Ordering::SeqCst);
@@ -474,39 +556,53 @@ impl WebSocketClient {
             tokio::spawn(async move {
                 while let Some(msg) = ws_receiver.next().await {
                     match msg {
-                        Ok(Message::Text(text)) => {
-                            Self::handle_message_static(
-                                text.to_string(),
-                                &app_handle,
-                                &last_pong_time,
-                                &consecutive_failures,
-                            )
-                            .await;
+                        Ok(Message::Close(frame)) => {
+                            info!("WebSocket connection closed: {:?}", frame);
+                            let (code, reason) = match frame {
+                                Some(frame) => (
+                                    Some(u16::from(frame.code)),
+                                    Some(frame.reason.to_string()).filter(|r| !r.is_empty()),
+                                ),
+                                None => (None, None),
+                            };
+                            get_connection_diagnostics().record_closed(session_id, code, reason);
+                            is_ws_connected.store(false, Ordering::SeqCst);
+                            break;
                         }
-                        Ok(Message::Binary(data)) => {
-                            if let Ok(text) = String::from_utf8(data.to_vec()) {
+                        Ok(message) => match codec::decode(wire_format, message) {
+                            Ok(Some(frame)) => {
                                 Self::handle_message_static(
-                                    text,
+                                    frame,
                                     &app_handle,
                                     &last_pong_time,
                                     &consecutive_failures,
                                 )
                                 .await;
                             }
-                        }
-                        Ok(Message::Close(_)) => {
-                            info!("WebSocket connection closed");
-                            is_ws_connected.store(false, Ordering::SeqCst);
-                            break;
-                        }
+                            Ok(None) => {}
+                            Err(e) => {
+                                warn!("Failed to decode {:?} frame: {}", wire_format, e);
+                            }
+                        },
                         Err(e) => {
                             error!(" WebSocket message receive error: {}", e);
+                            get_connection_diagnostics().record_closed(
+                                session_id,
+                                None,
+                                Some(e.to_string()),
+                            );
                             is_ws_connected.store(false, Ordering::SeqCst);
                             break;
                         }
-                        _ => {}
                     }
                 }
+
+                // 已记录过关闭（关闭帧、接收错误或心跳超时）时此处会被忽略
+                get_connection_diagnostics().record_closed(
+                    session_id,
+                    None,
+                    Some("stream ended without close frame".to_string()),
+                );
             })
         };
 
@@ -548,28 +644,43 @@ impl WebSocketClient {
 
     /// 处理收到的消息（静态方法，用于异步任务）
     async fn handle_message_static(
-        text: String,
-        app_handle: &AppHandle,
+        frame: DecodedFrame,
+        app_handle: &AppHandle<R>,
         last_pong_time: &Arc<AtomicU64>,
         consecutive_failures: &Arc<AtomicU32>,
     ) {
-        info!("Received message: {}", text);
+        let json_value = match frame {
+            DecodedFrame::Json(value) => value,
+            DecodedFrame::Raw(text) => {
+                info!("Received message: {}", text);
+                // 非JSON消息，直接转发
+                let _ = app_handle.emit(
+                    "websocket-event",
+                    &WebSocketEvent::MessageReceived {
+                        message: serde_json::Value::String(text),
+                    },
+                );
+                return;
+            }
+        };
+        info!("Received message: {}", json_value);
 
         // 尝试解析心跳响应
-        if let Ok(ws_msg) = serde_json::from_str::<WsMessage>(&text) {
+        if let Ok(ws_msg) = WsMessage::deserialize(&json_value) {
             match ws_msg {
                 WsMessage::HeartbeatResponse { timestamp: _ } => {
                     let now = chrono::Utc::now().timestamp_millis() as u64;
                     last_pong_time.store(now, Ordering::SeqCst);
                     consecutive_failures.store(0, Ordering::SeqCst);
+                    let round_trip_time = get_connection_diagnostics().record_pong_received();
 
-                    info!("Received heartbeat response");
+                    info!("Received heartbeat response, rtt: {:?}ms", round_trip_time);
 
                     let health = ConnectionHealth {
                         is_healthy: true,
                         last_pong_time: Some(now),
                         consecutive_failures: 0,
-                        round_trip_time: None,
+                        round_trip_time,
                     };
 
                     let _ = app_handle.emit(
@@ -582,27 +693,16 @@ impl WebSocketClient {
             }
         }
 
-        // 处理业务消息
-        if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(&text) {
-            // 处理具体的业务消息类型
-            Self::process_business_message(&json_value, app_handle).await;
-
-            // 同时发送原始消息事件（保持兼容性）
-            let _ = app_handle.emit(
-                "websocket-event",
-                &WebSocketEvent::MessageReceived {
-                    message: json_value,
-                },
-            );
-        } else {
-            // 非JSON消息，直接转发
-            let _ = app_handle.emit(
-                "websocket-event",
-                &WebSocketEvent::MessageReceived {
-                    message: serde_json::Value::String(text),
-                },
-            );
-        }
+        // 处理具体的业务消息类型
+        Self::process_business_message(&json_value, app_handle).await;
+
+        // 同时发送原始消息事件（保持兼容性）
+        let _ = app_handle.emit(
+            "websocket-event",
+            &WebSocketEvent::MessageReceived {
+                message: json_value,
+            },
+        );
     }
 
     pub async fn send_ack(&self, message_id: &str) -> Result<()> {
@@ -653,7 +753,7 @@ impl WebSocketClient {
     }
 
     /// 处理业务消息类型
-    async fn process_business_message(message: &serde_json::Value, app_handle: &AppHandle) {
+    async fn process_business_message(message: &serde_json::Value, app_handle: &AppHandle<R>) {
         // 提取消息类型
         let message_type = message.get("type").and_then(|t| t.as_str()).unwrap_or("");
 
@@ -714,6 +814,24 @@ impl WebSocketClient {
                 info!("Message recalled");
                 let _ = app_handle.emit_to("home", "ws-msg-recall", data);
             }
+            "msgEdit" => {
+                info!("Message edited");
+                // 只转发已在本地生效的编辑，伪造或未开启时的编辑不通知前端
+                match data.map(|d| serde_json::from_value::<MessageEditEvent>(d.clone())) {
+                    Some(Ok(event)) => {
+                        let state: State<'_, AppData> = app_handle.state();
+                        match apply_message_edit_event(&state, event).await {
+                            Ok(true) => {
+                                let _ = app_handle.emit_to("home", "ws-msg-edit", data);
+                            }
+                            Ok(false) => {}
+                            Err(e) => error!("Failed to apply message edit: {}", e),
+                        }
+                    }
+                    Some(Err(e)) => warn!("Invalid message edit event: {}", e),
+                    None => {}
+                }
+            }
             "msgMarkItem" => {
                 info!("Message liked/disliked");
                 let _ = app_handle.emit_to("home", "ws-msg-mark-item", data);
@@ -873,6 +991,7 @@ impl WebSocketClient {
             let is_app_in_background = self.is_app_in_background.clone();
             let background_heartbeat_failures = self.background_heartbeat_failures.clone();
             let is_ws_connected = self.is_ws_connected.clone();
+            let session_id = self.session_id.clone();
 
             tokio::spawn(async move {
                 let mut heartbeat_interval = interval(Duration::from_millis(interval_ms));
@@ -886,11 +1005,11 @@ impl WebSocketClient {
                     if let Ok(json) = serde_json::to_value(&heartbeat_msg) {
                         let sender = message_sender.read().await;
                         if let Some(sender) = sender.as_ref() {
-                            let message = Message::Text(json.to_string().into());
-                            if let Err(e) = sender.send(message) {
+                            if let Err(e) = sender.send(json) {
                                 error!(" Failed to send heartbeat: {}", e);
                                 break;
                             }
+                            get_connection_diagnostics().record_ping_sent();
                         } else {
                             warn!("Heartbeat send failed: connection not established");
                             break;
@@ -929,11 +1048,21 @@ impl WebSocketClient {
                                 failures,
                                 time_since_pong
                             );
+                            get_connection_diagnostics().record_heartbeat_timeout(
+                                time_since_pong,
+                                failures,
+                                is_background,
+                            );
 
                             // 后台模式下更宽松的重连策略
                             let max_failures = if is_background { 5 } else { 3 };
                             if failures >= max_failures {
                                 error!("Consecutive heartbeat timeouts, triggering reconnection");
+                                get_connection_diagnostics().record_closed(
+                                    session_id.load(Ordering::SeqCst),
+                                    None,
+                                    Some("heartbeat timeout".to_string()),
+                                );
                                 // 心跳失败时标记连接断开
                                 is_ws_connected.store(false, Ordering::SeqCst);
                                 break;
@@ -971,10 +1100,9 @@ impl WebSocketClient {
 
             // 尝试发送每条消息
             for message in messages_to_send {
-                let text_message = Message::Text(message.to_string().into());
-                if let Err(e) = sender.send(text_message) {
+                if let Err(e) = sender.send(message) {
                     error!(" Failed to send pending message: {}", e);
-                    failed_messages.push(message);
+                    failed_messages.push(e.0);
                 }
             }
 
@@ -1050,6 +1178,10 @@ impl WebSocketClient {
             .is_app_in_background
             .swap(is_background, Ordering::SeqCst);
 
+        if is_background != was_background {
+            get_connection_diagnostics().record_background_changed(is_background);
+        }
+
         if is_background && !was_background {
             info!("App entered background mode");
             // 重置后台心跳失败计数
@@ -1180,8 +1312,10 @@ impl WebSocketClient {
         });
     }
 
-    async fn run_sync_messages(app_handle: &AppHandle) -> Result<(), String> {
-        let state: State<'_, AppData> = app_handle.state();
+    async fn run_sync_messages(app_handle: &AppHandle<R>) -> Result<(), String> {
+        let Some(state) = app_handle.try_state::<AppData>() else {
+            return Err("App state is not initialized".to_string());
+        };
 
         let params = Some(SyncMessagesParam {
             async_data: Some(true),
@@ -0,0 +1,231 @@
+// WebSocket 线路编解码
+//
+// tungstenite 目前不支持 RFC 7692 (permessage-deflate) 扩展，
+// 因此压缩与二进制编码通过 `Sec-WebSocket-Protocol` 子协议在握手时协商：
+// - `hula.msgpack.v1`：业务消息以 MessagePack 二进制帧传输
+// - `hula.json.deflate.v1`：较大的 JSON 以 deflate 压缩后的二进制帧传输，小消息仍为文本帧
+// - `hula.json.v1` / 未协商：与旧版服务端一致的 JSON 文本帧
+//
+// 这些子协议需要服务端支持，默认不协商（握手不带子协议头，与旧版客户端一致），
+// 只有配置 `backend.ws_wire_formats` 声明了服务端支持的格式时才会提供。
+//
+// 上层只处理 `serde_json::Value`，不感知具体线路格式。
+
+use anyhow::Result;
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use tokio_tungstenite::tungstenite::{
+    Error as WsError,
+    client::IntoClientRequest,
+    error::ProtocolError,
+    handshake::client::{Request, Response},
+    http::HeaderValue,
+    protocol::Message,
+};
+
+pub const SUBPROTOCOL_HEADER: &str = "Sec-WebSocket-Protocol";
+/// 超过该字节数的 JSON 才进行压缩，小消息压缩收益不足以抵消开销
+const DEFLATE_THRESHOLD: usize = 1024;
+/// 解压后的最大字节数，防止压缩炸弹
+const MAX_INFLATED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// 线路格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WireFormat {
+    Json,
+    JsonDeflate,
+    MessagePack,
+}
+
+impl WireFormat {
+    pub fn subprotocol(self) -> &'static str {
+        match self {
+            WireFormat::Json => "hula.json.v1",
+            WireFormat::JsonDeflate => "hula.json.deflate.v1",
+            WireFormat::MessagePack => "hula.msgpack.v1",
+        }
+    }
+
+    pub fn from_subprotocol(value: &str) -> Option<Self> {
+        [
+            WireFormat::Json,
+            WireFormat::JsonDeflate,
+            WireFormat::MessagePack,
+        ]
+        .into_iter()
+        .find(|format| format.subprotocol() == value.trim())
+    }
+}
+
+/// 解码后的帧
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedFrame {
+    Json(serde_json::Value),
+    /// 非 JSON 文本，原样转发
+    Raw(String),
+}
+
+/// 构建握手请求；只有提供了 JSON 文本以外的格式时才携带子协议头，
+/// 这样纯 JSON 的配置与旧版服务端的握手完全一致
+pub fn build_request(url: &str, offer: &[WireFormat]) -> Result<Request, WsError> {
+    let mut request = url.into_client_request()?;
+
+    if offer.iter().any(|format| *format != WireFormat::Json) {
+        let protocols = offer
+            .iter()
+            .map(|format| format.subprotocol())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let value = HeaderValue::from_str(&protocols)
+            .map_err(|e| WsError::HttpFormat(e.into()))?;
+        request.headers_mut().insert(SUBPROTOCOL_HEADER, value);
+    }
+
+    Ok(request)
+}
+
+/// 根据握手响应确定本次连接使用的格式
+pub fn negotiated_format(response: &Response) -> WireFormat {
+    response
+        .headers()
+        .get(SUBPROTOCOL_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(WireFormat::from_subprotocol)
+        .unwrap_or(WireFormat::Json)
+}
+
+/// 握手是否因为服务端不支持子协议而失败（旧版服务端）
+pub fn is_subprotocol_rejected(err: &WsError) -> bool {
+    matches!(
+        err,
+        WsError::Protocol(ProtocolError::SecWebSocketSubProtocolError(_))
+    )
+}
+
+/// 编码待发送的消息
+pub fn encode(format: WireFormat, value: &serde_json::Value) -> Result<Message> {
+    match format {
+        WireFormat::Json => Ok(Message::Text(value.to_string().into())),
+        WireFormat::JsonDeflate => {
+            let text = value.to_string();
+            if text.len() < DEFLATE_THRESHOLD {
+                return Ok(Message::Text(text.into()));
+            }
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(text.as_bytes())?;
+            Ok(Message::Binary(encoder.finish()?.into()))
+        }
+        WireFormat::MessagePack => Ok(Message::Binary(rmp_serde::to_vec_named(value)?.into())),
+    }
+}
+
+/// 解码收到的消息，控制帧返回 None
+pub fn decode(format: WireFormat, message: Message) -> Result<Option<DecodedFrame>> {
+    let frame = match message {
+        Message::Text(text) => decode_text(text.to_string()),
+        Message::Binary(data) => match format {
+            WireFormat::MessagePack => {
+                DecodedFrame::Json(rmp_serde::from_slice::<serde_json::Value>(&data)?)
+            }
+            WireFormat::JsonDeflate => decode_text(inflate(&data)?),
+            WireFormat::Json => decode_text(String::from_utf8(data.to_vec())?),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(frame))
+}
+
+fn decode_text(text: String) -> DecodedFrame {
+    match serde_json::from_str::<serde_json::Value>(&text) {
+        Ok(value) => DecodedFrame::Json(value),
+        Err(_) => DecodedFrame::Raw(text),
+    }
+}
+
+fn inflate(data: &[u8]) -> Result<String> {
+    let mut text = String::new();
+    DeflateDecoder::new(data)
+        .take(MAX_INFLATED_SIZE + 1)
+        .read_to_string(&mut text)?;
+    if text.len() as u64 > MAX_INFLATED_SIZE {
+        return Err(anyhow::anyhow!("Inflated frame exceeds {} bytes", MAX_INFLATED_SIZE));
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn large_payload() -> serde_json::Value {
+        json!({
+            "type": "memberChange",
+            "data": { "uids": (0..500).map(|i| i.to_string()).collect::<Vec<_>>() }
+        })
+    }
+
+    #[test]
+    fn test_round_trip_all_formats() {
+        let small = json!({ "type": "2" });
+        for format in [
+            WireFormat::Json,
+            WireFormat::JsonDeflate,
+            WireFormat::MessagePack,
+        ] {
+            for value in [small.clone(), large_payload()] {
+                let encoded = encode(format, &value).unwrap();
+                assert_eq!(
+                    decode(format, encoded).unwrap(),
+                    Some(DecodedFrame::Json(value))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_deflate_only_above_threshold() {
+        let small = encode(WireFormat::JsonDeflate, &json!({ "type": "2" })).unwrap();
+        assert!(matches!(small, Message::Text(_)));
+
+        let large = encode(WireFormat::JsonDeflate, &large_payload()).unwrap();
+        match large {
+            Message::Binary(data) => assert!(data.len() < large_payload().to_string().len()),
+            other => panic!("expected binary frame, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_non_json_text_is_forwarded_raw() {
+        let frame = decode(WireFormat::Json, Message::Text("pong".into())).unwrap();
+        assert_eq!(frame, Some(DecodedFrame::Raw("pong".to_string())));
+        assert_eq!(decode(WireFormat::Json, Message::Ping(Vec::new().into())).unwrap(), None);
+    }
+
+    #[test]
+    fn test_subprotocol_header() {
+        let request = build_request("ws://localhost/ws", &[WireFormat::Json]).unwrap();
+        assert!(request.headers().get(SUBPROTOCOL_HEADER).is_none());
+
+        // 默认配置不协商子协议，握手与旧版客户端一致
+        let config = crate::websocket::WebSocketConfig::default();
+        let request = build_request("ws://localhost/ws", &config.wire_formats).unwrap();
+        assert!(request.headers().get(SUBPROTOCOL_HEADER).is_none());
+
+        let request = build_request(
+            "ws://localhost/ws",
+            &[WireFormat::JsonDeflate, WireFormat::Json],
+        )
+        .unwrap();
+        assert_eq!(
+            request.headers().get(SUBPROTOCOL_HEADER).unwrap(),
+            "hula.json.deflate.v1, hula.json.v1"
+        );
+        assert_eq!(
+            WireFormat::from_subprotocol(" hula.msgpack.v1"),
+            Some(WireFormat::MessagePack)
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use crate::AppData;
 
-use super::{client::WebSocketClient, types::*};
+use super::{
+    client::WebSocketClient,
+    diagnostics::{DiagnosticsSnapshot, DisconnectCause, get_connection_diagnostics},
+    types::*,
+};
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, OnceLock};
 use tauri::{AppHandle, State};
@@ -69,10 +73,13 @@ pub async fn ws_init_connection(
     let client_container = get_websocket_client_container();
     let rc = state.rc.lock().await;
 
+    let backend = state.config.lock().await.backend.clone();
     let config = WebSocketConfig {
-        server_url: state.config.lock().await.backend.ws_url.clone(),
+        server_url: backend.ws_url,
         client_id: params.client_id,
         token: rc.token.clone(),
+        // 只提供服务端声明支持的格式，未声明时握手不带子协议头
+        wire_formats: backend.ws_wire_formats,
         ..Default::default()
     };
 
@@ -123,7 +130,7 @@ pub async fn ws_disconnect(_app_handle: AppHandle) -> Result<SuccessResponse, St
     let mut client_guard = client_container.write().await;
 
     if let Some(client) = client_guard.take() {
-        client.internal_disconnect().await;
+        client.internal_disconnect(DisconnectCause::Manual).await;
     }
 
     info!("WebSocket connection disconnected");
@@ -179,6 +186,12 @@ pub async fn ws_get_health(_app_handle: AppHandle) -> Result<ConnectionHealth, S
     }
 }
 
+/// 获取连接诊断信息（生命周期事件日志与 RTT 统计）
+#[tauri::command]
+pub async fn ws_get_diagnostics(_app_handle: AppHandle) -> Result<DiagnosticsSnapshot, String> {
+    Ok(get_connection_diagnostics().snapshot())
+}
+
 /// 强制重连
 #[tauri::command]
 pub async fn ws_force_reconnect(_app_handle: AppHandle) -> Result<SuccessResponse, String> {
@@ -0,0 +1,460 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+/// 事件环形缓冲区容量
+const MAX_EVENTS: usize = 200;
+/// 参与 RTT 百分位统计的最近样本数
+const MAX_RTT_SAMPLES: usize = 120;
+
+// 全局连接诊断实例，客户端被销毁重建后历史依然保留
+static GLOBAL_DIAGNOSTICS: OnceLock<ConnectionDiagnostics> = OnceLock::new();
+
+/// 获取全局连接诊断实例
+pub fn get_connection_diagnostics() -> &'static ConnectionDiagnostics {
+    GLOBAL_DIAGNOSTICS.get_or_init(ConnectionDiagnostics::new)
+}
+
+/// 客户端主动断开连接的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectCause {
+    /// 用户退出登录或前端请求断开
+    Manual,
+    /// 强制重连前关闭旧连接
+    Reconnect,
+}
+
+/// 连接生命周期事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConnectionEventKind {
+    ConnectAttempt,
+    Connected,
+    ConnectFailed,
+    Closed,
+    HeartbeatTimeout,
+    EnteredBackground,
+    EnteredForeground,
+    ManualDisconnect,
+}
+
+/// 单条连接生命周期事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionEvent {
+    pub kind: ConnectionEventKind,
+    pub timestamp: i64,
+    /// 附加说明（错误信息、关闭原因等）
+    pub detail: Option<String>,
+    /// 服务端关闭帧中的关闭码
+    pub close_code: Option<u16>,
+    /// 与该事件相关的持续时长：握手耗时、会话时长或后台停留时长
+    pub duration_ms: Option<u64>,
+}
+
+/// RTT 统计
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RttStats {
+    pub sample_count: usize,
+    pub last: Option<u64>,
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    pub p50: Option<u64>,
+    pub p90: Option<u64>,
+    pub p99: Option<u64>,
+}
+
+/// 诊断快照，供 `ws_get_diagnostics` 返回
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsSnapshot {
+    pub events: Vec<ConnectionEvent>,
+    pub rtt: RttStats,
+    pub total_connect_attempts: u64,
+    pub total_connect_failures: u64,
+    pub total_disconnects: u64,
+    pub current_session_started_at: Option<i64>,
+    pub longest_session_ms: u64,
+    pub is_in_background: bool,
+}
+
+#[derive(Debug, Default)]
+struct DiagnosticsState {
+    events: VecDeque<ConnectionEvent>,
+    rtt_samples: VecDeque<u64>,
+    pending_ping_at: Option<i64>,
+    connect_started_at: Option<i64>,
+    session_started_at: Option<i64>,
+    /// 最近一次建立的会话编号，关闭事件据此去重
+    session_id: u64,
+    background_since: Option<i64>,
+    total_connect_attempts: u64,
+    total_connect_failures: u64,
+    total_disconnects: u64,
+    longest_session_ms: u64,
+}
+
+impl DiagnosticsState {
+    fn push(
+        &mut self,
+        kind: ConnectionEventKind,
+        now: i64,
+        detail: Option<String>,
+        close_code: Option<u16>,
+        duration_ms: Option<u64>,
+    ) {
+        if self.events.len() >= MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(ConnectionEvent {
+            kind,
+            timestamp: now,
+            detail,
+            close_code,
+            duration_ms,
+        });
+    }
+
+    fn elapsed(since: Option<i64>, now: i64) -> Option<u64> {
+        since.map(|start| now.saturating_sub(start).max(0) as u64)
+    }
+
+    fn connect_attempt(&mut self, now: i64, detail: Option<String>) {
+        self.total_connect_attempts += 1;
+        self.connect_started_at = Some(now);
+        self.push(ConnectionEventKind::ConnectAttempt, now, detail, None, None);
+    }
+
+    fn connected(&mut self, now: i64, detail: Option<String>) -> u64 {
+        let handshake = Self::elapsed(self.connect_started_at.take(), now);
+        self.session_started_at = Some(now);
+        self.session_id += 1;
+        self.pending_ping_at = None;
+        self.push(ConnectionEventKind::Connected, now, detail, None, handshake);
+        self.session_id
+    }
+
+    fn connect_failed(&mut self, now: i64, error: String) {
+        self.total_connect_failures += 1;
+        let elapsed = Self::elapsed(self.connect_started_at.take(), now);
+        self.push(
+            ConnectionEventKind::ConnectFailed,
+            now,
+            Some(error),
+            None,
+            elapsed,
+        );
+    }
+
+    /// 结束当前会话，返回会话时长；没有进行中的会话时返回 None
+    fn end_session(&mut self, now: i64) -> Option<u64> {
+        let duration = Self::elapsed(self.session_started_at.take(), now)?;
+        self.total_disconnects += 1;
+        self.pending_ping_at = None;
+        self.longest_session_ms = self.longest_session_ms.max(duration);
+        Some(duration)
+    }
+
+    /// 记录会话关闭；同一会话只记录第一次，已结束或已被新连接取代的会话直接忽略
+    fn closed(
+        &mut self,
+        now: i64,
+        session_id: u64,
+        close_code: Option<u16>,
+        reason: Option<String>,
+    ) {
+        if session_id != self.session_id {
+            return;
+        }
+        let Some(duration) = self.end_session(now) else {
+            return;
+        };
+        self.push(
+            ConnectionEventKind::Closed,
+            now,
+            reason,
+            close_code,
+            Some(duration),
+        );
+    }
+
+    fn disconnect(&mut self, now: i64, cause: DisconnectCause) {
+        match cause {
+            DisconnectCause::Manual => {
+                let duration = self.end_session(now);
+                self.push(
+                    ConnectionEventKind::ManualDisconnect,
+                    now,
+                    None,
+                    None,
+                    duration,
+                );
+            }
+            DisconnectCause::Reconnect => {
+                self.closed(
+                    now,
+                    self.session_id,
+                    None,
+                    Some("force reconnect".to_string()),
+                );
+            }
+        }
+    }
+
+    fn heartbeat_timeout(&mut self, now: i64, since_pong_ms: u64, failures: u32, background: bool) {
+        let detail = format!(
+            "{} mode, consecutive failures: {}",
+            if background { "background" } else { "foreground" },
+            failures
+        );
+        self.push(
+            ConnectionEventKind::HeartbeatTimeout,
+            now,
+            Some(detail),
+            None,
+            Some(since_pong_ms),
+        );
+    }
+
+    fn background_changed(&mut self, now: i64, is_background: bool) {
+        if is_background {
+            self.background_since = Some(now);
+            self.push(ConnectionEventKind::EnteredBackground, now, None, None, None);
+        } else {
+            let duration = Self::elapsed(self.background_since.take(), now);
+            self.push(
+                ConnectionEventKind::EnteredForeground,
+                now,
+                None,
+                None,
+                duration,
+            );
+        }
+    }
+
+    fn ping_sent(&mut self, now: i64) {
+        // 只记录最早一个未应答的心跳，避免连续超时时 RTT 被低估
+        if self.pending_ping_at.is_none() {
+            self.pending_ping_at = Some(now);
+        }
+    }
+
+    fn pong_received(&mut self, now: i64) -> Option<u64> {
+        let rtt = Self::elapsed(self.pending_ping_at.take(), now)?;
+        if self.rtt_samples.len() >= MAX_RTT_SAMPLES {
+            self.rtt_samples.pop_front();
+        }
+        self.rtt_samples.push_back(rtt);
+        Some(rtt)
+    }
+
+    fn rtt_stats(&self) -> RttStats {
+        if self.rtt_samples.is_empty() {
+            return RttStats::default();
+        }
+
+        let mut sorted: Vec<u64> = self.rtt_samples.iter().copied().collect();
+        sorted.sort_unstable();
+
+        RttStats {
+            sample_count: sorted.len(),
+            last: self.rtt_samples.back().copied(),
+            min: sorted.first().copied(),
+            max: sorted.last().copied(),
+            p50: Some(percentile(&sorted, 50.0)),
+            p90: Some(percentile(&sorted, 90.0)),
+            p99: Some(percentile(&sorted, 99.0)),
+        }
+    }
+
+    fn snapshot(&self) -> DiagnosticsSnapshot {
+        DiagnosticsSnapshot {
+            events: self.events.iter().cloned().collect(),
+            rtt: self.rtt_stats(),
+            total_connect_attempts: self.total_connect_attempts,
+            total_connect_failures: self.total_connect_failures,
+            total_disconnects: self.total_disconnects,
+            current_session_started_at: self.session_started_at,
+            longest_session_ms: self.longest_session_ms,
+            is_in_background: self.background_since.is_some(),
+        }
+    }
+}
+
+/// 最近秩法计算百分位，`sorted` 必须非空且已升序
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// WebSocket 连接诊断信息
+///
+/// 使用同步锁保存，方便在 `set_app_background_state` 等同步方法中直接记录
+#[derive(Debug)]
+pub struct ConnectionDiagnostics {
+    state: Mutex<DiagnosticsState>,
+}
+
+impl ConnectionDiagnostics {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(DiagnosticsState::default()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DiagnosticsState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn now() -> i64 {
+        chrono::Utc::now().timestamp_millis()
+    }
+
+    pub fn record_connect_attempt(&self, detail: Option<String>) {
+        self.lock().connect_attempt(Self::now(), detail);
+    }
+
+    /// 记录连接建立，返回本次会话编号
+    pub fn record_connected(&self, detail: Option<String>) -> u64 {
+        self.lock().connected(Self::now(), detail)
+    }
+
+    pub fn record_connect_failed(&self, error: String) {
+        self.lock().connect_failed(Self::now(), error);
+    }
+
+    pub fn record_closed(&self, session_id: u64, close_code: Option<u16>, reason: Option<String>) {
+        self.lock()
+            .closed(Self::now(), session_id, close_code, reason);
+    }
+
+    pub fn record_disconnect(&self, cause: DisconnectCause) {
+        self.lock().disconnect(Self::now(), cause);
+    }
+
+    pub fn record_heartbeat_timeout(&self, since_pong_ms: u64, failures: u32, background: bool) {
+        self.lock()
+            .heartbeat_timeout(Self::now(), since_pong_ms, failures, background);
+    }
+
+    pub fn record_background_changed(&self, is_background: bool) {
+        self.lock().background_changed(Self::now(), is_background);
+    }
+
+    pub fn record_ping_sent(&self) {
+        self.lock().ping_sent(Self::now());
+    }
+
+    /// 记录收到心跳响应，返回本次 RTT
+    pub fn record_pong_received(&self) -> Option<u64> {
+        self.lock().pong_received(Self::now())
+    }
+
+    /// 最近一次 RTT
+    pub fn last_rtt(&self) -> Option<u64> {
+        self.lock().rtt_samples.back().copied()
+    }
+
+    pub fn snapshot(&self) -> DiagnosticsSnapshot {
+        self.lock().snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_buffer_is_bounded() {
+        let mut state = DiagnosticsState::default();
+        for i in 0..(MAX_EVENTS as i64 + 10) {
+            state.connect_attempt(i, None);
+        }
+
+        let snapshot = state.snapshot();
+        assert_eq!(snapshot.events.len(), MAX_EVENTS);
+        assert_eq!(snapshot.events[0].timestamp, 10);
+        assert_eq!(snapshot.total_connect_attempts, MAX_EVENTS as u64 + 10);
+    }
+
+    #[test]
+    fn test_session_and_handshake_durations() {
+        let mut state = DiagnosticsState::default();
+        state.connect_attempt(1_000, None);
+        let session = state.connected(1_250, None);
+        state.closed(61_250, session, Some(1006), Some("abnormal".to_string()));
+
+        let snapshot = state.snapshot();
+        assert_eq!(snapshot.events[1].duration_ms, Some(250));
+        assert_eq!(snapshot.events[2].close_code, Some(1006));
+        assert_eq!(snapshot.events[2].duration_ms, Some(60_000));
+        assert_eq!(snapshot.longest_session_ms, 60_000);
+        assert_eq!(snapshot.total_disconnects, 1);
+        assert!(snapshot.current_session_started_at.is_none());
+    }
+
+    #[test]
+    fn test_session_closed_once() {
+        let mut state = DiagnosticsState::default();
+        let first = state.connected(0, None);
+        state.closed(1_000, first, None, Some("heartbeat timeout".to_string()));
+        // 接收任务随后结束，不应再记录一次关闭
+        state.closed(1_500, first, None, Some("stream ended".to_string()));
+
+        // 旧连接的接收任务在新会话建立后才结束，也不应结束新会话
+        let second = state.connected(2_000, None);
+        state.closed(2_500, first, Some(1006), None);
+        assert_eq!(state.snapshot().current_session_started_at, Some(2_000));
+
+        state.disconnect(3_000, DisconnectCause::Reconnect);
+        state.closed(3_100, second, Some(1000), None);
+
+        let snapshot = state.snapshot();
+        let closed: Vec<_> = snapshot
+            .events
+            .iter()
+            .filter(|e| e.kind == ConnectionEventKind::Closed)
+            .map(|e| e.detail.as_deref())
+            .collect();
+        assert_eq!(
+            closed,
+            vec![Some("heartbeat timeout"), Some("force reconnect")]
+        );
+        assert_eq!(snapshot.total_disconnects, 2);
+    }
+
+    #[test]
+    fn test_rtt_percentiles() {
+        let mut state = DiagnosticsState::default();
+        for rtt in 1..=100 {
+            state.ping_sent(0);
+            assert_eq!(state.pong_received(rtt), Some(rtt as u64));
+        }
+        // 没有未应答的心跳时不产生样本
+        assert_eq!(state.pong_received(500), None);
+
+        let rtt = state.rtt_stats();
+        assert_eq!(rtt.sample_count, 100);
+        assert_eq!(rtt.min, Some(1));
+        assert_eq!(rtt.max, Some(100));
+        assert_eq!(rtt.p50, Some(50));
+        assert_eq!(rtt.p90, Some(90));
+        assert_eq!(rtt.p99, Some(99));
+    }
+
+    #[test]
+    fn test_background_duration() {
+        let mut state = DiagnosticsState::default();
+        state.background_changed(1_000, true);
+        assert!(state.snapshot().is_in_background);
+        state.background_changed(4_000, false);
+
+        let snapshot = state.snapshot();
+        assert!(!snapshot.is_in_background);
+        assert_eq!(
+            snapshot.events.last().map(|e| e.duration_ms),
+            Some(Some(3_000))
+        );
+    }
+}
@@ -1,7 +1,9 @@
 /// WebSocket 模块
 /// 提供 WebSocket 连接管理、心跳机制、消息处理等功能
 pub mod client;
+pub mod codec;
 pub mod commands;
+pub mod diagnostics;
 pub mod message;
 pub mod types;
 
@@ -1,3 +1,4 @@
+use super::codec::WireFormat;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -47,6 +48,8 @@ pub struct WebSocketConfig {
     pub heartbeat_timeout: u64,
     pub max_reconnect_attempts: u32,
     pub reconnect_delay_ms: u64,
+    /// 握手时按优先级提供的线路格式，为空时不协商，使用 JSON 文本
+    pub wire_formats: Vec<WireFormat>,
 }
 
 impl Default for WebSocketConfig {
@@ -60,6 +63,7 @@ impl Default for WebSocketConfig {
             // 0 表示无限重连
             max_reconnect_attempts: 0,
             reconnect_delay_ms: 1000, // 1秒
+            wire_formats: Vec::new(),
         }
     }
 }